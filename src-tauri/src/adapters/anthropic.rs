@@ -57,6 +57,16 @@ enum AnthropicContent {
         tool_use_id: String,
         content: String,
     },
+    #[serde(rename = "image")]
+    Image { source: AnthropicImageSource },
+}
+
+#[derive(Serialize, Clone)]
+struct AnthropicImageSource {
+    #[serde(rename = "type")]
+    type_: String, // "base64"
+    media_type: String,
+    data: String,
 }
 
 #[derive(Serialize)]
@@ -124,6 +134,18 @@ impl ModelAdapter for AnthropicAdapter {
                 }
             }
 
+            if m.role == Role::User {
+                for attachment in m.attachments.unwrap_or_default() {
+                    content.push(AnthropicContent::Image {
+                        source: AnthropicImageSource {
+                            type_: "base64".to_string(),
+                            media_type: attachment.mime_type,
+                            data: attachment.data,
+                        },
+                    });
+                }
+            }
+
             if let Some(tool_calls) = m.tool_calls {
                 for tc in tool_calls {
                     let input = serde_json::from_str(&tc.arguments).unwrap_or(json!({}));
@@ -0,0 +1,94 @@
+use crate::domain::models::{ConfirmationRequest, ConfirmationResponse, Question};
+use crate::domain::ports::InteractionProvider;
+use async_trait::async_trait;
+use serde_json::Value;
+
+/// Which requests an unattended run approves
+#[derive(Debug, Clone, PartialEq)]
+pub enum AutoPolicy {
+    /// Approve everything except suspected doom loops
+    AllowAll,
+    DenyAll,
+    /// Approve requests whose tool name or request type is listed
+    Allow(Vec<String>),
+}
+
+impl AutoPolicy {
+    /// `allow`, `deny`, or a comma-separated list such as `read_file,bash,doom_loop`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "" => Err("Empty approval policy".to_string()),
+            "allow" | "all" => Ok(AutoPolicy::AllowAll),
+            "deny" | "none" => Ok(AutoPolicy::DenyAll),
+            list => Ok(AutoPolicy::Allow(
+                list.split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+            )),
+        }
+    }
+
+    pub fn allows(&self, request: &ConfirmationRequest) -> bool {
+        match self {
+            AutoPolicy::AllowAll => request.kind != "doom_loop",
+            AutoPolicy::DenyAll => false,
+            AutoPolicy::Allow(names) => names.iter().any(|n| *n == request.tool_name || *n == request.kind),
+        }
+    }
+}
+
+/// Answers every request from a fixed policy, for scripts and CI
+pub struct AutoResponder {
+    policy: AutoPolicy,
+}
+
+impl AutoResponder {
+    pub fn new(policy: AutoPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+#[async_trait]
+impl InteractionProvider for AutoResponder {
+    async fn confirm(&self, request: ConfirmationRequest) -> Result<ConfirmationResponse, String> {
+        Ok(ConfirmationResponse {
+            allowed: self.policy.allows(&request),
+            always: false,
+            pattern: None,
+        })
+    }
+
+    async fn ask(&self, _session_id: &str, _questions: Vec<Question>) -> Result<Value, String> {
+        Err("No one is available to answer questions in this run; proceed with your best judgement and state your assumptions.".to_string())
+    }
+
+    fn notify(&self, _event: &str, _payload: Value) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        let shell = ConfirmationRequest::shell("s", "cargo test", "cargo*".to_string());
+        let looping = ConfirmationRequest::permission("s", "doom_loop", "bash", "bash:ls", "bash:*".to_string());
+
+        assert!(AutoPolicy::parse("allow").unwrap().allows(&shell));
+        assert!(!AutoPolicy::parse("allow").unwrap().allows(&looping));
+        assert!(!AutoPolicy::parse("deny").unwrap().allows(&shell));
+
+        let listed = AutoPolicy::parse("read_file, bash").unwrap();
+        assert!(listed.allows(&shell));
+        assert!(listed.allows(&looping));
+        assert!(!AutoPolicy::parse("read_file").unwrap().allows(&shell));
+        assert!(AutoPolicy::parse(" ").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_questions_are_refused() {
+        let responder = AutoResponder::new(AutoPolicy::AllowAll);
+        assert!(responder.ask("s", vec![]).await.is_err());
+    }
+}
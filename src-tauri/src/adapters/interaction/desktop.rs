@@ -0,0 +1,84 @@
+use super::{PendingRequests, DEFAULT_TIMEOUT};
+use crate::domain::models::{ConfirmationRequest, ConfirmationResponse, Question};
+use crate::domain::ports::InteractionProvider;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+#[derive(Serialize)]
+struct ConfirmationEvent<'a> {
+    id: &'a str,
+    #[serde(flatten)]
+    request: &'a ConfirmationRequest,
+}
+
+#[derive(Serialize)]
+struct QuestionEvent<'a> {
+    id: &'a str,
+    session_id: &'a str,
+    questions: &'a [Question],
+}
+
+/// Prompts shown in the desktop app; answers come back through the
+/// `confirm_action` and `resolve_question` commands.
+pub struct DesktopInteraction {
+    app: AppHandle,
+    pending: Arc<PendingRequests>,
+    timeout: Duration,
+}
+
+impl DesktopInteraction {
+    pub fn new(app: AppHandle, pending: Arc<PendingRequests>) -> Self {
+        Self {
+            app,
+            pending,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+#[async_trait]
+impl InteractionProvider for DesktopInteraction {
+    async fn confirm(&self, request: ConfirmationRequest) -> Result<ConfirmationResponse, String> {
+        let (id, rx) = self.pending
+            .register_confirmation(&request.session_id, request.parent_session_id.as_deref());
+
+        let event = ConfirmationEvent {
+            id: &id,
+            request: &request,
+        };
+        if let Err(e) = self.app.emit("request-confirmation", &event) {
+            self.pending.discard(&id);
+            return Err(format!("Failed to emit confirmation event: {}", e));
+        }
+
+        self.pending.wait_confirmation(&id, rx, self.timeout).await
+    }
+
+    async fn ask(&self, session_id: &str, questions: Vec<Question>) -> Result<Value, String> {
+        let (id, rx) = self.pending.register_question(session_id);
+
+        let event = QuestionEvent {
+            id: &id,
+            session_id,
+            questions: &questions,
+        };
+        if let Err(e) = self.app.emit("agent:question", &event) {
+            self.pending.discard(&id);
+            return Err(format!("Failed to emit question event: {}", e));
+        }
+
+        self.pending.wait_question(&id, rx, self.timeout).await
+    }
+
+    fn notify(&self, event: &str, payload: Value) {
+        let _ = self.app.emit(event, payload);
+    }
+
+    fn cancel_session(&self, session_id: &str) {
+        self.pending.cancel_session(session_id);
+    }
+}
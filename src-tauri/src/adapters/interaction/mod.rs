@@ -0,0 +1,233 @@
+//! Implementations of [`InteractionProvider`]: the desktop UI, an
+//! interactive terminal, and an unattended policy for scripts and CI.
+
+pub mod auto;
+pub mod desktop;
+pub mod terminal;
+
+pub use auto::{AutoPolicy, AutoResponder};
+pub use desktop::DesktopInteraction;
+pub use terminal::TerminalInteraction;
+
+use crate::domain::models::{ConfirmationRequest, ConfirmationResponse, Question};
+use crate::domain::ports::InteractionProvider;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// How long a request waits for an answer before it is abandoned
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+struct Waiter<T> {
+    session_id: String,
+    /// Parent of a sub-agent session; cancelling it cancels the request too
+    parent_session_id: Option<String>,
+    tx: oneshot::Sender<T>,
+}
+
+impl<T> Waiter<T> {
+    fn belongs_to(&self, session_id: &str) -> bool {
+        self.session_id == session_id || self.parent_session_id.as_deref() == Some(session_id)
+    }
+}
+
+type Waiters<T> = Mutex<HashMap<String, Waiter<T>>>;
+
+/// Requests sent to a frontend that are waiting for its answer
+#[derive(Default)]
+pub struct PendingRequests {
+    confirmations: Waiters<ConfirmationResponse>,
+    questions: Waiters<Value>,
+}
+
+impl PendingRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_confirmation(
+        &self,
+        session_id: &str,
+        parent_session_id: Option<&str>,
+    ) -> (String, oneshot::Receiver<ConfirmationResponse>) {
+        register(&self.confirmations, session_id, parent_session_id)
+    }
+
+    pub fn register_question(&self, session_id: &str) -> (String, oneshot::Receiver<Value>) {
+        register(&self.questions, session_id, None)
+    }
+
+    pub fn resolve_confirmation(&self, id: &str, response: ConfirmationResponse) -> Result<(), String> {
+        resolve(&self.confirmations, id, response)
+            .map_err(|_| "Confirmation ID not found or already processed".to_string())
+    }
+
+    pub fn resolve_question(&self, id: &str, answers: Value) -> Result<(), String> {
+        resolve(&self.questions, id, answers).map_err(|_| format!("Question {} not found or already answered", id))
+    }
+
+    pub async fn wait_confirmation(
+        &self,
+        id: &str,
+        rx: oneshot::Receiver<ConfirmationResponse>,
+        timeout: Duration,
+    ) -> Result<ConfirmationResponse, String> {
+        wait(&self.confirmations, id, rx, timeout, "Confirmation").await
+    }
+
+    pub async fn wait_question(&self, id: &str, rx: oneshot::Receiver<Value>, timeout: Duration) -> Result<Value, String> {
+        wait(&self.questions, id, rx, timeout, "Question").await
+    }
+
+    /// Forget a request that could not be delivered
+    pub fn discard(&self, id: &str) {
+        lock(&self.confirmations).remove(id);
+        lock(&self.questions).remove(id);
+    }
+
+    /// Drop every request of `session_id` and of the sub-agents it runs;
+    /// their waiters fail immediately
+    pub fn cancel_session(&self, session_id: &str) {
+        lock(&self.confirmations).retain(|_, w| !w.belongs_to(session_id));
+        lock(&self.questions).retain(|_, w| !w.belongs_to(session_id));
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.confirmations).len() + lock(&self.questions).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn lock<T>(waiters: &Waiters<T>) -> std::sync::MutexGuard<'_, HashMap<String, Waiter<T>>> {
+    waiters.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn register<T>(waiters: &Waiters<T>, session_id: &str, parent_session_id: Option<&str>) -> (String, oneshot::Receiver<T>) {
+    let id = Uuid::new_v4().to_string();
+    let (tx, rx) = oneshot::channel();
+    lock(waiters).insert(
+        id.clone(),
+        Waiter {
+            session_id: session_id.to_string(),
+            parent_session_id: parent_session_id.map(String::from),
+            tx,
+        },
+    );
+    (id, rx)
+}
+
+fn resolve<T>(waiters: &Waiters<T>, id: &str, value: T) -> Result<(), ()> {
+    let waiter = lock(waiters).remove(id).ok_or(())?;
+    waiter.tx.send(value).map_err(|_| ())
+}
+
+async fn wait<T>(
+    waiters: &Waiters<T>,
+    id: &str,
+    rx: oneshot::Receiver<T>,
+    timeout: Duration,
+    what: &str,
+) -> Result<T, String> {
+    match tokio::time::timeout(timeout, rx).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(_)) => Err(format!("{} was cancelled before it was answered", what)),
+        Err(_) => {
+            lock(waiters).remove(id);
+            Err(format!("{} timed out after {} seconds without a response", what, timeout.as_secs()))
+        }
+    }
+}
+
+/// Wraps the parent's provider for a sub-agent so its requests and events
+/// name the parent session and the agent that raised them.
+pub struct SubagentInteraction {
+    inner: Arc<dyn InteractionProvider>,
+    parent_session_id: String,
+    agent: String,
+}
+
+impl SubagentInteraction {
+    pub fn new(inner: Arc<dyn InteractionProvider>, parent_session_id: String, agent: String) -> Self {
+        Self {
+            inner,
+            parent_session_id,
+            agent,
+        }
+    }
+}
+
+#[async_trait]
+impl InteractionProvider for SubagentInteraction {
+    async fn confirm(&self, mut request: ConfirmationRequest) -> Result<ConfirmationResponse, String> {
+        request.parent_session_id.get_or_insert_with(|| self.parent_session_id.clone());
+        request.agent.get_or_insert_with(|| self.agent.clone());
+        self.inner.confirm(request).await
+    }
+
+    async fn ask(&self, session_id: &str, questions: Vec<Question>) -> Result<Value, String> {
+        self.inner.ask(session_id, questions).await
+    }
+
+    fn notify(&self, event: &str, mut payload: Value) {
+        if let Some(obj) = payload.as_object_mut() {
+            obj.entry("parent_session_id").or_insert_with(|| json!(self.parent_session_id));
+            obj.entry("agent").or_insert_with(|| json!(self.agent));
+        }
+        self.inner.notify(event, payload);
+    }
+
+    fn cancel_session(&self, session_id: &str) {
+        self.inner.cancel_session(session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allow() -> ConfirmationResponse {
+        ConfirmationResponse {
+            allowed: true,
+            always: false,
+            pattern: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_confirmation() {
+        let pending = PendingRequests::new();
+        let (id, rx) = pending.register_confirmation("s1", None);
+        pending.resolve_confirmation(&id, allow()).unwrap();
+        let response = pending.wait_confirmation(&id, rx, DEFAULT_TIMEOUT).await.unwrap();
+        assert!(response.allowed);
+        assert!(pending.is_empty());
+        assert!(pending.resolve_confirmation(&id, allow()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_cleans_up() {
+        let pending = PendingRequests::new();
+        let (id, rx) = pending.register_question("s1");
+        let result = pending.wait_question(&id, rx, Duration::from_millis(10)).await;
+        assert!(result.unwrap_err().contains("timed out"));
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_session() {
+        let pending = PendingRequests::new();
+        let (id, rx) = pending.register_confirmation("s1", None);
+        let (_other, _other_rx) = pending.register_confirmation("s2", None);
+        pending.cancel_session("s1");
+        assert_eq!(pending.len(), 1);
+        let result = pending.wait_confirmation(&id, rx, DEFAULT_TIMEOUT).await;
+        assert!(result.unwrap_err().contains("cancelled"));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::domain::models::{ConfirmationRequest, ConfirmationResponse, Question};
+use crate::domain::ports::InteractionProvider;
+use async_trait::async_trait;
+use serde_json::{json, Map, Value};
+use std::io::{BufRead, Write};
+
+/// Prompts on stderr and reads answers from stdin. Progress goes to stderr
+/// too, so stdout only carries the agent's answer.
+#[derive(Default)]
+pub struct TerminalInteraction {
+    /// Only one prompt may read stdin at a time
+    prompt_lock: tokio::sync::Mutex<()>,
+}
+
+impl TerminalInteraction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+async fn read_answer(prompt: String) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || {
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "{}", prompt);
+        let _ = stderr.flush();
+
+        let mut line = String::new();
+        let read = std::io::stdin()
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read answer: {}", e))?;
+        if read == 0 {
+            return Err("Input closed before an answer was given".to_string());
+        }
+        Ok(line.trim().to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn describe(request: &ConfirmationRequest) -> String {
+    let mut lines = vec![match request.kind.as_str() {
+        "diff" => format!("Review change: {}", request.summary()),
+        "shell" => format!("Run command: {}", request.command.as_deref().unwrap_or("")),
+        "doom_loop" => format!("Possible loop: {}", request.summary()),
+        "mode" => format!("Outside research mode: {}", request.summary()),
+        _ => format!("Permission request: {}", request.summary()),
+    }];
+
+    if let (Some(old), Some(new)) = (request.old_content.as_deref(), request.new_content.as_deref()) {
+        lines.push(format!("  {} → {} lines", old.lines().count(), new.lines().count()));
+    } else if let Some(new) = request.new_content.as_deref() {
+        lines.push(format!("  new file, {} lines", new.lines().count()));
+    }
+    if let Some(reason) = &request.reason {
+        lines.push(format!("  {}", reason));
+    }
+    if let Some(agent) = &request.agent {
+        lines.push(format!("  (from sub-agent {})", agent));
+    }
+    lines.join("\n")
+}
+
+/// Option values for an answer like `1,3`; free text is passed through
+fn parse_choice(answer: &str, question: &Question) -> Value {
+    let picks: Option<Vec<String>> = answer
+        .split(',')
+        .map(|part| {
+            let index = part.trim().parse::<usize>().ok()?.checked_sub(1)?;
+            question.options.get(index).map(|o| o.value.clone())
+        })
+        .collect();
+
+    match picks {
+        Some(values) if !values.is_empty() => {
+            if question.multiple {
+                json!(values)
+            } else {
+                json!(values[0])
+            }
+        }
+        _ => json!(answer),
+    }
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    let first_line = text.lines().next().unwrap_or("");
+    if first_line.chars().count() > max {
+        format!("{}…", first_line.chars().take(max).collect::<String>())
+    } else {
+        first_line.to_string()
+    }
+}
+
+#[async_trait]
+impl InteractionProvider for TerminalInteraction {
+    async fn confirm(&self, request: ConfirmationRequest) -> Result<ConfirmationResponse, String> {
+        let _guard = self.prompt_lock.lock().await;
+
+        let prompt = format!(
+            "\n{}\n  Allow? [y]es / [n]o / [a]lways ({}): ",
+            describe(&request),
+            request.suggested_pattern
+        );
+        let answer = read_answer(prompt).await?.to_lowercase();
+
+        Ok(match answer.as_str() {
+            "y" | "yes" => ConfirmationResponse {
+                allowed: true,
+                always: false,
+                pattern: None,
+            },
+            "a" | "always" => ConfirmationResponse {
+                allowed: true,
+                always: true,
+                pattern: Some(request.suggested_pattern.clone()),
+            },
+            _ => ConfirmationResponse {
+                allowed: false,
+                always: false,
+                pattern: None,
+            },
+        })
+    }
+
+    async fn ask(&self, _session_id: &str, questions: Vec<Question>) -> Result<Value, String> {
+        let _guard = self.prompt_lock.lock().await;
+
+        let mut answers = Map::new();
+        for question in &questions {
+            let mut prompt = format!("\n{}: {}\n", question.header, question.question);
+            for (i, option) in question.options.iter().enumerate() {
+                prompt.push_str(&format!("  {}. {} — {}\n", i + 1, option.label, option.description));
+            }
+            prompt.push_str(if question.multiple {
+                "  Choose numbers separated by commas, or type an answer: "
+            } else {
+                "  Choose a number, or type an answer: "
+            });
+
+            let answer = read_answer(prompt).await?;
+            answers.insert(question.id.clone(), parse_choice(&answer, question));
+        }
+
+        Ok(Value::Object(answers))
+    }
+
+    fn notify(&self, event: &str, payload: Value) {
+        let text = |key: &str| payload.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string();
+
+        match event {
+            "agent-tool-call" => {
+                eprintln!("  → {} {}", text("tool_name"), truncate(&text("arguments"), 100));
+            }
+            "agent-tool-result" => {
+                let content = text("content");
+                if content.starts_with("Error:") {
+                    eprintln!("  ✗ {}", truncate(&content, 120));
+                }
+            }
+            "subagent-started" => eprintln!("  ⇢ sub-agent {}: {}", text("agent"), text("description")),
+            "subagent-finished" => eprintln!("  ⇠ sub-agent {} finished", text("agent")),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::QuestionOption;
+
+    fn question(multiple: bool) -> Question {
+        let option = |v: &str| QuestionOption {
+            label: v.to_uppercase(),
+            description: String::new(),
+            value: v.to_string(),
+        };
+        Question {
+            id: "q".to_string(),
+            header: "Pick".to_string(),
+            question: "Which?".to_string(),
+            options: vec![option("a"), option("b"), option("c")],
+            multiple,
+        }
+    }
+
+    #[test]
+    fn test_parse_choice() {
+        assert_eq!(parse_choice("2", &question(false)), json!("b"));
+        assert_eq!(parse_choice("1, 3", &question(true)), json!(["a", "c"]));
+        assert_eq!(parse_choice("7", &question(false)), json!("7"));
+        assert_eq!(parse_choice("use v2", &question(false)), json!("use v2"));
+    }
+}
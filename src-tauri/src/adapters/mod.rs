@@ -2,4 +2,36 @@ pub mod openai;
 pub mod gemini;
 pub mod anthropic;
 pub mod ollama;
+pub mod interaction;
 pub mod tools;
+
+use crate::domain::ports::ModelAdapter;
+use std::sync::Arc;
+
+/// Build a model adapter for an explicitly chosen provider
+pub fn build_model_adapter(provider: &str, api_key: &str, model_id: &str) -> Result<Arc<dyn ModelAdapter>, String> {
+    let model: Arc<dyn ModelAdapter> = match provider {
+        "openai" => Arc::new(openai::OpenAIAdapter::new(api_key.to_string())),
+        "gemini" => Arc::new(gemini::GeminiAdapter::new(api_key.to_string(), model_id.to_string())),
+        "anthropic" => Arc::new(anthropic::AnthropicAdapter::new(api_key.to_string(), model_id.to_string())),
+        "ollama" => Arc::new(ollama::OllamaAdapter::new(None)),
+        _ => return Err(format!("Unsupported provider: {}", provider)),
+    };
+    Ok(model)
+}
+
+/// Guess the provider from a model id
+pub fn provider_for_model(model_id: &str) -> &'static str {
+    if model_id.starts_with("llama")
+        || model_id.starts_with("mistral")
+        || model_id.starts_with("codellama")
+        || model_id.starts_with("deepseek") {
+        "ollama"
+    } else if model_id.starts_with("gemini") {
+        "gemini"
+    } else if model_id.starts_with("claude") {
+        "anthropic"
+    } else {
+        "openai"
+    }
+}
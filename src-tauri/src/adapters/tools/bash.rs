@@ -1,50 +1,93 @@
-use crate::domain::ports::Tool;
-use crate::domain::models::ToolResult;
+use crate::adapters::tools::jobs::JobManager;
+use crate::adapters::tools::sandbox::Sandbox;
+use crate::adapters::tools::shell::{OutputStream, ShellSession};
+use crate::domain::ports::{InteractionProvider, Tool};
+use crate::domain::models::{ConfirmationRequest, ToolResult};
 use async_trait::async_trait;
 use serde_json::{json, Value};
-use std::path::PathBuf;
-use tokio::process::Command;
-use tauri::{AppHandle, Emitter};
-use tokio::sync::oneshot;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use uuid::Uuid;
-use serde::Serialize;
-
-#[derive(Serialize, Clone)]
-struct ShellConfirmationRequest {
-    id: String,
-    session_id: String,
-    #[serde(rename = "type")]
-    type_: String,
-    command: String,
-    suggested_pattern: String,
-}
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Seconds a command may run when the call sets no timeout
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+const MAX_TIMEOUT_SECS: u64 = 600;
 
 pub struct BashTool {
     pub workspace_root: PathBuf,
     pub session_id: String,
-    pub app: AppHandle,
-    pub pending_confirmations: Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+    pub interaction: Arc<dyn InteractionProvider>,
     pub permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+    /// Started on first use and kept for the whole session
+    shell: tokio::sync::Mutex<Option<ShellSession>>,
+    jobs: Arc<JobManager>,
 }
 
 impl BashTool {
     pub fn new(
         workspace_root: PathBuf,
         session_id: String,
-        app: AppHandle,
-        pending_confirmations: Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+        interaction: Arc<dyn InteractionProvider>,
         permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+        jobs: Arc<JobManager>,
     ) -> Self {
         Self { 
             workspace_root,
             session_id,
-            app,
-            pending_confirmations,
+            interaction,
             permission_manager,
+            shell: tokio::sync::Mutex::new(None),
+            jobs,
+        }
+    }
+}
+
+/// Check `command` against the `bash` permission rules, asking the user
+/// when they say so, and return the sandbox it must run in, if any.
+/// Sandboxed commands may skip the prompt when the sandbox config says so.
+pub async fn authorize_command(
+    command: &str,
+    session_id: &str,
+    workspace_root: &Path,
+    permission_manager: &tokio::sync::Mutex<crate::config::PermissionConfig>,
+    interaction: &dyn InteractionProvider,
+) -> Result<Option<Sandbox>, String> {
+    let (allowed, sandbox) = {
+        let config = permission_manager.lock().await;
+        let sandbox = Sandbox::from_config(config.sandbox.as_ref(), workspace_root)?;
+        let auto_allow = sandbox.is_some() && config.sandbox.as_ref().is_some_and(|s| s.auto_allow);
+        let allowed = match config.bash.evaluate(command) {
+            crate::config::Action::Allow => true,
+            crate::config::Action::Ask => auto_allow,
+            crate::config::Action::Deny => false,
+        };
+        (allowed, sandbox)
+    };
+    if allowed {
+        return Ok(sandbox);
+    }
+
+    let suggested_pattern = if command.contains(' ') {
+        format!("{}*", command.split(' ').next().unwrap_or(command))
+    } else {
+        command.to_string()
+    };
+    let request = ConfirmationRequest::shell(session_id, command, suggested_pattern);
+    let response = interaction.confirm(request).await?;
+    if !response.allowed {
+        return Err("User denied shell command execution.".to_string());
+    }
+
+    if response.always {
+        if let Some(pattern) = response.pattern {
+            let mut config = permission_manager.lock().await;
+            config.bash.rules.push(crate::config::manager::PermissionRule {
+                pattern,
+                action: crate::config::Action::Allow,
+            });
         }
     }
+    Ok(sandbox)
 }
 
 #[async_trait]
@@ -56,94 +99,108 @@ impl Tool for BashTool {
     fn schema(&self) -> Value {
         json!({
             "name": "bash",
-            "description": "Execute a shell command",
+            "description": "Execute a command in a persistent shell. The working directory, exported variables and activated environments carry over between calls; other tools still resolve relative paths from the workspace root. Commands get no stdin. A command that outlives its timeout is killed and the shell restarts. Start long-running processes such as dev servers with background: true and inspect them with job_status, job_output and job_kill.",
             "parameters": {
                 "type": "object",
                 "properties": {
                     "command": {
                         "type": "string",
                         "description": "The command to execute"
+                    },
+                    "timeout": {
+                        "type": "integer",
+                        "description": "Seconds before the command is killed (default: 120, max: 600)"
+                    },
+                    "background": {
+                        "type": "boolean",
+                        "description": "Start the command as a background job in the shell's current directory and return its job id immediately. Jobs do not see variables exported in the shell."
+                    },
+                    "reset": {
+                        "type": "boolean",
+                        "description": "Restart the shell in the workspace root with a fresh environment before running the command (the command may then be omitted)"
                     }
-                },
-                "required": ["command"]
+                }
             }
         })
     }
 
     async fn execute(&self, input: Value) -> ToolResult {
-        let command_str = input.get("command")
-            .and_then(|v| v.as_str())
-            .ok_or("Missing 'command' parameter")?;
-
-        // Check if allowed by permission manager
-        let allowed = {
-            let config = self.permission_manager.lock().await;
-            config.bash.evaluate(command_str) == crate::config::Action::Allow
-        };
-
-        if !allowed {
-            // --- Confirmation Logic ---
-            let request_id = Uuid::new_v4().to_string();
-            println!("BashTool: Requesting confirmation for id={} session={}", request_id, self.session_id);
-            let (tx, rx) = oneshot::channel();
+        let reset = input.get("reset").and_then(|v| v.as_bool()).unwrap_or(false);
+        if reset {
+            *self.shell.lock().await = None;
+        }
 
-            {
-                let mut map = self.pending_confirmations.lock().unwrap();
-                map.insert(request_id.clone(), tx);
+        let command_str = match input.get("command").and_then(|v| v.as_str()).filter(|c| !c.trim().is_empty()) {
+            Some(command) => command,
+            None if reset => {
+                return Ok(json!({
+                    "reset": true,
+                    "cwd": self.workspace_root.to_string_lossy(),
+                }));
             }
+            None => return Err("Missing 'command' parameter".to_string()),
+        };
 
-            let suggested_pattern = if command_str.contains(' ') {
-                format!("{}*", command_str.split(' ').next().unwrap_or(command_str))
-            } else {
-                command_str.to_string()
-            };
-
-            let event = ShellConfirmationRequest {
-                id: request_id.clone(),
-                session_id: self.session_id.clone(),
-                type_: "shell".to_string(),
-                command: command_str.to_string(),
-                suggested_pattern,
-            };
-
-            self.app.emit("request-confirmation", &event)
-                .map_err(|e| format!("Failed to emit confirmation event: {}", e))?;
-
-            // Wait for user response
-            let response = rx.await.map_err(|_| "Confirmation channel closed without response".to_string())?;
+        let sandbox = authorize_command(
+            command_str,
+            &self.session_id,
+            &self.workspace_root,
+            &self.permission_manager,
+            self.interaction.as_ref(),
+        )
+        .await?;
+
+        let mut shell = self.shell.lock().await;
+        if input.get("background").and_then(|v| v.as_bool()).unwrap_or(false) {
+            let cwd = shell.as_ref().map(|s| s.cwd().to_path_buf()).unwrap_or_else(|| self.workspace_root.clone());
+            return self.jobs.start(command_str, &cwd, sandbox.as_ref());
+        }
 
-            if !response.allowed {
-                return Err("User denied shell command execution.".to_string());
-            }
+        // A changed sandbox config takes effect in a fresh shell
+        if !shell.as_mut().is_some_and(|s| s.is_alive() && s.sandbox() == sandbox.as_ref()) {
+            *shell = Some(ShellSession::spawn(&self.workspace_root, sandbox.as_ref())?);
+        }
+        let session = shell.as_mut().ok_or("Shell is not running")?;
+
+        let timeout = input
+            .get("timeout")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_TIMEOUT_SECS)
+            .clamp(1, MAX_TIMEOUT_SECS);
+        let on_output = |stream: OutputStream, chunk: &str| {
+            self.interaction.notify(
+                "bash-output",
+                json!({
+                    "session_id": self.session_id,
+                    "stream": stream.as_str(),
+                    "chunk": chunk,
+                }),
+            );
+        };
+        let output = session.run(command_str, Duration::from_secs(timeout), &on_output).await?;
 
-            if response.always {
-                if let Some(pattern) = response.pattern {
-                    let mut config = self.permission_manager.lock().await;
-                    config.bash.rules.push(crate::config::manager::PermissionRule {
-                        pattern,
-                        action: crate::config::Action::Allow,
-                    });
-                }
-            }
-            // --------------------------
+        // The command ended the shell; the next call starts a fresh one
+        if output.exit_code.is_none() {
+            *shell = None;
         }
 
-        // Use sh -c to execute the command string
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(command_str)
-            .current_dir(&self.workspace_root)
-            .output()
-            .await
-            .map_err(|e| format!("Failed to execute command: {}", e))?;
-
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-
-        Ok(json!({
-            "stdout": stdout,
-            "stderr": stderr,
-            "exit_code": output.status.code(),
-        }))
+        let mut result = json!({
+            "stdout": output.stdout,
+            "stderr": output.stderr,
+            "exit_code": output.exit_code,
+            "cwd": output.cwd.to_string_lossy(),
+        });
+        if let Some(sandbox) = &sandbox {
+            result["sandboxed"] = json!(true);
+            result["network"] = json!(sandbox.network());
+        }
+        if output.timed_out {
+            result["timed_out"] = json!(true);
+            result["note"] = json!(format!(
+                "Command killed after {} seconds. The shell was restarted, so its working directory and environment are reset. Run long-lived processes with background: true.",
+                timeout
+            ));
+        }
+        Ok(result)
     }
 }
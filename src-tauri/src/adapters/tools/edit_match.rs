@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Minimum average line similarity for a block match
+const SIMILARITY_THRESHOLD: f64 = 0.8;
+/// Blocks scoring this close to the best one make the match ambiguous
+const AMBIGUITY_MARGIN: f64 = 0.05;
+/// Skip similarity matching when it would compare more line pairs
+const MAX_SIMILARITY_PAIRS: usize = 200_000;
+/// Columns a tab counts for when converting indentation
+const TAB_WIDTH: usize = 4;
+
+/// Whether two lines count as equal for a line-based strategy
+type LineMatcher = fn(&str, &str) -> bool;
+
+/// How the `old_text` of an edit was located, from strictest to loosest
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchStrategy {
+    Exact,
+    /// Lines equal apart from trailing whitespace
+    LineTrimmed,
+    /// Lines equal apart from leading and trailing whitespace
+    IndentNormalized,
+    /// Lines similar enough on average
+    Similarity,
+}
+
+impl MatchStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchStrategy::Exact => "exact",
+            MatchStrategy::LineTrimmed => "line_trimmed",
+            MatchStrategy::IndentNormalized => "indentation",
+            MatchStrategy::Similarity => "similarity",
+        }
+    }
+}
+
+/// Non-overlapping matches of a block, found by the first strategy that
+/// matched at all
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockMatches {
+    pub strategy: MatchStrategy,
+    pub ranges: Vec<Range<usize>>,
+}
+
+/// Find `old` in `content`, trying each strategy in turn. All strategies
+/// but the exact one match whole lines.
+pub fn find_matches(content: &str, old: &str) -> Option<BlockMatches> {
+    let exact: Vec<Range<usize>> = content
+        .match_indices(old)
+        .map(|(start, text)| start..start + text.len())
+        .collect();
+    if !exact.is_empty() {
+        return Some(BlockMatches { strategy: MatchStrategy::Exact, ranges: exact });
+    }
+
+    let old_lines = block_lines(old);
+    if old_lines.iter().all(|line| line.trim().is_empty()) {
+        return None;
+    }
+    let spans = line_spans(content);
+    let lines: Vec<&str> = spans.iter().map(|span| &content[span.clone()]).collect();
+    let to_range = |start: usize| spans[start].start..spans[start + old_lines.len() - 1].end;
+
+    let line_strategies: [(MatchStrategy, LineMatcher); 2] = [
+        (MatchStrategy::LineTrimmed, |a, b| a.trim_end() == b.trim_end()),
+        (MatchStrategy::IndentNormalized, |a, b| a.trim() == b.trim()),
+    ];
+    for (strategy, equal) in line_strategies {
+        let starts = matching_windows(&lines, &old_lines, equal);
+        if !starts.is_empty() {
+            return Some(BlockMatches { strategy, ranges: starts.into_iter().map(to_range).collect() });
+        }
+    }
+
+    let starts = similar_windows(&lines, &old_lines);
+    if starts.is_empty() {
+        return None;
+    }
+    Some(BlockMatches {
+        strategy: MatchStrategy::Similarity,
+        ranges: starts.into_iter().map(to_range).collect(),
+    })
+}
+
+/// What replaces `range` of `content`, found by `strategy` for `old`, when
+/// it becomes `new`, and the range it replaces. Loose matches get `new`
+/// re-indented to the matched lines and the file's indentation style.
+pub fn replacement(
+    content: &str,
+    range: Range<usize>,
+    strategy: MatchStrategy,
+    old: &str,
+    new: &str,
+) -> (Range<usize>, String) {
+    let mut range = range;
+    let replacement = match strategy {
+        MatchStrategy::Exact => new.to_string(),
+        _ => {
+            // Line matches stop before the newline that ends the block
+            let new = if old.ends_with('\n') { new.strip_suffix('\n').unwrap_or(new) } else { new };
+            let replacement = match strategy {
+                MatchStrategy::LineTrimmed => new.to_string(),
+                _ => reindent(new, old, &content[range.clone()], indent_style(content)),
+            };
+            // Deleting whole lines also removes their line break
+            if replacement.is_empty() {
+                if content[range.end..].starts_with('\n') {
+                    range.end += 1;
+                } else if content[..range.start].ends_with('\n') {
+                    range.start -= 1;
+                }
+            }
+            replacement
+        }
+    };
+    (range, replacement)
+}
+
+/// Lines of an edit block; a final newline does not start another line
+fn block_lines(text: &str) -> Vec<&str> {
+    text.strip_suffix('\n').unwrap_or(text).split('\n').collect()
+}
+
+/// Byte ranges of the lines of `content`, without their newlines
+fn line_spans(content: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for (index, _) in content.match_indices('\n') {
+        spans.push(start..index);
+        start = index + 1;
+    }
+    spans.push(start..content.len());
+    spans
+}
+
+/// Start lines of non-overlapping windows whose lines all equal `old`
+fn matching_windows(lines: &[&str], old: &[&str], equal: LineMatcher) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut start = 0;
+    while start + old.len() <= lines.len() {
+        if old.iter().enumerate().all(|(k, line)| equal(lines[start + k], line)) {
+            starts.push(start);
+            start += old.len();
+        } else {
+            start += 1;
+        }
+    }
+    starts
+}
+
+/// The best window above [`SIMILARITY_THRESHOLD`], plus any others scoring
+/// within [`AMBIGUITY_MARGIN`] of it so the caller can report ambiguity
+fn similar_windows(lines: &[&str], old: &[&str]) -> Vec<usize> {
+    if old.len() > lines.len() || lines.len() * old.len() > MAX_SIMILARITY_PAIRS {
+        return Vec::new();
+    }
+
+    let mut scored: Vec<(usize, f64)> = (0..=lines.len() - old.len())
+        .map(|start| {
+            let total: f64 = old
+                .iter()
+                .enumerate()
+                .map(|(k, line)| line_similarity(lines[start + k].trim(), line.trim()))
+                .sum();
+            (start, total / old.len() as f64)
+        })
+        .filter(|(_, score)| *score >= SIMILARITY_THRESHOLD)
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let best = match scored.first() {
+        Some((_, score)) => *score,
+        None => return Vec::new(),
+    };
+    let mut starts: Vec<usize> = Vec::new();
+    for (start, score) in scored {
+        if score < best - AMBIGUITY_MARGIN {
+            break;
+        }
+        if starts.iter().all(|other| start.abs_diff(*other) >= old.len()) {
+            starts.push(start);
+        }
+    }
+    starts.sort_unstable();
+    starts
+}
+
+/// 1.0 for equal strings, falling with the edit distance
+fn line_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IndentStyle {
+    Tabs,
+    Spaces(usize),
+}
+
+impl IndentStyle {
+    fn columns(&self) -> usize {
+        match self {
+            IndentStyle::Tabs => TAB_WIDTH,
+            IndentStyle::Spaces(width) => *width,
+        }
+    }
+
+    fn render(&self, levels: usize) -> String {
+        match self {
+            IndentStyle::Tabs => "\t".repeat(levels),
+            IndentStyle::Spaces(width) => " ".repeat(width * levels),
+        }
+    }
+}
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+fn columns(indent: &str) -> usize {
+    indent.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+}
+
+/// Tabs if any line is tab-indented, otherwise the most common increase in
+/// indentation between consecutive lines
+fn indent_style(text: &str) -> Option<IndentStyle> {
+    let indents: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(leading_whitespace)
+        .collect();
+    if indents.iter().any(|indent| indent.starts_with('\t')) {
+        return Some(IndentStyle::Tabs);
+    }
+
+    let mut steps: HashMap<usize, usize> = HashMap::new();
+    let mut previous = 0;
+    for indent in indents {
+        let width = indent.len();
+        if width > previous {
+            *steps.entry(width - previous).or_default() += 1;
+        }
+        previous = width;
+    }
+    steps
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map(|(width, _)| IndentStyle::Spaces(width))
+}
+
+/// Move `new` from the indentation of `old` to that of `matched`, keeping
+/// each line's depth relative to the block and using the file's style
+fn reindent(new: &str, old: &str, matched: &str, file_style: Option<IndentStyle>) -> String {
+    let base = |text: &str| {
+        text.lines()
+            .find(|line| !line.trim().is_empty())
+            .map(leading_whitespace)
+            .unwrap_or("")
+            .to_string()
+    };
+    let old_base = base(old);
+    let file_base = base(matched);
+    let edit_style = indent_style(&format!("{}\n{}", old, new));
+    let file_style = file_style.or(edit_style).unwrap_or(IndentStyle::Spaces(TAB_WIDTH));
+    if old_base == file_base && edit_style.is_none_or(|style| style == file_style) {
+        return new.to_string();
+    }
+    let unit = edit_style.unwrap_or(file_style).columns();
+
+    new.split('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                return String::new();
+            }
+            let depth = columns(leading_whitespace(line));
+            let (prefix, relative) = match depth.checked_sub(columns(&old_base)) {
+                Some(relative) => (file_base.clone(), relative),
+                None => (String::new(), depth),
+            };
+            format!(
+                "{}{}{}{}",
+                prefix,
+                file_style.render(relative / unit),
+                " ".repeat(relative % unit),
+                line.trim_start()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(content: &str, old: &str, new: &str) -> (MatchStrategy, String) {
+        let found = find_matches(content, old).expect("no match");
+        assert_eq!(found.ranges.len(), 1);
+        let range = found.ranges[0].clone();
+        let (range, replacement) = replacement(content, range, found.strategy, old, new);
+        let mut updated = content.to_string();
+        updated.replace_range(range, &replacement);
+        (found.strategy, updated)
+    }
+
+    #[test]
+    fn test_strategy_cascade() {
+        let content = "fn main() {\n    let a = 1;   \n    call(a);\n}\n";
+
+        assert_eq!(edit(content, "call(a);", "call(b);").0, MatchStrategy::Exact);
+
+        let (strategy, updated) = edit(content, "    let a = 1;\n    call(a);\n", "    let a = 2;\n    call(a);\n");
+        assert_eq!(strategy, MatchStrategy::LineTrimmed);
+        assert_eq!(updated, "fn main() {\n    let a = 2;\n    call(a);\n}\n");
+
+        let (strategy, updated) = edit(content, "let a = 1;\ncall(a);", "let a = 1;\nif a > 0 {\n    call(a);\n}");
+        assert_eq!(strategy, MatchStrategy::IndentNormalized);
+        assert_eq!(updated, "fn main() {\n    let a = 1;\n    if a > 0 {\n        call(a);\n    }\n}\n");
+
+        let (strategy, updated) = edit(content, "    let a = 1;\n    cal(a)\n", "    run();\n");
+        assert_eq!(strategy, MatchStrategy::Similarity);
+        assert_eq!(updated, "fn main() {\n    run();\n}\n");
+
+        assert!(find_matches(content, "    let b = 7;\n    other();\n").is_none());
+    }
+
+    #[test]
+    fn test_reindent_keeps_file_tabs() {
+        let content = "impl A {\n\tfn a() {\n\t\tone();\n\t}\n}\n";
+        let (strategy, updated) = edit(
+            content,
+            "    fn a() {\n        one();\n    }\n",
+            "    fn a() {\n        one();\n        two();\n    }\n",
+        );
+        assert_eq!(strategy, MatchStrategy::IndentNormalized);
+        assert_eq!(updated, "impl A {\n\tfn a() {\n\t\tone();\n\t\ttwo();\n\t}\n}\n");
+    }
+
+    #[test]
+    fn test_ambiguous_and_deleted_blocks() {
+        let content = "a\n  x = 1\nb\n  x = 1\n";
+        assert_eq!(find_matches(content, "x = 1\n").unwrap().ranges.len(), 2);
+
+        let (_, updated) = edit("keep\n  drop me\nkeep too\n", "drop me  \n", "");
+        assert_eq!(updated, "keep\nkeep too\n");
+    }
+}
@@ -0,0 +1,263 @@
+//! Turning file bytes into something a model can read: decoded text shown
+//! in numbered line windows, images as attachments and notebooks as cells.
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use serde_json::Value;
+use std::path::Path;
+
+/// Lines returned when the caller gives no limit
+pub const DEFAULT_LIMIT: usize = 2000;
+
+/// Longer lines are cut, so one minified line cannot fill the context
+const MAX_LINE_CHARS: usize = 2000;
+
+/// A window stops early once its content reaches this size
+const MAX_OUTPUT_BYTES: usize = 100 * 1024;
+
+/// Bytes checked for NULs and UTF-16 patterns
+const SNIFF_BYTES: usize = 8192;
+
+/// Image type of `path` by extension, for the formats vision models accept
+pub fn image_mime(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+pub fn is_notebook(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ipynb"))
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Decoded {
+    pub text: String,
+    /// Name of the encoding used, e.g. `UTF-8` or `UTF-16LE`
+    pub encoding: &'static str,
+}
+
+/// Decode file content as text. `encoding` is a label such as `utf-16le` or
+/// `latin1`; without one, a byte order mark, valid UTF-8 or UTF-16 patterns
+/// decide, and anything else falls back to Windows-1252 (a superset of
+/// Latin-1). `Ok(None)` means the content looks binary.
+pub fn decode(bytes: &[u8], encoding: Option<&str>) -> Result<Option<Decoded>, String> {
+    if let Some(label) = encoding {
+        let encoding = Encoding::for_label(label.trim().as_bytes())
+            .ok_or_else(|| format!("Unknown encoding '{}'", label))?;
+        let (text, _) = encoding.decode_with_bom_removal(bytes);
+        return Ok(Some(Decoded { text: text.into_owned(), encoding: encoding.name() }));
+    }
+
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return Ok(Some(Decoded { text: text.into_owned(), encoding: encoding.name() }));
+    }
+
+    let sample = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    if let Some(encoding) = sniff_utf16(sample) {
+        let (text, _) = encoding.decode_without_bom_handling(bytes);
+        return Ok(Some(Decoded { text: text.into_owned(), encoding: encoding.name() }));
+    }
+    if sample.contains(&0) {
+        return Ok(None);
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok(Some(Decoded { text: text.to_string(), encoding: UTF_8.name() }));
+    }
+    let (text, _) = WINDOWS_1252.decode_without_bom_handling(bytes);
+    Ok(Some(Decoded { text: text.into_owned(), encoding: WINDOWS_1252.name() }))
+}
+
+/// UTF-16 without a byte order mark: mostly-ASCII text has a NUL in every
+/// other byte, and binary formats do not keep that up
+fn sniff_utf16(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let zeros_at = |parity: usize| sample.iter().skip(parity).step_by(2).take(pairs).filter(|&&b| b == 0).count();
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    if odd * 10 >= pairs * 9 && even == 0 {
+        Some(UTF_16LE)
+    } else if even * 10 >= pairs * 9 && odd == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Window {
+    /// The lines, each prefixed with its number and a tab
+    pub content: String,
+    /// 1-based and inclusive; `end_line` is `start_line - 1` when empty
+    pub start_line: usize,
+    pub end_line: usize,
+    pub total_lines: usize,
+}
+
+impl Window {
+    /// Where to continue reading, if the window stops before the end
+    pub fn next_offset(&self) -> Option<usize> {
+        (self.end_line < self.total_lines).then_some(self.end_line + 1)
+    }
+}
+
+/// Up to `limit` lines of `text` starting at the 1-based line `offset`,
+/// numbered like `cat -n`. Ends early when the output grows too large.
+pub fn window(text: &str, offset: usize, limit: usize) -> Result<Window, String> {
+    let total_lines = text.lines().count();
+    let offset = offset.max(1);
+    if offset > total_lines.max(1) {
+        return Err(format!("Offset {} is past the end of the file ({} lines)", offset, total_lines));
+    }
+
+    let mut content = String::new();
+    let mut end_line = offset - 1;
+    for (index, line) in text.lines().enumerate().skip(offset - 1).take(limit) {
+        if !content.is_empty() && content.len() + line.len() > MAX_OUTPUT_BYTES {
+            break;
+        }
+        match line.char_indices().nth(MAX_LINE_CHARS) {
+            Some((cut, _)) => content.push_str(&format!("{:>6}\t{}... [line truncated]\n", index + 1, &line[..cut])),
+            None => content.push_str(&format!("{:>6}\t{}\n", index + 1, line)),
+        }
+        end_line = index + 1;
+    }
+
+    Ok(Window { content, start_line: offset, end_line, total_lines })
+}
+
+/// Render a Jupyter notebook as its cells' sources followed by their
+/// text outputs. Rich outputs are named but not included.
+pub fn render_notebook(json: &str) -> Result<String, String> {
+    let notebook: Value = serde_json::from_str(json).map_err(|e| format!("Invalid notebook: {}", e))?;
+    let cells = notebook
+        .get("cells")
+        .and_then(|c| c.as_array())
+        .ok_or("Invalid notebook: no cells")?;
+
+    let mut out = String::new();
+    for (index, cell) in cells.iter().enumerate() {
+        let kind = cell.get("cell_type").and_then(|t| t.as_str()).unwrap_or("code");
+        match cell.get("execution_count").and_then(|c| c.as_u64()) {
+            Some(count) => out.push_str(&format!("# Cell {} ({}) [{}]\n", index + 1, kind, count)),
+            None => out.push_str(&format!("# Cell {} ({})\n", index + 1, kind)),
+        }
+        push_block(&mut out, &joined(cell.get("source")));
+
+        let outputs = cell.get("outputs").and_then(|o| o.as_array()).map(Vec::as_slice).unwrap_or(&[]);
+        for output in outputs {
+            let rendered = render_output(output);
+            if !rendered.is_empty() {
+                out.push_str("# Output\n");
+                push_block(&mut out, &rendered);
+            }
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn render_output(output: &Value) -> String {
+    match output.get("output_type").and_then(|t| t.as_str()) {
+        Some("stream") => joined(output.get("text")),
+        Some("error") => {
+            let field = |name: &str| output.get(name).and_then(|v| v.as_str()).unwrap_or("");
+            format!("{}: {}", field("ename"), field("evalue"))
+        }
+        Some("execute_result" | "display_data") => {
+            let Some(data) = output.get("data").and_then(|d| d.as_object()) else {
+                return String::new();
+            };
+            if let Some(text) = data.get("text/plain") {
+                return joined(Some(text));
+            }
+            data.keys().map(|mime| format!("[{} output not shown]", mime)).collect::<Vec<_>>().join("\n")
+        }
+        _ => String::new(),
+    }
+}
+
+/// Notebook text fields are either a string or a list of lines
+fn joined(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts.iter().filter_map(|p| p.as_str()).collect(),
+        _ => String::new(),
+    }
+}
+
+fn push_block(out: &mut String, text: &str) {
+    out.push_str(text);
+    if !text.is_empty() && !text.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_detects_encodings_and_binary() {
+        let utf16: Vec<u8> = "héllo\n".encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        let decoded = decode(&utf16, None).unwrap().unwrap();
+        assert_eq!((decoded.text.as_str(), decoded.encoding), ("héllo\n", "UTF-16LE"));
+
+        let mut with_bom = vec![0xFE, 0xFF];
+        with_bom.extend("hi".encode_utf16().flat_map(|u| u.to_be_bytes()));
+        assert_eq!(decode(&with_bom, None).unwrap().unwrap().text, "hi");
+
+        let latin1 = b"caf\xe9\n";
+        let decoded = decode(latin1, None).unwrap().unwrap();
+        assert_eq!((decoded.text.as_str(), decoded.encoding), ("café\n", "windows-1252"));
+        assert_eq!(decode("café".as_bytes(), None).unwrap().unwrap().encoding, "UTF-8");
+
+        assert_eq!(decode(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\x01", None).unwrap(), None);
+        assert_eq!(decode(latin1, Some("latin1")).unwrap().unwrap().text, "café\n");
+        assert!(decode(latin1, Some("klingon")).is_err());
+    }
+
+    #[test]
+    fn test_window_numbers_and_pages_lines() {
+        let text = "one\ntwo\r\nthree\nfour\n";
+        let window = window(text, 2, 2).unwrap();
+        assert_eq!(window.content, "     2\ttwo\n     3\tthree\n");
+        assert_eq!((window.start_line, window.end_line, window.total_lines), (2, 3, 4));
+        assert_eq!(window.next_offset(), Some(4));
+
+        let long = "x".repeat(MAX_LINE_CHARS + 10);
+        assert!(super::window(&long, 1, 10).unwrap().content.ends_with("x... [line truncated]\n"));
+
+        assert!(super::window(text, 9, 10).is_err());
+        assert_eq!(super::window("", 1, 10).unwrap().next_offset(), None);
+    }
+
+    #[test]
+    fn test_render_notebook() {
+        let notebook = r##"{
+            "cells": [
+                {"cell_type": "markdown", "source": ["# Title\n", "Intro"]},
+                {"cell_type": "code", "execution_count": 3, "source": "print(1)\n1/0",
+                 "outputs": [
+                    {"output_type": "stream", "name": "stdout", "text": ["1\n"]},
+                    {"output_type": "display_data", "data": {"image/png": "iVBOR"}},
+                    {"output_type": "error", "ename": "ZeroDivisionError", "evalue": "division by zero", "traceback": []}
+                 ]}
+            ]
+        }"##;
+        let rendered = render_notebook(notebook).unwrap();
+        assert_eq!(
+            rendered,
+            "# Cell 1 (markdown)\n# Title\nIntro\n\n\
+             # Cell 2 (code) [3]\nprint(1)\n1/0\n# Output\n1\n# Output\n[image/png output not shown]\n\
+             # Output\nZeroDivisionError: division by zero\n\n"
+        );
+        assert!(render_notebook("{}").is_err());
+    }
+}
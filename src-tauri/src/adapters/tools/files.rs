@@ -1,15 +1,25 @@
-use crate::domain::ports::Tool;
-use crate::domain::models::ToolResult;
+use crate::domain::ports::{InteractionProvider, Tool};
+use crate::domain::models::{ConfirmationRequest, ToolResult};
 use async_trait::async_trait;
 use serde_json::{json, Value};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use tokio::fs;
-use tauri::{AppHandle, Emitter};
-use tokio::sync::oneshot;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use uuid::Uuid;
-use serde::{Serialize, Deserialize};
+use std::sync::Arc;
+use serde::Deserialize;
+use crate::domain::checkpoint::CheckpointStore;
+use crate::adapters::tools::edit_match::{find_matches, replacement, MatchStrategy};
+use crate::adapters::tools::transaction::EditTransaction;
+use crate::adapters::tools::file_view;
+use crate::adapters::tools::read_tracker::ReadTracker;
+use base64::Engine;
+
+/// Images above this size are refused rather than attached
+const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Text files above this size are refused; bash can look at parts of them
+const MAX_READ_BYTES: u64 = 50 * 1024 * 1024;
 
 fn expand_tilde(input: &str) -> String {
     if input.starts_with("~") {
@@ -20,7 +30,7 @@ fn expand_tilde(input: &str) -> String {
     input.to_string()
 }
 
-fn normalize_path(path: &Path) -> PathBuf {
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
     let mut normalized = PathBuf::new();
     for component in path.components() {
         match component {
@@ -105,49 +115,35 @@ fn add_allow_rule_with_variants(
     }
 }
 
-#[derive(Serialize, Clone)]
-struct ConfirmationRequest {
-    id: String,
-    session_id: String,
-    #[serde(rename = "type")]
-    type_: String,
-    file_path: String,
-    old_content: Option<String>,
-    new_content: String,
-    suggested_pattern: String,
+/// Record a tool write in the session checkpoint store, if any
+pub(crate) fn record_checkpoint_write(checkpoints: Option<&CheckpointStore>, path: &Path) {
+    if let Some(checkpoints) = checkpoints {
+        if let Err(e) = checkpoints.record_write(path) {
+            eprintln!("Warning: Failed to record checkpoint for {}: {}", path.display(), e);
+        }
+    }
 }
 
 pub struct ReadFileTool {
     pub workspace_root: PathBuf,
     pub permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
     pub session_id: String,
-    pub app: AppHandle,
-    pub pending_confirmations: Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+    pub interaction: Arc<dyn InteractionProvider>,
+    pub read_tracker: Arc<ReadTracker>,
 }
 
 impl ReadFileTool {
     pub fn new(
         workspace_root: PathBuf,
         session_id: String,
-        app: AppHandle,
-        pending_confirmations: Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+        interaction: Arc<dyn InteractionProvider>,
         permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+        read_tracker: Arc<ReadTracker>,
     ) -> Self {
-        Self { workspace_root, permission_manager, session_id, app, pending_confirmations }
+        Self { workspace_root, permission_manager, session_id, interaction, read_tracker }
     }
 }
 
-#[derive(Serialize, Clone)]
-struct PermissionConfirmationRequest {
-    id: String,
-    session_id: String,
-    #[serde(rename = "type")]
-    type_: String,
-    tool_name: String,
-    input: String,
-    suggested_pattern: String,
-}
-
 #[async_trait]
 impl Tool for ReadFileTool {
     fn name(&self) -> &'static str {
@@ -157,13 +153,25 @@ impl Tool for ReadFileTool {
     fn schema(&self) -> Value {
         json!({
             "name": "read_file",
-            "description": "Read the contents of a file",
+            "description": "Read a file. Text comes back as numbered lines (number, tab, line), at most 2000 lines per call; use offset and limit to page through longer files. Images (png, jpg, gif, webp) are attached so you can see them, Jupyter notebooks are shown as cells with their outputs, and UTF-16 and Latin-1 files are decoded. Binary files are refused.",
             "parameters": {
                 "type": "object",
                 "properties": {
                     "path": {
                         "type": "string",
                         "description": "Path to the file relative to workspace root"
+                    },
+                    "offset": {
+                        "type": "integer",
+                        "description": "1-based line to start reading from (default: 1)"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of lines to read (default: 2000)"
+                    },
+                    "encoding": {
+                        "type": "string",
+                        "description": "Text encoding to use instead of detecting it, e.g. utf-8, utf-16le, latin1"
                     }
                 },
                 "required": ["path"]
@@ -197,28 +205,15 @@ impl Tool for ReadFileTool {
         }
 
         if location_action == crate::config::Action::Ask {
-            let request_id = Uuid::new_v4().to_string();
-            let (tx, rx) = oneshot::channel();
-
-            {
-                let mut map = self.pending_confirmations.lock().unwrap();
-                map.insert(request_id.clone(), tx);
-            }
-
             let suggested_pattern = canonicalize_or_normalize(&path).to_string_lossy().to_string();
-            let event = PermissionConfirmationRequest {
-                id: request_id.clone(),
-                session_id: self.session_id.clone(),
-                type_: "permission".to_string(),
-                tool_name: "read_file".to_string(),
-                input: expanded_path_str.clone(),
-                suggested_pattern: suggested_pattern.clone(),
-            };
-
-            self.app.emit("request-confirmation", &event)
-                .map_err(|e| format!("Failed to emit confirmation event: {}", e))?;
-
-            let response = rx.await.map_err(|_| "Confirmation channel closed without response".to_string())?;
+            let request = ConfirmationRequest::permission(
+                &self.session_id,
+                "permission",
+                "read_file",
+                &expanded_path_str,
+                suggested_pattern.clone(),
+            );
+            let response = self.interaction.confirm(request).await?;
 
             if !response.allowed {
                 return Err("User denied file read.".to_string());
@@ -243,28 +238,15 @@ impl Tool for ReadFileTool {
                 return Err("Access denied: Permission denied for this file type".to_string());
             }
             crate::config::Action::Ask => {
-                let request_id = Uuid::new_v4().to_string();
-                let (tx, rx) = oneshot::channel();
-
-                {
-                    let mut map = self.pending_confirmations.lock().unwrap();
-                    map.insert(request_id.clone(), tx);
-                }
-
                 let suggested_pattern = path_str.to_string();
-                let event = PermissionConfirmationRequest {
-                    id: request_id.clone(),
-                    session_id: self.session_id.clone(),
-                    type_: "permission".to_string(),
-                    tool_name: "read_file".to_string(),
-                    input: path_str.to_string(),
-                    suggested_pattern: suggested_pattern.clone(),
-                };
-
-                self.app.emit("request-confirmation", &event)
-                    .map_err(|e| format!("Failed to emit confirmation event: {}", e))?;
-
-                let response = rx.await.map_err(|_| "Confirmation channel closed without response".to_string())?;
+                let request = ConfirmationRequest::permission(
+                    &self.session_id,
+                    "permission",
+                    "read_file",
+                    path_str,
+                    suggested_pattern.clone(),
+                );
+                let response = self.interaction.confirm(request).await?;
 
                 if !response.allowed {
                     return Err("User denied file read.".to_string());
@@ -284,35 +266,115 @@ impl Tool for ReadFileTool {
             crate::config::Action::Allow => {}
         }
 
-        match fs::read_to_string(path).await {
-            Ok(content) => Ok(json!({ "content": content })),
-            Err(e) => Err(format!("Failed to read file: {}", e)),
+        let offset = input.get("offset").and_then(|v| v.as_u64()).unwrap_or(1) as usize;
+        let limit = input.get("limit")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+            .unwrap_or(file_view::DEFAULT_LIMIT)
+            .max(1);
+        let encoding = input.get("encoding").and_then(|v| v.as_str());
+
+        let metadata = fs::metadata(&path).await.map_err(|e| format!("Failed to read file: {}", e))?;
+        if metadata.is_dir() {
+            return Err(format!("{} is a directory; use the list tool to see its contents", path_str));
+        }
+
+        if let Some(mime_type) = file_view::image_mime(&path) {
+            if metadata.len() > MAX_IMAGE_BYTES {
+                return Err(format!(
+                    "{} is {} bytes, larger than the {} MB limit for images",
+                    path_str, metadata.len(), MAX_IMAGE_BYTES / (1024 * 1024)
+                ));
+            }
+            let bytes = fs::read(&path).await.map_err(|e| format!("Failed to read file: {}", e))?;
+            self.read_tracker.record_content(&path, &bytes);
+            // Attachments are moved out of the result and shown to the model as an image
+            return Ok(json!({
+                "path": path_str,
+                "type": "image",
+                "mime_type": mime_type,
+                "size": metadata.len(),
+                "attachments": [{
+                    "name": path_str,
+                    "mime_type": mime_type,
+                    "data": base64::engine::general_purpose::STANDARD.encode(&bytes),
+                }],
+            }));
         }
+
+        if metadata.len() > MAX_READ_BYTES {
+            return Err(format!(
+                "{} is {} MB, larger than the {} MB read_file limit. Use search, or bash with head, tail or sed -n, to look at parts of it.",
+                path_str, metadata.len() / (1024 * 1024), MAX_READ_BYTES / (1024 * 1024)
+            ));
+        }
+        let bytes = fs::read(&path).await.map_err(|e| format!("Failed to read file: {}", e))?;
+        self.read_tracker.record_content(&path, &bytes);
+
+        let (text, decoded_as) = if file_view::is_notebook(&path) {
+            (file_view::render_notebook(&String::from_utf8_lossy(&bytes))?, None)
+        } else {
+            match file_view::decode(&bytes, encoding)? {
+                Some(decoded) => (decoded.text, Some(decoded.encoding)),
+                None => {
+                    return Err(format!(
+                        "{} is a binary file ({} bytes); read_file only shows text, images and notebooks",
+                        path_str, bytes.len()
+                    ))
+                }
+            }
+        };
+
+        let window = file_view::window(&text, offset, limit)?;
+        let mut result = json!({
+            "path": path_str,
+            "content": window.content,
+            "start_line": window.start_line,
+            "end_line": window.end_line,
+            "total_lines": window.total_lines,
+            "truncated": window.next_offset().is_some(),
+        });
+        if file_view::is_notebook(&path) {
+            result["type"] = json!("notebook");
+        }
+        if let Some(encoding) = decoded_as.filter(|e| *e != "UTF-8") {
+            result["encoding"] = json!(encoding);
+        }
+        if let Some(next) = window.next_offset() {
+            result["note"] = json!(format!(
+                "Showing lines {}-{} of {}. Call read_file with offset={} to read more.",
+                window.start_line, window.end_line, window.total_lines, next
+            ));
+        }
+        Ok(result)
     }
 }
 
 pub struct WriteFileTool {
     pub workspace_root: PathBuf,
     pub session_id: String,
-    pub app: AppHandle,
-    pub pending_confirmations: Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+    pub interaction: Arc<dyn InteractionProvider>,
     pub permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+    pub checkpoints: Option<Arc<CheckpointStore>>,
+    pub read_tracker: Arc<ReadTracker>,
 }
 
 impl WriteFileTool {
     pub fn new(
         workspace_root: PathBuf,
         session_id: String,
-        app: AppHandle,
-        pending_confirmations: Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+        interaction: Arc<dyn InteractionProvider>,
         permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+        checkpoints: Option<Arc<CheckpointStore>>,
+        read_tracker: Arc<ReadTracker>,
     ) -> Self {
         Self { 
             workspace_root,
             session_id,
-            app,
-            pending_confirmations,
+            interaction,
             permission_manager,
+            checkpoints,
+            read_tracker,
         }
     }
 }
@@ -326,7 +388,7 @@ impl Tool for WriteFileTool {
     fn schema(&self) -> Value {
         json!({
             "name": "write_file",
-            "description": "Overwrite a file with new content",
+            "description": "Create a file or overwrite it with new content. An existing file must have been read in this session, and is refused if it changed since.",
             "parameters": {
                 "type": "object",
                 "properties": {
@@ -337,6 +399,10 @@ impl Tool for WriteFileTool {
                     "content": {
                         "type": "string",
                         "description": "New content for the file"
+                    },
+                    "allow_unread": {
+                        "type": "boolean",
+                        "description": "Change an existing file that has not been read in this session (default: false)"
                     }
                 },
                 "required": ["path", "content"]
@@ -373,10 +439,11 @@ impl Tool for WriteFileTool {
             return Err("Access denied: Path is outside workspace and not allowed by config".to_string());
         }
 
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).await.map_err(|e| format!("Failed to create directories: {}", e))?;
-        }
+        let allow_unread = input.get("allow_unread").and_then(|v| v.as_bool()).unwrap_or(false);
+        self.read_tracker.check(&path, path_str, allow_unread)?;
+
+        let mut transaction = EditTransaction::new();
+        transaction.stage(path.clone(), path_str, content.to_string())?;
 
         // Check if allowed by permission manager
         let allowed = {
@@ -386,37 +453,11 @@ impl Tool for WriteFileTool {
 
         if !allowed {
             // --- Confirmation Logic ---
-            let old_content = if path.exists() {
-                Some(fs::read_to_string(&path).await.unwrap_or_default())
-            } else {
-                None
-            };
-
-            let request_id = Uuid::new_v4().to_string();
-            println!("WriteFileTool: Requesting confirmation for id={} session={}", request_id, self.session_id);
-            let (tx, rx) = oneshot::channel();
-
-            {
-                let mut map = self.pending_confirmations.lock().unwrap();
-                map.insert(request_id.clone(), tx);
-            }
-
-            let event = ConfirmationRequest {
-                id: request_id.clone(),
-                session_id: self.session_id.clone(),
-                type_: "diff".to_string(),
-                file_path: path_str.to_string(),
-                old_content,
-                new_content: content.to_string(),
-                suggested_pattern: path_str.to_string(),
-            };
-
-            self.app.emit("request-confirmation", &event)
-                .map_err(|e| format!("Failed to emit confirmation event: {}", e))?;
+            let request = transaction.confirmation(&self.session_id, "write_file");
 
             // Wait for user response
             // This blocks the tool execution (and thus the agent step) until frontend responds
-            let response = rx.await.map_err(|_| "Confirmation channel closed without response".to_string())?;
+            let response = self.interaction.confirm(request).await?;
 
             if !response.allowed {
                 return Err("User denied file write.".to_string());
@@ -435,19 +476,19 @@ impl Tool for WriteFileTool {
             // --------------------------
         }
 
-        match fs::write(path, content).await {
-            Ok(_) => Ok(json!({ "status": "success" })),
-            Err(e) => Err(format!("Failed to write file: {}", e)),
-        }
+        transaction.commit(self.checkpoints.as_deref())?;
+        self.read_tracker.record(&path);
+        Ok(json!({ "status": "success" }))
     }
 }
 
 pub struct EditFileTool {
     pub workspace_root: PathBuf,
     pub session_id: String,
-    pub app: AppHandle,
-    pub pending_confirmations: Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+    pub interaction: Arc<dyn InteractionProvider>,
     pub permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+    pub checkpoints: Option<Arc<CheckpointStore>>,
+    pub read_tracker: Arc<ReadTracker>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -460,90 +501,142 @@ struct EditBlock {
     replace_all: Option<bool>,
 }
 
-fn replace_nth_occurrence(
-    content: &str,
-    old_text: &str,
-    new_text: &str,
-    occurrence: usize,
-) -> Result<String, String> {
-    let mut match_iter = content.match_indices(old_text).enumerate();
-    while let Some((index, (start, _))) = match_iter.next() {
-        if index == occurrence {
-            let mut updated = String::with_capacity(content.len() - old_text.len() + new_text.len());
-            updated.push_str(&content[..start]);
-            updated.push_str(new_text);
-            updated.push_str(&content[start + old_text.len()..]);
-            return Ok(updated);
+/// Edited content and how each block's old_text was located
+#[derive(Debug)]
+struct EditOutcome {
+    content: String,
+    strategies: Vec<MatchStrategy>,
+}
+
+/// Content with LF line endings, for matching, and where it had CRLF
+struct LfView {
+    text: String,
+    /// Offsets in `text` of the line breaks that were CRLF
+    crlf_breaks: Vec<usize>,
+}
+
+impl LfView {
+    fn new(content: &str) -> Self {
+        let mut crlf_breaks = Vec::new();
+        for (index, _) in content.match_indices("\r\n") {
+            crlf_breaks.push(index - crlf_breaks.len());
+        }
+        Self {
+            text: content.replace("\r\n", "\n"),
+            crlf_breaks,
         }
     }
 
-    Err(format!("Search block occurrence {} not found.", occurrence))
+    /// Offset in the original content of `offset` in `text`
+    fn original_offset(&self, offset: usize) -> usize {
+        offset + self.crlf_breaks.partition_point(|&index| index < offset)
+    }
+}
+
+/// Line ending for text replacing `range` of `content`: the one ending the
+/// first replaced line, or the file's most common one on its last line
+fn line_ending_at(content: &str, range: Range<usize>) -> &'static str {
+    let crlf = match content[range.start..].find('\n') {
+        Some(index) => content[..range.start + index].ends_with('\r'),
+        None => content.matches("\r\n").count() * 2 > content.matches('\n').count(),
+    };
+    if crlf {
+        "\r\n"
+    } else {
+        "\n"
+    }
 }
 
 fn apply_edits_to_content(
     original_content: &str,
     edits: &[EditBlock],
     path_label: &str,
-) -> Result<String, String> {
+) -> Result<EditOutcome, String> {
     let mut updated_content = original_content.to_string();
+    let mut strategies = Vec::with_capacity(edits.len());
 
     for edit in edits {
         if edit.old_text.is_empty() {
             return Err("Invalid edit block: old_text cannot be empty".to_string());
         }
-
-        let matches: Vec<usize> = updated_content.match_indices(&edit.old_text)
-            .map(|(idx, _)| idx)
-            .collect();
-
-        if matches.is_empty() {
-            return Err(format!(
-                "Could not find exact match for search block in {}. Ensure old_text matches the file content exactly, including whitespace.",
+        let old_text = edit.old_text.replace("\r\n", "\n");
+        let new_text = edit.new_text.replace("\r\n", "\n");
+
+        // Match with LF line endings; replaced lines keep the ending they had
+        let view = LfView::new(&updated_content);
+        let found = find_matches(&view.text, &old_text).ok_or_else(|| {
+            format!(
+                "Could not find a match for search block in {}, even ignoring whitespace and indentation differences. Re-read the file and copy old_text from it.",
                 path_label
-            ));
-        }
+            )
+        })?;
 
-        if edit.replace_all.unwrap_or(false) {
+        let ranges = if edit.replace_all.unwrap_or(false) {
             if edit.occurrence.is_some() {
                 return Err("Invalid edit block: replace_all cannot be combined with occurrence".to_string());
             }
-            updated_content = updated_content.replace(&edit.old_text, &edit.new_text);
-            continue;
-        }
-
-        let occurrence = match edit.occurrence {
-            Some(index) => index,
-            None => {
-                if matches.len() > 1 {
-                    return Err(format!(
-                        "Search block is not unique in {}. Provide more context in old_text or specify occurrence.",
-                        path_label
-                    ));
-                }
-                0
+            // Replacing every loose match could rewrite blocks that only look alike
+            if found.strategy != MatchStrategy::Exact {
+                return Err(format!(
+                    "replace_all needs old_text to match {} exactly, but it only matched loosely ({}). Copy old_text from the file or replace the occurrences one at a time.",
+                    path_label,
+                    found.strategy.as_str()
+                ));
             }
+            found.ranges
+        } else {
+            let occurrence = match edit.occurrence {
+                Some(index) => index,
+                None => {
+                    if found.ranges.len() > 1 {
+                        return Err(format!(
+                            "Search block is not unique in {}. Provide more context in old_text or specify occurrence.",
+                            path_label
+                        ));
+                    }
+                    0
+                }
+            };
+            let range = found
+                .ranges
+                .get(occurrence)
+                .cloned()
+                .ok_or_else(|| format!("Search block occurrence {} not found.", occurrence))?;
+            vec![range]
         };
 
-        updated_content = replace_nth_occurrence(&updated_content, &edit.old_text, &edit.new_text, occurrence)?;
+        // Back to front so earlier ranges stay valid
+        for range in ranges.into_iter().rev() {
+            let (range, text) = replacement(&view.text, range, found.strategy, &old_text, &new_text);
+            let range = view.original_offset(range.start)..view.original_offset(range.end);
+            let text = text.replace('\n', line_ending_at(&updated_content, range.clone()));
+            updated_content.replace_range(range, &text);
+        }
+        strategies.push(found.strategy);
     }
 
-    Ok(updated_content)
+    Ok(EditOutcome {
+        content: updated_content,
+        strategies,
+    })
 }
 
 impl EditFileTool {
     pub fn new(
         workspace_root: PathBuf,
         session_id: String,
-        app: AppHandle,
-        pending_confirmations: Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+        interaction: Arc<dyn InteractionProvider>,
         permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+        checkpoints: Option<Arc<CheckpointStore>>,
+        read_tracker: Arc<ReadTracker>,
     ) -> Self {
         Self {
             workspace_root,
             session_id,
-            app,
-            pending_confirmations,
+            interaction,
             permission_manager,
+            checkpoints,
+            read_tracker,
         }
     }
 }
@@ -572,7 +665,7 @@ impl Tool for EditFileTool {
                             "properties": {
                                 "old_text": {
                                     "type": "string",
-                                    "description": "The exact text to find in the file"
+                                    "description": "The text to find in the file. Copy it exactly; small whitespace and indentation differences are tolerated"
                                 },
                                 "new_text": {
                                     "type": "string",
@@ -584,12 +677,16 @@ impl Tool for EditFileTool {
                                 },
                                 "replace_all": {
                                     "type": "boolean",
-                                    "description": "Replace all occurrences of old_text"
+                                    "description": "Replace all exact occurrences of old_text"
                                 }
                             },
                             "required": ["old_text", "new_text"]
                         },
                         "description": "A list of search/replace blocks"
+                    },
+                    "allow_unread": {
+                        "type": "boolean",
+                        "description": "Change an existing file that has not been read in this session (default: false)"
                     }
                 },
                 "required": ["path", "edits"]
@@ -629,6 +726,8 @@ impl Tool for EditFileTool {
         if !path.exists() {
             return Err(format!("File does not exist: {}", path_str));
         }
+        let allow_unread = input.get("allow_unread").and_then(|v| v.as_bool()).unwrap_or(false);
+        self.read_tracker.check(&path, path_str, allow_unread)?;
 
         let original_content = fs::read_to_string(&path).await
             .map_err(|e| format!("Failed to read file: {}", e))?;
@@ -639,7 +738,10 @@ impl Tool for EditFileTool {
                 .map_err(|e| format!("Invalid edit block: {}", e)))
             .collect::<Result<Vec<_>, _>>()?;
 
-        let new_content = apply_edits_to_content(&original_content, &edits, path_str)?;
+        let EditOutcome { content: new_content, strategies } =
+            apply_edits_to_content(&original_content, &edits, path_str)?;
+        let mut transaction = EditTransaction::new();
+        transaction.stage(path.clone(), path_str, new_content)?;
 
         // Check if allowed by permission manager
         let allowed = {
@@ -649,29 +751,9 @@ impl Tool for EditFileTool {
 
         if !allowed {
             // --- Confirmation Logic ---
-            let request_id = Uuid::new_v4().to_string();
-            println!("EditFileTool: Requesting confirmation for id={} session={}", request_id, self.session_id);
-            let (tx, rx) = oneshot::channel();
-
-            {
-                let mut map = self.pending_confirmations.lock().unwrap();
-                map.insert(request_id.clone(), tx);
-            }
-
-            let event = ConfirmationRequest {
-                id: request_id.clone(),
-                session_id: self.session_id.clone(),
-                type_: "diff".to_string(),
-                file_path: path_str.to_string(),
-                old_content: Some(original_content),
-                new_content: new_content.clone(),
-                suggested_pattern: path_str.to_string(),
-            };
-
-            self.app.emit("request-confirmation", &event)
-                .map_err(|e| format!("Failed to emit confirmation event: {}", e))?;
+            let request = transaction.confirmation(&self.session_id, "edit_file");
 
-            let response = rx.await.map_err(|_| "Confirmation channel closed without response".to_string())?;
+            let response = self.interaction.confirm(request).await?;
 
             if !response.allowed {
                 return Err("User denied file edit.".to_string());
@@ -690,16 +772,217 @@ impl Tool for EditFileTool {
             // --------------------------
         }
 
-        match fs::write(path, new_content).await {
-            Ok(_) => Ok(json!({ "status": "success", "message": format!("Applied {} edit(s) to {}", edits_val.len(), path_str) })),
-            Err(e) => Err(format!("Failed to write file: {}", e)),
+        transaction.commit(self.checkpoints.as_deref())?;
+        self.read_tracker.record(&path);
+        Ok(json!({
+            "status": "success",
+            "message": format!("Applied {} edit(s) to {}", edits_val.len(), path_str),
+            "match_strategies": strategies.iter().map(|s| s.as_str()).collect::<Vec<_>>(),
+        }))
+    }
+}
+
+pub struct MultiEditTool {
+    pub workspace_root: PathBuf,
+    pub session_id: String,
+    pub interaction: Arc<dyn InteractionProvider>,
+    pub permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+    pub checkpoints: Option<Arc<CheckpointStore>>,
+    pub read_tracker: Arc<ReadTracker>,
+}
+
+/// One file of a `multi_edit` call: search/replace edits or new content
+#[derive(Deserialize)]
+struct FileEdits {
+    path: String,
+    #[serde(default)]
+    edits: Vec<EditBlock>,
+    #[serde(default)]
+    content: Option<String>,
+}
+
+impl MultiEditTool {
+    pub fn new(
+        workspace_root: PathBuf,
+        session_id: String,
+        interaction: Arc<dyn InteractionProvider>,
+        permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+        checkpoints: Option<Arc<CheckpointStore>>,
+        read_tracker: Arc<ReadTracker>,
+    ) -> Self {
+        Self {
+            workspace_root,
+            session_id,
+            interaction,
+            permission_manager,
+            checkpoints,
+            read_tracker,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for MultiEditTool {
+    fn name(&self) -> &'static str {
+        "multi_edit"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "name": "multi_edit",
+            "description": "Change several files in one all-or-nothing step, e.g. for a rename or refactoring across files. Every edit is validated before anything is written; if one edit does not match or a write fails, no file is changed. Each file gets either search/replace edits (as in edit_file) or its full new content.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "files": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "path": {
+                                    "type": "string",
+                                    "description": "Path to the file relative to workspace root"
+                                },
+                                "edits": {
+                                    "type": "array",
+                                    "items": {
+                                        "type": "object",
+                                        "properties": {
+                                            "old_text": { "type": "string" },
+                                            "new_text": { "type": "string" },
+                                            "occurrence": { "type": "integer" },
+                                            "replace_all": { "type": "boolean" }
+                                        },
+                                        "required": ["old_text", "new_text"]
+                                    },
+                                    "description": "Search/replace blocks applied in order"
+                                },
+                                "content": {
+                                    "type": "string",
+                                    "description": "Full new content; creates the file if it does not exist"
+                                }
+                            },
+                            "required": ["path"]
+                        },
+                        "description": "Files to change; a path may appear more than once"
+                    },
+                    "allow_unread": {
+                        "type": "boolean",
+                        "description": "Change existing files that have not been read in this session (default: false)"
+                    }
+                },
+                "required": ["files"]
+            }
+        })
+    }
+
+    async fn execute(&self, input: Value) -> ToolResult {
+        let files: Vec<FileEdits> = serde_json::from_value(
+            input.get("files").cloned().ok_or("Missing 'files' parameter")?,
+        )
+        .map_err(|e| format!("Invalid files parameter: {}", e))?;
+        if files.is_empty() {
+            return Err("Invalid files parameter: no files given".to_string());
         }
+
+        let allow_unread = input.get("allow_unread").and_then(|v| v.as_bool()).unwrap_or(false);
+
+        // Validate everything before asking or writing
+        let mut transaction = EditTransaction::new();
+        let mut summaries = Vec::with_capacity(files.len());
+        let mut needs_confirmation = false;
+        for file in &files {
+            let expanded_path_str = expand_tilde(&file.path);
+            let path = if std::path::Path::new(&expanded_path_str).is_absolute() {
+                PathBuf::from(expanded_path_str)
+            } else {
+                self.workspace_root.join(expanded_path_str)
+            };
+
+            let rule_allows = {
+                let config = self.permission_manager.lock().await;
+                if config.check_path_access(&path, &self.workspace_root) != crate::config::Action::Allow {
+                    return Err(format!(
+                        "Access denied: {} is outside workspace and not allowed by config",
+                        file.path
+                    ));
+                }
+                match file.content {
+                    Some(_) => config.write.evaluate(&file.path),
+                    None => config.edit.evaluate(&file.path),
+                }
+            };
+            needs_confirmation |= rule_allows != crate::config::Action::Allow;
+            self.read_tracker.check(&path, &file.path, allow_unread)?;
+
+            let (content, summary) = match (&file.content, file.edits.is_empty()) {
+                (Some(_), false) => {
+                    return Err(format!("Invalid entry for {}: give either edits or content, not both", file.path));
+                }
+                (None, true) => {
+                    return Err(format!("Invalid entry for {}: give edits or content", file.path));
+                }
+                (Some(content), true) => (
+                    content.clone(),
+                    json!({ "path": file.path, "written": true }),
+                ),
+                (None, false) => {
+                    let current = transaction
+                        .read(&path)?
+                        .ok_or_else(|| format!("File does not exist: {}", file.path))?;
+                    let outcome = apply_edits_to_content(&current, &file.edits, &file.path)?;
+                    let strategies: Vec<&str> = outcome.strategies.iter().map(|s| s.as_str()).collect();
+                    (
+                        outcome.content,
+                        json!({ "path": file.path, "edits": file.edits.len(), "match_strategies": strategies }),
+                    )
+                }
+            };
+            transaction.stage(path, &file.path, content)?;
+            summaries.push(summary);
+        }
+
+        if needs_confirmation {
+            let request = transaction.confirmation(&self.session_id, "multi_edit");
+            let response = self.interaction.confirm(request).await?;
+
+            if !response.allowed {
+                return Err("User denied multi-file edit. No files were changed.".to_string());
+            }
+
+            if response.always {
+                let mut config = self.permission_manager.lock().await;
+                for file in &files {
+                    let rules = match file.content {
+                        Some(_) => &mut config.write.rules,
+                        None => &mut config.edit.rules,
+                    };
+                    add_allow_rule_with_variants(rules, file.path.clone(), &file.path, &self.workspace_root);
+                }
+            }
+        }
+
+        let labels = transaction.labels();
+        let paths = transaction.paths();
+        transaction.commit(self.checkpoints.as_deref())?;
+        for path in &paths {
+            self.read_tracker.record(path);
+        }
+        Ok(json!({
+            "status": "success",
+            "message": format!("Changed {} file(s): {}", labels.len(), labels.join(", ")),
+            "files": summaries,
+        }))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{apply_edits_to_content, EditBlock};
+    use super::{apply_edits_to_content, EditBlock, MatchStrategy, MultiEditTool, ReadFileTool, ReadTracker, WriteFileTool};
+    use crate::adapters::interaction::auto::{AutoPolicy, AutoResponder};
+    use crate::domain::ports::Tool;
+    use serde_json::json;
+    use std::sync::Arc;
 
     #[test]
     fn apply_edits_single_match() {
@@ -711,7 +994,7 @@ mod tests {
             replace_all: None,
         }];
 
-        let updated = apply_edits_to_content(content, &edits, "test.txt").unwrap();
+        let updated = apply_edits_to_content(content, &edits, "test.txt").unwrap().content;
         assert!(updated.contains("theta"));
         assert!(!updated.contains("beta"));
     }
@@ -740,7 +1023,7 @@ mod tests {
             replace_all: None,
         }];
 
-        let updated = apply_edits_to_content(content, &edits, "test.txt").unwrap();
+        let updated = apply_edits_to_content(content, &edits, "test.txt").unwrap().content;
         assert_eq!(updated, "dup\nvalue\nswap");
     }
 
@@ -754,10 +1037,24 @@ mod tests {
             replace_all: Some(true),
         }];
 
-        let updated = apply_edits_to_content(content, &edits, "test.txt").unwrap();
+        let updated = apply_edits_to_content(content, &edits, "test.txt").unwrap().content;
         assert_eq!(updated, "swap\nvalue\nswap");
     }
 
+    #[test]
+    fn apply_edits_replace_all_needs_exact_matches() {
+        let content = "fn a() {\n    dup();\n}\nfn b() {\n\tdup();\n}\n";
+        let edits = vec![EditBlock {
+            old_text: "dup();  \n".to_string(),
+            new_text: "swap();\n".to_string(),
+            occurrence: None,
+            replace_all: Some(true),
+        }];
+
+        let err = apply_edits_to_content(content, &edits, "test.txt").unwrap_err();
+        assert!(err.contains("exactly"));
+    }
+
     #[test]
     fn apply_edits_empty_old_text_fails() {
         let content = "data";
@@ -771,4 +1068,131 @@ mod tests {
         let err = apply_edits_to_content(content, &edits, "test.txt").unwrap_err();
         assert!(err.contains("old_text cannot be empty"));
     }
+
+    #[test]
+    fn apply_edits_tolerates_whitespace_and_keeps_crlf() {
+        let content = "if ok {\r\n\treturn 1;  \r\n}\r\n";
+        let edits = vec![EditBlock {
+            old_text: "if ok {\n    return 1;\n}".to_string(),
+            new_text: "if ok {\n    return 2;\n}".to_string(),
+            occurrence: None,
+            replace_all: None,
+        }];
+
+        let outcome = apply_edits_to_content(content, &edits, "test.txt").unwrap();
+        assert_eq!(outcome.content, "if ok {\r\n\treturn 2;\r\n}\r\n");
+        assert_eq!(outcome.strategies, vec![MatchStrategy::IndentNormalized]);
+    }
+
+    #[test]
+    fn apply_edits_keeps_line_endings_in_mixed_files() {
+        let content = "a\r\nb\r\nc\nd\n";
+        let block = |old: &str, new: &str| EditBlock {
+            old_text: old.to_string(),
+            new_text: new.to_string(),
+            occurrence: None,
+            replace_all: None,
+        };
+        let edits = vec![block("b\n", "b1\nb2\n"), block("c\nd", "c1\nc2\nd")];
+
+        let outcome = apply_edits_to_content(content, &edits, "test.txt").unwrap();
+        assert_eq!(outcome.content, "a\r\nb1\r\nb2\r\nc1\nc2\nd\n");
+        assert_eq!(outcome.strategies, vec![MatchStrategy::Exact, MatchStrategy::Exact]);
+    }
+
+    #[tokio::test]
+    async fn multi_edit_is_all_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.rs"), "fn old_name() {}\n").unwrap();
+        std::fs::write(dir.path().join("b.rs"), "old_name();\n").unwrap();
+        let reads = Arc::new(ReadTracker::new());
+        reads.record(&dir.path().join("a.rs"));
+        reads.record(&dir.path().join("b.rs"));
+        let tool = MultiEditTool::new(
+            dir.path().to_path_buf(),
+            "s1".to_string(),
+            Arc::new(AutoResponder::new(AutoPolicy::DenyAll)),
+            Arc::new(tokio::sync::Mutex::new(crate::config::PermissionConfig::default())),
+            None,
+            reads,
+        );
+
+        let failing = json!({ "files": [
+            { "path": "a.rs", "edits": [{ "old_text": "old_name", "new_text": "new_name" }] },
+            { "path": "b.rs", "edits": [{ "old_text": "missing()", "new_text": "new_name()" }] }
+        ]});
+        assert!(tool.execute(failing).await.is_err());
+        assert_eq!(std::fs::read_to_string(dir.path().join("a.rs")).unwrap(), "fn old_name() {}\n");
+
+        let renaming = json!({ "files": [
+            { "path": "a.rs", "edits": [{ "old_text": "old_name", "new_text": "new_name" }] },
+            { "path": "b.rs", "edits": [{ "old_text": "old_name", "new_text": "new_name" }] },
+            { "path": "c.rs", "content": "new_name();\n" }
+        ]});
+        let result = tool.execute(renaming).await.unwrap();
+        assert_eq!(result["files"].as_array().unwrap().len(), 3);
+        assert_eq!(std::fs::read_to_string(dir.path().join("b.rs")).unwrap(), "new_name();\n");
+        assert!(dir.path().join("c.rs").exists());
+    }
+
+    #[tokio::test]
+    async fn read_file_pages_images_and_binaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let lines: Vec<String> = (1..=5).map(|i| format!("line {}", i)).collect();
+        std::fs::write(dir.path().join("a.txt"), lines.join("\n")).unwrap();
+        std::fs::write(dir.path().join("logo.png"), b"\x89PNG").unwrap();
+        std::fs::write(dir.path().join("data.bin"), b"\x00\x01\x02\xff\x00").unwrap();
+        let tool = ReadFileTool::new(
+            dir.path().to_path_buf(),
+            "s1".to_string(),
+            Arc::new(AutoResponder::new(AutoPolicy::DenyAll)),
+            Arc::new(tokio::sync::Mutex::new(crate::config::PermissionConfig::default())),
+            Arc::new(ReadTracker::new()),
+        );
+
+        let result = tool.execute(json!({ "path": "a.txt", "offset": 2, "limit": 2 })).await.unwrap();
+        assert_eq!(result["content"], "     2\tline 2\n     3\tline 3\n");
+        assert_eq!(result["total_lines"], 5);
+        assert!(result["note"].as_str().unwrap().contains("offset=4"));
+
+        let result = tool.execute(json!({ "path": "logo.png" })).await.unwrap();
+        assert_eq!(result["attachments"][0]["mime_type"], "image/png");
+        assert_eq!(result["attachments"][0]["data"], "iVBORw==");
+
+        let err = tool.execute(json!({ "path": "data.bin" })).await.unwrap_err();
+        assert!(err.contains("binary file"));
+    }
+
+    #[tokio::test]
+    async fn write_file_refuses_stale_and_unread_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "original\n").unwrap();
+        let reads = Arc::new(ReadTracker::new());
+        let tool = WriteFileTool::new(
+            dir.path().to_path_buf(),
+            "s1".to_string(),
+            Arc::new(AutoResponder::new(AutoPolicy::DenyAll)),
+            Arc::new(tokio::sync::Mutex::new(crate::config::PermissionConfig::default())),
+            None,
+            reads.clone(),
+        );
+
+        let err = tool.execute(json!({ "path": "a.txt", "content": "mine\n" })).await.unwrap_err();
+        assert!(err.contains("has not been read"));
+
+        reads.record(&path);
+        // The user edits the file after the agent read it
+        std::fs::write(&path, "user edit\n").unwrap();
+        let err = tool.execute(json!({ "path": "a.txt", "content": "mine\n" })).await.unwrap_err();
+        assert!(err.contains("changed on disk"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "user edit\n");
+
+        reads.record(&path);
+        tool.execute(json!({ "path": "a.txt", "content": "mine\n" })).await.unwrap();
+        // The agent's own write keeps its view current
+        tool.execute(json!({ "path": "a.txt", "content": "again\n" })).await.unwrap();
+        tool.execute(json!({ "path": "new.txt", "content": "new\n" })).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "again\n");
+    }
 }
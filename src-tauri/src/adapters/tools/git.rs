@@ -1,89 +1,80 @@
-use crate::domain::ports::Tool;
-use crate::domain::models::ToolResult;
+use crate::domain::ports::{InteractionProvider, Tool};
+use crate::domain::models::{ConfirmationRequest, ToolResult};
 use async_trait::async_trait;
-use git2::{Repository, StatusOptions, Signature};
+use git2::{
+    build::CheckoutBuilder, BlameOptions, BranchType, Diff, DiffFormat, DiffOptions, Oid, Repository, Signature,
+    StatusOptions, Tree,
+};
 use serde_json::{json, Value};
-use std::path::PathBuf;
-use std::process::Command;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Diffs are cut at this size so one large change cannot fill the context
+const MAX_DIFF_BYTES: usize = 100 * 1024;
+
+/// Lines blamed when the caller gives no range end
+const DEFAULT_BLAME_LINES: usize = 200;
+
+/// Commands that can discard work, gated by the `git_destructive` permission
+const DESTRUCTIVE_COMMANDS: &[&str] = &["switch", "stash_push", "stash_pop", "restore"];
 
 pub struct GitTool {
     pub workspace_root: PathBuf,
+    pub session_id: String,
+    pub interaction: Arc<dyn InteractionProvider>,
+    pub permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
 }
 
 impl GitTool {
-    pub fn new(workspace_root: PathBuf) -> Self {
-        Self { workspace_root }
-    }
-
-    fn run_git(&self, args: Vec<&str>) -> Result<String, String> {
-        let output = Command::new("git")
-            .current_dir(&self.workspace_root)
-            .args(args)
-            .output()
-            .map_err(|e| format!("Failed to execute git: {}", e))?;
-
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        } else {
-            Err(String::from_utf8_lossy(&output.stderr).to_string())
-        }
+    pub fn new(
+        workspace_root: PathBuf,
+        session_id: String,
+        interaction: Arc<dyn InteractionProvider>,
+        permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
+    ) -> Self {
+        Self { workspace_root, session_id, interaction, permission_manager }
     }
 
     fn get_repo(&self) -> Result<Repository, String> {
-        Repository::open(&self.workspace_root)
-            .map_err(|e| format!("Failed to open git repository: {}", e))
+        open_repo(&self.workspace_root)
     }
 
-    fn git_status(&self) -> Result<Value, String> {
-        let repo = self.get_repo()?;
-        let mut status_opts = StatusOptions::new();
-        status_opts.include_untracked(true);
-
-        let statuses = repo.statuses(Some(&mut status_opts))
-            .map_err(|e| format!("Failed to get statuses: {}", e))?;
-
-        let mut staged = Vec::new();
-        let mut unstaged = Vec::new();
-        let mut untracked = Vec::new();
-        let mut conflicted = Vec::new();
-
-        for entry in statuses.iter() {
-            let path = entry.path().unwrap_or("unknown").to_string();
-            let status = entry.status();
-
-            if status.is_index_new() || status.is_index_modified() || status.is_index_deleted() || status.is_index_renamed() || status.is_index_typechange() {
-                staged.push(path);
-            } else if status.is_wt_modified() || status.is_wt_deleted() {
-                unstaged.push(path);
-            } else if status.is_wt_new() {
-                untracked.push(path);
-            } else if status.is_conflicted() {
-                conflicted.push(path);
+    /// Ask before a destructive command unless `git_destructive` allows it.
+    /// Rules match `<command> <arguments>`, e.g. `restore src/main.rs`.
+    async fn check_destructive(&self, command: &str, subject: &str) -> Result<(), String> {
+        let input = format!("{} {}", command, subject).trim().to_string();
+        let action = {
+            let config = self.permission_manager.lock().await;
+            config.git_destructive.evaluate(&input)
+        };
+
+        match action {
+            crate::config::Action::Allow => Ok(()),
+            crate::config::Action::Deny => Err(format!("Access denied: git {} is not allowed by config", command)),
+            crate::config::Action::Ask => {
+                let suggested_pattern = format!("{}*", command);
+                let request = ConfirmationRequest::permission(
+                    &self.session_id,
+                    "permission",
+                    "git",
+                    &input,
+                    suggested_pattern.clone(),
+                );
+                let response = self.interaction.confirm(request).await?;
+                if !response.allowed {
+                    return Err(format!("User denied git {}.", command));
+                }
+                if response.always {
+                    let mut config = self.permission_manager.lock().await;
+                    config.git_destructive.rules.push(crate::config::manager::PermissionRule {
+                        pattern: response.pattern.unwrap_or(suggested_pattern),
+                        action: crate::config::Action::Allow,
+                    });
+                }
+                Ok(())
             }
         }
-
-        // Get current branch
-        let head = repo.head().ok();
-        let branch_name = head.as_ref()
-            .and_then(|h| h.shorthand())
-            .unwrap_or("HEAD detached")
-            .to_string();
-
-        // Get latest commit message
-        let latest_commit = repo.head()
-            .ok()
-            .and_then(|h| h.target())
-            .and_then(|oid| repo.find_commit(oid).ok())
-            .map(|commit| commit.message().unwrap_or("").to_string());
-
-        Ok(json!({
-            "branch": branch_name,
-            "staged": staged,
-            "unstaged": unstaged,
-            "untracked": untracked,
-            "conflicted": conflicted,
-            "latest_commit": latest_commit,
-        }))
     }
 
     fn git_add(&self, files: Vec<String>) -> Result<String, String> {
@@ -93,7 +84,7 @@ impl GitTool {
 
         let file_count = files.len();
         for file in &files {
-            let path = PathBuf::from(file);
+            let path = repo_path(&repo, file)?;
             index.add_path(&path)
                 .map_err(|e| format!("Failed to add file {}: {}", file, e))?;
         }
@@ -114,8 +105,7 @@ impl GitTool {
         let tree = repo.find_tree(tree_id)
             .map_err(|e| format!("Failed to find tree: {}", e))?;
 
-        let signature = Signature::now("Anvil Agent", "agent@anvil.local")
-            .map_err(|e| format!("Failed to create signature: {}", e))?;
+        let signature = signature(&repo)?;
 
         let parent_commit = repo.head()
             .ok()
@@ -166,13 +156,369 @@ impl GitTool {
         Ok(json!({ "commits": commits }))
     }
 
-    fn git_diff(&self, staged: bool) -> Result<String, String> {
-        if staged {
-            self.run_git(vec!["diff", "--cached"])
-        } else {
-            self.run_git(vec!["diff"])
+    /// Without revisions, unstaged (or with `staged`, staged) changes. With
+    /// `from` only, `from` against the working tree (or the index); with both,
+    /// `from` against `to`.
+    fn git_diff(&self, staged: bool, from: Option<&str>, to: Option<&str>, path: Option<&str>) -> Result<Value, String> {
+        let repo = self.get_repo()?;
+        let mut opts = diff_options(&repo, path)?;
+        let opts = Some(&mut opts);
+
+        let diff = match (from, to) {
+            (None, None) if staged => {
+                let head = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+                repo.diff_tree_to_index(head.as_ref(), None, opts)
+            }
+            (None, None) => repo.diff_index_to_workdir(None, opts),
+            (Some(from), None) => {
+                let from = tree_at(&repo, from)?;
+                if staged {
+                    repo.diff_tree_to_index(Some(&from), None, opts)
+                } else {
+                    repo.diff_tree_to_workdir_with_index(Some(&from), opts)
+                }
+            }
+            (Some(from), Some(to)) => {
+                let (from, to) = (tree_at(&repo, from)?, tree_at(&repo, to)?);
+                repo.diff_tree_to_tree(Some(&from), Some(&to), opts)
+            }
+            (None, Some(_)) => return Err("'to' requires 'from'".to_string()),
+        }
+        .map_err(|e| format!("Failed to compute diff: {}", e))?;
+
+        let (diff, truncated) = patch_text(&diff)?;
+        Ok(json!({ "diff": diff, "truncated": truncated }))
+    }
+
+    fn git_show(&self, rev: &str, path: Option<&str>) -> Result<Value, String> {
+        let repo = self.get_repo()?;
+        let commit = repo.revparse_single(rev)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| format!("Failed to resolve '{}': {}", rev, e))?;
+        let tree = commit.tree().map_err(|e| format!("Failed to get tree: {}", e))?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree().map_err(|e| format!("Failed to get tree: {}", e))?),
+            Err(_) => None,
+        };
+
+        let mut opts = diff_options(&repo, path)?;
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut opts))
+            .map_err(|e| format!("Failed to compute diff: {}", e))?;
+        let stats = diff.stats().map_err(|e| format!("Failed to compute diff stats: {}", e))?;
+        let (patch, truncated) = patch_text(&diff)?;
+        let author = commit.author();
+
+        Ok(json!({
+            "id": commit.id().to_string(),
+            "parents": commit.parent_ids().map(|id| id.to_string()).collect::<Vec<_>>(),
+            "author": author.name().unwrap_or("Unknown"),
+            "email": author.email().unwrap_or(""),
+            "time": author.when().seconds(),
+            "message": commit.message().unwrap_or(""),
+            "files_changed": stats.files_changed(),
+            "insertions": stats.insertions(),
+            "deletions": stats.deletions(),
+            "diff": patch,
+            "truncated": truncated,
+        }))
+    }
+
+    /// Who last changed each line in `start_line..=end_line` of the working
+    /// copy. Lines changed since HEAD have no commit.
+    fn git_blame(&self, file: &str, start_line: usize, end_line: Option<usize>) -> Result<Value, String> {
+        let repo = self.get_repo()?;
+        let path = repo_path(&repo, file)?;
+        let content = std::fs::read(self.workspace_root.join(&path))
+            .map_err(|e| format!("Failed to read {}: {}", file, e))?;
+        let text = String::from_utf8_lossy(&content);
+        let total_lines = text.lines().count();
+        let start_line = start_line.max(1);
+        let end_line = end_line.unwrap_or(start_line + DEFAULT_BLAME_LINES - 1).min(total_lines);
+        if start_line > end_line {
+            return Err(format!("Line range {}-{} is outside the file ({} lines)", start_line, end_line, total_lines));
+        }
+
+        let committed = repo.blame_file(&path, Some(&mut BlameOptions::new()))
+            .map_err(|e| format!("Failed to blame {}: {}", file, e))?;
+        let blame = committed.blame_buffer(&content)
+            .map_err(|e| format!("Failed to blame {}: {}", file, e))?;
+
+        let mut commits: HashMap<String, Value> = HashMap::new();
+        let mut lines = Vec::new();
+        for (index, line) in text.lines().enumerate().take(end_line).skip(start_line - 1) {
+            let number = index + 1;
+            let oid = blame.get_line(number).map(|hunk| hunk.final_commit_id()).unwrap_or_else(Oid::zero);
+            let commit = if oid.is_zero() {
+                Value::Null
+            } else {
+                let id = short_id(oid);
+                if !commits.contains_key(&id) {
+                    let commit = repo.find_commit(oid).map_err(|e| format!("Failed to find commit: {}", e))?;
+                    commits.insert(id.clone(), json!({
+                        "author": commit.author().name().unwrap_or("Unknown"),
+                        "time": commit.time().seconds(),
+                        "summary": commit.summary().unwrap_or(""),
+                    }));
+                }
+                Value::String(id)
+            };
+            lines.push(json!({ "line": number, "commit": commit, "content": line }));
+        }
+
+        Ok(json!({
+            "path": file,
+            "start_line": start_line,
+            "end_line": end_line,
+            "total_lines": total_lines,
+            "lines": lines,
+            "commits": commits,
+        }))
+    }
+
+    fn git_branches(&self) -> Result<Value, String> {
+        let repo = self.get_repo()?;
+        let branches = repo.branches(Some(BranchType::Local))
+            .map_err(|e| format!("Failed to list branches: {}", e))?;
+
+        let mut list = Vec::new();
+        for branch in branches {
+            let (branch, _) = branch.map_err(|e| format!("Failed to read branch: {}", e))?;
+            let name = branch.name().ok().flatten().unwrap_or("").to_string();
+            let commit = branch.get().peel_to_commit().ok();
+            let upstream = branch.upstream().ok()
+                .and_then(|upstream| upstream.name().ok().flatten().map(String::from));
+            list.push(json!({
+                "name": name,
+                "current": branch.is_head(),
+                "commit": commit.as_ref().map(|c| short_id(c.id())),
+                "summary": commit.as_ref().and_then(|c| c.summary().map(String::from)),
+                "upstream": upstream,
+            }));
+        }
+
+        Ok(json!({ "branches": list }))
+    }
+
+    fn git_branch_create(&self, name: &str, start_point: &str) -> Result<String, String> {
+        let repo = self.get_repo()?;
+        let commit = repo.revparse_single(start_point)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| format!("Failed to resolve '{}': {}", start_point, e))?;
+        repo.branch(name, &commit, false)
+            .map_err(|e| format!("Failed to create branch {}: {}", name, e))?;
+        Ok(format!("Created branch '{}' at {}", name, short_id(commit.id())))
+    }
+
+    /// Check out a local branch. Refuses when local changes would be
+    /// overwritten, like `git switch`.
+    fn git_switch(&self, name: &str) -> Result<String, String> {
+        let repo = self.get_repo()?;
+        let branch = repo.find_branch(name, BranchType::Local)
+            .map_err(|e| format!("Branch {} not found: {}", name, e))?;
+        let reference = branch.get();
+        let refname = reference.name().ok_or("Branch name is not valid UTF-8")?;
+        let target = reference.peel(git2::ObjectType::Commit)
+            .map_err(|e| format!("Failed to resolve branch {}: {}", name, e))?;
+
+        repo.checkout_tree(&target, Some(CheckoutBuilder::new().safe()))
+            .map_err(|e| format!("Cannot switch to {}: {}. Commit or stash your changes first.", name, e))?;
+        repo.set_head(refname)
+            .map_err(|e| format!("Failed to update HEAD: {}", e))?;
+
+        Ok(format!("Switched to branch '{}'", name))
+    }
+
+    fn git_stash_push(&self, message: Option<&str>, include_untracked: bool) -> Result<String, String> {
+        let mut repo = self.get_repo()?;
+        let signature = signature(&repo)?;
+        let flags = include_untracked.then_some(git2::StashFlags::INCLUDE_UNTRACKED);
+        let oid = repo.stash_save2(&signature, message, flags)
+            .map_err(|e| format!("Failed to stash changes: {}", e.message()))?;
+        Ok(format!("Saved working directory changes as stash {}", short_id(oid)))
+    }
+
+    fn git_stash_pop(&self, index: usize) -> Result<String, String> {
+        let mut repo = self.get_repo()?;
+        repo.stash_pop(index, None)
+            .map_err(|e| format!("Failed to apply stash@{{{}}}: {}", index, e.message()))?;
+        Ok(format!("Applied and dropped stash@{{{}}}", index))
+    }
+
+    fn git_stash_list(&self) -> Result<Value, String> {
+        let mut repo = self.get_repo()?;
+        let mut stashes = Vec::new();
+        repo.stash_foreach(|index, message, oid| {
+            stashes.push(json!({ "index": index, "message": message, "id": short_id(*oid) }));
+            true
+        })
+        .map_err(|e| format!("Failed to list stashes: {}", e))?;
+        Ok(json!({ "stashes": stashes }))
+    }
+
+    /// Discard staged and unstaged changes to `files`, restoring them as they
+    /// are in HEAD
+    fn git_restore(&self, files: &[String]) -> Result<String, String> {
+        let repo = self.get_repo()?;
+        let head = repo.head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| format!("Failed to read HEAD: {}", e))?;
+        let tree = head.tree().map_err(|e| format!("Failed to get tree: {}", e))?;
+
+        let mut paths = Vec::new();
+        for file in files {
+            let path = repo_path(&repo, file)?;
+            tree.get_path(&path).map_err(|_| format!("{} does not exist in HEAD", file))?;
+            paths.push(path);
+        }
+
+        repo.reset_default(Some(head.as_object()), &paths)
+            .map_err(|e| format!("Failed to unstage changes: {}", e))?;
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force().disable_pathspec_match(true);
+        for path in &paths {
+            checkout.path(path);
+        }
+        repo.checkout_head(Some(&mut checkout))
+            .map_err(|e| format!("Failed to restore files: {}", e))?;
+
+        Ok(format!("Restored {} file(s) from HEAD", paths.len()))
+    }
+}
+
+fn open_repo(workspace_root: &Path) -> Result<Repository, String> {
+    Repository::open(workspace_root)
+        .map_err(|e| format!("Failed to open git repository: {}", e))
+}
+
+/// Branch, staged, unstaged, untracked and conflicted files of the
+/// repository at `workspace_root`
+pub fn status_summary(workspace_root: &Path) -> Result<Value, String> {
+    let repo = open_repo(workspace_root)?;
+    let mut status_opts = StatusOptions::new();
+    status_opts.include_untracked(true);
+
+    let statuses = repo.statuses(Some(&mut status_opts))
+        .map_err(|e| format!("Failed to get statuses: {}", e))?;
+
+    let mut staged = Vec::new();
+    let mut unstaged = Vec::new();
+    let mut untracked = Vec::new();
+    let mut conflicted = Vec::new();
+
+    for entry in statuses.iter() {
+        let path = entry.path().unwrap_or("unknown").to_string();
+        let status = entry.status();
+
+        if status.is_index_new() || status.is_index_modified() || status.is_index_deleted() || status.is_index_renamed() || status.is_index_typechange() {
+            staged.push(path);
+        } else if status.is_wt_modified() || status.is_wt_deleted() {
+            unstaged.push(path);
+        } else if status.is_wt_new() {
+            untracked.push(path);
+        } else if status.is_conflicted() {
+            conflicted.push(path);
         }
     }
+
+    // Get current branch
+    let head = repo.head().ok();
+    let branch_name = head.as_ref()
+        .and_then(|h| h.shorthand())
+        .unwrap_or("HEAD detached")
+        .to_string();
+
+    // Get latest commit message
+    let latest_commit = repo.head()
+        .ok()
+        .and_then(|h| h.target())
+        .and_then(|oid| repo.find_commit(oid).ok())
+        .map(|commit| commit.message().unwrap_or("").to_string());
+
+    Ok(json!({
+        "branch": branch_name,
+        "staged": staged,
+        "unstaged": unstaged,
+        "untracked": untracked,
+        "conflicted": conflicted,
+        "latest_commit": latest_commit,
+    }))
+}
+
+/// The user's configured identity, or the agent's when none is set
+fn signature(repo: &Repository) -> Result<Signature<'static>, String> {
+    repo.signature()
+        .or_else(|_| Signature::now("Anvil Agent", "agent@anvil.local"))
+        .map_err(|e| format!("Failed to create signature: {}", e))
+}
+
+/// `file` relative to the repository's working directory, which is how
+/// git2 expects paths. Paths leaving the repository are refused.
+fn repo_path(repo: &Repository, file: &str) -> Result<PathBuf, String> {
+    let workdir = repo.workdir().ok_or("Repository has no working directory")?;
+    let path = Path::new(file);
+    let relative = if path.is_absolute() {
+        let workdir = std::fs::canonicalize(workdir).unwrap_or_else(|_| workdir.to_path_buf());
+        let absolute = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        absolute.strip_prefix(&workdir)
+            .map(Path::to_path_buf)
+            .map_err(|_| format!("{} is outside the repository", file))?
+    } else {
+        path.to_path_buf()
+    };
+
+    let normalized = super::files::normalize_path(&relative);
+    if normalized.as_os_str().is_empty() || relative.components().any(|c| c == Component::ParentDir) {
+        return Err(format!("{} is not a file in the repository", file));
+    }
+    Ok(normalized)
+}
+
+fn tree_at<'r>(repo: &'r Repository, rev: &str) -> Result<Tree<'r>, String> {
+    repo.revparse_single(rev)
+        .and_then(|object| object.peel_to_tree())
+        .map_err(|e| format!("Failed to resolve '{}': {}", rev, e))
+}
+
+fn diff_options(repo: &Repository, path: Option<&str>) -> Result<DiffOptions, String> {
+    let mut opts = DiffOptions::new();
+    if let Some(path) = path {
+        opts.pathspec(repo_path(repo, path)?);
+    }
+    Ok(opts)
+}
+
+/// The diff as a unified patch, cut at `MAX_DIFF_BYTES`
+fn patch_text(diff: &Diff) -> Result<(String, bool), String> {
+    let mut text = String::new();
+    let mut truncated = false;
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        if truncated {
+            return true;
+        }
+        let content = String::from_utf8_lossy(line.content());
+        if text.len() + content.len() > MAX_DIFF_BYTES {
+            truncated = true;
+            return true;
+        }
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            text.push(line.origin());
+        }
+        text.push_str(&content);
+        true
+    })
+    .map_err(|e| format!("Failed to format diff: {}", e))?;
+    Ok((text, truncated))
+}
+
+fn short_id(oid: Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
+fn string_list(input: &Value, key: &str) -> Vec<String> {
+    input.get(key)
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+        .unwrap_or_default()
 }
 
 #[async_trait]
@@ -184,23 +530,31 @@ impl Tool for GitTool {
     fn schema(&self) -> Value {
         json!({
             "name": "git",
-            "description": "Execute git commands using git2 library (status, add, commit, log, diff)",
+            "description": "Work with the workspace's git repository. Read: status, log, diff, show, blame, branches, stash_list. Change: add, commit, branch_create. Commands that can discard work (switch, stash_push, stash_pop, restore) may need the user's approval.",
             "parameters": {
                 "type": "object",
                 "properties": {
                     "command": {
                         "type": "string",
-                        "enum": ["status", "add", "commit", "log", "diff"],
+                        "enum": [
+                            "status", "add", "commit", "log", "diff", "show", "blame",
+                            "branches", "branch_create", "switch",
+                            "stash_push", "stash_pop", "stash_list", "restore"
+                        ],
                         "description": "The git command to run"
                     },
                     "files": {
                         "type": "array",
                         "items": { "type": "string" },
-                        "description": "File paths for 'add' command"
+                        "description": "File paths for 'add' and 'restore'"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "File for 'blame', or limits 'diff' and 'show' to this path"
                     },
                     "message": {
                         "type": "string",
-                        "description": "Commit message for 'commit' command"
+                        "description": "Message for 'commit' (required) and 'stash_push'"
                     },
                     "count": {
                         "type": "integer",
@@ -208,7 +562,39 @@ impl Tool for GitTool {
                     },
                     "staged": {
                         "type": "boolean",
-                        "description": "Show staged changes for 'diff' command"
+                        "description": "For 'diff': compare against the index instead of the working tree"
+                    },
+                    "from": {
+                        "type": "string",
+                        "description": "For 'diff': revision to compare from, e.g. HEAD~3 or main"
+                    },
+                    "to": {
+                        "type": "string",
+                        "description": "For 'diff': revision to compare to. Omit to compare against the working tree"
+                    },
+                    "rev": {
+                        "type": "string",
+                        "description": "Commit for 'show' (default: HEAD), start point for 'branch_create' (default: HEAD)"
+                    },
+                    "name": {
+                        "type": "string",
+                        "description": "Branch name for 'branch_create' and 'switch'"
+                    },
+                    "start_line": {
+                        "type": "integer",
+                        "description": "First line for 'blame' (1-based, default: 1)"
+                    },
+                    "end_line": {
+                        "type": "integer",
+                        "description": "Last line for 'blame' (default: 200 lines from start_line)"
+                    },
+                    "index": {
+                        "type": "integer",
+                        "description": "Stash to pop for 'stash_pop' (default: 0, the latest)"
+                    },
+                    "include_untracked": {
+                        "type": "boolean",
+                        "description": "For 'stash_push': also stash untracked files"
                     }
                 },
                 "required": ["command"]
@@ -220,17 +606,24 @@ impl Tool for GitTool {
         let command = input.get("command")
             .and_then(|v| v.as_str())
             .ok_or("Missing 'command' parameter")?;
+        let str_param = |key: &str| input.get(key).and_then(|v| v.as_str()).filter(|s| !s.trim().is_empty());
+        let required = |key: &str| str_param(key).ok_or_else(|| format!("Missing '{}' parameter for '{}' command", key, command));
+
+        if DESTRUCTIVE_COMMANDS.contains(&command) {
+            let subject = match command {
+                "switch" => str_param("name").unwrap_or("").to_string(),
+                "restore" => string_list(&input, "files").join(" "),
+                _ => String::new(),
+            };
+            self.check_destructive(command, &subject).await?;
+        }
 
         match command {
             "status" => {
-                self.git_status()
+                status_summary(&self.workspace_root)
             }
             "add" => {
-                let files: Vec<String> = input.get("files")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
-                    .unwrap_or_default();
-
+                let files = string_list(&input, "files");
                 if files.is_empty() {
                     return Err("No files specified for 'add' command".to_string());
                 }
@@ -239,11 +632,7 @@ impl Tool for GitTool {
                     .map(|msg| json!({ "message": msg }))
             }
             "commit" => {
-                let message = input.get("message")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Anvil agent commit");
-
-                self.git_commit(message)
+                self.git_commit(required("message")?)
                     .map(|msg| json!({ "message": msg }))
             }
             "log" => {
@@ -258,10 +647,131 @@ impl Tool for GitTool {
                     .and_then(|v| v.as_bool())
                     .unwrap_or(false);
 
-                self.git_diff(staged)
-                    .map(|diff| json!({ "diff": diff }))
+                self.git_diff(staged, str_param("from"), str_param("to"), str_param("path"))
+            }
+            "show" => self.git_show(str_param("rev").unwrap_or("HEAD"), str_param("path")),
+            "blame" => {
+                let start_line = input.get("start_line").and_then(|v| v.as_u64()).unwrap_or(1) as usize;
+                let end_line = input.get("end_line").and_then(|v| v.as_u64()).map(|v| v as usize);
+                self.git_blame(required("path")?, start_line, end_line)
+            }
+            "branches" => self.git_branches(),
+            "branch_create" => {
+                self.git_branch_create(required("name")?, str_param("rev").unwrap_or("HEAD"))
+                    .map(|msg| json!({ "message": msg }))
+            }
+            "switch" => self.git_switch(required("name")?).map(|msg| json!({ "message": msg })),
+            "stash_push" => {
+                let include_untracked = input.get("include_untracked").and_then(|v| v.as_bool()).unwrap_or(false);
+                self.git_stash_push(str_param("message"), include_untracked)
+                    .map(|msg| json!({ "message": msg }))
+            }
+            "stash_pop" => {
+                let index = input.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                self.git_stash_pop(index).map(|msg| json!({ "message": msg }))
+            }
+            "stash_list" => self.git_stash_list(),
+            "restore" => {
+                let files = string_list(&input, "files");
+                if files.is_empty() {
+                    return Err("No files specified for 'restore' command".to_string());
+                }
+                self.git_restore(&files).map(|msg| json!({ "message": msg }))
             }
             _ => Err(format!("Unknown git command: {}", command)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::interaction::auto::{AutoPolicy, AutoResponder};
+
+    fn commit_all(repo: &Repository, message: &str) -> Oid {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.as_ref().into_iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents).unwrap()
+    }
+
+    fn tool(root: &Path, policy: AutoPolicy) -> GitTool {
+        GitTool::new(
+            root.to_path_buf(),
+            "s1".to_string(),
+            Arc::new(AutoResponder::new(policy)),
+            Arc::new(tokio::sync::Mutex::new(crate::config::PermissionConfig::default())),
+        )
+    }
+
+    #[tokio::test]
+    async fn diff_show_and_blame_use_revisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        let first = commit_all(&repo, "first");
+        std::fs::write(dir.path().join("a.txt"), "one\nTWO\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "b2\n").unwrap();
+        commit_all(&repo, "second");
+        std::fs::write(dir.path().join("a.txt"), "one\nTWO\nthree\n").unwrap();
+        let git = tool(dir.path(), AutoPolicy::DenyAll);
+
+        let range = git.execute(json!({ "command": "diff", "from": first.to_string(), "to": "HEAD", "path": "a.txt" })).await.unwrap();
+        let range = range["diff"].as_str().unwrap();
+        assert!(range.contains("-two\n+TWO\n") && !range.contains("b2"));
+
+        let worktree = git.execute(json!({ "command": "diff" })).await.unwrap();
+        assert!(worktree["diff"].as_str().unwrap().contains("+three\n"));
+
+        let show = git.execute(json!({ "command": "show" })).await.unwrap();
+        assert_eq!(show["message"], "second");
+        assert_eq!(show["files_changed"], 2);
+
+        let blame = git.execute(json!({ "command": "blame", "path": "a.txt", "start_line": 2 })).await.unwrap();
+        let lines = blame["lines"].as_array().unwrap();
+        assert_eq!(lines.len(), 2);
+        let second = lines[0]["commit"].as_str().unwrap();
+        assert_eq!(blame["commits"][second]["summary"], "second");
+        assert!(lines[1]["commit"].is_null());
+
+        assert!(git.execute(json!({ "command": "commit" })).await.unwrap_err().contains("'message'"));
+    }
+
+    #[tokio::test]
+    async fn destructive_commands_need_permission() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        commit_all(&repo, "first");
+        std::fs::write(dir.path().join("a.txt"), "changed\n").unwrap();
+
+        let denied = tool(dir.path(), AutoPolicy::DenyAll);
+        assert!(denied.execute(json!({ "command": "restore", "files": ["a.txt"] })).await.is_err());
+        assert_eq!(std::fs::read_to_string(dir.path().join("a.txt")).unwrap(), "changed\n");
+
+        let git = tool(dir.path(), AutoPolicy::Allow(vec!["git".to_string()]));
+        git.execute(json!({ "command": "stash_push", "message": "wip" })).await.unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("a.txt")).unwrap(), "one\n");
+        let stashes = git.execute(json!({ "command": "stash_list" })).await.unwrap();
+        assert_eq!(stashes["stashes"].as_array().unwrap().len(), 1);
+
+        git.execute(json!({ "command": "branch_create", "name": "feature" })).await.unwrap();
+        git.execute(json!({ "command": "switch", "name": "feature" })).await.unwrap();
+        let branches = git.execute(json!({ "command": "branches" })).await.unwrap();
+        let current: Vec<_> = branches["branches"].as_array().unwrap().iter().filter(|b| b["current"] == true).collect();
+        assert_eq!(current[0]["name"], "feature");
+
+        git.execute(json!({ "command": "stash_pop" })).await.unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("a.txt")).unwrap(), "changed\n");
+        git.execute(json!({ "command": "add", "files": ["a.txt"] })).await.unwrap();
+        git.execute(json!({ "command": "restore", "files": ["a.txt"] })).await.unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("a.txt")).unwrap(), "one\n");
+        let status = git.execute(json!({ "command": "status" })).await.unwrap();
+        assert!(status["staged"].as_array().unwrap().is_empty());
+    }
+}
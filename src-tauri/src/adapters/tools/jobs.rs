@@ -0,0 +1,421 @@
+use crate::adapters::tools::sandbox::Sandbox;
+use crate::adapters::tools::shell::kill_process_group;
+use crate::domain::models::ToolResult;
+use crate::domain::ports::Tool;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+
+/// Output lines kept per job; older lines are dropped
+const MAX_JOB_LINES: usize = 5000;
+/// Lines returned by `job_output` when no range is given
+const DEFAULT_TAIL_LINES: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    Running,
+    Exited(Option<i32>),
+    Killed,
+}
+
+impl JobState {
+    fn describe(&self) -> String {
+        match self {
+            JobState::Running => "running".to_string(),
+            JobState::Exited(Some(code)) => format!("exited with code {}", code),
+            JobState::Exited(None) => "terminated by a signal".to_string(),
+            JobState::Killed => "killed".to_string(),
+        }
+    }
+}
+
+/// Combined stdout and stderr of a job, capped at [`MAX_JOB_LINES`]
+#[derive(Debug, Default)]
+struct JobLog {
+    lines: VecDeque<String>,
+    /// Lines dropped from the front; line numbers stay stable across drops
+    dropped: usize,
+}
+
+impl JobLog {
+    fn push(&mut self, line: String) {
+        self.lines.push_back(line);
+        while self.lines.len() > MAX_JOB_LINES {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.dropped + self.lines.len()
+    }
+
+    /// Lines from line number `since`, or the last `tail` lines
+    fn read(&self, since: Option<usize>, tail: usize) -> (usize, Vec<String>) {
+        let start = match since {
+            Some(since) => since.max(self.dropped),
+            None => self.total().saturating_sub(tail).max(self.dropped),
+        };
+        let lines = self.lines.iter().skip(start - self.dropped).cloned().collect();
+        (start, lines)
+    }
+}
+
+struct Job {
+    id: String,
+    command: String,
+    cwd: PathBuf,
+    pid: Option<u32>,
+    sandboxed: bool,
+    started: Instant,
+    state: Mutex<JobState>,
+    log: Mutex<JobLog>,
+}
+
+impl Job {
+    fn state(&self) -> JobState {
+        self.state.lock().map(|s| s.clone()).unwrap_or(JobState::Killed)
+    }
+
+    fn summary(&self) -> Value {
+        json!({
+            "job_id": self.id,
+            "command": self.command,
+            "cwd": self.cwd.to_string_lossy(),
+            "pid": self.pid,
+            "sandboxed": self.sandboxed,
+            "status": self.state().describe(),
+            "running": self.state() == JobState::Running,
+            "elapsed_secs": self.started.elapsed().as_secs(),
+            "output_lines": self.log.lock().map(|l| l.total()).unwrap_or(0),
+        })
+    }
+}
+
+/// Background processes of one session, such as dev servers or watchers.
+/// Every job leads its own process group; all of them are killed when the
+/// manager is dropped with the session.
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start `command` in `cwd`, inside `sandbox` when given, and return
+    /// the job id right away
+    pub fn start(&self, command: &str, cwd: &Path, sandbox: Option<&Sandbox>) -> Result<Value, String> {
+        let mut process = match sandbox {
+            Some(sandbox) => sandbox.command("sh", &["-c", command], cwd),
+            None => {
+                let mut process = Command::new("sh");
+                process.arg("-c").arg(command);
+                process
+            }
+        };
+        process
+            .current_dir(cwd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        process.process_group(0);
+
+        let mut child = process
+            .spawn()
+            .map_err(|e| format!("Failed to start background job: {}", e))?;
+
+        let id = {
+            let jobs = self.jobs.lock().map_err(|_| "Failed to lock jobs")?;
+            (1..).map(|n| format!("job-{}", n)).find(|id| !jobs.contains_key(id)).unwrap_or_default()
+        };
+        let job = Arc::new(Job {
+            id: id.clone(),
+            command: command.to_string(),
+            cwd: cwd.to_path_buf(),
+            pid: child.id(),
+            sandboxed: sandbox.is_some(),
+            started: Instant::now(),
+            state: Mutex::new(JobState::Running),
+            log: Mutex::new(JobLog::default()),
+        });
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(collect_lines(stdout, job.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(collect_lines(stderr, job.clone()));
+        }
+        let waiter = job.clone();
+        tokio::spawn(async move {
+            let status = child.wait().await;
+            if let Ok(mut state) = waiter.state.lock() {
+                if *state == JobState::Running {
+                    *state = JobState::Exited(status.ok().and_then(|s| s.code()));
+                }
+            }
+        });
+
+        let summary = job.summary();
+        self.jobs.lock().map_err(|_| "Failed to lock jobs")?.insert(id, job);
+        Ok(summary)
+    }
+
+    fn get(&self, id: &str) -> Result<Arc<Job>, String> {
+        let jobs = self.jobs.lock().map_err(|_| "Failed to lock jobs")?;
+        jobs.get(id)
+            .cloned()
+            .ok_or_else(|| format!("No background job '{}'", id))
+    }
+
+    pub fn status(&self, id: Option<&str>) -> Result<Value, String> {
+        match id {
+            Some(id) => Ok(self.get(id)?.summary()),
+            None => {
+                let jobs = self.jobs.lock().map_err(|_| "Failed to lock jobs")?;
+                let mut list: Vec<&Arc<Job>> = jobs.values().collect();
+                list.sort_by_key(|job| job.started);
+                Ok(json!({ "jobs": list.iter().map(|job| job.summary()).collect::<Vec<_>>() }))
+            }
+        }
+    }
+
+    pub fn output(&self, id: &str, since: Option<usize>, tail: Option<usize>) -> Result<Value, String> {
+        let job = self.get(id)?;
+        let log = job.log.lock().map_err(|_| "Failed to lock job output")?;
+        let (start, lines) = log.read(since, tail.unwrap_or(DEFAULT_TAIL_LINES));
+        Ok(json!({
+            "job_id": id,
+            "status": job.state().describe(),
+            "from_line": start,
+            "next_line": log.total(),
+            "output": lines.concat(),
+        }))
+    }
+
+    pub fn kill(&self, id: &str) -> Result<Value, String> {
+        let job = self.get(id)?;
+        if job.state() == JobState::Running {
+            if let Some(pid) = job.pid {
+                kill_process_group(pid);
+            }
+            if let Ok(mut state) = job.state.lock() {
+                *state = JobState::Killed;
+            }
+        }
+        Ok(job.summary())
+    }
+}
+
+impl Drop for JobManager {
+    fn drop(&mut self) {
+        if let Ok(jobs) = self.jobs.lock() {
+            for job in jobs.values() {
+                if let (JobState::Running, Some(pid)) = (job.state(), job.pid) {
+                    kill_process_group(pid);
+                }
+            }
+        }
+    }
+}
+
+async fn collect_lines<R: AsyncRead + Unpin>(reader: R, job: Arc<Job>) {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                if let Ok(mut log) = job.log.lock() {
+                    log.push(String::from_utf8_lossy(&line).to_string());
+                }
+            }
+        }
+    }
+}
+
+fn job_id(input: &Value) -> Result<&str, String> {
+    input
+        .get("job_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| "Missing 'job_id' parameter".to_string())
+}
+
+pub struct JobStatusTool {
+    jobs: Arc<JobManager>,
+}
+
+impl JobStatusTool {
+    pub fn new(jobs: Arc<JobManager>) -> Self {
+        Self { jobs }
+    }
+}
+
+#[async_trait]
+impl Tool for JobStatusTool {
+    fn name(&self) -> &'static str {
+        "job_status"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "name": "job_status",
+            "description": "Show the state of background jobs started with bash (background: true). Without job_id, lists every job of this session.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "job_id": {
+                        "type": "string",
+                        "description": "Job to inspect"
+                    }
+                }
+            }
+        })
+    }
+
+    async fn execute(&self, input: Value) -> ToolResult {
+        self.jobs.status(input.get("job_id").and_then(|v| v.as_str()))
+    }
+}
+
+pub struct JobOutputTool {
+    jobs: Arc<JobManager>,
+}
+
+impl JobOutputTool {
+    pub fn new(jobs: Arc<JobManager>) -> Self {
+        Self { jobs }
+    }
+}
+
+#[async_trait]
+impl Tool for JobOutputTool {
+    fn name(&self) -> &'static str {
+        "job_output"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "name": "job_output",
+            "description": "Read the combined stdout and stderr of a background job. Pass the returned next_line as since to get only new output.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "job_id": {
+                        "type": "string",
+                        "description": "Job to read"
+                    },
+                    "since": {
+                        "type": "integer",
+                        "description": "First line number to return"
+                    },
+                    "tail": {
+                        "type": "integer",
+                        "description": "Without since, how many of the last lines to return (default: 100)"
+                    }
+                },
+                "required": ["job_id"]
+            }
+        })
+    }
+
+    async fn execute(&self, input: Value) -> ToolResult {
+        let since = input.get("since").and_then(|v| v.as_u64()).map(|n| n as usize);
+        let tail = input.get("tail").and_then(|v| v.as_u64()).map(|n| n as usize);
+        self.jobs.output(job_id(&input)?, since, tail)
+    }
+}
+
+pub struct JobKillTool {
+    jobs: Arc<JobManager>,
+}
+
+impl JobKillTool {
+    pub fn new(jobs: Arc<JobManager>) -> Self {
+        Self { jobs }
+    }
+}
+
+#[async_trait]
+impl Tool for JobKillTool {
+    fn name(&self) -> &'static str {
+        "job_kill"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "name": "job_kill",
+            "description": "Stop a background job and all processes it started.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "job_id": {
+                        "type": "string",
+                        "description": "Job to stop"
+                    }
+                },
+                "required": ["job_id"]
+            }
+        })
+    }
+
+    async fn execute(&self, input: Value) -> ToolResult {
+        self.jobs.kill(job_id(&input)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_job_log_cursor() {
+        let mut log = JobLog::default();
+        for i in 0..MAX_JOB_LINES + 10 {
+            log.push(format!("{}\n", i));
+        }
+        assert_eq!(log.total(), MAX_JOB_LINES + 10);
+
+        let (start, lines) = log.read(None, 2);
+        assert_eq!(start, MAX_JOB_LINES + 8);
+        assert_eq!(lines.concat(), format!("{}\n{}\n", MAX_JOB_LINES + 8, MAX_JOB_LINES + 9));
+        // Dropped lines cannot be read any more
+        assert_eq!(log.read(Some(0), 2).0, 10);
+    }
+
+    #[tokio::test]
+    async fn test_background_job_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = JobManager::new();
+
+        let started = jobs.start("echo ready; sleep 30", dir.path(), None).unwrap();
+        let id = started["job_id"].as_str().unwrap().to_string();
+        assert_eq!(started["running"], true);
+
+        let mut output = Value::Null;
+        for _ in 0..50 {
+            output = jobs.output(&id, Some(0), None).unwrap();
+            if output["next_line"] == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(output["output"], "ready\n");
+
+        assert_eq!(jobs.kill(&id).unwrap()["status"], "killed");
+        assert_eq!(jobs.status(None).unwrap()["jobs"].as_array().unwrap().len(), 1);
+        assert!(jobs.output("job-9", None, None).is_err());
+    }
+}
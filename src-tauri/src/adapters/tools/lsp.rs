@@ -1,19 +1,20 @@
-use crate::domain::ports::Tool;
-use crate::domain::models::ToolResult;
+use crate::adapters::tools::read_tracker::ReadTracker;
+use crate::adapters::tools::registry::ToolContext;
+use crate::adapters::tools::transaction::EditTransaction;
+use crate::domain::checkpoint::CheckpointStore;
+use crate::domain::ports::{InteractionProvider, Tool};
+use crate::domain::models::{ConfirmationRequest, ToolResult};
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::collections::HashMap;
+use crate::lsp::client::path_to_uri;
+use crate::lsp::edits::{apply_text_edits, uri_to_path, workspace_edit_changes, FileChange};
+use crate::lsp::servers::{server_for, server_named};
+use crate::lsp::{infer_language_id, resolve_servers, LspClient, LspPool};
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
-use std::sync::Mutex;
-use tauri::{AppHandle, Emitter};
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::process::Command;
-use tokio::time::{timeout, Duration};
-use tokio::sync::oneshot;
-use url::Url;
-use uuid::Uuid;
+use std::sync::Arc;
+use tokio::time::Duration;
 
 fn expand_tilde(input: &str) -> String {
     if input.starts_with("~") {
@@ -114,20 +115,26 @@ pub struct LspTool {
     pub permission_manager: std::sync::Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
     pub lsp_config: Option<crate::config::LspConfig>,
     pub session_id: String,
-    pub app: AppHandle,
-    pub pending_confirmations: std::sync::Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+    pub interaction: std::sync::Arc<dyn InteractionProvider>,
+    pub pool: Arc<LspPool>,
+    pub checkpoints: Option<Arc<CheckpointStore>>,
+    pub read_tracker: Arc<ReadTracker>,
 }
 
 impl LspTool {
-    pub fn new(
-        workspace_root: PathBuf,
-        permission_manager: std::sync::Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
-        lsp_config: Option<crate::config::LspConfig>,
-        session_id: String,
-        app: AppHandle,
-        pending_confirmations: std::sync::Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
-    ) -> Self {
-        Self { workspace_root, permission_manager, lsp_config, session_id, app, pending_confirmations }
+    /// `lsp` tool for `session_id` with the workspace, permissions and
+    /// language servers of `context`
+    pub fn new(context: &ToolContext, session_id: String, read_tracker: Arc<ReadTracker>) -> Self {
+        Self {
+            workspace_root: context.workspace_root.clone(),
+            permission_manager: context.permission_manager.clone(),
+            lsp_config: context.lsp_config.clone(),
+            session_id,
+            interaction: context.interaction.clone(),
+            pool: context.lsp.clone(),
+            checkpoints: context.checkpoints.clone(),
+            read_tracker,
+        }
     }
 
     async fn request_confirmation(
@@ -135,41 +142,11 @@ impl LspTool {
         input: &str,
         suggested_pattern: String,
     ) -> Result<crate::domain::models::ConfirmationResponse, String> {
-        let request_id = Uuid::new_v4().to_string();
-        let (tx, rx) = oneshot::channel();
-
-        {
-            let mut map = self.pending_confirmations.lock().unwrap();
-            map.insert(request_id.clone(), tx);
-        }
-
-        let event = PermissionConfirmationRequest {
-            id: request_id.clone(),
-            session_id: self.session_id.clone(),
-            type_: "permission".to_string(),
-            tool_name: "lsp".to_string(),
-            input: input.to_string(),
-            suggested_pattern,
-        };
-
-        self.app.emit("request-confirmation", &event)
-            .map_err(|e| format!("Failed to emit confirmation event: {}", e))?;
-
-        rx.await.map_err(|_| "Confirmation channel closed without response".to_string())
+        let request = ConfirmationRequest::permission(&self.session_id, "permission", "lsp", input, suggested_pattern);
+        self.interaction.confirm(request).await
     }
 }
 
-#[derive(Serialize, Clone)]
-struct PermissionConfirmationRequest {
-    id: String,
-    session_id: String,
-    #[serde(rename = "type")]
-    type_: String,
-    tool_name: String,
-    input: String,
-    suggested_pattern: String,
-}
-
 #[derive(Debug, Deserialize)]
 struct LspInput {
     path: String,
@@ -179,15 +156,46 @@ struct LspInput {
     #[serde(default)]
     character: Option<u64>,
     #[serde(default)]
+    end_line: Option<u64>,
+    #[serde(default)]
+    end_character: Option<u64>,
+    #[serde(default)]
     language_id: Option<String>,
     #[serde(default)]
     server: Option<String>,
     #[serde(default)]
     include_declaration: Option<bool>,
     #[serde(default)]
+    new_name: Option<String>,
+    #[serde(default)]
+    query: Option<String>,
+    #[serde(default)]
+    apply: Option<usize>,
+    #[serde(default)]
+    tab_size: Option<u64>,
+    #[serde(default)]
+    insert_spaces: Option<bool>,
+    #[serde(default)]
     timeout_ms: Option<u64>,
+    #[serde(default)]
+    allow_unread: bool,
 }
 
+const REQUESTS: &[&str] = &[
+    "definition",
+    "references",
+    "diagnostics",
+    "hover",
+    "document_symbols",
+    "workspace_symbols",
+    "implementation",
+    "incoming_calls",
+    "outgoing_calls",
+    "rename",
+    "code_actions",
+    "format",
+];
+
 #[async_trait]
 impl Tool for LspTool {
     fn name(&self) -> &'static str {
@@ -197,17 +205,17 @@ impl Tool for LspTool {
     fn schema(&self) -> Value {
         json!({
             "name": "lsp",
-            "description": "Query an LSP server for definitions, references, or diagnostics.",
+            "description": "Ask the language server about code: definition, references, implementation, hover (type info), diagnostics, document_symbols, workspace_symbols (search by name), incoming_calls and outgoing_calls. It can also change code semantically: rename a symbol across the workspace, list code_actions at a position and apply one by index, and format a file. Changes go through the same review as file edits.",
             "parameters": {
                 "type": "object",
                 "properties": {
                     "path": {
                         "type": "string",
-                        "description": "Path to the file relative to workspace root"
+                        "description": "Path to the file relative to workspace root; for workspace_symbols any file in the language to search"
                     },
                     "request": {
                         "type": "string",
-                        "enum": ["definition", "references", "diagnostics"],
+                        "enum": REQUESTS,
                         "description": "LSP request type"
                     },
                     "line": {
@@ -218,21 +226,53 @@ impl Tool for LspTool {
                         "type": "integer",
                         "description": "0-based character offset"
                     },
+                    "end_line": {
+                        "type": "integer",
+                        "description": "0-based end line of the range for code_actions (default: line)"
+                    },
+                    "end_character": {
+                        "type": "integer",
+                        "description": "0-based end character of the range for code_actions (default: character)"
+                    },
                     "language_id": {
                         "type": "string",
                         "description": "Optional languageId override (e.g. rust, typescript)"
                     },
                     "server": {
                         "type": "string",
-                        "description": "Optional LSP server command to use"
+                        "description": "Language (e.g. rust) or server command to use instead of the one for the file type"
                     },
                     "include_declaration": {
                         "type": "boolean",
                         "description": "Include declaration in references request"
                     },
+                    "new_name": {
+                        "type": "string",
+                        "description": "New name for rename"
+                    },
+                    "query": {
+                        "type": "string",
+                        "description": "Symbol name or prefix for workspace_symbols"
+                    },
+                    "apply": {
+                        "type": "integer",
+                        "description": "Index of the code action to apply, from a previous code_actions listing"
+                    },
+                    "tab_size": {
+                        "type": "integer",
+                        "description": "Indent width for format (default: 4)"
+                    },
+                    "insert_spaces": {
+                        "type": "boolean",
+                        "description": "Indent with spaces for format (default: true)"
+                    },
                     "timeout_ms": {
                         "type": "integer",
                         "description": "Timeout in milliseconds for LSP response"
+                    },
+                    "allow_unread": {
+                        "type": "boolean",
+                        "description": "Let rename, format and code actions change files that have not been read in this session (default: false)"
                     }
                 },
                 "required": ["path", "request"]
@@ -243,26 +283,15 @@ impl Tool for LspTool {
     async fn execute(&self, input: Value) -> ToolResult {
         let args: LspInput = serde_json::from_value(input)
             .map_err(|e| format!("Invalid LSP input: {}", e))?;
+        if !REQUESTS.contains(&args.request.as_str()) {
+            return Err(format!("Unsupported LSP request. Use one of: {}", REQUESTS.join(", ")));
+        }
 
-        let config = self.lsp_config.clone().ok_or("LSP not configured. Set lsp.enabled and lsp.servers in .anvil/anvil.json".to_string())?;
+        let config = self.lsp_config.clone().unwrap_or_default();
         if config.enabled == Some(false) {
             return Err("LSP is disabled. Enable lsp.enabled in .anvil/anvil.json".to_string());
         }
-
-        let servers = config.servers.unwrap_or_default();
-        if servers.is_empty() {
-            return Err("No LSP servers configured. Set lsp.servers in .anvil/anvil.json".to_string());
-        }
-
-        let server_command = if let Some(server) = args.server.as_ref() {
-            if servers.iter().any(|entry| entry == server) {
-                server.clone()
-            } else {
-                return Err(format!("Requested LSP server not found in config: {}", server));
-            }
-        } else {
-            servers[0].clone()
-        };
+        let specs = resolve_servers(Some(&config));
 
         let path = resolve_target_path(&self.workspace_root, &args.path)?;
 
@@ -316,30 +345,29 @@ impl Tool for LspTool {
             crate::config::Action::Allow => {}
         }
 
-        let file_content = tokio::fs::read_to_string(&path).await
-            .map_err(|e| format!("Failed to read file: {}", e))?;
+        if !path.is_file() {
+            return Err(format!("File not found: {}", path.display()));
+        }
 
-        let language_id = args.language_id
+        let language_id = args.language_id.clone()
             .or_else(|| infer_language_id(&path).map(|id| id.to_string()))
             .unwrap_or_else(|| "plaintext".to_string());
 
-        let line = args.line.unwrap_or(0);
-        let character = args.character.unwrap_or(0);
-        let include_declaration = args.include_declaration.unwrap_or(false);
-        let timeout_ms = args.timeout_ms.unwrap_or(4000);
-
-        let result = run_lsp_request(
-            &server_command,
-            &self.workspace_root,
-            &path,
-            &language_id,
-            &file_content,
-            &args.request,
-            line,
-            character,
-            include_declaration,
-            Duration::from_millis(timeout_ms),
-        ).await?;
+        let spec = match args.server.as_deref() {
+            Some(name) => server_named(&specs, name)
+                .ok_or_else(|| format!("Requested LSP server not found in config: {}", name))?,
+            None => server_for(&specs, &path)?,
+        };
+        let server_command = spec.command_line();
+        let root = spec.project_root(&path, &self.workspace_root);
+        let client = self.pool.client(&self.workspace_root, &root, spec).await?;
+        if let Some(capability) = required_capability(&args.request) {
+            let supported = client.capabilities().get(capability).is_some_and(|c| !c.is_null() && c != &json!(false));
+            if client.capabilities().is_object() && !supported {
+                return Err(format!("LSP server `{}` does not support {}", server_command, args.request));
+            }
+        }
+        let result = self.run_request(&client, &path, &language_id, &args).await?;
 
         Ok(json!({
             "server": server_command,
@@ -367,311 +395,422 @@ fn resolve_target_path(workspace_root: &Path, input_path: &str) -> Result<PathBu
     }
 }
 
-fn path_to_uri(path: &Path) -> Result<String, String> {
-    let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
-    Url::from_file_path(canonical)
-        .map(|url| url.to_string())
-        .map_err(|_| "Failed to convert file path to URI".to_string())
-}
+impl LspTool {
+    async fn run_request(
+        &self,
+        client: &LspClient,
+        file_path: &Path,
+        language_id: &str,
+        args: &LspInput,
+    ) -> Result<Value, String> {
+        let text_document = json!({ "uri": path_to_uri(file_path)? });
+        let line = args.line.unwrap_or(0);
+        let character = args.character.unwrap_or(0);
+        let position = json!({ "line": line, "character": character });
+        let timeout_duration = Duration::from_millis(args.timeout_ms.unwrap_or(4000));
 
-fn parse_server_command(command: &str) -> Result<(String, Vec<String>), String> {
-    let mut parts = command.split_whitespace();
-    let program = parts.next().ok_or("LSP server command is empty")?.to_string();
-    let args = parts.map(|part| part.to_string()).collect::<Vec<_>>();
-    Ok((program, args))
-}
+        if args.request == "diagnostics" {
+            return client.diagnostics(file_path, language_id, timeout_duration).await;
+        }
+        client.sync_document(file_path, Some(language_id)).await?;
+        let at_position = json!({ "textDocument": text_document, "position": position });
+
+        match args.request.as_str() {
+            "definition" => request_with_retry(client, "textDocument/definition", at_position, timeout_duration).await,
+            "implementation" => request_with_retry(client, "textDocument/implementation", at_position, timeout_duration).await,
+            "references" => {
+                let mut params = at_position;
+                params["context"] = json!({ "includeDeclaration": args.include_declaration.unwrap_or(false) });
+                request_with_retry(client, "textDocument/references", params, timeout_duration).await
+            }
+            "hover" => {
+                let hover = request_with_retry(client, "textDocument/hover", at_position, timeout_duration).await?;
+                if hover.is_null() {
+                    return Ok(json!("No hover information at this position"));
+                }
+                Ok(json!({
+                    "contents": hover_text(hover.get("contents").unwrap_or(&Value::Null)),
+                    "range": hover.get("range"),
+                }))
+            }
+            "document_symbols" => {
+                let symbols = request_with_retry(
+                    client,
+                    "textDocument/documentSymbol",
+                    json!({ "textDocument": text_document }),
+                    timeout_duration,
+                ).await?;
+                Ok(self.symbols_summary(&symbols))
+            }
+            "workspace_symbols" => {
+                let query = args.query.clone().unwrap_or_default();
+                let symbols = request_with_retry(client, "workspace/symbol", json!({ "query": query }), timeout_duration).await?;
+                Ok(self.symbols_summary(&symbols))
+            }
+            "incoming_calls" | "outgoing_calls" => {
+                let (method, end) = match args.request.as_str() {
+                    "incoming_calls" => ("callHierarchy/incomingCalls", "from"),
+                    _ => ("callHierarchy/outgoingCalls", "to"),
+                };
+                let items = request_with_retry(client, "textDocument/prepareCallHierarchy", at_position, timeout_duration).await?;
+                let mut hierarchy = Vec::new();
+                for item in items.as_array().cloned().unwrap_or_default() {
+                    let calls = client.request(method, json!({ "item": item }), timeout_duration).await?;
+                    let calls: Vec<Value> = calls
+                        .as_array()
+                        .map(|calls| {
+                            calls
+                                .iter()
+                                .filter_map(|call| call.get(end))
+                                .map(|target| self.symbol_summary(target))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    hierarchy.push(json!({ "symbol": self.symbol_summary(&item), "calls": calls }));
+                }
+                Ok(Value::Array(hierarchy))
+            }
+            "rename" => {
+                let new_name = args
+                    .new_name
+                    .as_deref()
+                    .filter(|name| !name.trim().is_empty())
+                    .ok_or("rename needs new_name")?;
+                let mut params = at_position;
+                params["newName"] = json!(new_name);
+                let edit = client.request("textDocument/rename", params, timeout_duration).await?;
+                if edit.is_null() {
+                    return Err("Nothing to rename at this position".to_string());
+                }
+                self.apply_changes(workspace_edit_changes(&edit)?, args.allow_unread).await
+            }
+            "code_actions" => self.code_actions(client, file_path, language_id, args, timeout_duration).await,
+            "format" => {
+                let options = json!({
+                    "tabSize": args.tab_size.unwrap_or(4),
+                    "insertSpaces": args.insert_spaces.unwrap_or(true),
+                });
+                let edits = client
+                    .request("textDocument/formatting", json!({ "textDocument": text_document, "options": options }), timeout_duration)
+                    .await?;
+                let edits = edits.as_array().cloned().unwrap_or_default();
+                if edits.is_empty() {
+                    return Ok(json!({ "status": "success", "message": "Already formatted" }));
+                }
+                self.apply_changes(vec![FileChange::Edit { path: file_path.to_path_buf(), edits }], args.allow_unread)
+                    .await
+            }
+            other => Err(format!("Unsupported LSP request: {}", other)),
+        }
+    }
 
-fn infer_language_id(path: &Path) -> Option<&'static str> {
-    match path.extension().and_then(|ext| ext.to_str()) {
-        Some("rs") => Some("rust"),
-        Some("ts") => Some("typescript"),
-        Some("tsx") => Some("typescriptreact"),
-        Some("js") => Some("javascript"),
-        Some("jsx") => Some("javascriptreact"),
-        Some("py") => Some("python"),
-        Some("go") => Some("go"),
-        Some("java") => Some("java"),
-        Some("c") => Some("c"),
-        Some("h") => Some("c"),
-        Some("cpp") => Some("cpp"),
-        Some("hpp") => Some("cpp"),
-        Some("json") => Some("json"),
-        Some("yaml") | Some("yml") => Some("yaml"),
-        Some("md") => Some("markdown"),
-        _ => None,
+    /// List the code actions for a range, or apply the one at `args.apply`
+    async fn code_actions(
+        &self,
+        client: &LspClient,
+        file_path: &Path,
+        language_id: &str,
+        args: &LspInput,
+        timeout_duration: Duration,
+    ) -> Result<Value, String> {
+        let start_line = args.line.unwrap_or(0);
+        let end_line = args.end_line.unwrap_or(start_line);
+        let range = json!({
+            "start": { "line": start_line, "character": args.character.unwrap_or(0) },
+            "end": { "line": end_line, "character": args.end_character.or(args.character).unwrap_or(0) },
+        });
+        // Quick fixes are offered for the diagnostics they fix
+        let diagnostics: Vec<Value> = client
+            .diagnostics(file_path, language_id, timeout_duration)
+            .await?
+            .as_array()
+            .map(|all| {
+                all.iter()
+                    .filter(|d| {
+                        let first = d.pointer("/range/start/line").and_then(|l| l.as_u64()).unwrap_or(0);
+                        let last = d.pointer("/range/end/line").and_then(|l| l.as_u64()).unwrap_or(first);
+                        first <= end_line && last >= start_line
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let actions = client
+            .request(
+                "textDocument/codeAction",
+                json!({
+                    "textDocument": { "uri": path_to_uri(file_path)? },
+                    "range": range,
+                    "context": { "diagnostics": diagnostics, "triggerKind": 1 }
+                }),
+                timeout_duration,
+            )
+            .await?;
+        let actions = actions.as_array().cloned().unwrap_or_default();
+
+        let Some(index) = args.apply else {
+            let listing: Vec<Value> = actions
+                .iter()
+                .enumerate()
+                .map(|(index, action)| {
+                    json!({
+                        "index": index,
+                        "title": action.get("title"),
+                        "kind": action.get("kind"),
+                        "preferred": action.get("isPreferred").and_then(|p| p.as_bool()).unwrap_or(false),
+                    })
+                })
+                .collect();
+            return Ok(Value::Array(listing));
+        };
+
+        let mut action = actions
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("No code action with index {}; {} available", index, actions.len()))?;
+        let title = action.get("title").and_then(|t| t.as_str()).unwrap_or("code action").to_string();
+        let resolvable = client
+            .capabilities()
+            .pointer("/codeActionProvider/resolveProvider")
+            .and_then(|r| r.as_bool())
+            .unwrap_or(false);
+        if action.get("edit").is_none() && resolvable && !action.get("command").is_some_and(|c| c.is_string()) {
+            action = client.request("codeAction/resolve", action, timeout_duration).await?;
+        }
+
+        match action.get("edit") {
+            Some(edit) => self.apply_changes(workspace_edit_changes(edit)?, args.allow_unread).await,
+            None => Err(format!(
+                "'{}' runs a server command instead of returning an edit and cannot be applied here",
+                title
+            )),
+        }
     }
-}
 
-async fn write_message(writer: &mut tokio::process::ChildStdin, value: &Value) -> Result<(), String> {
-    let payload = value.to_string();
-    let header = format!("Content-Length: {}\r\n\r\n", payload.as_bytes().len());
-    writer.write_all(header.as_bytes()).await.map_err(|e| e.to_string())?;
-    writer.write_all(payload.as_bytes()).await.map_err(|e| e.to_string())?;
-    writer.flush().await.map_err(|e| e.to_string())?;
-    Ok(())
-}
+    /// Apply server edits like `multi_edit` does: validated together,
+    /// reviewed unless edit rules allow every file, written all or nothing
+    async fn apply_changes(&self, changes: Vec<FileChange>, allow_unread: bool) -> Result<Value, String> {
+        let mut transaction = EditTransaction::new();
+        let mut needs_confirmation = false;
+        for change in changes {
+            match change {
+                FileChange::Edit { path, edits } => {
+                    let label = self.label(&path);
+                    needs_confirmation |= self.check_editable(&path, &label, allow_unread).await?;
+                    let current = transaction
+                        .read(&path)?
+                        .ok_or_else(|| format!("File does not exist: {}", label))?;
+                    transaction.stage(path, &label, apply_text_edits(&current, &edits)?)?;
+                }
+                FileChange::Create { path, overwrite } => {
+                    let label = self.label(&path);
+                    needs_confirmation |= self.check_editable(&path, &label, allow_unread).await?;
+                    if overwrite || !transaction.exists(&path)? {
+                        transaction.stage(path, &label, String::new())?;
+                    }
+                }
+                FileChange::Rename { from, to, overwrite } => {
+                    let (from_label, to_label) = (self.label(&from), self.label(&to));
+                    needs_confirmation |= self.check_editable(&from, &from_label, allow_unread).await?;
+                    needs_confirmation |= self.check_editable(&to, &to_label, allow_unread).await?;
+                    let content = transaction
+                        .read(&from)?
+                        .ok_or_else(|| format!("File does not exist: {}", from_label))?;
+                    if !overwrite && transaction.exists(&to)? {
+                        return Err(format!("Cannot rename {}: {} already exists", from_label, to_label));
+                    }
+                    transaction.stage(to, &to_label, content)?;
+                    transaction.stage_delete(from, &from_label)?;
+                }
+                FileChange::Delete { path } => {
+                    let label = self.label(&path);
+                    needs_confirmation |= self.check_editable(&path, &label, allow_unread).await?;
+                    transaction.stage_delete(path, &label)?;
+                }
+            }
+        }
+        if transaction.is_empty() {
+            return Ok(json!({ "status": "success", "message": "The server returned no changes" }));
+        }
 
-async fn read_message(reader: &mut BufReader<tokio::process::ChildStdout>) -> Result<Value, String> {
-    let mut content_length: Option<usize> = None;
-    loop {
-        let mut line = String::new();
-        let bytes = reader.read_line(&mut line).await.map_err(|e| e.to_string())?;
-        if bytes == 0 {
-            return Err("LSP server closed the connection".to_string());
+        let labels = transaction.labels();
+        if needs_confirmation {
+            let request = transaction.confirmation(&self.session_id, "lsp");
+            let response = self.interaction.confirm(request).await?;
+            if !response.allowed {
+                return Err("User denied the change. No files were changed.".to_string());
+            }
+            if response.always {
+                let mut config = self.permission_manager.lock().await;
+                for label in &labels {
+                    add_allow_rule_with_variants(&mut config.edit.rules, label.clone(), label, &self.workspace_root);
+                }
+            }
         }
-        let trimmed = line.trim_end();
-        if trimmed.is_empty() {
-            break;
+
+        let paths = transaction.paths();
+        transaction.commit(self.checkpoints.as_deref())?;
+        for path in &paths {
+            self.read_tracker.record(path);
         }
-        if let Some(value) = trimmed.strip_prefix("Content-Length:") {
-            content_length = value.trim().parse::<usize>().ok();
+        self.pool.did_change(&self.workspace_root, &paths).await;
+        Ok(json!({
+            "status": "success",
+            "message": format!("Changed {} file(s): {}", labels.len(), labels.join(", ")),
+            "files": labels,
+        }))
+    }
+
+    /// Whether the edit rules allow changing `path` without review. Files
+    /// the session has not read need `allow_unread`; ones that changed on
+    /// disk since it read them are refused.
+    async fn check_editable(&self, path: &Path, label: &str, allow_unread: bool) -> Result<bool, String> {
+        let action = {
+            let config = self.permission_manager.lock().await;
+            if config.check_path_access(path, &self.workspace_root) != crate::config::Action::Allow {
+                return Err(format!("Access denied: {} is outside workspace and not allowed by config", label));
+            }
+            config.edit.evaluate(label)
+        };
+        if action == crate::config::Action::Deny {
+            return Err(format!("Access denied: editing {} is not allowed", label));
         }
+        self.read_tracker.check(path, label, allow_unread)?;
+        Ok(action != crate::config::Action::Allow)
     }
 
-    let length = content_length.ok_or("Missing Content-Length header from LSP server".to_string())?;
-    let mut buffer = vec![0u8; length];
-    reader.read_exact(&mut buffer).await.map_err(|e| e.to_string())?;
-    serde_json::from_slice(&buffer).map_err(|e| format!("Invalid LSP JSON: {}", e))
-}
+    /// `path` relative to the workspace when inside it
+    fn label(&self, path: &Path) -> String {
+        path.strip_prefix(&self.workspace_root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    }
 
-async fn read_response_with_id(
-    reader: &mut BufReader<tokio::process::ChildStdout>,
-    expected_id: i64,
-    timeout_duration: Duration,
-) -> Result<Value, String> {
-    let deadline = tokio::time::Instant::now() + timeout_duration;
-    loop {
-        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
-        if remaining.is_zero() {
-            return Err("Timed out waiting for LSP response".to_string());
-        }
-        let message = timeout(remaining, read_message(reader))
-            .await
-            .map_err(|_| "Timed out waiting for LSP response".to_string())??;
-        if message.get("id").and_then(|id| id.as_i64()) == Some(expected_id) {
-            return Ok(message);
+    fn symbols_summary(&self, symbols: &Value) -> Value {
+        match symbols.as_array() {
+            Some(symbols) => Value::Array(symbols.iter().map(|symbol| self.symbol_summary(symbol)).collect()),
+            None => Value::Array(Vec::new()),
         }
     }
-}
 
-async fn request_with_retry(
-    stdin: &mut tokio::process::ChildStdin,
-    reader: &mut BufReader<tokio::process::ChildStdout>,
-    method: &str,
-    params: Value,
-    timeout_duration: Duration,
-) -> Result<Value, String> {
-    let mut attempt = 0;
-    loop {
-        let id = 2 + attempt;
-        let request = json!({
-            "jsonrpc": "2.0",
-            "id": id,
-            "method": method,
-            "params": params,
+    /// Compact form of a `DocumentSymbol`, `SymbolInformation`,
+    /// `WorkspaceSymbol` or `CallHierarchyItem`
+    fn symbol_summary(&self, symbol: &Value) -> Value {
+        let mut summary = json!({
+            "name": symbol.get("name"),
+            "kind": symbol.get("kind").and_then(|k| k.as_u64()).map(symbol_kind_name),
         });
-        write_message(stdin, &request).await?;
-        let response = read_response_with_id(reader, id, timeout_duration).await?;
-
-        if let Some(error) = response.get("error") {
-            return Err(format!("LSP error: {}", error));
+        if let Some(detail) = symbol.get("detail").filter(|d| d.as_str().is_some_and(|d| !d.is_empty())) {
+            summary["detail"] = detail.clone();
+        }
+        if let Some(container) = symbol.get("containerName") {
+            summary["container"] = container.clone();
         }
 
-        let result = response.get("result").cloned().unwrap_or(json!(null));
-        if !result.is_null() || attempt > 0 {
-            return Ok(result);
+        let uri = symbol
+            .get("uri")
+            .or_else(|| symbol.pointer("/location/uri"))
+            .and_then(|u| u.as_str());
+        if let Some(path) = uri.and_then(|uri| uri_to_path(uri).ok()) {
+            summary["path"] = json!(self.label(&path));
+        }
+        let line = symbol
+            .pointer("/selectionRange/start/line")
+            .or_else(|| symbol.pointer("/range/start/line"))
+            .or_else(|| symbol.pointer("/location/range/start/line"));
+        if let Some(line) = line {
+            summary["line"] = line.clone();
         }
 
-        attempt += 1;
-        tokio::time::sleep(Duration::from_millis(200)).await;
+        if let Some(children) = symbol.get("children").and_then(|c| c.as_array()).filter(|c| !c.is_empty()) {
+            summary["children"] = Value::Array(children.iter().map(|child| self.symbol_summary(child)).collect());
+        }
+        summary
     }
 }
 
-async fn collect_diagnostics(
-    reader: &mut BufReader<tokio::process::ChildStdout>,
-    uri: &str,
-    timeout_duration: Duration,
-) -> Result<Value, String> {
-    let deadline = tokio::time::Instant::now() + timeout_duration;
-    loop {
-        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
-        if remaining.is_zero() {
-            return Ok(json!([]));
-        }
-
-        let message = match timeout(remaining, read_message(reader)).await {
-            Ok(result) => result?,
-            Err(_) => return Ok(json!([])),
-        };
+/// Server capability a request needs, from the initialize response
+fn required_capability(request: &str) -> Option<&'static str> {
+    match request {
+        "definition" => Some("definitionProvider"),
+        "references" => Some("referencesProvider"),
+        "hover" => Some("hoverProvider"),
+        "document_symbols" => Some("documentSymbolProvider"),
+        "workspace_symbols" => Some("workspaceSymbolProvider"),
+        "implementation" => Some("implementationProvider"),
+        "incoming_calls" | "outgoing_calls" => Some("callHierarchyProvider"),
+        "rename" => Some("renameProvider"),
+        "code_actions" => Some("codeActionProvider"),
+        "format" => Some("documentFormattingProvider"),
+        _ => None,
+    }
+}
 
-        if message.get("method").and_then(|m| m.as_str()) == Some("textDocument/publishDiagnostics") {
-            if let Some(params) = message.get("params") {
-                if params.get("uri").and_then(|u| u.as_str()) == Some(uri) {
-                    return Ok(params.get("diagnostics").cloned().unwrap_or(json!([])));
-                }
+/// Plain text of hover contents: `MarkupContent`, a `MarkedString` or a
+/// list of them
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(hover_text).collect::<Vec<_>>().join("\n\n"),
+        Value::Object(object) => {
+            let value = object.get("value").and_then(|v| v.as_str()).unwrap_or("");
+            match object.get("language").and_then(|l| l.as_str()) {
+                Some(language) => format!("```{}\n{}\n```", language, value),
+                None => value.to_string(),
             }
         }
+        _ => String::new(),
     }
 }
 
-async fn run_lsp_request(
-    server_command: &str,
-    workspace_root: &Path,
-    file_path: &Path,
-    language_id: &str,
-    file_content: &str,
-    request_type: &str,
-    line: u64,
-    character: u64,
-    include_declaration: bool,
+fn symbol_kind_name(kind: u64) -> &'static str {
+    const KINDS: [&str; 26] = [
+        "file", "module", "namespace", "package", "class", "method", "property", "field", "constructor",
+        "enum", "interface", "function", "variable", "constant", "string", "number", "boolean", "array",
+        "object", "key", "null", "enum_member", "struct", "event", "operator", "type_parameter",
+    ];
+    kind.checked_sub(1)
+        .and_then(|index| KINDS.get(index as usize))
+        .copied()
+        .unwrap_or("unknown")
+}
+
+/// Servers still loading the project answer with null; ask once more
+async fn request_with_retry(
+    client: &LspClient,
+    method: &str,
+    params: Value,
     timeout_duration: Duration,
 ) -> Result<Value, String> {
-    let (program, args) = parse_server_command(server_command)?;
-
-    let mut child = Command::new(program)
-        .args(args)
-        .current_dir(workspace_root)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()
-        .map_err(|e| format!("Failed to start LSP server: {}", e))?;
-
-    let mut stdin = child.stdin.take().ok_or("Failed to open LSP stdin")?;
-    let stdout = child.stdout.take().ok_or("Failed to open LSP stdout")?;
-    let mut reader = BufReader::new(stdout);
-
-    let workspace_uri = path_to_uri(workspace_root)?;
-    let document_uri = path_to_uri(file_path)?;
-    let workspace_name = workspace_root
-        .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("workspace")
-        .to_string();
-
-    let initialize = json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "initialize",
-        "params": {
-            "processId": std::process::id(),
-            "rootUri": workspace_uri,
-            "rootPath": workspace_root.to_string_lossy(),
-            "workspaceFolders": [{
-                "uri": workspace_uri,
-                "name": workspace_name,
-            }],
-            "capabilities": {
-                "textDocument": {
-                    "definition": { "dynamicRegistration": false },
-                    "references": { "dynamicRegistration": false }
-                }
-            }
-        }
-    });
-
-    write_message(&mut stdin, &initialize).await?;
-    let _ = read_response_with_id(&mut reader, 1, timeout_duration).await?;
-
-    let initialized = json!({
-        "jsonrpc": "2.0",
-        "method": "initialized",
-        "params": {}
-    });
-    write_message(&mut stdin, &initialized).await?;
-
-    let did_open = json!({
-        "jsonrpc": "2.0",
-        "method": "textDocument/didOpen",
-        "params": {
-            "textDocument": {
-                "uri": document_uri,
-                "languageId": language_id,
-                "version": 1,
-                "text": file_content
-            }
-        }
-    });
-    write_message(&mut stdin, &did_open).await?;
-    tokio::time::sleep(Duration::from_millis(150)).await;
-
-    let result = match request_type {
-        "definition" => {
-            request_with_retry(
-                &mut stdin,
-                &mut reader,
-                "textDocument/definition",
-                json!({
-                    "textDocument": { "uri": document_uri },
-                    "position": { "line": line, "character": character }
-                }),
-                timeout_duration,
-            ).await?
-        }
-        "references" => {
-            request_with_retry(
-                &mut stdin,
-                &mut reader,
-                "textDocument/references",
-                json!({
-                    "textDocument": { "uri": document_uri },
-                    "position": { "line": line, "character": character },
-                    "context": { "includeDeclaration": include_declaration }
-                }),
-                timeout_duration,
-            ).await?
-        }
-        "diagnostics" => {
-            collect_diagnostics(&mut reader, &document_uri, timeout_duration).await?
-        }
-        _ => {
-            return Err("Unsupported LSP request. Use definition, references, or diagnostics".to_string());
-        }
-    };
-
-    let shutdown = json!({
-        "jsonrpc": "2.0",
-        "id": 99,
-        "method": "shutdown",
-        "params": null
-    });
-    let _ = write_message(&mut stdin, &shutdown).await;
-    let _ = read_response_with_id(&mut reader, 99, timeout_duration).await;
-
-    let exit = json!({
-        "jsonrpc": "2.0",
-        "method": "exit",
-        "params": null
-    });
-    let _ = write_message(&mut stdin, &exit).await;
-
-    let _ = child.kill().await;
-    Ok(result)
+    let result = client.request(method, params.clone(), timeout_duration).await?;
+    if !result.is_null() {
+        return Ok(result);
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    client.request(method, params, timeout_duration).await
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{infer_language_id, parse_server_command};
-    use std::path::PathBuf;
-
-    #[test]
-    fn infer_language_from_extension() {
-        assert_eq!(infer_language_id(&PathBuf::from("main.rs")), Some("rust"));
-        assert_eq!(infer_language_id(&PathBuf::from("app.tsx")), Some("typescriptreact"));
-        assert_eq!(infer_language_id(&PathBuf::from("script.py")), Some("python"));
-        assert_eq!(infer_language_id(&PathBuf::from("README.md")), Some("markdown"));
-    }
+    use super::{hover_text, symbol_kind_name};
+    use serde_json::json;
 
     #[test]
-    fn parse_server_command_splits_args() {
-        let (program, args) = parse_server_command("typescript-language-server --stdio").unwrap();
-        assert_eq!(program, "typescript-language-server");
-        assert_eq!(args, vec!["--stdio".to_string()]);
+    fn hover_text_reads_every_content_shape() {
+        assert_eq!(hover_text(&json!({ "kind": "markdown", "value": "**x**" })), "**x**");
+        assert_eq!(
+            hover_text(&json!([{ "language": "rust", "value": "fn a()" }, "docs"])),
+            "```rust\nfn a()\n```\n\ndocs"
+        );
     }
 
     #[test]
-    fn parse_server_command_requires_program() {
-        let err = parse_server_command("").unwrap_err();
-        assert!(err.contains("empty"));
+    fn symbol_kinds_have_names() {
+        assert_eq!(symbol_kind_name(12), "function");
+        assert_eq!(symbol_kind_name(23), "struct");
+        assert_eq!(symbol_kind_name(0), "unknown");
+        assert_eq!(symbol_kind_name(99), "unknown");
     }
 }
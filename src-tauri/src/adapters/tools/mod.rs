@@ -1,5 +1,12 @@
 pub mod files;
+pub mod file_view;
+pub mod edit_match;
+pub mod transaction;
+pub mod read_tracker;
 pub mod bash;
+pub mod shell;
+pub mod sandbox;
+pub mod jobs;
 pub mod git;
 pub mod search;
 pub mod symbols;
@@ -7,9 +14,12 @@ pub mod glob;
 pub mod list;
 pub mod web;
 pub mod patch;
+pub mod unified_diff;
 pub mod question;
 pub mod todo;
 pub mod todoread;
 pub mod skill;
 pub mod mcp_tool;
 pub mod lsp;
+pub mod registry;
+pub mod task;
@@ -2,16 +2,24 @@ use crate::domain::ports::Tool;
 use crate::domain::models::ToolResult;
 use async_trait::async_trait;
 use serde_json::{json, Value};
-use std::path::PathBuf;
-use tokio::fs;
+use crate::adapters::tools::files::normalize_path;
+use crate::adapters::tools::read_tracker::ReadTracker;
+use crate::adapters::tools::transaction::EditTransaction;
+use crate::adapters::tools::unified_diff::{self, FilePatch, HunkOutcome};
+use crate::domain::checkpoint::CheckpointStore;
+use git2::{IndexEntry, IndexTime, Repository};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub struct PatchTool {
     pub workspace_root: PathBuf,
+    pub checkpoints: Option<Arc<CheckpointStore>>,
+    pub read_tracker: Arc<ReadTracker>,
 }
 
 impl PatchTool {
-    pub fn new(workspace_root: PathBuf) -> Self {
-        Self { workspace_root }
+    pub fn new(workspace_root: PathBuf, checkpoints: Option<Arc<CheckpointStore>>, read_tracker: Arc<ReadTracker>) -> Self {
+        Self { workspace_root, checkpoints, read_tracker }
     }
 }
 
@@ -24,7 +32,7 @@ impl Tool for PatchTool {
     fn schema(&self) -> Value {
         json!({
             "name": "patch",
-            "description": "Apply a unified diff patch to files in the workspace. Supports dry-run mode to preview changes without applying them. Useful for applying code changes from diffs, PRs, or patches.",
+            "description": "Apply a unified diff or git-format patch to files in the workspace. Hunks are located even when their line numbers are off, with up to two lines of context fuzz. Git headers for new, deleted and renamed files and mode changes are supported; binary patches are not. When a hunk does not fit a file tracked by git, a 3-way merge against the version the patch was made from is tried. Either every file is written or none are. Use dry_run to check that the patch applies and see where each hunk would go.",
             "parameters": {
                 "type": "object",
                 "properties": {
@@ -38,11 +46,15 @@ impl Tool for PatchTool {
                     },
                     "dry_run": {
                         "type": "boolean",
-                        "description": "If true, preview changes without applying them (default: false)"
+                        "description": "If true, check that every hunk applies and report per-hunk results without writing anything, like `git apply --check` (default: false)"
                     },
                     "strip": {
                         "type": "integer",
-                        "description": "Number of leading path components to strip from file paths in the patch (default: 0)"
+                        "description": "Number of leading path components to strip from file paths in the patch. Default: 1 when the paths use git's a/ and b/ prefixes, otherwise 0"
+                    },
+                    "allow_unread": {
+                        "type": "boolean",
+                        "description": "Change existing files that have not been read in this session (default: false; dry runs may always check them)"
                     }
                 },
                 "required": ["patch"]
@@ -57,115 +69,53 @@ impl Tool for PatchTool {
 
         let base_path = input.get("path")
             .and_then(|v| v.as_str())
-            .map(|p| self.workspace_root.join(p))
+            .map(|p| normalize_path(&self.workspace_root.join(p)))
             .unwrap_or_else(|| self.workspace_root.clone());
 
         let dry_run = input.get("dry_run")
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
 
-        let strip = input.get("strip")
-            .and_then(|v| v.as_u64())
-            .unwrap_or(0) as usize;
-
         // Security check: ensure base_path is within workspace
         if !base_path.starts_with(&self.workspace_root) {
             return Err("Access denied: Path is outside workspace".to_string());
         }
 
-        // Parse the unified diff patch
-        let patch_set = match patch::Patch::from_single(patch_content) {
-            Ok(patch) => vec![patch],
-            Err(e) => {
-                // Try parsing as multiple patches
-                match patch::Patch::from_multiple(patch_content) {
-                    Ok(patches) => patches,
-                    Err(_) => return Err(format!("Failed to parse patch: {}. Ensure it's a valid unified diff format.", e)),
-                }
-            }
-        };
+        let patches = unified_diff::parse(patch_content)
+            .map_err(|e| format!("Failed to parse patch: {}. Ensure it's a valid unified diff format.", e))?;
 
-        let mut results: Vec<PatchResult> = Vec::new();
-        let mut total_applied = 0;
-        let mut total_failed = 0;
-
-        for patch in patch_set {
-            // Apply strip to the old/new file paths
-            let old_path = strip_path_components(&patch.old.path, strip);
-            let new_path = strip_path_components(&patch.new.path, strip);
-
-            // Resolve full paths
-            let old_full_path = base_path.join(&old_path);
-            let new_full_path = base_path.join(&new_path);
-
-            // Security check for both paths
-            if !old_full_path.starts_with(&self.workspace_root) || !new_full_path.starts_with(&self.workspace_root) {
-                results.push(PatchResult {
-                    file: new_path.to_string_lossy().to_string(),
-                    status: "error".to_string(),
-                    message: Some("Path outside workspace after strip".to_string()),
-                });
-                total_failed += 1;
-                continue;
+        let strip = input.get("strip")
+            .and_then(|v| v.as_u64())
+            .map(|n| n as usize)
+            .unwrap_or_else(|| default_strip(&patches));
+
+        let allow_unread = input.get("allow_unread").and_then(|v| v.as_bool()).unwrap_or(false);
+
+        let mut applier = Applier {
+            workspace_root: &self.workspace_root,
+            base_path,
+            strip,
+            dry_run,
+            allow_unread,
+            read_tracker: &self.read_tracker,
+            repo: Repository::discover(&self.workspace_root).ok(),
+            transaction: EditTransaction::new(),
+        };
+        let mut results: Vec<PatchResult> = patches.iter().map(|patch| applier.apply(patch)).collect();
+
+        let total_failed = results.iter().filter(|r| r.status == "error").count();
+        let mut total_applied = results.len() - total_failed;
+        if total_failed > 0 {
+            for result in results.iter_mut().filter(|r| r.status == "success") {
+                result.status = "skipped".to_string();
+                result.message = Some("Not written because another file in the patch failed".to_string());
             }
-
-            // For dry_run, just report what would happen
-            if dry_run {
-                results.push(PatchResult {
-                    file: new_path.to_string_lossy().to_string(),
-                    status: "dry_run".to_string(),
-                    message: Some(format!("Would apply {} hunks to {}", patch.hunks.len(), new_path.display())),
-                });
-                continue;
-            }
-
-            // Read the original file if it exists
-            let original_content = if old_full_path.exists() {
-                match fs::read_to_string(&old_full_path).await {
-                    Ok(content) => content,
-                    Err(e) => {
-                        results.push(PatchResult {
-                            file: new_path.to_string_lossy().to_string(),
-                            status: "error".to_string(),
-                            message: Some(format!("Failed to read file {}: {}", old_path.display(), e)),
-                        });
-                        total_failed += 1;
-                        continue;
-                    }
-                }
-            } else {
-                // File doesn't exist - this might be a new file creation
-                String::new()
-            };
-
-            // Apply the patch
-            match apply_patch(&original_content, &patch) {
-                Ok(new_content) => {
-                    // Write the patched content
-                    if let Err(e) = fs::write(&new_full_path, new_content).await {
-                        results.push(PatchResult {
-                            file: new_path.to_string_lossy().to_string(),
-                            status: "error".to_string(),
-                            message: Some(format!("Failed to write file {}: {}", new_path.display(), e)),
-                        });
-                        total_failed += 1;
-                    } else {
-                        results.push(PatchResult {
-                            file: new_path.to_string_lossy().to_string(),
-                            status: "success".to_string(),
-                            message: Some(format!("Applied {} hunks", patch.hunks.len())),
-                        });
-                        total_applied += 1;
-                    }
-                }
-                Err(e) => {
-                    results.push(PatchResult {
-                        file: new_path.to_string_lossy().to_string(),
-                        status: "error".to_string(),
-                        message: Some(format!("Failed to apply patch: {}", e)),
-                    });
-                    total_failed += 1;
-                }
+            total_applied = 0;
+        } else if !dry_run && !applier.transaction.is_empty() {
+            let paths = applier.transaction.paths();
+            applier.transaction.commit(self.checkpoints.as_deref())?;
+            for path in &paths {
+                self.read_tracker.record(path);
             }
         }
 
@@ -184,6 +134,32 @@ struct PatchResult {
     file: String,
     status: String,
     message: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hunks: Vec<HunkOutcome>,
+    /// Applied through a 3-way merge with the git base version
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    merged: bool,
+}
+
+impl PatchResult {
+    fn error(file: &str, message: String) -> Self {
+        Self {
+            file: file.to_string(),
+            status: "error".to_string(),
+            message: Some(message),
+            hunks: Vec::new(),
+            merged: false,
+        }
+    }
+}
+
+/// git-style `a/` and `b/` prefixes on every path mean `-p1`
+fn default_strip(patches: &[FilePatch]) -> usize {
+    let prefixed = patches.iter().all(|patch| {
+        patch.old_path.as_deref().is_none_or(|path| path.starts_with("a/"))
+            && patch.new_path.as_deref().is_none_or(|path| path.starts_with("b/"))
+    });
+    usize::from(prefixed)
 }
 
 fn strip_path_components(path: &str, strip: usize) -> PathBuf {
@@ -195,98 +171,220 @@ fn strip_path_components(path: &str, strip: usize) -> PathBuf {
     }
 }
 
-fn apply_patch(original: &str, patch: &patch::Patch) -> Result<String, String> {
-    let lines: Vec<&str> = original.lines().collect();
-    let mut result_lines: Vec<String> = lines.iter().map(|&s| s.to_string()).collect();
-    let mut offset: isize = 0;
+/// State for applying the files of one patch
+struct Applier<'a> {
+    workspace_root: &'a Path,
+    base_path: PathBuf,
+    strip: usize,
+    dry_run: bool,
+    allow_unread: bool,
+    read_tracker: &'a ReadTracker,
+    repo: Option<Repository>,
+    /// Every file is staged, so later files see earlier changes to the same path
+    transaction: EditTransaction,
+}
 
-    for hunk in &patch.hunks {
-        // Calculate the actual line number in the current file
-        let hunk_start = (hunk.old_range.start as isize - 1 + offset) as usize;
-        
-        // Validate context lines match before applying
-        if !validate_context(&lines, hunk, hunk_start) {
-            return Err("Context lines do not match - the file may have changed".to_string());
+impl Applier<'_> {
+    fn apply(&mut self, patch: &FilePatch) -> PatchResult {
+        let (old_rel, new_rel) = match &patch.rename {
+            Some((from, to)) => (Some(PathBuf::from(from)), Some(PathBuf::from(to))),
+            None => (
+                patch.old_path.as_deref().map(|p| strip_path_components(p, self.strip)),
+                patch.new_path.as_deref().map(|p| strip_path_components(p, self.strip)),
+            ),
+        };
+        let label = new_rel.as_ref().or(old_rel.as_ref()).map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+
+        if patch.binary {
+            return PatchResult::error(&label, "Binary patches are not supported".to_string());
         }
-        
-        // Apply the hunk
-        let mut line_idx = hunk_start;
-        let mut removed_count = 0;
-        
-        for line in &hunk.lines {
-            match line {
-                patch::Line::Context(_) => {
-                    // Context line - just advance
-                    line_idx += 1;
-                }
-                patch::Line::Remove(_) => {
-                    // Remove line at current position
-                    let actual_idx = line_idx - removed_count;
-                    if actual_idx < result_lines.len() {
-                        result_lines.remove(actual_idx);
-                        removed_count += 1;
-                        offset -= 1;
+        if let Some(mode) = patch.new_mode.filter(|mode| mode & 0o170000 != 0o100000) {
+            return PatchResult::error(&label, format!("Unsupported file mode {:o}; only regular files can be patched", mode));
+        }
+        let (old_path, new_path) = match (self.resolve(old_rel.as_deref()), self.resolve(new_rel.as_deref())) {
+            (Ok(old), Ok(new)) => (old, new),
+            _ => return PatchResult::error(&label, "Path outside workspace after strip".to_string()),
+        };
+
+        // A dry run writes nothing, so it may check files that were not read
+        if let Some(path) = &old_path {
+            if let Err(e) = self.read_tracker.check(path, &label, self.allow_unread || self.dry_run) {
+                return PatchResult::error(&label, e);
+            }
+        }
+
+        let current = match &old_path {
+            Some(path) => match self.transaction.read(path) {
+                Ok(Some(content)) => Some(content),
+                Ok(None) => return PatchResult::error(&label, format!("File not found: {}", label)),
+                Err(e) => return PatchResult::error(&label, e),
+            },
+            None => None,
+        };
+        if let (None, Some(path)) = (&old_path, &new_path) {
+            if !matches!(self.transaction.exists(path), Ok(false)) {
+                return PatchResult::error(&label, format!("Cannot create {}: it already exists", label));
+            }
+        }
+
+        let application = unified_diff::apply(current.as_deref().unwrap_or(""), &patch.hunks);
+        let mut result = PatchResult {
+            file: label.clone(),
+            status: if self.dry_run { "dry_run" } else { "success" }.to_string(),
+            message: None,
+            hunks: application.hunks.clone(),
+            merged: false,
+        };
+        let content = match application.content {
+            Some(content) => content,
+            None => {
+                let failed = application.failed_hunks();
+                let mut message = format!("{} of {} hunks failed to apply", failed, patch.hunks.len());
+                let merge = match (&old_path, &current) {
+                    (Some(path), Some(current)) => self.three_way(patch, path, current),
+                    _ => None,
+                };
+                match merge {
+                    Some(Ok(merged)) => {
+                        result.merged = true;
+                        merged
                     }
-                }
-                patch::Line::Add(s) => {
-                    // Add new line at current position
-                    let actual_idx = line_idx - removed_count;
-                    if actual_idx >= result_lines.len() {
-                        result_lines.push(s.to_string());
-                    } else {
-                        result_lines.insert(actual_idx, s.to_string());
+                    failed => {
+                        if let Some(Err(e)) = failed {
+                            message.push_str(&format!("; 3-way merge failed: {}", e));
+                        }
+                        result.status = "error".to_string();
+                        result.message = Some(message);
+                        return result;
                     }
-                    line_idx += 1;
-                    offset += 1;
-                    removed_count += 1; // Account for the fact we added a line
                 }
             }
+        };
+
+        let staged = match (old_path, new_path) {
+            (Some(old), None) if content.trim().is_empty() => self.transaction.stage_delete(old, &label),
+            (Some(_), None) => Err("File still has content after removing the deleted lines".to_string()),
+            (old, Some(new)) => self.stage(patch, old, new, &label, content),
+            (None, None) => Ok(()),
+        };
+        if let Err(e) = staged {
+            return PatchResult::error(&label, e);
         }
+
+        result.message = Some(describe(patch, &old_rel, self.dry_run, result.merged));
+        result
     }
 
-    Ok(result_lines.join("\n"))
-}
+    /// Stage the new content, mode change and, for a rename, the removal
+    /// of the old path
+    fn stage(&mut self, patch: &FilePatch, old: Option<PathBuf>, new: PathBuf, label: &str, content: String) -> Result<(), String> {
+        self.transaction.stage(new.clone(), label, content)?;
+        if let Some(mode) = patch.mode_change() {
+            self.transaction.set_executable(&new, mode & 0o111 != 0)?;
+        }
+        match (old, &patch.rename) {
+            (Some(old), Some((from, _))) if old != new => self.transaction.stage_delete(old, from),
+            _ => Ok(()),
+        }
+    }
 
-/// Validate that context lines in the hunk match the file at the expected positions
-fn validate_context(lines: &[&str], hunk: &patch::Hunk, start_line: usize) -> bool {
-    let mut file_line = start_line;
-    
-    for line in &hunk.lines {
-        match line {
-            patch::Line::Context(expected) => {
-                // Context line must match exactly
-                if file_line >= lines.len() {
-                    return false; // Expected context but file ended
-                }
-                if lines[file_line] != *expected {
-                    return false; // Context doesn't match
-                }
-                file_line += 1;
-            }
-            patch::Line::Remove(expected) => {
-                // The line to be removed must exist and match
-                if file_line >= lines.len() {
-                    return false;
-                }
-                if lines[file_line] != *expected {
-                    return false;
-                }
-                file_line += 1;
-            }
-            patch::Line::Add(_) => {
-                // Added lines don't need validation against the file
-                // They just indicate where new content will be inserted
-            }
+    /// Resolve a patch path under the base directory. `Ok(None)` stands for
+    /// `/dev/null`; paths that leave the workspace are an error.
+    fn resolve(&self, rel: Option<&Path>) -> Result<Option<PathBuf>, ()> {
+        let Some(rel) = rel else {
+            return Ok(None);
+        };
+        let full = normalize_path(&self.base_path.join(rel));
+        if rel.as_os_str().is_empty() || !full.starts_with(self.workspace_root) || full == self.workspace_root {
+            return Err(());
         }
+        Ok(Some(full))
+    }
+
+    /// Apply the hunks to the version of the file the patch was made from,
+    /// then merge that with the current content. `None` when the file is
+    /// not tracked by git.
+    fn three_way(&self, patch: &FilePatch, path: &Path, current: &str) -> Option<Result<String, String>> {
+        let repo = self.repo.as_ref()?;
+        let workdir = repo.workdir()?.canonicalize().ok()?;
+        let rel = path.canonicalize().ok()?.strip_prefix(&workdir).ok()?.to_path_buf();
+
+        let from_index_line = patch.old_blob.as_deref()
+            .and_then(|id| repo.revparse_single(id).ok())
+            .and_then(|object| object.peel_to_blob().ok());
+        let base = from_index_line.or_else(|| {
+            let tree = repo.head().ok()?.peel_to_tree().ok()?;
+            tree.get_path(&rel).ok()?.to_object(repo).ok()?.peel_to_blob().ok()
+        })?;
+
+        let Ok(base_text) = std::str::from_utf8(base.content()) else {
+            return Some(Err("the git base version is not UTF-8 text".to_string()));
+        };
+        let Some(theirs) = unified_diff::apply(base_text, &patch.hunks).content else {
+            return Some(Err("the hunks do not apply to the git base version either".to_string()));
+        };
+
+        let merged = merge_blobs(repo, &rel, base.id(), current, &theirs)
+            .map_err(|e| format!("git error: {}", e.message()))
+            .and_then(|merged| merged.ok_or_else(|| "the changes conflict with edits made since".to_string()));
+        Some(merged)
+    }
+}
+
+/// Merge `ours` and `theirs` against the `base` blob. The two sides are
+/// written to an in-memory object store so nothing is added to the
+/// repository. `None` if the merge has conflicts.
+fn merge_blobs(repo: &Repository, rel: &Path, base: git2::Oid, ours: &str, theirs: &str) -> Result<Option<String>, git2::Error> {
+    let odb = repo.odb()?;
+    let _mempack = odb.add_new_mempack_backend(1000)?;
+    let ours = odb.write(git2::ObjectType::Blob, ours.as_bytes())?;
+    let theirs = odb.write(git2::ObjectType::Blob, theirs.as_bytes())?;
+
+    let entry = |id: git2::Oid| IndexEntry {
+        ctime: IndexTime::new(0, 0),
+        mtime: IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: 0o100644,
+        uid: 0,
+        gid: 0,
+        file_size: 0,
+        id,
+        flags: 0,
+        flags_extended: 0,
+        path: rel.to_string_lossy().replace('\\', "/").into_bytes(),
+    };
+    let merged = repo.merge_file_from_index(&entry(base), &entry(ours), &entry(theirs), None)?;
+    Ok(merged
+        .is_automergeable()
+        .then(|| String::from_utf8_lossy(merged.content()).to_string()))
+}
+
+fn describe(patch: &FilePatch, old_rel: &Option<PathBuf>, dry_run: bool, merged: bool) -> String {
+    let verb = if dry_run { "Would apply" } else { "Applied" };
+    let mut message = format!("{} {} hunks", verb, patch.hunks.len());
+    if patch.old_path.is_none() {
+        message.push_str(", creating the file");
+    } else if patch.new_path.is_none() {
+        message.push_str(", deleting the file");
+    }
+    if let (Some(_), Some(from)) = (&patch.rename, old_rel) {
+        message.push_str(&format!(", renamed from {}", from.display()));
+    }
+    if let Some(mode) = patch.mode_change().filter(|_| patch.old_path.is_some()) {
+        message.push_str(&format!(", mode {:o}", mode));
+    }
+    if merged {
+        message.push_str(", using a 3-way merge because the file changed since the patch was made");
     }
-    
-    true
+    message
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::TempDir;
+    use tokio::fs;
 
     #[tokio::test]
     async fn test_patch_basic() {
@@ -307,9 +405,10 @@ mod tests {
  line3
 "#;
 
-        let tool = PatchTool::new(workspace.clone());
+        let tool = PatchTool::new(workspace.clone(), None, Arc::new(ReadTracker::new()));
         let input = json!({
-            "patch": patch_content
+            "patch": patch_content,
+            "allow_unread": true
         });
 
         let result = tool.execute(input).await.unwrap();
@@ -341,7 +440,7 @@ mod tests {
  line3
 "#;
 
-        let tool = PatchTool::new(workspace.clone());
+        let tool = PatchTool::new(workspace.clone(), None, Arc::new(ReadTracker::new()));
         let input = json!({
             "patch": patch_content,
             "dry_run": true
@@ -362,7 +461,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let workspace = temp_dir.path().to_path_buf();
 
-        let tool = PatchTool::new(workspace.clone());
+        let tool = PatchTool::new(workspace.clone(), None, Arc::new(ReadTracker::new()));
         // Use an absolute path outside the workspace to test security
         let outside_path = "/etc";
         let input = json!({
@@ -380,7 +479,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let workspace = temp_dir.path().to_path_buf();
 
-        let tool = PatchTool::new(workspace.clone());
+        let tool = PatchTool::new(workspace.clone(), None, Arc::new(ReadTracker::new()));
         let input = json!({
             "patch": "This is not a valid patch"
         });
@@ -389,4 +488,158 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Failed to parse patch"));
     }
+
+    #[tokio::test]
+    async fn test_patch_git_rename_delete_and_new_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = temp_dir.path().to_path_buf();
+        fs::write(workspace.join("old.txt"), "keep\nold\n").await.unwrap();
+        fs::write(workspace.join("gone.txt"), "bye\n").await.unwrap();
+
+        let patch_content = "\
+diff --git a/old.txt b/new.txt
+similarity index 50%
+rename from old.txt
+rename to new.txt
+--- a/old.txt
++++ b/new.txt
+@@ -1,2 +1,2 @@
+ keep
+-old
++new
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+diff --git a/bin/run.sh b/bin/run.sh
+new file mode 100755
+--- /dev/null
++++ b/bin/run.sh
+@@ -0,0 +1 @@
++echo run
+";
+        let tool = PatchTool::new(workspace.clone(), None, Arc::new(ReadTracker::new()));
+        let result = tool.execute(json!({ "patch": patch_content, "allow_unread": true })).await.unwrap();
+        assert_eq!(result["applied"], 3, "{}", result);
+
+        assert!(!workspace.join("old.txt").exists());
+        assert_eq!(fs::read_to_string(workspace.join("new.txt")).await.unwrap(), "keep\nnew\n");
+        assert!(!workspace.join("gone.txt").exists());
+        assert_eq!(fs::read_to_string(workspace.join("bin/run.sh")).await.unwrap(), "echo run\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(workspace.join("bin/run.sh")).unwrap().permissions().mode();
+            assert_ne!(mode & 0o100, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_patch_dry_run_reports_failed_hunks() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = temp_dir.path().to_path_buf();
+        fs::write(workspace.join("a.txt"), "one\ntwo\n").await.unwrap();
+        fs::write(workspace.join("b.txt"), "x\n").await.unwrap();
+
+        let patch_content = "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+ one
+-three
++3
+--- b.txt
++++ b.txt
+@@ -1 +1 @@
+-x
++y
+";
+        let tool = PatchTool::new(workspace.clone(), None, Arc::new(ReadTracker::new()));
+        let result = tool.execute(json!({ "patch": patch_content, "dry_run": true })).await.unwrap();
+        let results = result["results"].as_array().unwrap();
+
+        assert_eq!(results[0]["status"], "error");
+        assert_eq!(results[0]["hunks"][0]["status"], "failed");
+        assert_eq!(results[0]["hunks"][0]["nearby"][1], "    2: two");
+        assert_eq!(results[1]["status"], "dry_run");
+        assert_eq!(results[1]["hunks"][0]["status"], "applied");
+        assert_eq!(fs::read_to_string(workspace.join("b.txt")).await.unwrap(), "x\n");
+    }
+
+    #[tokio::test]
+    async fn test_patch_three_way_merge_with_git_base() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = temp_dir.path().to_path_buf();
+        fs::write(workspace.join("file.txt"), "a\nb\nc\nd\ne\nf\ng\nh\ni\n").await.unwrap();
+
+        let repo = Repository::init(&workspace).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("file.txt")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "base", &tree, &[]).unwrap();
+
+        // A context line too far inside the hunk for fuzz changed after the patch was made
+        let current = "a\nb\nC\nd\ne\nf\ng\nh\ni\n";
+        fs::write(workspace.join("file.txt"), current).await.unwrap();
+        let patch_content = "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,8 +1,8 @@
+ a
+ b
+ c
+ d
+-e
++E
+ f
+ g
+ h
+";
+        let tool = PatchTool::new(workspace.clone(), None, Arc::new(ReadTracker::new()));
+        let objects = count_files(&workspace.join(".git/objects"));
+        let result = tool.execute(json!({ "patch": patch_content, "dry_run": true })).await.unwrap();
+        assert_eq!(result["results"][0]["status"], "dry_run", "{}", result);
+        assert_eq!(result["results"][0]["merged"], true);
+        // The merge inputs are kept in memory, not written to the repository
+        assert_eq!(count_files(&workspace.join(".git/objects")), objects);
+        assert_eq!(fs::read_to_string(workspace.join("file.txt")).await.unwrap(), current);
+
+        let result = tool.execute(json!({ "patch": patch_content, "allow_unread": true })).await.unwrap();
+        assert_eq!(result["results"][0]["status"], "success", "{}", result);
+        assert_eq!(fs::read_to_string(workspace.join("file.txt")).await.unwrap(), "a\nb\nC\nd\nE\nf\ng\nh\ni\n");
+    }
+
+    #[tokio::test]
+    async fn test_patch_needs_files_read_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = temp_dir.path().to_path_buf();
+        let path = workspace.join("test.txt");
+        fs::write(&path, "line1\nline2\n").await.unwrap();
+        let patch_content = "--- test.txt\n+++ test.txt\n@@ -1,2 +1,2 @@\n line1\n-line2\n+line2_modified\n";
+
+        let reads = Arc::new(ReadTracker::new());
+        let tool = PatchTool::new(workspace.clone(), None, reads.clone());
+        let result = tool.execute(json!({ "patch": patch_content, "dry_run": true })).await.unwrap();
+        assert_eq!(result["results"][0]["status"], "dry_run", "{}", result);
+
+        let result = tool.execute(json!({ "patch": patch_content })).await.unwrap();
+        assert_eq!(result["results"][0]["status"], "error");
+        assert!(result["results"][0]["message"].as_str().unwrap().contains("has not been read"));
+        assert_eq!(fs::read_to_string(&path).await.unwrap(), "line1\nline2\n");
+
+        reads.record(&path);
+        let result = tool.execute(json!({ "patch": patch_content })).await.unwrap();
+        assert_eq!(result["results"][0]["status"], "success", "{}", result);
+    }
+
+    fn count_files(dir: &Path) -> usize {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| if e.path().is_dir() { count_files(&e.path()) } else { 1 })
+            .sum()
+    }
 }
@@ -1,65 +1,20 @@
-use crate::domain::ports::Tool;
-use crate::domain::models::ToolResult;
+use crate::domain::ports::{InteractionProvider, Tool};
+use crate::domain::models::{Question, QuestionOption, ToolResult};
 use async_trait::async_trait;
 use serde_json::{json, Value};
-use tauri::{AppHandle, Emitter};
-use tokio::sync::oneshot;
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-use uuid::Uuid;
-
-/// Global registry for pending questions
-/// This allows the resolve_question command to find and answer questions
-static GLOBAL_QUESTION_REGISTRY: once_cell::sync::Lazy<Arc<Mutex<HashMap<String, oneshot::Sender<Value>>>>> = 
-    once_cell::sync::Lazy::new(|| Arc::new(Mutex::new(HashMap::new())));
+use std::sync::Arc;
 
 pub struct QuestionTool {
-    app: AppHandle,
+    session_id: String,
+    interaction: Arc<dyn InteractionProvider>,
 }
 
 impl QuestionTool {
-    pub fn new(app: AppHandle) -> Self {
-        Self { app }
-    }
-
-    /// Resolve a question by ID with the given answers
-    pub fn resolve_question(question_id: String, answers: Value) -> Result<(), String> {
-        let registry = GLOBAL_QUESTION_REGISTRY.clone();
-        let mut pending = registry.lock()
-            .map_err(|e| format!("Failed to lock question registry: {}", e))?;
-        
-        if let Some(sender) = pending.remove(&question_id) {
-            sender.send(answers)
-                .map_err(|_| "Failed to send answer - receiver dropped".to_string())?;
-            Ok(())
-        } else {
-            Err(format!("Question {} not found or already answered", question_id))
-        }
+    pub fn new(session_id: String, interaction: Arc<dyn InteractionProvider>) -> Self {
+        Self { session_id, interaction }
     }
 }
 
-#[derive(serde::Serialize, Clone)]
-struct QuestionRequest {
-    id: String,
-    questions: Vec<Question>,
-}
-
-#[derive(serde::Serialize, Clone)]
-struct Question {
-    id: String,
-    header: String,
-    question: String,
-    options: Vec<QuestionOption>,
-    multiple: bool,
-}
-
-#[derive(serde::Serialize, Clone)]
-struct QuestionOption {
-    label: String,
-    description: String,
-    value: String,
-}
-
 #[async_trait]
 impl Tool for QuestionTool {
     fn name(&self) -> &'static str {
@@ -196,47 +151,9 @@ impl Tool for QuestionTool {
             });
         }
 
-        // Generate unique ID for this question set
-        let question_id = Uuid::new_v4().to_string();
-
-        // Create channel for receiving answer
-        let (tx, rx) = oneshot::channel();
-
-        // Store the sender in global registry
-        {
-            let registry = GLOBAL_QUESTION_REGISTRY.clone();
-            let mut pending = registry.lock()
-                .map_err(|e| format!("Failed to lock question registry: {}", e))?;
-            pending.insert(question_id.clone(), tx);
-        }
-
-        // Emit event to frontend
-        let request = QuestionRequest {
-            id: question_id.clone(),
-            questions,
-        };
-
-        self.app.emit("agent:question", request)
-            .map_err(|e| format!("Failed to emit question event: {}", e))?;
-
-        // Wait for response (with timeout)
-        let timeout_duration = std::time::Duration::from_secs(300); // 5 minute timeout
-        
-        let answers = match tokio::time::timeout(timeout_duration, rx).await {
-            Ok(Ok(answers)) => answers,
-            Ok(Err(_)) => return Err("Question channel closed unexpectedly".to_string()),
-            Err(_) => {
-                // Timeout - clean up
-                let registry = GLOBAL_QUESTION_REGISTRY.clone();
-                let mut pending = registry.lock()
-                    .map_err(|e| format!("Failed to lock question registry: {}", e))?;
-                pending.remove(&question_id);
-                return Err("Question timed out - no response within 5 minutes".to_string());
-            }
-        };
+        let answers = self.interaction.ask(&self.session_id, questions).await?;
 
         Ok(json!({
-            "question_id": question_id,
             "answers": answers,
             "completed": true
         }))
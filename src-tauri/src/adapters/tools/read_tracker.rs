@@ -0,0 +1,120 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Content hash of a file as the session last saw it. Timestamps are not
+/// used: they are too coarse to catch quick successive saves.
+type Stamp = [u8; 32];
+
+/// Files one session has read or written, so tools can refuse to change a
+/// file based on an outdated view of it, e.g. after the user edited it in
+/// the editor while the agent was working.
+#[derive(Default)]
+pub struct ReadTracker {
+    seen: Mutex<HashMap<PathBuf, Stamp>>,
+}
+
+impl ReadTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember that the session saw `content` at `path`
+    pub fn record_content(&self, path: &Path, content: &[u8]) {
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.insert(key(path), Sha256::digest(content).into());
+        }
+    }
+
+    /// Remember `path` as it is on disk now, after the session wrote or
+    /// deleted it
+    pub fn record(&self, path: &Path) {
+        match fs::read(path) {
+            Ok(content) => self.record_content(path, &content),
+            Err(_) => {
+                if let Ok(mut seen) = self.seen.lock() {
+                    seen.remove(&key(path));
+                }
+            }
+        }
+    }
+
+    /// Whether the session may change `path`: it must not exist, or be
+    /// unchanged since the session last saw it. An existing file the
+    /// session never saw is only allowed with `allow_unread`.
+    pub fn check(&self, path: &Path, label: &str, allow_unread: bool) -> Result<(), String> {
+        let stamp = self.seen.lock().ok().and_then(|seen| seen.get(&key(path)).copied());
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(format!("Failed to read {}: {}", label, e));
+            }
+            Err(_) if stamp.is_some() => {
+                return Err(format!(
+                    "{} was deleted since you last read it. Check with the user before creating it again.",
+                    label
+                ))
+            }
+            Err(_) => return Ok(()),
+        };
+
+        let Some(stamp) = stamp else {
+            if allow_unread {
+                return Ok(());
+            }
+            return Err(format!(
+                "{} has not been read in this session. Read it with read_file first, or set allow_unread to true to change it without reading.",
+                label
+            ));
+        };
+
+        if Stamp::from(Sha256::digest(&content)) == stamp {
+            return Ok(());
+        }
+        Err(format!(
+            "{} changed on disk since you last read it, probably edited by the user. Read it again with read_file and make your change against the current content.",
+            label
+        ))
+    }
+}
+
+/// The same file under any spelling of its path, also once it is deleted
+fn key(path: &Path) -> PathBuf {
+    match (path.parent().and_then(|p| fs::canonicalize(p).ok()), path.file_name()) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_requires_a_current_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        let tracker = ReadTracker::new();
+
+        assert!(tracker.check(&path, "a.txt", false).is_ok());
+        fs::write(&path, "one\n").unwrap();
+        assert!(tracker.check(&path, "a.txt", false).unwrap_err().contains("has not been read"));
+        assert!(tracker.check(&path, "a.txt", true).is_ok());
+
+        tracker.record_content(&path, b"one\n");
+        assert!(tracker.check(&dir.path().join("./a.txt"), "a.txt", false).is_ok());
+
+        fs::write(&path, "two\n").unwrap();
+        assert!(tracker.check(&path, "a.txt", true).unwrap_err().contains("changed on disk"));
+
+        tracker.record(&path);
+        assert!(tracker.check(&path, "a.txt", false).is_ok());
+
+        fs::remove_file(&path).unwrap();
+        assert!(tracker.check(&path, "a.txt", false).unwrap_err().contains("deleted"));
+        tracker.record(&path);
+        assert!(tracker.check(&path, "a.txt", false).is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::adapters::tools::{
+    bash::BashTool, files::EditFileTool, files::MultiEditTool, files::ReadFileTool, files::WriteFileTool, git::GitTool,
+    glob::GlobTool, jobs::{JobKillTool, JobManager, JobOutputTool, JobStatusTool}, list::ListTool, lsp::LspTool, mcp_tool::load_mcp_tools, patch::PatchTool,
+    question::QuestionTool, read_tracker::ReadTracker, search::SearchTool, skill::SkillTool, symbols::SymbolsTool,
+    task::{ParentModel, TaskTool}, todo::TodoWriteTool, todoread::TodoReadTool, web::WebFetchTool,
+};
+use crate::config::{AgentDefinition, LspConfig, PermissionConfig};
+use crate::domain::checkpoint::CheckpointStore;
+use crate::domain::ports::{InteractionProvider, Tool};
+use crate::lsp::LspPool;
+use crate::storage::Storage;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Everything needed to build the tool set of one session
+#[derive(Clone)]
+pub struct ToolContext {
+    pub workspace_root: PathBuf,
+    pub interaction: Arc<dyn InteractionProvider>,
+    pub permission_manager: Arc<tokio::sync::Mutex<PermissionConfig>>,
+    pub lsp_config: Option<LspConfig>,
+    pub lsp: Arc<LspPool>,
+    pub checkpoints: Option<Arc<CheckpointStore>>,
+}
+
+impl ToolContext {
+    /// Built-in tools bound to `session_id`
+    pub fn builtin_tools(&self, session_id: &str) -> Vec<Arc<dyn Tool>> {
+        let path = &self.workspace_root;
+        let id = session_id.to_string();
+        let jobs = Arc::new(JobManager::new());
+        let reads = Arc::new(ReadTracker::new());
+
+        vec![
+            Arc::new(ReadFileTool::new(
+                path.clone(),
+                id.clone(),
+                self.interaction.clone(),
+                self.permission_manager.clone(),
+                reads.clone(),
+            )),
+            Arc::new(WriteFileTool::new(
+                path.clone(),
+                id.clone(),
+                self.interaction.clone(),
+                self.permission_manager.clone(),
+                self.checkpoints.clone(),
+                reads.clone(),
+            )),
+            Arc::new(BashTool::new(
+                path.clone(),
+                id.clone(),
+                self.interaction.clone(),
+                self.permission_manager.clone(),
+                jobs.clone(),
+            )),
+            Arc::new(JobStatusTool::new(jobs.clone())),
+            Arc::new(JobOutputTool::new(jobs.clone())),
+            Arc::new(JobKillTool::new(jobs)),
+            Arc::new(GitTool::new(
+                path.clone(),
+                id.clone(),
+                self.interaction.clone(),
+                self.permission_manager.clone(),
+            )),
+            Arc::new(SearchTool::new(path.clone(), self.permission_manager.clone())),
+            Arc::new(LspTool::new(self, id.clone(), reads.clone())),
+            Arc::new(EditFileTool::new(
+                path.clone(),
+                id.clone(),
+                self.interaction.clone(),
+                self.permission_manager.clone(),
+                self.checkpoints.clone(),
+                reads.clone(),
+            )),
+            Arc::new(MultiEditTool::new(
+                path.clone(),
+                id.clone(),
+                self.interaction.clone(),
+                self.permission_manager.clone(),
+                self.checkpoints.clone(),
+                reads.clone(),
+            )),
+            Arc::new(SymbolsTool::new(path.clone())),
+            Arc::new(GlobTool::new(path.clone(), self.permission_manager.clone())),
+            Arc::new(ListTool::new(path.clone(), self.permission_manager.clone())),
+            Arc::new(WebFetchTool::new()),
+            Arc::new(PatchTool::new(path.clone(), self.checkpoints.clone(), reads)),
+            Arc::new(QuestionTool::new(id.clone(), self.interaction.clone())),
+            Arc::new(TodoWriteTool::new(path.clone())),
+            Arc::new(TodoReadTool::new(path.clone())),
+            Arc::new(SkillTool::new(
+                path.clone(),
+                id,
+                self.interaction.clone(),
+                self.permission_manager.clone(),
+            )),
+        ]
+    }
+
+    /// Built-in tools plus MCP tools from configuration
+    pub async fn session_tools(&self, session_id: &str) -> Vec<Arc<dyn Tool>> {
+        let mut tools = self.builtin_tools(session_id);
+
+        match load_mcp_tools(&self.workspace_root).await {
+            Ok(mcp_tools) => {
+                tools.extend(mcp_tools);
+            }
+            Err(e) => {
+                println!("⚠️  Warning: Failed to load MCP tools: {}", e);
+            }
+        }
+
+        tools
+    }
+
+    /// Full tool set of a top-level session: session tools plus `task`,
+    /// restricted to what `definition` allows
+    pub async fn agent_tools(
+        &self,
+        session_id: Uuid,
+        storage: Arc<Mutex<Option<Storage>>>,
+        model: ParentModel,
+        definition: Option<&AgentDefinition>,
+    ) -> Vec<Arc<dyn Tool>> {
+        let mut tools = self.session_tools(&session_id.to_string()).await;
+        tools.push(Arc::new(TaskTool::new(self.clone(), storage, session_id, model)));
+        filter_tools(tools, definition)
+    }
+}
+
+/// Restrict a tool set to the tools an agent definition allows
+pub fn filter_tools(tools: Vec<Arc<dyn Tool>>, definition: Option<&AgentDefinition>) -> Vec<Arc<dyn Tool>> {
+    match definition {
+        Some(def) => tools.into_iter().filter(|t| def.allows_tool(t.name())).collect(),
+        None => tools,
+    }
+}
@@ -0,0 +1,257 @@
+use crate::config::SandboxConfig;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use tokio::process::Command;
+
+/// Variables passed into the sandbox when the config names no others.
+/// Everything else, including API keys and tokens, is dropped.
+const SAFE_ENV: &[&str] = &[
+    "PATH", "HOME", "USER", "LOGNAME", "SHELL", "LANG", "LANGUAGE", "TERM", "COLORTERM", "TZ",
+    "NO_COLOR",
+];
+
+/// Workspace directories that stay read-only inside the sandbox: the local
+/// config could otherwise switch the sandbox off or allow more commands, and
+/// git hooks run later outside of it
+const PROTECTED_DIRS: &[&str] = &[".anvil", ".git"];
+
+/// Filesystem and network isolation for shell commands, built on bubblewrap.
+///
+/// The whole filesystem is mounted read-only; the workspace and any extra
+/// writable paths are bound read-write on top, except for [`PROTECTED_DIRS`],
+/// and `/tmp` is a private tmpfs.
+/// No new session is started, so killing the caller's process group still
+/// reaches every process in the sandbox.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sandbox {
+    workspace: PathBuf,
+    writable: Vec<PathBuf>,
+    network: bool,
+    env: Vec<String>,
+}
+
+impl Sandbox {
+    /// The sandbox commands should run in, or `None` to run them directly.
+    /// Fails when the config requires a sandbox this system cannot provide.
+    pub fn from_config(config: Option<&SandboxConfig>, workspace: &Path) -> Result<Option<Self>, String> {
+        let config = match config {
+            Some(config) if config.enabled => config,
+            _ => return Ok(None),
+        };
+        if !Self::is_available() {
+            if config.required {
+                return Err(
+                    "The bash sandbox is required but bubblewrap (bwrap) is not available on this system".to_string(),
+                );
+            }
+            return Ok(None);
+        }
+
+        let workspace = workspace.canonicalize().unwrap_or_else(|_| workspace.to_path_buf());
+        let writable = config
+            .writable
+            .iter()
+            .map(|path| {
+                let expanded = match (path.strip_prefix('~'), dirs::home_dir()) {
+                    (Some(rest), Some(home)) => home.join(rest.trim_start_matches('/')),
+                    _ => PathBuf::from(path),
+                };
+                workspace.join(expanded)
+            })
+            .collect();
+
+        Ok(Some(Self {
+            workspace,
+            writable,
+            network: config.network,
+            env: config.env.clone(),
+        }))
+    }
+
+    /// Whether bubblewrap is installed and can create namespaces here.
+    /// Checked once per process.
+    pub fn is_available() -> bool {
+        static AVAILABLE: OnceLock<bool> = OnceLock::new();
+        *AVAILABLE.get_or_init(|| {
+            cfg!(target_os = "linux")
+                && std::process::Command::new("bwrap")
+                    .args(["--ro-bind", "/", "/", "--unshare-pid", "--", "true"])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .is_ok_and(|status| status.success())
+        })
+    }
+
+    pub fn network(&self) -> bool {
+        self.network
+    }
+
+    /// A command running `program` with `args` inside the sandbox, starting
+    /// in `cwd` with a scrubbed environment
+    pub fn command(&self, program: &str, args: &[&str], cwd: &Path) -> Command {
+        let mut command = Command::new("bwrap");
+        command
+            .args(self.bwrap_args(cwd))
+            .arg("--")
+            .arg(program)
+            .args(args)
+            .current_dir(cwd)
+            .env_clear()
+            .envs(scrub_env(std::env::vars(), &self.env))
+            .env("TMPDIR", "/tmp");
+        command
+    }
+
+    fn bwrap_args(&self, cwd: &Path) -> Vec<String> {
+        let mut args: Vec<String> = ["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        // Binds come after the tmpfs so a workspace under /tmp stays visible
+        let workspace = self.workspace.to_string_lossy().to_string();
+        args.extend(["--bind".to_string(), workspace.clone(), workspace]);
+        for path in &self.writable {
+            let path = path.to_string_lossy().to_string();
+            args.extend(["--bind-try".to_string(), path.clone(), path]);
+        }
+        // Last, so no writable bind can expose them again
+        for dir in PROTECTED_DIRS {
+            let path = self.workspace.join(dir).to_string_lossy().to_string();
+            args.extend(["--ro-bind-try".to_string(), path.clone(), path]);
+        }
+
+        args.extend(["--unshare-pid", "--unshare-ipc", "--unshare-uts"].map(String::from));
+        if !self.network {
+            args.push("--unshare-net".to_string());
+        }
+        args.extend(["--chdir".to_string(), cwd.to_string_lossy().to_string()]);
+        args
+    }
+}
+
+/// Keep the variables in [`SAFE_ENV`], any `LC_*` locale settings and the
+/// configured `extra` names. An extra name ending in `*` matches a prefix.
+fn scrub_env(vars: impl IntoIterator<Item = (String, String)>, extra: &[String]) -> Vec<(String, String)> {
+    let allowed = |name: &str| {
+        SAFE_ENV.contains(&name)
+            || name.starts_with("LC_")
+            || extra.iter().any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => pattern == name,
+            })
+    };
+    vars.into_iter().filter(|(name, _)| allowed(name)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sandbox(network: bool) -> Sandbox {
+        Sandbox {
+            workspace: PathBuf::from("/tmp/project"),
+            writable: vec![PathBuf::from("/home/me/.cache")],
+            network,
+            env: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_bwrap_args() {
+        let args = sandbox(false).bwrap_args(Path::new("/tmp/project/src"));
+        let joined = args.join(" ");
+        assert!(joined.starts_with("--ro-bind / / --dev /dev --proc /proc --tmpfs /tmp --bind /tmp/project /tmp/project"));
+        assert!(joined.contains("--bind-try /home/me/.cache /home/me/.cache"));
+        assert!(joined.contains("--unshare-net"));
+        assert!(joined.ends_with("--chdir /tmp/project/src"));
+
+        let args = sandbox(true).bwrap_args(Path::new("/tmp/project"));
+        assert!(!args.contains(&"--unshare-net".to_string()));
+    }
+
+    #[test]
+    fn test_config_and_git_dirs_stay_read_only() {
+        let mut sandbox = sandbox(false);
+        sandbox.writable.push(PathBuf::from("/tmp/project/.git"));
+        let args = sandbox.bwrap_args(Path::new("/tmp/project"));
+        let position = |flag: &str, path: &str| {
+            args.windows(3)
+                .position(|w| w[0] == flag && w[1] == path && w[2] == path)
+                .unwrap_or_else(|| panic!("missing {} {}", flag, path))
+        };
+
+        let workspace = position("--bind", "/tmp/project");
+        let writable = position("--bind-try", "/tmp/project/.git");
+        for dir in ["/tmp/project/.anvil", "/tmp/project/.git"] {
+            let read_only = position("--ro-bind-try", dir);
+            assert!(read_only > workspace && read_only > writable);
+        }
+    }
+
+    #[test]
+    fn test_scrub_env() {
+        let vars = [
+            ("PATH", "/usr/bin"),
+            ("LC_ALL", "C"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+            ("OPENAI_API_KEY", "sk-test"),
+            ("CARGO_HOME", "/cargo"),
+            ("CARGO_TARGET_DIR", "/target"),
+            ("NODE_ENV", "test"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+
+        let kept: Vec<String> = scrub_env(vars.clone(), &[]).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(kept, vec!["PATH", "LC_ALL"]);
+
+        let extra = vec!["CARGO_*".to_string(), "NODE_ENV".to_string()];
+        let kept: Vec<String> = scrub_env(vars, &extra).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(kept, vec!["PATH", "LC_ALL", "CARGO_HOME", "CARGO_TARGET_DIR", "NODE_ENV"]);
+    }
+
+    #[test]
+    fn test_disabled_or_missing_config_runs_unsandboxed() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(Sandbox::from_config(None, dir.path()), Ok(None));
+
+        let config = SandboxConfig {
+            enabled: false,
+            network: false,
+            writable: Vec::new(),
+            env: Vec::new(),
+            auto_allow: true,
+            required: true,
+        };
+        assert_eq!(Sandbox::from_config(Some(&config), dir.path()), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_writes_outside_workspace_fail() {
+        if !Sandbox::is_available() {
+            return;
+        }
+        let workspace = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir_in(dirs::home_dir().unwrap()).unwrap();
+        let sandbox = Sandbox {
+            workspace: workspace.path().to_path_buf(),
+            writable: Vec::new(),
+            network: false,
+            env: Vec::new(),
+        };
+
+        let script = format!("touch inside && touch {}/outside", outside.path().display());
+        let output = sandbox
+            .command("sh", &["-c", &script], workspace.path())
+            .output()
+            .await
+            .unwrap();
+
+        assert!(!output.status.success());
+        assert!(workspace.path().join("inside").exists());
+        assert!(!outside.path().join("outside").exists());
+    }
+}
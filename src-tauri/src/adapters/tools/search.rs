@@ -2,28 +2,219 @@ use crate::domain::ports::Tool;
 use crate::domain::models::ToolResult;
 use async_trait::async_trait;
 use serde_json::{json, Value};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use ignore::overrides::{Override, OverrideBuilder};
 use ignore::WalkBuilder;
-use grep::searcher::Searcher;
-use grep::searcher::sinks::UTF8;
-use grep::regex::RegexMatcher;
-use std::sync::{Arc, Mutex};
+use grep::searcher::{BinaryDetection, Searcher, SearcherBuilder, Sink, SinkContext, SinkMatch};
+use grep::regex::{RegexMatcher, RegexMatcherBuilder};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Results returned when the call sets no `max_results`
+const DEFAULT_MAX_RESULTS: usize = 500;
+const MAX_RESULTS_LIMIT: usize = 5000;
+const MAX_CONTEXT_LINES: usize = 20;
+/// Ignore file honored on top of .gitignore, in the same format
+const ANVIL_IGNORE: &str = ".anvilignore";
 
 pub struct SearchTool {
     pub workspace_root: PathBuf,
+    pub permission_manager: Arc<Mutex<crate::config::PermissionConfig>>,
 }
 
 impl SearchTool {
-    pub fn new(workspace_root: PathBuf) -> Self {
-        Self { workspace_root }
+    pub fn new(workspace_root: PathBuf, permission_manager: Arc<Mutex<crate::config::PermissionConfig>>) -> Self {
+        Self { workspace_root, permission_manager }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputMode {
+    Content,
+    FilesWithMatches,
+    Count,
+}
+
+impl OutputMode {
+    fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.unwrap_or("content") {
+            "content" => Ok(OutputMode::Content),
+            "files_with_matches" => Ok(OutputMode::FilesWithMatches),
+            "count" => Ok(OutputMode::Count),
+            other => Err(format!(
+                "Invalid output_mode '{}': expected content, files_with_matches or count",
+                other
+            )),
+        }
     }
 }
 
-#[derive(serde::Serialize)]
+#[derive(Debug, serde::Serialize)]
 struct SearchMatch {
     path: String,
     line_number: u64,
     content: String,
+    /// A line around a match, returned because `context` was set
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    context: bool,
+}
+
+struct SearchRequest {
+    matcher: RegexMatcher,
+    context: usize,
+    multiline: bool,
+    mode: OutputMode,
+    max_results: usize,
+}
+
+#[derive(Debug, Default)]
+struct SearchOutcome {
+    /// Matching lines and their context, in content mode
+    matches: Vec<SearchMatch>,
+    match_count: usize,
+    /// Files with their number of matches, in the other modes
+    files: Vec<(String, u64)>,
+    truncated: bool,
+}
+
+/// Collects the results of one file into the shared outcome
+struct Collector<'a> {
+    path: &'a str,
+    request: &'a SearchRequest,
+    outcome: &'a mut SearchOutcome,
+    file_matches: u64,
+}
+
+impl Collector<'_> {
+    fn push(&mut self, line_number: Option<u64>, bytes: &[u8], context: bool) {
+        self.outcome.matches.push(SearchMatch {
+            path: self.path.to_string(),
+            line_number: line_number.unwrap_or(0),
+            content: String::from_utf8_lossy(bytes).trim_end().to_string(),
+            context,
+        });
+    }
+}
+
+impl Sink for Collector<'_> {
+    type Error = std::io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        self.file_matches += 1;
+        match self.request.mode {
+            OutputMode::Content => {
+                if self.outcome.match_count >= self.request.max_results {
+                    self.outcome.truncated = true;
+                    return Ok(false);
+                }
+                self.outcome.match_count += 1;
+                self.push(mat.line_number(), mat.bytes(), false);
+                Ok(true)
+            }
+            // One match is enough to list the file
+            OutputMode::FilesWithMatches => Ok(false),
+            OutputMode::Count => Ok(true),
+        }
+    }
+
+    fn context(&mut self, _searcher: &Searcher, ctx: &SinkContext<'_>) -> Result<bool, Self::Error> {
+        self.push(ctx.line_number(), ctx.bytes(), true);
+        Ok(true)
+    }
+}
+
+fn build_matcher(pattern: &str, case_insensitive: bool, fixed_strings: bool, multiline: bool) -> Result<RegexMatcher, String> {
+    RegexMatcherBuilder::new()
+        .case_insensitive(case_insensitive)
+        .fixed_strings(fixed_strings)
+        .multi_line(multiline)
+        .dot_matches_new_line(multiline)
+        .build(pattern)
+        .map_err(|e| format!("Invalid regex: {}", e))
+}
+
+/// Include and exclude globs in .gitignore syntax, relative to `base`
+fn build_overrides(base: &Path, include: Option<&str>, exclude: Option<&str>) -> Result<Override, String> {
+    let mut builder = OverrideBuilder::new(base);
+    if let Some(include) = include {
+        builder.add(include).map_err(|e| format!("Invalid include glob: {}", e))?;
+    }
+    if let Some(exclude) = exclude {
+        builder
+            .add(&format!("!{}", exclude))
+            .map_err(|e| format!("Invalid exclude glob: {}", e))?;
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Search every file under `base` that is not ignored, excluded by the
+/// overrides or `denied`. Paths are reported relative to `root`.
+fn run_search(
+    request: &SearchRequest,
+    root: &Path,
+    base: &Path,
+    overrides: Override,
+    denied: &dyn Fn(&str) -> bool,
+) -> SearchOutcome {
+    let mut outcome = SearchOutcome::default();
+    let mut searcher = SearcherBuilder::new()
+        .line_number(true)
+        .multi_line(request.multiline)
+        .before_context(request.context)
+        .after_context(request.context)
+        .binary_detection(BinaryDetection::quit(b'\x00'))
+        .build();
+
+    let walker = WalkBuilder::new(base)
+        .hidden(false)
+        .git_ignore(true)
+        .add_custom_ignore_filename(ANVIL_IGNORE)
+        .overrides(overrides)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    for result in walker {
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+
+        if !entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
+            continue;
+        }
+
+        let path = entry.path();
+        let relative_path = path.strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string();
+        if denied(&relative_path) {
+            continue;
+        }
+
+        let mut collector = Collector {
+            path: &relative_path,
+            request,
+            outcome: &mut outcome,
+            file_matches: 0,
+        };
+        let _ = searcher.search_path(&request.matcher, path, &mut collector);
+        let file_matches = collector.file_matches;
+
+        if request.mode != OutputMode::Content && file_matches > 0 {
+            if outcome.files.len() >= request.max_results {
+                outcome.truncated = true;
+            } else {
+                outcome.files.push((relative_path, file_matches));
+            }
+        }
+        if outcome.truncated {
+            break;
+        }
+    }
+
+    outcome
 }
 
 #[async_trait]
@@ -35,17 +226,50 @@ impl Tool for SearchTool {
     fn schema(&self) -> Value {
         json!({
             "name": "search",
-            "description": "Search for a pattern in the codebase using regex. Respects .gitignore.",
+            "description": "Search file contents with a regex, like ripgrep. Respects .gitignore and .anvilignore and skips binary files. Reports when results were truncated.",
             "parameters": {
                 "type": "object",
                 "properties": {
                     "pattern": {
                         "type": "string",
-                        "description": "Regex pattern to search for"
+                        "description": "Regex pattern to search for (a literal string with fixed_strings)"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to search, relative to the workspace root (default: workspace root)"
                     },
                     "include": {
                         "type": "string",
-                        "description": "Optional glob pattern for files to include"
+                        "description": "Only search files matching this glob, e.g. *.rs or src/**/*.{ts,tsx}"
+                    },
+                    "exclude": {
+                        "type": "string",
+                        "description": "Skip files matching this glob"
+                    },
+                    "case_insensitive": {
+                        "type": "boolean",
+                        "description": "Match regardless of case (like rg -i)"
+                    },
+                    "fixed_strings": {
+                        "type": "boolean",
+                        "description": "Treat the pattern as a literal string (like rg -F)"
+                    },
+                    "context": {
+                        "type": "integer",
+                        "description": "Lines of context to show before and after each match (like rg -C, max 20)"
+                    },
+                    "multiline": {
+                        "type": "boolean",
+                        "description": "Let the pattern span lines; . also matches newlines"
+                    },
+                    "output_mode": {
+                        "type": "string",
+                        "enum": ["content", "files_with_matches", "count"],
+                        "description": "content returns matching lines (default), files_with_matches only file paths, count the matches per file"
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "description": "Maximum matching lines, or files in the other modes (default: 500)"
                     }
                 },
                 "required": ["pattern"]
@@ -57,63 +281,150 @@ impl Tool for SearchTool {
         let pattern = input.get("pattern")
             .and_then(|v| v.as_str())
             .ok_or("Missing 'pattern' parameter")?;
+        let flag = |name: &str| input.get(name).and_then(|v| v.as_bool()).unwrap_or(false);
+        let text = |name: &str| input.get(name).and_then(|v| v.as_str()).filter(|s| !s.trim().is_empty());
+
+        let multiline = flag("multiline");
+        let mode = OutputMode::parse(text("output_mode"))?;
+        let max_results = input
+            .get("max_results")
+            .and_then(|v| v.as_u64())
+            .map(|n| (n as usize).clamp(1, MAX_RESULTS_LIMIT))
+            .unwrap_or(DEFAULT_MAX_RESULTS);
+        let request = SearchRequest {
+            matcher: build_matcher(pattern, flag("case_insensitive"), flag("fixed_strings"), multiline)?,
+            context: input.get("context").and_then(|v| v.as_u64()).unwrap_or(0).min(MAX_CONTEXT_LINES as u64) as usize,
+            multiline,
+            mode,
+            max_results,
+        };
 
-        let matcher = RegexMatcher::new(pattern)
-            .map_err(|e| format!("Invalid regex: {}", e))?;
-        
-        let matches = Arc::new(Mutex::new(Vec::new()));
-        let mut searcher = Searcher::new();
-        
         let root = self.workspace_root.clone();
-        let matches_clone = Arc::clone(&matches);
+        let base = match text("path") {
+            Some(path) => root.join(path),
+            None => root.clone(),
+        };
+        let overrides = build_overrides(&base, text("include"), text("exclude"))?;
+
+        // Files the read or grep rules deny (e.g. .env) are never searched
+        let permissions = {
+            let config = self.permission_manager.lock().await;
+            if config.check_path_access(&base, &root) != crate::config::Action::Allow {
+                return Err("Access denied: Path is outside workspace and not allowed by config".to_string());
+            }
+            config.clone()
+        };
+        let denied = move |path: &str| {
+            let name = Path::new(path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            [path, name.as_str()].iter().any(|p| {
+                permissions.read.evaluate(p) == crate::config::Action::Deny
+                    || permissions.grep.evaluate(p) == crate::config::Action::Deny
+            })
+        };
 
         // Run search in a blocking thread since ignore/grep are synchronous
-        tokio::task::spawn_blocking(move || {
-            let walker = WalkBuilder::new(&root)
-                .hidden(false)
-                .git_ignore(true)
-                .build();
-
-            for result in walker {
-                let entry = match result {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
-                };
-
-                if !entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
-                    continue;
-                }
+        let outcome = tokio::task::spawn_blocking(move || run_search(&request, &root, &base, overrides, &denied))
+            .await
+            .map_err(|e| e.to_string())?;
 
-                let path = entry.path().to_owned();
-                let relative_path = path.strip_prefix(&root)
-                    .unwrap_or(&path)
-                    .to_string_lossy()
-                    .to_string();
-
-                let matches_inner = Arc::clone(&matches_clone);
-                let _ = searcher.search_path(
-                    &matcher,
-                    &path,
-                    UTF8(|line_num, line| {
-                        let mut m = matches_inner.lock().unwrap();
-                        m.push(SearchMatch {
-                            path: relative_path.clone(),
-                            line_number: line_num,
-                            content: line.trim_end().to_string(),
-                        });
-                        if m.len() > 500 {
-                            return Ok(false); // Cap results
-                        }
-                        Ok(true)
-                    }),
-                );
-            }
-        }).await.map_err(|e| e.to_string())?;
+        let mut result = match mode {
+            OutputMode::Content => json!({
+                "matches": outcome.matches,
+                "count": outcome.match_count,
+            }),
+            OutputMode::FilesWithMatches => json!({
+                "files": outcome.files.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+                "count": outcome.files.len(),
+            }),
+            OutputMode::Count => json!({
+                "files": outcome.files.iter().map(|(path, count)| json!({ "path": path, "count": count })).collect::<Vec<_>>(),
+                "count": outcome.files.len(),
+                "total": outcome.files.iter().map(|(_, count)| count).sum::<u64>(),
+            }),
+        };
+        result["truncated"] = json!(outcome.truncated);
+        if outcome.truncated {
+            result["note"] = json!(format!(
+                "Stopped after {} results; more exist. Narrow the pattern, path or include glob, or raise max_results.",
+                max_results
+            ));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "fn alpha() {}\n// TODO: Alpha\nfn beta() {}\n").unwrap();
+        std::fs::write(root.join("src/main.ts"), "const alpha = 1; // a.b\n").unwrap();
+        std::fs::write(root.join("notes.md"), "alpha\n").unwrap();
+        std::fs::write(root.join("secret.txt"), "alpha\n").unwrap();
+        std::fs::write(root.join(ANVIL_IGNORE), "notes.md\n").unwrap();
+        dir
+    }
+
+    fn request(pattern: &str, mode: OutputMode, context: usize, max_results: usize) -> SearchRequest {
+        SearchRequest {
+            matcher: build_matcher(pattern, false, false, false).unwrap(),
+            context,
+            multiline: false,
+            mode,
+            max_results,
+        }
+    }
+
+    fn search(dir: &Path, request: &SearchRequest, include: Option<&str>, exclude: Option<&str>) -> SearchOutcome {
+        let overrides = build_overrides(dir, include, exclude).unwrap();
+        run_search(request, dir, dir, overrides, &|path| path == "secret.txt")
+    }
+
+    #[test]
+    fn test_globs_ignore_files_and_denied_paths() {
+        let dir = workspace();
+        let req = request("alpha", OutputMode::FilesWithMatches, 0, 10);
+
+        let outcome = search(dir.path(), &req, None, None);
+        let files: Vec<&str> = outcome.files.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(files, vec!["src/lib.rs", "src/main.ts"]);
+
+        let outcome = search(dir.path(), &req, Some("*.rs"), None);
+        assert_eq!(outcome.files.len(), 1);
+        let outcome = search(dir.path(), &req, None, Some("*.rs"));
+        assert_eq!(outcome.files[0].0, "src/main.ts");
+    }
+
+    #[test]
+    fn test_case_literal_and_context() {
+        let dir = workspace();
+        let mut req = request("alpha", OutputMode::Count, 0, 10);
+        req.matcher = build_matcher("alpha", true, false, false).unwrap();
+        let outcome = search(dir.path(), &req, Some("*.rs"), None);
+        assert_eq!(outcome.files, vec![("src/lib.rs".to_string(), 2)]);
+
+        req.matcher = build_matcher("a.b", false, true, false).unwrap();
+        let outcome = search(dir.path(), &req, None, None);
+        assert_eq!(outcome.files, vec![("src/main.ts".to_string(), 1)]);
+
+        let outcome = search(dir.path(), &request("TODO", OutputMode::Content, 1, 10), None, None);
+        let lines: Vec<(u64, bool)> = outcome.matches.iter().map(|m| (m.line_number, m.context)).collect();
+        assert_eq!(lines, vec![(1, true), (2, false), (3, true)]);
+        assert_eq!(outcome.match_count, 1);
+    }
+
+    #[test]
+    fn test_truncation_is_reported() {
+        let dir = workspace();
+        let outcome = search(dir.path(), &request("fn", OutputMode::Content, 0, 1), None, None);
+        assert_eq!(outcome.match_count, 1);
+        assert!(outcome.truncated);
 
-        let final_matches = matches.lock().unwrap();
-        Ok(json!({
-            "matches": *final_matches,
-            "count": final_matches.len()
-        }))
+        let outcome = search(dir.path(), &request("fn", OutputMode::Content, 0, 2), None, None);
+        assert!(!outcome.truncated);
     }
 }
@@ -0,0 +1,291 @@
+use crate::adapters::tools::sandbox::Sandbox;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+/// Result of one command run in a [`ShellSession`]
+#[derive(Debug, Clone)]
+pub struct ShellOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` when the shell itself exited, e.g. after `exit` or a timeout
+    pub exit_code: Option<i32>,
+    /// Working directory after the command
+    pub cwd: PathBuf,
+    /// The command ran past its timeout and the shell was killed
+    pub timed_out: bool,
+}
+
+/// A long-running shell whose state (working directory, exported variables,
+/// activated virtualenvs) carries over between commands.
+///
+/// Each command is sent as an `eval` of a quoted string followed by a
+/// sentinel line on stdout and stderr; the sentinel carries the exit code
+/// and the new working directory. Quoting keeps a command with a syntax
+/// error from swallowing the sentinel. The shell leads its own process
+/// group, so a timed-out command is killed together with its children.
+pub struct ShellSession {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    stderr: BufReader<ChildStderr>,
+    cwd: PathBuf,
+    sandbox: Option<Sandbox>,
+}
+
+impl ShellSession {
+    /// Start a shell in `cwd`, inside `sandbox` when given; prefers bash
+    /// without profile or rc files
+    pub fn spawn(cwd: &Path, sandbox: Option<&Sandbox>) -> Result<Self, String> {
+        let mut child = Self::command("bash", &["--noprofile", "--norc"], cwd, sandbox)
+            .spawn()
+            .or_else(|_| Self::command("sh", &[], cwd, sandbox).spawn())
+            .map_err(|e| format!("Failed to start shell: {}", e))?;
+
+        let stdin = child.stdin.take().ok_or("Shell has no stdin")?;
+        let stdout = child.stdout.take().ok_or("Shell has no stdout")?;
+        let stderr = child.stderr.take().ok_or("Shell has no stderr")?;
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            stderr: BufReader::new(stderr),
+            cwd: cwd.to_path_buf(),
+            sandbox: sandbox.cloned(),
+        })
+    }
+
+    fn command(program: &str, args: &[&str], cwd: &Path, sandbox: Option<&Sandbox>) -> Command {
+        let mut command = match sandbox {
+            Some(sandbox) => sandbox.command(program, args, cwd),
+            None => {
+                let mut command = Command::new(program);
+                command.args(args);
+                command
+            }
+        };
+        command
+            .current_dir(cwd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        command.process_group(0);
+        command
+    }
+
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    pub fn sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_ref()
+    }
+
+    /// Whether the shell process is still running
+    pub fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Run `command` and wait up to `timeout` for it to finish, passing each
+    /// output line to `on_output` as it arrives. Commands read EOF on stdin.
+    /// On timeout the shell's process group is killed; start a new session.
+    pub async fn run(
+        &mut self,
+        command: &str,
+        timeout: Duration,
+        on_output: &(dyn Fn(OutputStream, &str) + Sync),
+    ) -> Result<ShellOutput, String> {
+        let sentinel = format!("__ANVIL_{}__", Uuid::new_v4().simple());
+        let script = format!(
+            "__anvil_cmd={}\neval \"$__anvil_cmd\" < /dev/null\n__anvil_status=$?\nprintf '\\n{s} %s %s\\n' \"$__anvil_status\" \"$PWD\"\nprintf '\\n{s}\\n' >&2\n",
+            shell_quote(command),
+            s = sentinel
+        );
+
+        self.stdin
+            .write_all(script.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to send command to shell: {}", e))?;
+        self.stdin
+            .flush()
+            .await
+            .map_err(|e| format!("Failed to send command to shell: {}", e))?;
+
+        // Collected outside the reads so a timeout keeps what arrived so far
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let finished = tokio::time::timeout(timeout, async {
+            tokio::join!(
+                read_until_sentinel(&mut self.stdout, &sentinel, &mut stdout, OutputStream::Stdout, on_output),
+                read_until_sentinel(&mut self.stderr, &sentinel, &mut stderr, OutputStream::Stderr, on_output)
+            )
+        })
+        .await;
+
+        let (status_line, timed_out) = match finished {
+            Ok((status, err)) => {
+                err.map_err(|e| format!("Failed to read shell output: {}", e))?;
+                (status.map_err(|e| format!("Failed to read shell output: {}", e))?, false)
+            }
+            Err(_) => {
+                if let Some(pid) = self.child.id() {
+                    kill_process_group(pid);
+                }
+                (None, true)
+            }
+        };
+
+        // No sentinel means the shell exited mid-command
+        let exit_code = match status_line {
+            Some(line) => {
+                let mut parts = line.splitn(2, ' ');
+                let code = parts.next().and_then(|c| c.parse().ok());
+                if let Some(cwd) = parts.next().filter(|p| !p.is_empty()) {
+                    self.cwd = PathBuf::from(cwd);
+                }
+                code
+            }
+            None => {
+                // Reap the shell so `is_alive` reports it gone
+                let _ = tokio::time::timeout(Duration::from_secs(1), self.child.wait()).await;
+                None
+            }
+        };
+
+        Ok(ShellOutput {
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+            exit_code,
+            cwd: self.cwd.clone(),
+            timed_out,
+        })
+    }
+}
+
+/// Kill every process in the group led by `pid`
+pub fn kill_process_group(pid: u32) {
+    let _ = std::process::Command::new("kill")
+        .args(["-9", "--", &format!("-{}", pid)])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// Collect lines into `output` until the sentinel line. Returns whatever
+/// followed the sentinel on its line, or `None` on EOF.
+async fn read_until_sentinel<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    sentinel: &str,
+    output: &mut Vec<u8>,
+    stream: OutputStream,
+    on_output: &(dyn Fn(OutputStream, &str) + Sync),
+) -> std::io::Result<Option<String>> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            return Ok(None);
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        if let Some(rest) = text.strip_prefix(sentinel) {
+            // Drop the newline printed to put the sentinel on its own line
+            if output.last() == Some(&b'\n') {
+                output.pop();
+            }
+            return Ok(Some(rest.trim().to_string()));
+        }
+        // Blank lines are not streamed; one of them is the newline before the sentinel
+        if text != "\n" {
+            on_output(stream, &text);
+        }
+        output.extend_from_slice(&line);
+    }
+}
+
+/// Single-quote `text` for POSIX shells
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn ignore(_: OutputStream, _: &str) {}
+
+    #[tokio::test]
+    async fn test_state_persists_between_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let mut shell = ShellSession::spawn(dir.path(), None).unwrap();
+
+        let out = shell.run("cd sub && export GREETING='it''s me'", TIMEOUT, &ignore).await.unwrap();
+        assert_eq!(out.exit_code, Some(0));
+        assert!(out.cwd.ends_with("sub"));
+
+        let out = shell.run("echo \"$GREETING\"; echo oops >&2; false", TIMEOUT, &ignore).await.unwrap();
+        assert_eq!(out.stdout, "its me\n");
+        assert_eq!(out.stderr, "oops\n");
+        assert_eq!(out.exit_code, Some(1));
+        assert!(shell.cwd().ends_with("sub"));
+    }
+
+    #[tokio::test]
+    async fn test_syntax_errors_and_exit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut shell = ShellSession::spawn(dir.path(), None).unwrap();
+
+        let out = shell.run("echo 'unterminated", TIMEOUT, &ignore).await.unwrap();
+        assert_ne!(out.exit_code, Some(0));
+        let out = shell.run("printf 'no newline'", TIMEOUT, &ignore).await.unwrap();
+        assert_eq!(out.stdout, "no newline");
+
+        let out = shell.run("exit 3", TIMEOUT, &ignore).await.unwrap();
+        assert_eq!(out.exit_code, None);
+        assert!(!shell.is_alive());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_children_and_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut shell = ShellSession::spawn(dir.path(), None).unwrap();
+        let lines = std::sync::Mutex::new(Vec::new());
+        let collect = |stream: OutputStream, line: &str| {
+            lines.lock().unwrap().push((stream, line.to_string()));
+        };
+
+        let out = shell
+            .run("echo started; sleep 30 & wait", Duration::from_millis(300), &collect)
+            .await
+            .unwrap();
+        assert!(out.timed_out);
+        assert_eq!(out.exit_code, None);
+        assert_eq!(out.stdout, "started\n");
+        assert_eq!(*lines.lock().unwrap(), vec![(OutputStream::Stdout, "started\n".to_string())]);
+        assert!(!shell.is_alive());
+    }
+}
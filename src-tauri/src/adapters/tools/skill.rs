@@ -1,50 +1,42 @@
-use crate::domain::ports::Tool;
-use crate::domain::models::ToolResult;
+use crate::domain::ports::{InteractionProvider, Tool};
+use crate::domain::models::{ConfirmationRequest, ToolResult};
 use crate::config::{SkillLoader, SkillDiscovery, PermissionConfig, Action};
 use async_trait::async_trait;
-use serde::{Serialize};
 use serde_json::{json, Value};
 use std::path::PathBuf;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex as StdMutex};
-use tauri::{AppHandle, Emitter};
-use tokio::sync::oneshot;
+use std::sync::Arc;
 use tokio::sync::Mutex;
-use uuid::Uuid;
 
 pub struct SkillTool {
     workspace_root: PathBuf,
     permission_manager: Arc<Mutex<PermissionConfig>>,
     session_id: String,
-    app: Option<AppHandle>,
-    pending_confirmations: Option<Arc<StdMutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>>,
+    interaction: Arc<dyn InteractionProvider>,
 }
 
 impl SkillTool {
     pub fn new(
         workspace_root: PathBuf,
         session_id: String,
-        app: AppHandle,
-        pending_confirmations: Arc<StdMutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>,
+        interaction: Arc<dyn InteractionProvider>,
         permission_manager: Arc<Mutex<PermissionConfig>>,
     ) -> Self {
         Self {
             workspace_root,
             permission_manager,
             session_id,
-            app: Some(app),
-            pending_confirmations: Some(pending_confirmations),
+            interaction,
         }
     }
 
     #[cfg(test)]
     pub fn new_for_test(workspace_root: PathBuf, permission_manager: Arc<Mutex<PermissionConfig>>) -> Self {
+        use crate::adapters::interaction::{AutoPolicy, AutoResponder};
         Self {
             workspace_root,
             permission_manager,
             session_id: String::new(),
-            app: None,
-            pending_confirmations: None,
+            interaction: Arc::new(AutoResponder::new(AutoPolicy::DenyAll)),
         }
     }
     
@@ -58,48 +50,17 @@ impl SkillTool {
         &self,
         skill_name: &str,
     ) -> Result<crate::domain::models::ConfirmationResponse, String> {
-        let request_id = Uuid::new_v4().to_string();
-        let (tx, rx) = oneshot::channel();
-
-        let Some(pending) = self.pending_confirmations.as_ref() else {
-            return Err("Confirmation unavailable for skill invocation.".to_string());
-        };
-        let Some(app) = self.app.as_ref() else {
-            return Err("Confirmation unavailable for skill invocation.".to_string());
-        };
-
-        {
-            let mut map = pending.lock().unwrap();
-            map.insert(request_id.clone(), tx);
-        }
-
-        let event = PermissionConfirmationRequest {
-            id: request_id.clone(),
-            session_id: self.session_id.clone(),
-            type_: "permission".to_string(),
-            tool_name: "skill".to_string(),
-            input: skill_name.to_string(),
-            suggested_pattern: skill_name.to_string(),
-        };
-
-        app.emit("request-confirmation", &event)
-            .map_err(|e| format!("Failed to emit confirmation event: {}", e))?;
-
-        rx.await.map_err(|_| "Confirmation channel closed without response".to_string())
+        let request = ConfirmationRequest::permission(
+            &self.session_id,
+            "permission",
+            "skill",
+            skill_name,
+            skill_name.to_string(),
+        );
+        self.interaction.confirm(request).await
     }
 }
 
-#[derive(Serialize, Clone)]
-struct PermissionConfirmationRequest {
-    id: String,
-    session_id: String,
-    #[serde(rename = "type")]
-    type_: String,
-    tool_name: String,
-    input: String,
-    suggested_pattern: String,
-}
-
 #[async_trait]
 impl Tool for SkillTool {
     fn name(&self) -> &'static str {
@@ -0,0 +1,368 @@
+use crate::adapters::build_model_adapter;
+use crate::adapters::interaction::SubagentInteraction;
+use crate::adapters::tools::registry::{filter_tools, ToolContext};
+use crate::config::{AgentDefinition, AgentRegistry, ConfigManager};
+use crate::domain::agent::Agent;
+use crate::domain::models::{AgentMode, AgentPermissions, AgentSession, ModelId, ToolResult};
+use crate::domain::ports::{InteractionProvider, ModelAdapter, Tool};
+use crate::storage::Storage;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// The model a parent session runs with; sub-agents inherit it unless their
+/// definition pins another model or provider.
+#[derive(Clone)]
+pub struct ParentModel {
+    pub adapter: Arc<dyn ModelAdapter>,
+    pub model_id: ModelId,
+    pub provider: String,
+    pub api_key: String,
+}
+
+/// Tools of the general-purpose sub-agent started without `subagent_type`.
+/// It only investigates, so it cannot change the workspace unasked.
+const GENERAL_AGENT_TOOLS: &[&str] = &["read_file", "list", "glob", "search", "list_symbols", "lsp", "webfetch", "todoread"];
+
+#[derive(Serialize, Clone)]
+struct SubagentEvent {
+    session_id: String,
+    parent_session_id: String,
+    agent: String,
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    success: Option<bool>,
+}
+
+/// Spawns a child agent with a fresh context to work on a self-contained task
+pub struct TaskTool {
+    context: ToolContext,
+    storage: Arc<Mutex<Option<Storage>>>,
+    parent_session_id: Uuid,
+    parent_model: ParentModel,
+}
+
+impl TaskTool {
+    pub fn new(
+        context: ToolContext,
+        storage: Arc<Mutex<Option<Storage>>>,
+        parent_session_id: Uuid,
+        parent_model: ParentModel,
+    ) -> Self {
+        Self {
+            context,
+            storage,
+            parent_session_id,
+            parent_model,
+        }
+    }
+
+    fn resolve_model(
+        &self,
+        definition: Option<&AgentDefinition>,
+        config: &crate::config::Config,
+    ) -> Result<(Arc<dyn ModelAdapter>, ModelId), String> {
+        let provider = definition.and_then(|d| d.provider.clone());
+        let model = definition.and_then(|d| d.model.clone());
+
+        if provider.is_none() && model.is_none() {
+            return Ok((self.parent_model.adapter.clone(), self.parent_model.model_id.clone()));
+        }
+
+        let provider = provider.unwrap_or_else(|| self.parent_model.provider.clone());
+        let model_id = model.unwrap_or_else(|| self.parent_model.model_id.0.clone());
+        let api_key = if provider == self.parent_model.provider {
+            self.parent_model.api_key.clone()
+        } else {
+            config
+                .provider
+                .get(&provider)
+                .and_then(|c| c.api_key.clone())
+                .unwrap_or_default()
+        };
+
+        let adapter = build_model_adapter(&provider, &api_key, &model_id)?;
+        Ok((adapter, ModelId(model_id)))
+    }
+
+    fn save_transcript(&self, session: &AgentSession) {
+        let Ok(guard) = self.storage.lock() else {
+            return;
+        };
+        if let Some(storage) = guard.as_ref() {
+            if let Err(e) = storage.save_session(session) {
+                eprintln!("Warning: Failed to save sub-agent transcript {}: {}", session.id, e);
+            }
+        }
+    }
+
+    fn emit(&self, event: &str, payload: &SubagentEvent) {
+        if let Ok(payload) = serde_json::to_value(payload) {
+            self.context.interaction.notify(event, payload);
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for TaskTool {
+    fn name(&self) -> &'static str {
+        "task"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "name": "task",
+            "description": "Delegate a self-contained task to a sub-agent. The sub-agent starts with a fresh context, works with its own tool set until done, and returns only its final report. Use it for broad searches or focused subtasks whose intermediate steps you do not need to see.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "description": {
+                        "type": "string",
+                        "description": "Short (3-5 word) summary of the task"
+                    },
+                    "prompt": {
+                        "type": "string",
+                        "description": "Complete instructions for the sub-agent, including what to report back"
+                    },
+                    "subagent_type": {
+                        "type": "string",
+                        "description": "Name of a configured agent to run as (see .anvil/agents). Omit for a general-purpose agent that can only read and search."
+                    }
+                },
+                "required": ["description", "prompt"]
+            }
+        })
+    }
+
+    async fn execute(&self, input: Value) -> ToolResult {
+        let prompt = input
+            .get("prompt")
+            .and_then(|v| v.as_str())
+            .filter(|p| !p.trim().is_empty())
+            .ok_or("Missing prompt")?
+            .to_string();
+        let description = input
+            .get("description")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let subagent_type = input
+            .get("subagent_type")
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty());
+
+        let mut config_manager = ConfigManager::new();
+        let _ = config_manager.load(Some(&self.context.workspace_root));
+        let config = config_manager.config();
+
+        let definition = match subagent_type {
+            Some(name) => Some(AgentRegistry::find(&self.context.workspace_root, config, name)?),
+            None => None,
+        };
+        let agent_name = definition
+            .as_ref()
+            .map(|d| d.name.clone())
+            .unwrap_or_else(|| "general".to_string());
+
+        // Children share the parent's permission state unless their definition overrides it
+        let permission_manager = match definition.as_ref().filter(|d| d.permission.is_some()) {
+            Some(def) => {
+                let base = self.context.permission_manager.lock().await.clone();
+                Arc::new(tokio::sync::Mutex::new(def.effective_permissions(&base)?))
+            }
+            None => self.context.permission_manager.clone(),
+        };
+        let permissions = permission_manager.lock().await.clone();
+
+        let (model, model_id) = self.resolve_model(definition.as_ref(), config)?;
+
+        let child_id = Uuid::new_v4();
+        // Prompts raised by the child are attributed to it and its parent
+        let interaction: Arc<dyn InteractionProvider> = Arc::new(SubagentInteraction::new(
+            self.context.interaction.clone(),
+            self.parent_session_id.to_string(),
+            agent_name.clone(),
+        ));
+        let child_context = ToolContext {
+            permission_manager: permission_manager.clone(),
+            interaction: interaction.clone(),
+            ..self.context.clone()
+        };
+        let mut tools = filter_tools(
+            child_context.session_tools(&child_id.to_string()).await,
+            definition.as_ref(),
+        );
+        if definition.is_none() {
+            tools.retain(|t| GENERAL_AGENT_TOOLS.contains(&t.name()));
+        }
+
+        let session = AgentSession {
+            id: child_id,
+            workspace_path: self.context.workspace_root.clone(),
+            model: model_id,
+            // Research mode asks before lsp requests that edit files
+            mode: match &definition {
+                Some(def) => def.mode.clone().unwrap_or(AgentMode::Build),
+                None => AgentMode::Research,
+            },
+            messages: vec![],
+            permissions: AgentPermissions { config: permissions },
+            agent: None,
+            parent_id: Some(self.parent_session_id),
+            plan: None,
+        };
+
+        let mut agent = Agent::new(
+            session,
+            model,
+            tools,
+            permission_manager,
+            interaction,
+        );
+        if let Some(def) = definition {
+            agent.set_definition(def);
+        }
+
+        let mut event = SubagentEvent {
+            session_id: child_id.to_string(),
+            parent_session_id: self.parent_session_id.to_string(),
+            agent: agent_name.clone(),
+            description,
+            success: None,
+        };
+        self.emit("subagent-started", &event);
+
+        let result = agent.step(Some(prompt), None).await;
+
+        self.save_transcript(&agent.get_session());
+
+        event.success = Some(result.is_ok());
+        self.emit("subagent-finished", &event);
+
+        let report = result.map_err(|e| format!("Sub-agent '{}' failed: {}", agent_name, e))?;
+        Ok(json!({
+            "session_id": child_id.to_string(),
+            "agent": agent_name,
+            "report": report,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::{ChatRequest, ChatResponse, ConfirmationRequest, ConfirmationResponse, Question, Role};
+    use tokio::sync::mpsc::Sender;
+
+    /// Answers at once and records the tools it was offered
+    #[derive(Default)]
+    struct ReportModel {
+        tools: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ModelAdapter for ReportModel {
+        async fn chat(&self, req: ChatRequest) -> ChatResponse {
+            *self.tools.lock().unwrap() = req
+                .tools
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|t| t["function"]["name"].as_str().map(String::from))
+                .collect();
+            ChatResponse {
+                content: "Found it in src/lib.rs".to_string(),
+                role: Role::Assistant,
+                tool_calls: None,
+                tool_call_id: None,
+            }
+        }
+
+        async fn stream(&self, req: ChatRequest, _tx: Sender<String>) -> ChatResponse {
+            self.chat(req).await
+        }
+    }
+
+    /// Records the events sent to the frontend
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<(String, Value)>>,
+    }
+
+    #[async_trait]
+    impl InteractionProvider for Recorder {
+        async fn confirm(&self, _request: ConfirmationRequest) -> Result<ConfirmationResponse, String> {
+            Err("no confirmations expected".to_string())
+        }
+
+        async fn ask(&self, _session_id: &str, _questions: Vec<Question>) -> Result<Value, String> {
+            Err("no questions expected".to_string())
+        }
+
+        fn notify(&self, event: &str, payload: Value) {
+            self.events.lock().unwrap().push((event.to_string(), payload));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_task_runs_subagent_and_saves_linked_transcript() {
+        let workspace = tempfile::tempdir().unwrap();
+        let data = tempfile::tempdir().unwrap();
+        let storage = Storage::new(&data.path().join("sessions.db").to_string_lossy()).unwrap();
+        let storage = Arc::new(Mutex::new(Some(storage)));
+        let recorder = Arc::new(Recorder::default());
+        let context = ToolContext {
+            workspace_root: workspace.path().to_path_buf(),
+            interaction: recorder.clone(),
+            permission_manager: Arc::new(tokio::sync::Mutex::new(crate::config::PermissionConfig::default())),
+            lsp_config: None,
+            lsp: Arc::new(crate::lsp::LspPool::new()),
+            checkpoints: None,
+        };
+        let parent_id = Uuid::new_v4();
+        let model = Arc::new(ReportModel::default());
+        let parent_model = ParentModel {
+            adapter: model.clone(),
+            model_id: ModelId("test-model".to_string()),
+            provider: "openai".to_string(),
+            api_key: String::new(),
+        };
+        let tool = TaskTool::new(context, storage.clone(), parent_id, parent_model);
+
+        assert!(tool.execute(json!({ "description": "empty" })).await.is_err());
+
+        let result = tool
+            .execute(json!({ "description": "find lib", "prompt": "Where is the library entry point?" }))
+            .await
+            .unwrap();
+        assert_eq!(result["report"], "Found it in src/lib.rs");
+        assert_eq!(result["agent"], "general");
+        // The general agent only gets tools that read
+        let offered = model.tools.lock().unwrap().clone();
+        assert!(offered.contains(&"read_file".to_string()));
+        assert!(offered.iter().all(|name| GENERAL_AGENT_TOOLS.contains(&name.as_str())));
+        for mutating in ["write_file", "edit_file", "multi_edit", "patch", "bash", "git", "task"] {
+            assert!(!offered.iter().any(|name| name == mutating), "{} offered", mutating);
+        }
+        let child_id = result["session_id"].as_str().unwrap().to_string();
+
+        let guard = storage.lock().unwrap();
+        let storage = guard.as_ref().unwrap();
+        let children = storage.list_child_sessions(&parent_id.to_string()).unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].id, child_id);
+        assert_eq!(storage.load_session(&child_id).unwrap().parent_id, Some(parent_id));
+        assert!(storage.list_sessions().unwrap().iter().all(|s| s.id != child_id));
+
+        let events = recorder.events.lock().unwrap();
+        let subagent_events: Vec<&(String, Value)> =
+            events.iter().filter(|(event, _)| event.starts_with("subagent-")).collect();
+        assert_eq!(subagent_events.len(), 2);
+        assert_eq!(subagent_events[0].0, "subagent-started");
+        assert_eq!(subagent_events[1].1["success"], true);
+        assert!(subagent_events
+            .iter()
+            .all(|(_, payload)| payload["parent_session_id"] == parent_id.to_string() && payload["session_id"] == child_id));
+    }
+}
@@ -0,0 +1,410 @@
+use crate::adapters::tools::files::record_checkpoint_write;
+use crate::domain::checkpoint::CheckpointStore;
+use crate::domain::models::ConfirmationRequest;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+struct StagedFile {
+    path: PathBuf,
+    /// Path as the model gave it, for messages and the confirmation UI
+    label: String,
+    /// Bytes on disk when the file was first staged; `None` for new files.
+    /// Kept as bytes so files that are not UTF-8 can be replaced, deleted
+    /// and restored as they were.
+    original: Option<Vec<u8>>,
+    /// New content; `None` deletes the file
+    content: Option<String>,
+    /// Executable bit to set on the written file, if it should change
+    executable: Option<bool>,
+}
+
+/// File changes that are validated up front and written all or nothing.
+///
+/// New contents are first written to temp files next to their targets and
+/// then renamed over them. If any step fails, files already renamed get
+/// their original content back and files created by the transaction are
+/// removed.
+#[derive(Default)]
+pub struct EditTransaction {
+    files: Vec<StagedFile>,
+}
+
+impl EditTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    fn find(&self, path: &Path) -> Option<&StagedFile> {
+        self.files.iter().find(|file| file.path == path)
+    }
+
+    /// Content of `path` as the transaction would leave it: what is staged,
+    /// otherwise what is on disk. `None` if the file does not exist.
+    pub fn read(&self, path: &Path) -> Result<Option<String>, String> {
+        if let Some(file) = self.find(path) {
+            return Ok(file.content.clone());
+        }
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read file {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Whether `path` exists once the transaction is applied. Unlike
+    /// [`read`](Self::read) this works for files that are not UTF-8.
+    pub fn exists(&self, path: &Path) -> Result<bool, String> {
+        match self.find(path) {
+            Some(file) => Ok(file.content.is_some()),
+            None => Ok(read_bytes(path)?.is_some()),
+        }
+    }
+
+    /// Set the new content of `path`. Staging a file again replaces the
+    /// content but keeps the original from the first time.
+    pub fn stage(&mut self, path: PathBuf, label: &str, content: String) -> Result<(), String> {
+        self.stage_content(path, label, Some(content))
+    }
+
+    /// Remove `path` when the transaction commits
+    pub fn stage_delete(&mut self, path: PathBuf, label: &str) -> Result<(), String> {
+        self.stage_content(path, label, None)
+    }
+
+    fn stage_content(&mut self, path: PathBuf, label: &str, content: Option<String>) -> Result<(), String> {
+        if let Some(file) = self.files.iter_mut().find(|file| file.path == path) {
+            file.content = content;
+            return Ok(());
+        }
+        let original = read_bytes(&path)?;
+        self.files.push(StagedFile {
+            path,
+            label: label.to_string(),
+            original,
+            content,
+            executable: None,
+        });
+        Ok(())
+    }
+
+    /// Set or clear the executable bit of a staged file when it is written.
+    /// Has no effect on platforms without Unix permissions.
+    pub fn set_executable(&mut self, path: &Path, executable: bool) -> Result<(), String> {
+        match self.files.iter_mut().find(|file| file.path == path) {
+            Some(file) => {
+                file.executable = Some(executable);
+                Ok(())
+            }
+            None => Err(format!("{} is not staged", path.display())),
+        }
+    }
+
+    /// Paths of the staged files, in staging order
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.iter().map(|file| file.path.clone()).collect()
+    }
+
+    /// Labels of the staged files, in staging order
+    pub fn labels(&self) -> Vec<String> {
+        self.files.iter().map(|file| file.label.clone()).collect()
+    }
+
+    /// One review request covering every staged file. Several files are
+    /// shown as one document with a header line before each file.
+    pub fn confirmation(&self, session_id: &str, tool_name: &str) -> ConfirmationRequest {
+        if let [file] = self.files.as_slice() {
+            return ConfirmationRequest::diff(
+                session_id,
+                tool_name,
+                &file.label,
+                file.original.as_deref().map(|original| String::from_utf8_lossy(original).to_string()),
+                file.content.clone().unwrap_or_default(),
+            );
+        }
+
+        let mut old = String::new();
+        let mut new = String::new();
+        for file in &self.files {
+            let header = match file.content {
+                Some(_) => format!("===== {} =====\n", file.label),
+                None => format!("===== {} (deleted) =====\n", file.label),
+            };
+            old.push_str(&header);
+            new.push_str(&header);
+            push_section(&mut old, &String::from_utf8_lossy(file.original.as_deref().unwrap_or_default()));
+            push_section(&mut new, file.content.as_deref().unwrap_or(""));
+        }
+
+        let title = format!("{} files: {}", self.files.len(), self.labels().join(", "));
+        let mut request = ConfirmationRequest::diff(session_id, tool_name, &title, Some(old), new);
+        // Several files have no single pattern to allow
+        request.suggested_pattern = String::new();
+        request
+    }
+
+    /// Write or delete every staged file, or none of them. Fails without
+    /// writing if a file changed on disk since it was staged, e.g. while
+    /// the user was reviewing the change, or can no longer be read.
+    pub fn commit(self, checkpoints: Option<&CheckpointStore>) -> Result<(), String> {
+        for file in &self.files {
+            let current = read_bytes(&file.path).map_err(|e| format!("{}. No files were changed.", e))?;
+            if current != file.original {
+                return Err(format!(
+                    "{} changed on disk while this change was pending. No files were changed. Read it again and redo the change.",
+                    file.label
+                ));
+            }
+        }
+
+        // Renaming over a symlink would replace the link, so write to its target
+        let targets: Vec<PathBuf> = self
+            .files
+            .iter()
+            .map(|file| fs::canonicalize(&file.path).unwrap_or_else(|_| file.path.clone()))
+            .collect();
+
+        // Deletions have no temp file
+        let mut temps: Vec<Option<PathBuf>> = Vec::with_capacity(targets.len());
+        for (file, target) in self.files.iter().zip(&targets) {
+            let Some(content) = &file.content else {
+                temps.push(None);
+                continue;
+            };
+            match write_temp(target, content.as_bytes(), file.executable) {
+                Ok(temp) => temps.push(Some(temp)),
+                Err(e) => {
+                    remove_all(&temps);
+                    return Err(format!("Failed to write {}: {}. No files were changed.", file.label, e));
+                }
+            }
+        }
+
+        if let Some(checkpoints) = checkpoints {
+            for target in &targets {
+                if let Err(e) = checkpoints.snapshot(target) {
+                    remove_all(&temps);
+                    return Err(e);
+                }
+            }
+        }
+
+        for (index, (temp, target)) in temps.iter().zip(&targets).enumerate() {
+            let result = match temp {
+                Some(temp) => fs::rename(temp, target),
+                None => match fs::remove_file(target) {
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                    other => other,
+                },
+            };
+            if let Err(e) = result {
+                self.roll_back(&targets[..index]);
+                remove_all(&temps[index..]);
+                return Err(format!(
+                    "Failed to write {}: {}. No files were changed.",
+                    self.files[index].label, e
+                ));
+            }
+        }
+
+        for target in &targets {
+            record_checkpoint_write(checkpoints, target);
+        }
+        Ok(())
+    }
+
+    /// Put back the original content of files already renamed into place
+    fn roll_back(&self, targets: &[PathBuf]) {
+        for (file, target) in self.files.iter().zip(targets) {
+            let _ = match &file.original {
+                Some(original) => write_temp(target, original, None).and_then(|temp| fs::rename(&temp, target)),
+                None => fs::remove_file(target),
+            };
+        }
+    }
+}
+
+fn push_section(document: &mut String, content: &str) {
+    document.push_str(content);
+    if !content.is_empty() && !content.ends_with('\n') {
+        document.push('\n');
+    }
+    document.push('\n');
+}
+
+/// Bytes of `path`, or `None` if it does not exist
+fn read_bytes(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read file {}: {}", path.display(), e)),
+    }
+}
+
+/// Write `content` to a hidden temp file next to `target`, keeping the
+/// target's permissions apart from a requested executable bit
+fn write_temp(target: &Path, content: &[u8], executable: Option<bool>) -> std::io::Result<PathBuf> {
+    let parent = target.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+
+    let name = target.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let temp = parent.join(format!(".{}.anvil-{}.tmp", name, &Uuid::new_v4().simple().to_string()[..8]));
+    fs::write(&temp, content)?;
+    if let Ok(metadata) = fs::metadata(target) {
+        let _ = fs::set_permissions(&temp, metadata.permissions());
+    }
+    #[cfg(unix)]
+    if let Some(executable) = executable {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = fs::metadata(&temp)?.permissions();
+        let mode = permissions.mode();
+        permissions.set_mode(if executable { mode | (mode & 0o444) >> 2 } else { mode & !0o111 });
+        fs::set_permissions(&temp, permissions)?;
+    }
+    #[cfg(not(unix))]
+    let _ = executable;
+    Ok(temp)
+}
+
+fn remove_all(paths: &[Option<PathBuf>]) {
+    for path in paths.iter().flatten() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_writes_all_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("a.txt");
+        fs::write(&existing, "old\n").unwrap();
+
+        let mut tx = EditTransaction::new();
+        tx.stage(existing.clone(), "a.txt", "first\n".to_string()).unwrap();
+        tx.stage(existing.clone(), "a.txt", "second\n".to_string()).unwrap();
+        tx.stage(dir.path().join("sub/b.txt"), "sub/b.txt", "new\n".to_string()).unwrap();
+        assert_eq!(tx.read(&existing).unwrap().as_deref(), Some("second\n"));
+
+        let request = tx.confirmation("s1", "multi_edit");
+        assert_eq!(request.old_content.as_deref(), Some("===== a.txt =====\nold\n\n===== sub/b.txt =====\n\n"));
+        assert_eq!(request.new_content.as_deref(), Some("===== a.txt =====\nsecond\n\n===== sub/b.txt =====\nnew\n\n"));
+
+        tx.commit(None).unwrap();
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "second\n");
+        assert_eq!(fs::read_to_string(dir.path().join("sub/b.txt")).unwrap(), "new\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_failed_rename_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("a.txt");
+        fs::write(&first, "keep\n").unwrap();
+        let created = dir.path().join("c.txt");
+        // A directory in the way makes the last rename fail
+        let blocked = dir.path().join("blocked");
+        fs::create_dir_all(blocked.join("inner")).unwrap();
+
+        let mut tx = EditTransaction::new();
+        tx.stage(first.clone(), "a.txt", "changed\n".to_string()).unwrap();
+        tx.stage(created.clone(), "c.txt", "created\n".to_string()).unwrap();
+        tx.files.push(StagedFile {
+            path: blocked.clone(),
+            label: "blocked".to_string(),
+            original: None,
+            content: Some("x".to_string()),
+            executable: None,
+        });
+
+        let err = tx.commit(None).unwrap_err();
+        assert!(err.contains("No files were changed"));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "keep\n");
+        assert!(!created.exists());
+        assert!(blocked.join("inner").is_dir());
+        let leftovers: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_delete_and_executable_bit() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("old.sh");
+        fs::write(&old, "echo hi\n").unwrap();
+        let new = dir.path().join("new.sh");
+
+        let mut tx = EditTransaction::new();
+        tx.stage(new.clone(), "new.sh", "echo hi\n".to_string()).unwrap();
+        tx.set_executable(&new, true).unwrap();
+        tx.stage_delete(old.clone(), "old.sh").unwrap();
+        assert_eq!(tx.read(&old).unwrap(), None);
+        assert!(tx.set_executable(&dir.path().join("other"), true).is_err());
+
+        tx.commit(None).unwrap();
+        assert!(!old.exists());
+        assert_ne!(fs::metadata(&new).unwrap().permissions().mode() & 0o100, 0);
+    }
+
+    #[test]
+    fn test_commit_refuses_files_changed_since_staging() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "old\n").unwrap();
+
+        let mut tx = EditTransaction::new();
+        tx.stage(path.clone(), "a.txt", "mine\n".to_string()).unwrap();
+        fs::write(&path, "theirs\n").unwrap();
+
+        assert!(tx.commit(None).unwrap_err().contains("changed on disk"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "theirs\n");
+    }
+
+    #[test]
+    fn test_files_that_are_not_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let replaced = dir.path().join("a.dat");
+        fs::write(&replaced, b"\xff\xfeold\n").unwrap();
+        let deleted = dir.path().join("b.dat");
+        fs::write(&deleted, b"\x00\xff").unwrap();
+
+        let mut tx = EditTransaction::new();
+        tx.stage(replaced.clone(), "a.dat", "text\n".to_string()).unwrap();
+        tx.stage_delete(deleted.clone(), "b.dat").unwrap();
+        assert!(tx.exists(&replaced).unwrap() && !tx.exists(&deleted).unwrap());
+        // Only the review shows the original as text
+        let request = tx.confirmation("s1", "patch");
+        assert!(request.old_content.unwrap().contains("\u{FFFD}\u{FFFD}old"));
+
+        tx.commit(None).unwrap();
+        assert_eq!(fs::read_to_string(&replaced).unwrap(), "text\n");
+        assert!(!deleted.exists());
+
+        // Restored byte for byte when a later file fails
+        fs::write(&replaced, b"\xffkeep").unwrap();
+        let blocked = dir.path().join("blocked");
+        fs::create_dir_all(blocked.join("inner")).unwrap();
+        let mut tx = EditTransaction::new();
+        tx.stage(replaced.clone(), "a.dat", "changed\n".to_string()).unwrap();
+        tx.files.push(StagedFile {
+            path: blocked,
+            label: "blocked".to_string(),
+            original: None,
+            content: Some("x".to_string()),
+            executable: None,
+        });
+        assert!(tx.commit(None).is_err());
+        assert_eq!(fs::read(&replaced).unwrap(), b"\xffkeep");
+    }
+}
@@ -0,0 +1,665 @@
+//! Parsing and applying unified diffs, including git's extended headers.
+//!
+//! Patches written by models often have wrong line numbers or hunk counts,
+//! so the parser ignores the counts and the applier searches for each hunk
+//! around its expected position, with a little fuzz, instead of trusting it.
+
+use serde::Serialize;
+
+/// Context lines that may be dropped from each end of a hunk
+const MAX_FUZZ: usize = 2;
+
+/// Lines of the file shown around a hunk that failed to apply
+const NEARBY_LINES: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    /// First old line, 1-based; for hunks without old lines, the line
+    /// after which the new lines go
+    pub old_start: usize,
+    pub lines: Vec<HunkLine>,
+    /// The old side of the hunk ends the file without a newline
+    pub old_missing_newline: bool,
+    /// The new side of the hunk ends the file without a newline
+    pub new_missing_newline: bool,
+}
+
+/// The changes to one file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilePatch {
+    /// Path as written in the patch; `None` for `/dev/null` (a new file)
+    pub old_path: Option<String>,
+    /// Path as written in the patch; `None` for `/dev/null` (a deletion)
+    pub new_path: Option<String>,
+    /// Paths from git's `rename from`/`rename to` lines, which have no prefix
+    pub rename: Option<(String, String)>,
+    pub old_mode: Option<u32>,
+    pub new_mode: Option<u32>,
+    /// Abbreviated blob id of the old file from git's `index` line
+    pub old_blob: Option<String>,
+    pub binary: bool,
+    /// Came from a `diff --git` header
+    pub git: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// The mode this patch sets, if it creates the file or changes its mode
+    pub fn mode_change(&self) -> Option<u32> {
+        match (self.old_mode, self.new_mode) {
+            (Some(old), Some(new)) if old != new => Some(new),
+            (None, Some(new)) => Some(new),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum HunkOutcome {
+    Applied {
+        /// 1-based line in the file as patched so far
+        line: usize,
+        /// Lines between where the hunk said it goes and where it was found
+        offset: isize,
+        /// Context lines dropped from each end to make it fit
+        fuzz: usize,
+        /// Matched only after ignoring trailing whitespace
+        ignored_whitespace: bool,
+    },
+    Failed {
+        reason: String,
+        /// Numbered file lines around where the hunk was expected
+        nearby: Vec<String>,
+    },
+}
+
+/// Result of applying one file's hunks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Application {
+    /// The patched content, when every hunk applied
+    pub content: Option<String>,
+    pub hunks: Vec<HunkOutcome>,
+}
+
+impl Application {
+    pub fn failed_hunks(&self) -> usize {
+        self.hunks
+            .iter()
+            .filter(|hunk| matches!(hunk, HunkOutcome::Failed { .. }))
+            .count()
+    }
+}
+
+/// Split a patch into per-file patches. Text before the first file header
+/// and between files (commit messages, email headers) is skipped.
+pub fn parse(text: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = text.lines().map(|line| line.strip_suffix('\r').unwrap_or(line)).collect();
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut current: Option<FilePatch> = None;
+    // Whether `current` already had its ---/+++ pair
+    let mut has_file_lines = false;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            patches.extend(current.take());
+            let (old, new) = split_git_paths(rest);
+            current = Some(FilePatch {
+                old_path: Some(old),
+                new_path: Some(new),
+                git: true,
+                ..FilePatch::default()
+            });
+            has_file_lines = false;
+            i += 1;
+            continue;
+        }
+
+        if is_file_header(&lines, i) {
+            let starts_new = match &current {
+                Some(patch) => !patch.git || has_file_lines || !patch.hunks.is_empty(),
+                None => true,
+            };
+            if starts_new {
+                patches.extend(current.take());
+            }
+            let patch = current.get_or_insert_with(FilePatch::default);
+            patch.old_path = header_path(&line[4..]);
+            patch.new_path = header_path(&lines[i + 1][4..]);
+            has_file_lines = true;
+            i += 2;
+            continue;
+        }
+
+        if line.starts_with("@@ ") {
+            let Some(patch) = current.as_mut() else {
+                return Err(format!("hunk at line {} has no file header before it", i + 1));
+            };
+            let (hunk, next) = parse_hunk(&lines, i)?;
+            patch.hunks.push(hunk);
+            i = next;
+            continue;
+        }
+
+        if let Some(patch) = current.as_mut().filter(|patch| patch.git && patch.hunks.is_empty()) {
+            parse_extended_header(patch, line);
+        }
+        i += 1;
+    }
+    patches.extend(current);
+
+    if patches.is_empty() {
+        return Err("no file headers (---/+++ or diff --git) found".to_string());
+    }
+    for patch in &patches {
+        if !patch.git && patch.hunks.is_empty() {
+            let name = patch.new_path.as_deref().or(patch.old_path.as_deref()).unwrap_or("?");
+            return Err(format!("no hunks for {}", name));
+        }
+    }
+    Ok(patches)
+}
+
+fn is_file_header(lines: &[&str], i: usize) -> bool {
+    lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|next| next.starts_with("+++ "))
+}
+
+fn parse_extended_header(patch: &mut FilePatch, line: &str) {
+    let mode = |value: &str| u32::from_str_radix(value.trim(), 8).ok();
+
+    if let Some(value) = line.strip_prefix("old mode ") {
+        patch.old_mode = mode(value);
+    } else if let Some(value) = line.strip_prefix("new mode ") {
+        patch.new_mode = mode(value);
+    } else if let Some(value) = line.strip_prefix("deleted file mode ") {
+        patch.old_mode = mode(value);
+        patch.new_path = None;
+    } else if let Some(value) = line.strip_prefix("new file mode ") {
+        patch.new_mode = mode(value);
+        patch.old_path = None;
+    } else if let Some(path) = line.strip_prefix("rename from ") {
+        let to = patch.rename.take().map(|(_, to)| to).unwrap_or_default();
+        patch.rename = Some((unquote(path), to));
+    } else if let Some(path) = line.strip_prefix("rename to ") {
+        let from = patch.rename.take().map(|(from, _)| from).unwrap_or_default();
+        patch.rename = Some((from, unquote(path)));
+    } else if let Some(value) = line.strip_prefix("index ") {
+        let mut parts = value.split_whitespace();
+        if let Some((old, _)) = parts.next().and_then(|ids| ids.split_once("..")) {
+            if old.bytes().any(|b| b != b'0') {
+                patch.old_blob = Some(old.to_string());
+            }
+        }
+        // `index <old>..<new> <mode>` when the mode is unchanged
+        if let Some(value) = parts.next() {
+            patch.old_mode = mode(value);
+            patch.new_mode = mode(value);
+        }
+    } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+        patch.binary = true;
+    }
+}
+
+/// Parse the hunk whose header is at `start`, returning it and the index
+/// of the first line after it
+fn parse_hunk(lines: &[&str], start: usize) -> Result<(Hunk, usize), String> {
+    let header = lines[start];
+    let (old_start, old_count, new_count) =
+        parse_hunk_header(header).ok_or_else(|| format!("invalid hunk header at line {}: {}", start + 1, header))?;
+
+    let mut hunk = Hunk {
+        old_start,
+        lines: Vec::new(),
+        old_missing_newline: false,
+        new_missing_newline: false,
+    };
+    let (mut old_seen, mut new_seen) = (0, 0);
+    let mut i = start + 1;
+
+    while i < lines.len() {
+        let line = lines[i];
+        // git format-patch ends with a "-- " signature line
+        if line == "-- " && old_seen >= old_count && new_seen >= new_count {
+            break;
+        }
+        if is_file_header(lines, i) {
+            break;
+        }
+        let parsed = match line.chars().next() {
+            Some(' ') => HunkLine::Context(line[1..].to_string()),
+            Some('-') => HunkLine::Remove(line[1..].to_string()),
+            Some('+') => HunkLine::Add(line[1..].to_string()),
+            Some('\\') => {
+                match hunk.lines.last() {
+                    Some(HunkLine::Remove(_)) => hunk.old_missing_newline = true,
+                    Some(HunkLine::Add(_)) => hunk.new_missing_newline = true,
+                    Some(HunkLine::Context(_)) => {
+                        hunk.old_missing_newline = true;
+                        hunk.new_missing_newline = true;
+                    }
+                    None => {}
+                }
+                i += 1;
+                continue;
+            }
+            // Editors and models often strip the space from empty context
+            // lines; treat a blank line as context unless the hunk ends there
+            None if continues_hunk(lines, i + 1) => HunkLine::Context(String::new()),
+            _ => break,
+        };
+        match &parsed {
+            HunkLine::Context(_) => {
+                old_seen += 1;
+                new_seen += 1;
+            }
+            HunkLine::Remove(_) => old_seen += 1,
+            HunkLine::Add(_) => new_seen += 1,
+        }
+        hunk.lines.push(parsed);
+        i += 1;
+    }
+
+    if hunk.lines.is_empty() {
+        return Err(format!("hunk at line {} is empty", start + 1));
+    }
+    Ok((hunk, i))
+}
+
+fn continues_hunk(lines: &[&str], from: usize) -> bool {
+    match lines[from.min(lines.len())..].iter().position(|line| !line.is_empty()) {
+        Some(offset) => {
+            let index = from + offset;
+            matches!(lines[index].chars().next(), Some(' ' | '-' | '+' | '\\')) && !is_file_header(lines, index)
+        }
+        None => false,
+    }
+}
+
+/// `@@ -12,5 +12,6 @@ fn name` to (12, 5, 6). A missing count means 1.
+fn parse_hunk_header(header: &str) -> Option<(usize, usize, usize)> {
+    let rest = header.strip_prefix("@@ -")?;
+    let (ranges, _) = rest.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = range(old)?;
+    let (_, new_count) = range(new)?;
+    Some((old_start, old_count, new_count))
+}
+
+/// The two paths of `diff --git a/x b/x`. Paths with spaces are ambiguous
+/// here, so prefer the split where both sides name the same file.
+fn split_git_paths(rest: &str) -> (String, String) {
+    if let Some(quoted) = rest.strip_prefix('"') {
+        if let Some(end) = quoted.find("\" ") {
+            return (unquote(&rest[..end + 2]), unquote(quoted[end + 2..].trim()));
+        }
+    }
+    let candidates: Vec<usize> = rest.match_indices(' ').map(|(i, _)| i).collect();
+    let same_file = candidates.iter().find(|&&i| {
+        let (old, new) = (&rest[..i], &rest[i + 1..]);
+        old.get(2..).is_some_and(|old| new.get(2..) == Some(old)) || old == new
+    });
+    match same_file.or(candidates.last()) {
+        Some(&i) => (unquote(&rest[..i]), unquote(&rest[i + 1..])),
+        None => (rest.to_string(), rest.to_string()),
+    }
+}
+
+/// Path from a `---`/`+++` line, without any trailing timestamp.
+/// `None` for `/dev/null`.
+fn header_path(value: &str) -> Option<String> {
+    let value = value.split('\t').next().unwrap_or(value).trim_end();
+    let path = unquote(value);
+    (path != "/dev/null").then_some(path)
+}
+
+/// Undo git's C-style quoting of unusual paths
+fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+        return value.to_string();
+    };
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+/// Apply `hunks` to `content`. Every hunk is tried, so a failed result
+/// reports all hunks that do not fit rather than only the first.
+pub fn apply(content: &str, hunks: &[Hunk]) -> Application {
+    let eol = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut ends_with_newline = content.is_empty() || content.ends_with('\n');
+    let mut lines: Vec<String> = content
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+        .collect();
+
+    let mut outcomes = Vec::with_capacity(hunks.len());
+    // Where the next hunk is expected relative to its own line numbers
+    let mut shift: isize = 0;
+    // Hunks apply in order and may not overlap
+    let mut floor = 0;
+    let mut all_applied = true;
+
+    for hunk in hunks {
+        let old: Vec<&str> = hunk.lines.iter().filter_map(old_text).collect();
+        let expected = if old.is_empty() { hunk.old_start } else { hunk.old_start.saturating_sub(1) };
+        let expected = (expected as isize + shift).clamp(0, lines.len() as isize) as usize;
+
+        let Some(found) = locate(&lines, hunk, expected, floor) else {
+            all_applied = false;
+            outcomes.push(HunkOutcome::Failed {
+                reason: failure_reason(&old, expected),
+                nearby: nearby(&lines, expected),
+            });
+            continue;
+        };
+
+        // Context keeps the file's version of each line
+        let kept = &hunk.lines[found.lead..hunk.lines.len() - found.trail];
+        let mut replacement = Vec::with_capacity(kept.len());
+        let mut cursor = found.start;
+        for line in kept {
+            match line {
+                HunkLine::Context(_) => {
+                    replacement.push(lines[cursor].clone());
+                    cursor += 1;
+                }
+                HunkLine::Remove(_) => cursor += 1,
+                HunkLine::Add(text) => replacement.push(text.clone()),
+            }
+        }
+
+        let reaches_end = cursor == lines.len();
+        if reaches_end && found.trail == 0 {
+            if hunk.new_missing_newline {
+                ends_with_newline = false;
+            } else if hunk.old_missing_newline {
+                ends_with_newline = true;
+            }
+        }
+
+        let offset = found.start as isize - (expected + found.lead) as isize;
+        let added = replacement.len();
+        lines.splice(found.start..cursor, replacement);
+        shift += offset + added as isize - (cursor - found.start) as isize;
+        floor = found.start + added;
+        outcomes.push(HunkOutcome::Applied {
+            line: found.start + 1,
+            offset,
+            fuzz: found.lead.max(found.trail),
+            ignored_whitespace: found.loose,
+        });
+    }
+
+    let content = all_applied.then(|| {
+        let mut out = lines.join(eol);
+        if ends_with_newline && !lines.is_empty() {
+            out.push_str(eol);
+        }
+        out
+    });
+    Application { content, hunks: outcomes }
+}
+
+fn old_text(line: &HunkLine) -> Option<&str> {
+    match line {
+        HunkLine::Context(text) | HunkLine::Remove(text) => Some(text),
+        HunkLine::Add(_) => None,
+    }
+}
+
+struct Found {
+    start: usize,
+    /// Hunk lines dropped from the start and end
+    lead: usize,
+    trail: usize,
+    loose: bool,
+}
+
+/// Find where the old side of `hunk` is in `lines`, searching outward from
+/// `expected` and allowing more fuzz only when less does not fit
+fn locate(lines: &[String], hunk: &Hunk, expected: usize, floor: usize) -> Option<Found> {
+    let leading_context = hunk.lines.iter().take_while(|l| matches!(l, HunkLine::Context(_))).count();
+    let trailing_context = hunk.lines.iter().rev().take_while(|l| matches!(l, HunkLine::Context(_))).count();
+
+    for fuzz in 0..=MAX_FUZZ {
+        let lead = fuzz.min(leading_context);
+        let trail = fuzz.min(trailing_context);
+        if fuzz > 0 && lead + trail == 0 {
+            break;
+        }
+        let kept = &hunk.lines[lead..hunk.lines.len().saturating_sub(trail).max(lead)];
+        let old: Vec<&str> = kept.iter().filter_map(old_text).collect();
+        // Dropping all the context of a pure insertion would let it go anywhere
+        if old.is_empty() && fuzz > 0 {
+            break;
+        }
+        if old.is_empty() {
+            return Some(Found { start: expected.max(floor).min(lines.len()), lead, trail, loose: false });
+        }
+        for loose in [false, true] {
+            if let Some(start) = search(lines, &old, expected + lead, floor, loose) {
+                return Some(Found { start, lead, trail, loose });
+            }
+        }
+    }
+    None
+}
+
+fn search(lines: &[String], old: &[&str], expected: usize, floor: usize, loose: bool) -> Option<usize> {
+    if old.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - old.len();
+    if floor > last {
+        return None;
+    }
+    let fits = |start: usize| {
+        lines[start..start + old.len()].iter().zip(old).all(|(line, want)| {
+            if loose {
+                line.trim_end() == want.trim_end()
+            } else {
+                line == want
+            }
+        })
+    };
+
+    let expected = expected.clamp(floor, last);
+    for distance in 0..=(last - floor) {
+        if let Some(start) = expected.checked_add(distance).filter(|&s| s <= last) {
+            if fits(start) {
+                return Some(start);
+            }
+        }
+        if let Some(start) = expected.checked_sub(distance).filter(|&s| s >= floor && distance > 0) {
+            if fits(start) {
+                return Some(start);
+            }
+        }
+    }
+    None
+}
+
+fn failure_reason(old: &[&str], expected: usize) -> String {
+    let first = old.iter().find(|line| !line.trim().is_empty()).copied().unwrap_or("");
+    format!(
+        "the {} original line(s) of this hunk, starting with {:?}, were not found near line {}",
+        old.len(),
+        first,
+        expected + 1
+    )
+}
+
+fn nearby(lines: &[String], around: usize) -> Vec<String> {
+    let start = around.saturating_sub(NEARBY_LINES);
+    let end = (around + NEARBY_LINES + 1).min(lines.len());
+    (start..end).map(|i| format!("{:>5}: {}", i + 1, lines[i])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_git_headers() {
+        let text = "\
+From 1234 Mon Sep 17 00:00:00 2001
+Subject: [PATCH] move things
+
+diff --git a/old.txt b/new.txt
+similarity index 90%
+rename from old.txt
+rename to new.txt
+index 83db48f..bf269f4 100644
+--- a/old.txt
++++ b/new.txt
+@@ -1,2 +1,2 @@
+ keep
+-old
++new
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index 83db48f..0000000
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+diff --git a/logo.png b/logo.png
+new file mode 100644
+index 0000000..3f1a2b4
+Binary files /dev/null and b/logo.png differ
+--
+2.40.0
+";
+        let patches = parse(text).unwrap();
+        assert_eq!(patches.len(), 4);
+
+        assert_eq!(patches[0].rename, Some(("old.txt".to_string(), "new.txt".to_string())));
+        assert_eq!(patches[0].old_blob.as_deref(), Some("83db48f"));
+        assert_eq!(patches[0].hunks[0].lines.len(), 3);
+        assert_eq!(patches[0].mode_change(), None);
+
+        assert_eq!(patches[1].new_path, None);
+        assert_eq!(patches[1].hunks[0].lines, vec![HunkLine::Remove("bye".to_string())]);
+
+        assert_eq!(patches[2].old_path.as_deref(), Some("a/run.sh"));
+        assert_eq!(patches[2].mode_change(), Some(0o100755));
+        assert!(patches[2].hunks.is_empty());
+
+        assert_eq!(patches[3].old_path, None);
+        assert!(patches[3].binary);
+
+        assert!(parse("This is not a valid patch").is_err());
+        assert!(parse("--- a.txt\n+++ a.txt\n").is_err());
+    }
+
+    #[test]
+    fn test_apply_with_offset_fuzz_and_loose_counts() {
+        let content = "intro\nextra\nfn main() {\n    one();\n    two();\n}\n";
+        // Wrong line numbers and counts
+        let patch = "\
+--- a.rs
++++ a.rs
+@@ -1,4 +1,9 @@
+ fn main() {
+     one();
+-    two();
++    three();
+ }
+";
+        let patches = parse(patch).unwrap();
+        let applied = apply(content, &patches[0].hunks);
+        assert_eq!(
+            applied.content.as_deref(),
+            Some("intro\nextra\nfn main() {\n    one();\n    three();\n}\n")
+        );
+        assert!(matches!(applied.hunks[0], HunkOutcome::Applied { line: 3, offset: 2, fuzz: 0, .. }));
+
+        // The first context line no longer matches, so it is dropped
+        let changed = "fn main() -> () {\n    one();\n    two();\n}\n";
+        let applied = apply(changed, &patches[0].hunks);
+        assert_eq!(applied.content.as_deref(), Some("fn main() -> () {\n    one();\n    three();\n}\n"));
+        assert!(matches!(applied.hunks[0], HunkOutcome::Applied { fuzz: 1, .. }));
+
+        let blank_context = parse("--- a\n+++ a\n@@ -1,3 +1,3 @@\n a\n\n-b\n+c\n").unwrap();
+        let applied = apply("a\n\nb\n", &blank_context[0].hunks);
+        assert_eq!(applied.content.as_deref(), Some("a\n\nc\n"));
+    }
+
+    #[test]
+    fn test_apply_reports_each_failed_hunk() {
+        let patch = "\
+--- a.txt
++++ a.txt
+@@ -1,1 +1,1 @@
+-missing
++x
+@@ -3,1 +3,1 @@
+-three
++3
+";
+        let patches = parse(patch).unwrap();
+        let applied = apply("one\ntwo\nthree\n", &patches[0].hunks);
+        assert_eq!(applied.content, None);
+        assert_eq!(applied.failed_hunks(), 1);
+        match &applied.hunks[0] {
+            HunkOutcome::Failed { reason, nearby } => {
+                assert!(reason.contains("\"missing\""));
+                assert_eq!(nearby[0], "    1: one");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(applied.hunks[1], HunkOutcome::Applied { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_apply_newlines_and_new_files() {
+        let patch = "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+ one
+-two
+\\ No newline at end of file
++2
+";
+        let patches = parse(patch).unwrap();
+        assert!(patches[0].hunks[0].old_missing_newline);
+        let applied = apply("one\r\ntwo", &patches[0].hunks);
+        assert_eq!(applied.content.as_deref(), Some("one\r\n2\r\n"));
+
+        let created = parse("--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1,2 @@\n+hello\n+world\n").unwrap();
+        assert_eq!(created[0].old_path, None);
+        assert_eq!(apply("", &created[0].hunks).content.as_deref(), Some("hello\nworld\n"));
+    }
+}
@@ -1,5 +1,8 @@
+use crate::adapters::interaction::PendingRequests;
 use crate::domain::agent::Agent;
 use crate::domain::orchestrator::Orchestrator;
+use crate::domain::steering::SteeringInbox;
+use crate::lsp::LspPool;
 use crate::storage::Storage;
 use crate::terminal::TerminalManager;
 use std::collections::HashMap;
@@ -9,17 +12,14 @@ use uuid::Uuid;
 pub struct AppState {
     pub agents: Arc<tokio::sync::Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<Agent>>>>>,
     pub terminal: Mutex<TerminalManager>,
-    pub pending_confirmations: Arc<
-        Mutex<
-            HashMap<
-                String,
-                tokio::sync::oneshot::Sender<crate::domain::models::ConfirmationResponse>,
-            >,
-        >,
-    >,
+    pub pending_requests: Arc<PendingRequests>,
+    /// Steering inboxes by session, reachable while the agent is busy
+    pub steering: Arc<Mutex<HashMap<Uuid, Arc<SteeringInbox>>>>,
     pub storage: Arc<Mutex<Option<Storage>>>,
     pub orchestrator: tokio::sync::Mutex<Option<Orchestrator>>,
     pub config_watchers: Arc<std::sync::Mutex<std::collections::HashSet<std::path::PathBuf>>>,
+    /// Language servers shared by the sessions of each workspace
+    pub lsp: Arc<LspPool>,
 }
 
 impl AppState {
@@ -27,10 +27,12 @@ impl AppState {
         Self {
             agents: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
             terminal: Mutex::new(TerminalManager::new()),
-            pending_confirmations: Arc::new(Mutex::new(HashMap::new())),
+            pending_requests: Arc::new(PendingRequests::new()),
+            steering: Arc::new(Mutex::new(HashMap::new())),
             storage: Arc::new(Mutex::new(None)),
             orchestrator: tokio::sync::Mutex::new(None),
             config_watchers: Arc::new(std::sync::Mutex::new(std::collections::HashSet::new())),
+            lsp: Arc::new(LspPool::new()),
         }
     }
 
@@ -46,6 +48,37 @@ impl AppState {
         Ok(())
     }
 
+    /// Remove worktrees left behind by sessions that were deleted or never
+    /// saved
+    pub fn clean_orphaned_worktrees(&self) {
+        let orphans = match self.with_storage(|storage| storage.orphaned_worktrees()) {
+            Ok(orphans) => orphans,
+            Err(e) => {
+                eprintln!("Warning: failed to list worktrees: {}", e);
+                return;
+            }
+        };
+        for worktree in orphans {
+            let removed = worktree
+                .remove()
+                .and_then(|_| self.with_storage(|storage| storage.delete_worktree(&worktree.session_id)));
+            if let Err(e) = removed {
+                eprintln!("Warning: failed to remove worktree {}: {}", worktree.path.display(), e);
+            }
+        }
+    }
+
+    /// Fail the prompts `session_id` and its sub-agents are waiting on.
+    /// A turn blocked on one still holds the agent, so dropping the agent
+    /// alone would leave them open until they time out.
+    pub fn cancel_prompts(&self, session_id: &str) {
+        self.pending_requests.cancel_session(session_id);
+        let children = self.with_storage(|storage| storage.list_child_sessions(session_id)).unwrap_or_default();
+        for child in children {
+            self.pending_requests.cancel_session(&child.id);
+        }
+    }
+
     pub fn with_storage<F, R>(&self, f: F) -> Result<R, String>
     where
         F: FnOnce(&Storage) -> Result<R, String>,
@@ -57,3 +90,58 @@ impl AppState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::interaction::DEFAULT_TIMEOUT;
+    use crate::domain::models::{AgentMode, AgentPermissions, AgentSession, ModelId};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn session(parent_id: Option<Uuid>) -> AgentSession {
+        AgentSession {
+            id: Uuid::new_v4(),
+            workspace_path: PathBuf::from("/tmp/project"),
+            model: ModelId("gpt-4".to_string()),
+            mode: AgentMode::Build,
+            messages: Vec::new(),
+            permissions: AgentPermissions { config: crate::config::PermissionConfig::default() },
+            agent: None,
+            parent_id,
+            plan: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_prompts_fails_pending_waiters_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AppState::new();
+        state.init_storage(&dir.path().join("sessions.db").to_string_lossy()).unwrap();
+        let parent = session(None);
+        let saved_child = session(Some(parent.id));
+        state
+            .with_storage(|storage| {
+                storage.save_session(&parent)?;
+                storage.save_session(&saved_child)
+            })
+            .unwrap();
+        let (parent_id, child_id) = (parent.id.to_string(), saved_child.id.to_string());
+
+        let pending = state.pending_requests.clone();
+        let own = pending.register_confirmation(&parent_id, None);
+        let saved = pending.register_confirmation(&child_id, None);
+        // A running sub-agent is not saved yet; its request names the parent
+        let running = pending.register_confirmation("running-child", Some(&parent_id));
+        let (_other, _other_rx) = pending.register_confirmation("other", None);
+
+        state.cancel_prompts(&parent_id);
+        for (id, rx) in [own, saved, running] {
+            let result = tokio::time::timeout(Duration::from_secs(1), pending.wait_confirmation(&id, rx, DEFAULT_TIMEOUT))
+                .await
+                .expect("waiter should fail at once");
+            assert!(result.unwrap_err().contains("cancelled"));
+        }
+        assert_eq!(pending.len(), 1);
+    }
+}
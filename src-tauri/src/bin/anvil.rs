@@ -0,0 +1,5 @@
+#[tokio::main]
+async fn main() {
+    let code = tauri_app_lib::cli::run(std::env::args().skip(1).collect()).await;
+    std::process::exit(code);
+}
@@ -0,0 +1,417 @@
+//! Headless `anvil` command: runs the same agent, config and session storage
+//! as the desktop app from a terminal, either interactively or for one prompt.
+
+use crate::adapters::interaction::{AutoPolicy, AutoResponder, TerminalInteraction};
+use crate::adapters::tools::task::ParentModel;
+use crate::adapters::{build_model_adapter, provider_for_model};
+use crate::config::agents::parse_mode;
+use crate::config::{AgentRegistry, ConfigManager};
+use crate::domain::agent::Agent;
+use crate::domain::models::{AgentMode, AgentPermissions, AgentSession, ModelId};
+use crate::domain::ports::InteractionProvider;
+use crate::lsp::LspPool;
+use crate::session::{build_agent, saved_definition, SessionHost};
+use crate::storage::Storage;
+use serde_json::json;
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Same identifier as the desktop app, so both see the same sessions
+const APP_IDENTIFIER: &str = "com.develvir.tauri-app";
+
+const USAGE: &str = "Usage: anvil [OPTIONS] [PROMPT]
+
+Runs the Anvil agent in the terminal. Without a prompt it starts an
+interactive session; with -p it answers one prompt and exits.
+
+Options:
+  -p, --print <PROMPT>   Run one prompt non-interactively (\"-\" reads stdin)
+  -m, --model <MODEL>    Model id (default: `model` from config)
+      --provider <NAME>  openai, anthropic, gemini or ollama (default: from model)
+  -a, --agent <NAME>     Start as a named agent
+      --mode <MODE>      build, plan or research
+  -C, --cwd <DIR>        Workspace directory (default: current directory)
+  -r, --resume <ID>      Continue a saved session
+      --json             Print the result as JSON instead of streaming text
+      --approve <POLICY> Answer confirmations without asking: allow, deny, or
+                         a comma-separated list of tools (default: ask on a
+                         terminal, deny otherwise)
+      --db <PATH>        Session database (default: the desktop app's)
+  -h, --help             Show this help
+
+Interactive commands: /mode <mode>, /session, /exit";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+    pub prompt: Option<String>,
+    pub model: Option<String>,
+    pub provider: Option<String>,
+    pub agent: Option<String>,
+    pub mode: Option<AgentMode>,
+    pub cwd: Option<PathBuf>,
+    pub resume: Option<String>,
+    pub json: bool,
+    pub approve: Option<AutoPolicy>,
+    pub db: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl CliArgs {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = CliArgs::default();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            let mut value = |name: &str| {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| format!("Missing value for {}", name))
+            };
+
+            match arg.as_str() {
+                "-p" | "--print" => parsed.prompt = Some(value(arg)?),
+                "-m" | "--model" => parsed.model = Some(value(arg)?),
+                "--provider" => parsed.provider = Some(value(arg)?),
+                "-a" | "--agent" => parsed.agent = Some(value(arg)?),
+                "--mode" => {
+                    let mode = value(arg)?;
+                    parsed.mode = Some(parse_mode(&mode).ok_or_else(|| format!("Unknown mode: {}", mode))?);
+                }
+                "-C" | "--cwd" => parsed.cwd = Some(PathBuf::from(value(arg)?)),
+                "-r" | "--resume" => parsed.resume = Some(value(arg)?),
+                "--json" => parsed.json = true,
+                "--approve" => parsed.approve = Some(AutoPolicy::parse(&value(arg)?)?),
+                "--db" => parsed.db = Some(PathBuf::from(value(arg)?)),
+                "-h" | "--help" => parsed.help = true,
+                other if other.starts_with('-') && other != "-" => {
+                    return Err(format!("Unknown option: {}", other));
+                }
+                other => {
+                    if parsed.prompt.is_some() {
+                        return Err(format!("Unexpected argument: {}", other));
+                    }
+                    parsed.prompt = Some(other.to_string());
+                }
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Entry point of the `anvil` binary; returns the process exit code
+pub async fn run(args: Vec<String>) -> i32 {
+    let args = match CliArgs::parse(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    if args.help {
+        println!("{}", USAGE);
+        return 0;
+    }
+
+    match run_cli(args).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+async fn run_cli(args: CliArgs) -> Result<(), String> {
+    let storage = open_storage(args.db.clone());
+    let host = SessionHost {
+        interaction: interaction_for(&args),
+        storage: Arc::new(Mutex::new(storage)),
+        lsp: Arc::new(LspPool::new()),
+    };
+
+    let prompt = match args.prompt.as_deref() {
+        Some("-") => Some(read_stdin()?),
+        Some(prompt) => Some(prompt.to_string()),
+        // Piped input without -p is a one-shot prompt too
+        None if !std::io::stdin().is_terminal() => Some(read_stdin()?),
+        None => None,
+    };
+
+    let mut agent = start_agent(&host, &args).await?;
+
+    let result = match prompt {
+        Some(prompt) => {
+            let result = run_prompt(&mut agent, prompt, args.json).await;
+            save(&host, &agent);
+            if args.json {
+                let session = agent.get_session();
+                let output = match &result {
+                    Ok(response) => json!({
+                        "session_id": session.id.to_string(),
+                        "model": session.model.0,
+                        "response": response,
+                    }),
+                    Err(e) => json!({
+                        "session_id": session.id.to_string(),
+                        "model": session.model.0,
+                        "error": e,
+                    }),
+                };
+                println!("{}", output);
+            }
+            result.map(|_| ())
+        }
+        None => interactive(&host, &mut agent, args.json).await,
+    };
+    host.lsp.shutdown_all().await;
+    result
+}
+
+/// Prompt on the terminal when someone is there to answer, otherwise
+/// apply the `--approve` policy (deny by default)
+fn interaction_for(args: &CliArgs) -> Arc<dyn InteractionProvider> {
+    match &args.approve {
+        Some(policy) => Arc::new(AutoResponder::new(policy.clone())),
+        None if std::io::stdin().is_terminal() && !args.json => Arc::new(TerminalInteraction::new()),
+        None => Arc::new(AutoResponder::new(AutoPolicy::DenyAll)),
+    }
+}
+
+async fn start_agent(host: &SessionHost, args: &CliArgs) -> Result<Agent, String> {
+    let workspace = match &args.cwd {
+        Some(dir) => dir.clone(),
+        None => std::env::current_dir().map_err(|e| e.to_string())?,
+    };
+    let workspace = workspace
+        .canonicalize()
+        .map_err(|e| format!("Workspace {} is not accessible: {}", workspace.display(), e))?;
+
+    let mut config_manager = ConfigManager::new();
+    let _ = config_manager.load(Some(&workspace));
+    let config = config_manager.config();
+
+    let (mut session, definition) = match &args.resume {
+        Some(id) => {
+            let guard = host.storage.lock().map_err(|e| e.to_string())?;
+            let storage = guard.as_ref().ok_or("Session storage is unavailable")?;
+            let session = storage.load_session(id)?;
+            let definition = saved_definition(&session.workspace_path, session.agent.as_deref());
+            (session, definition)
+        }
+        None => {
+            let definition = match args.agent.as_deref() {
+                Some(name) => Some(AgentRegistry::find(&workspace, config, name)?),
+                None => None,
+            };
+            let session = AgentSession {
+                id: Uuid::new_v4(),
+                workspace_path: workspace.clone(),
+                model: ModelId(String::new()),
+                mode: definition.as_ref().and_then(|d| d.mode.clone()).unwrap_or(AgentMode::Build),
+                messages: vec![],
+                permissions: AgentPermissions {
+                    config: config.permission.clone(),
+                },
+                agent: None,
+                parent_id: None,
+                plan: None,
+            };
+            (session, definition)
+        }
+    };
+
+    // Flags win over the agent definition, which wins over the session and config
+    let configured = config.model.clone().unwrap_or_default();
+    let (configured_provider, configured_model) = match configured.split_once('/') {
+        Some((provider, model)) => (Some(provider.to_string()), model.to_string()),
+        None => (None, configured),
+    };
+    let model_id = args
+        .model
+        .clone()
+        .or_else(|| definition.as_ref().and_then(|d| d.model.clone()))
+        .or_else(|| Some(session.model.0.clone()).filter(|m| !m.is_empty()))
+        .unwrap_or(configured_model);
+    let provider = args
+        .provider
+        .clone()
+        .or_else(|| definition.as_ref().and_then(|d| d.provider.clone()))
+        .or(configured_provider.filter(|_| args.model.is_none()))
+        .unwrap_or_else(|| provider_for_model(&model_id).to_string());
+
+    let api_key = config
+        .provider
+        .get(&provider)
+        .and_then(|c| c.api_key.clone())
+        .or_else(|| std::env::var(format!("{}_API_KEY", provider.to_uppercase())).ok())
+        .unwrap_or_default();
+    if api_key.is_empty() && provider != "ollama" {
+        return Err(format!(
+            "No API key for {}; set provider.{}.api_key in config or {}_API_KEY",
+            provider,
+            provider,
+            provider.to_uppercase()
+        ));
+    }
+
+    session.model = ModelId(model_id.clone());
+    if let Some(mode) = &args.mode {
+        session.mode = mode.clone();
+    }
+
+    let model = ParentModel {
+        adapter: build_model_adapter(&provider, &api_key, &model_id)?,
+        model_id: ModelId(model_id),
+        provider,
+        api_key,
+    };
+
+    build_agent(host, session, model, definition).await
+}
+
+/// Run one turn; streams to stdout unless the caller prints JSON
+async fn run_prompt(agent: &mut Agent, prompt: String, json: bool) -> Result<String, String> {
+    if json {
+        return agent.step(Some(prompt), None).await;
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+    let printer = tokio::spawn(async move {
+        let mut stdout = std::io::stdout();
+        while let Some(chunk) = rx.recv().await {
+            let _ = stdout.write_all(chunk.as_bytes());
+            let _ = stdout.flush();
+        }
+    });
+
+    let result = agent.step_stream(Some(prompt), None, tx).await;
+    let _ = printer.await;
+    println!();
+    result
+}
+
+async fn interactive(host: &SessionHost, agent: &mut Agent, json: bool) -> Result<(), String> {
+    let session = agent.get_session();
+    eprintln!(
+        "anvil · {} · {} · session {}",
+        session.workspace_path.display(),
+        session.model.0,
+        session.id
+    );
+    eprintln!("Type /exit to quit.");
+
+    let stdin = std::io::stdin();
+    loop {
+        eprint!("› ");
+        let _ = std::io::stderr().flush();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match line.split_once(' ').unwrap_or((line, "")) {
+            ("/exit" | "/quit", _) => break,
+            ("/session", _) => {
+                eprintln!("{}", agent.get_session().id);
+                continue;
+            }
+            ("/mode", mode) => {
+                match parse_mode(mode.trim()) {
+                    Some(mode) => agent.update_mode(mode),
+                    None => eprintln!("Unknown mode: {}", mode.trim()),
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        match run_prompt(agent, line.to_string(), json).await {
+            Ok(response) if json => println!("{}", json!({ "response": response })),
+            Ok(_) => {}
+            Err(e) => eprintln!("Error: {}", e),
+        }
+        save(host, agent);
+    }
+
+    Ok(())
+}
+
+fn open_storage(db: Option<PathBuf>) -> Option<Storage> {
+    let path = db.or_else(|| dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join("anvil.db")))?;
+    match Storage::new(&path.to_string_lossy()) {
+        Ok(storage) => Some(storage),
+        Err(e) => {
+            eprintln!("Warning: sessions will not be saved: {}", e);
+            None
+        }
+    }
+}
+
+fn save(host: &SessionHost, agent: &Agent) {
+    let Ok(guard) = host.storage.lock() else {
+        return;
+    };
+    if let Some(storage) = guard.as_ref() {
+        if let Err(e) = storage.save_session(&agent.get_session()) {
+            eprintln!("Warning: failed to save session: {}", e);
+        }
+    }
+}
+
+fn read_stdin() -> Result<String, String> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+    let input = input.trim().to_string();
+    if input.is_empty() {
+        return Err("Empty prompt".to_string());
+    }
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<CliArgs, String> {
+        CliArgs::parse(&list.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_print_mode() {
+        let parsed = args(&["-p", "fix the build", "--json", "-m", "claude-sonnet-4", "--mode", "plan"]).unwrap();
+        assert_eq!(parsed.prompt.as_deref(), Some("fix the build"));
+        assert!(parsed.json);
+        assert_eq!(parsed.model.as_deref(), Some("claude-sonnet-4"));
+        assert_eq!(parsed.mode, Some(AgentMode::Plan));
+    }
+
+    #[test]
+    fn test_parse_positional_and_errors() {
+        assert_eq!(args(&["hello"]).unwrap().prompt.as_deref(), Some("hello"));
+        assert_eq!(args(&["-p", "-"]).unwrap().prompt.as_deref(), Some("-"));
+        assert!(args(&["--model"]).is_err());
+        assert!(args(&["--mode", "yolo"]).is_err());
+        assert!(args(&["--frobnicate"]).is_err());
+        assert!(args(&["one", "two"]).is_err());
+        assert!(args(&["--approve", ""]).is_err());
+    }
+
+    #[test]
+    fn test_parse_approve_policy() {
+        assert_eq!(args(&["--approve", "allow"]).unwrap().approve, Some(AutoPolicy::AllowAll));
+        assert_eq!(
+            args(&["--approve", "read_file,bash"]).unwrap().approve,
+            Some(AutoPolicy::Allow(vec!["read_file".to_string(), "bash".to_string()]))
+        );
+    }
+}
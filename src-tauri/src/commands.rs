@@ -2,13 +2,20 @@ use crate::app_state::AppState;
 use crate::adapters::openai::OpenAIAdapter;
 use crate::adapters::gemini::GeminiAdapter;
 use crate::adapters::anthropic::AnthropicAdapter;
-use crate::adapters::ollama::OllamaAdapter;
-use crate::adapters::tools::{files::ReadFileTool, files::WriteFileTool, files::EditFileTool, bash::BashTool, git::GitTool, search::SearchTool, symbols::SymbolsTool, glob::GlobTool, list::ListTool, web::WebFetchTool, patch::PatchTool, question::QuestionTool, todo::TodoWriteTool, todoread::TodoReadTool, skill::SkillTool, lsp::LspTool, mcp_tool::load_mcp_tools};
-use crate::domain::agent::Agent;
+use crate::adapters::{build_model_adapter, provider_for_model};
+use crate::adapters::interaction::DesktopInteraction;
+use crate::adapters::tools::registry::ToolContext;
+use crate::adapters::tools::task::ParentModel;
+use crate::session::{build_agent, saved_definition, SessionHost};
+use crate::domain::checkpoint::{CheckpointStore, CheckpointSummary, RestoreReport};
 use crate::domain::orchestrator::{Orchestrator, Task, TaskStatus};
-use crate::domain::models::{AgentSession, AgentPermissions, ModelId, AgentMode, AgentRole};
-use crate::domain::ports::ModelAdapter;
+use crate::domain::shadow::{ShadowLog, ShadowTurn};
+use crate::domain::worktree::{MergeOutcome, SessionWorktree};
+use crate::domain::steering::SteeringMessage;
+use crate::domain::models::{AgentSession, AgentPermissions, ModelId, AgentMode, AgentRole, Role};
+use crate::domain::ports::{InteractionProvider, ModelAdapter};
 use crate::config::manager::PermissionConfig;
+use crate::config::{AgentDefinition, AgentRegistry};
 use crate::workflows::Workflow;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -84,6 +91,46 @@ pub fn resize_terminal(
     terminal.resize(cols, rows)
 }
 
+/// Interaction provider that prompts in the desktop UI
+fn desktop_interaction(app: &tauri::AppHandle, state: &AppState) -> Arc<dyn InteractionProvider> {
+    Arc::new(DesktopInteraction::new(app.clone(), state.pending_requests.clone()))
+}
+
+/// Build the agent for `session`, register it in app state and start
+/// watching the workspace config.
+async fn register_session_agent(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    session: AgentSession,
+    model: ParentModel,
+    definition: Option<AgentDefinition>,
+) -> Result<(), String> {
+    let host = SessionHost {
+        interaction: desktop_interaction(app, state),
+        storage: state.storage.clone(),
+        lsp: state.lsp.clone(),
+    };
+    let id = session.id;
+    let path = session.workspace_path.clone();
+    let agent = build_agent(&host, session, model, definition).await?;
+
+    if let Ok(mut steering) = state.steering.lock() {
+        steering.insert(id, agent.steering());
+    }
+    let mut agents = state.agents.lock().await;
+    agents.insert(id, Arc::new(Mutex::new(agent)));
+
+    crate::config::start_config_watcher(
+        state.agents.clone(),
+        state.config_watchers.clone(),
+        path,
+    );
+
+    Ok(())
+}
+
+/// Create a session. With `worktree` set, the session works in its own git
+/// worktree on a fresh branch, to be merged back or discarded when done
 #[tauri::command]
 pub async fn create_session(
     app: tauri::AppHandle,
@@ -92,6 +139,8 @@ pub async fn create_session(
     api_key: String,
     provider: String,
     model_id: String,
+    agent: Option<String>,
+    worktree: Option<bool>,
 ) -> Result<String, String> {
     let id = Uuid::new_v4();
     let path = PathBuf::from(&workspace_path);
@@ -100,117 +149,89 @@ pub async fn create_session(
         return Err("Workspace path does not exist".to_string());
     }
 
-    let model: Arc<dyn ModelAdapter> = match provider.as_str() {
-        "openai" => Arc::new(OpenAIAdapter::new(api_key.clone())),
-        "gemini" => Arc::new(GeminiAdapter::new(api_key.clone(), model_id.clone())),
-        "anthropic" => Arc::new(AnthropicAdapter::new(api_key.clone(), model_id.clone())),
-        "ollama" => Arc::new(OllamaAdapter::new(None)),
-        _ => return Err(format!("Unsupported provider: {}", provider)),
-    };
-
     let mut config_manager = crate::config::ConfigManager::new();
     let _ = config_manager.load(Some(&path));
     let config = config_manager.config();
-    let permission_manager = Arc::new(tokio::sync::Mutex::new(config.permission.clone()));
 
-    let mut tools: Vec<Arc<dyn crate::domain::ports::Tool>> = vec![
-        Arc::new(ReadFileTool::new(
-            path.clone(),
-            id.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(WriteFileTool::new(
-            path.clone(),
-            id.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(BashTool::new(
-            path.clone(),
-            id.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(GitTool::new(path.clone())),
-        Arc::new(SearchTool::new(path.clone())),
-        Arc::new(LspTool::new(
-            path.clone(),
-            permission_manager.clone(),
-            config.lsp.clone(),
-            id.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone()
-        )),
-        Arc::new(EditFileTool::new(
-            path.clone(),
-            id.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(SymbolsTool::new(path.clone())),
-        Arc::new(GlobTool::new(path.clone(), permission_manager.clone())),
-        Arc::new(ListTool::new(path.clone(), permission_manager.clone())),
-        Arc::new(WebFetchTool::new()),
-        Arc::new(PatchTool::new(path.clone())),
-        Arc::new(QuestionTool::new(app.clone())),
-        Arc::new(TodoWriteTool::new(path.clone())),
-        Arc::new(TodoReadTool::new(path.clone())),
-        Arc::new(SkillTool::new(
-            path.clone(),
-            id.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-    ];
-
-    // Load MCP tools from configuration
-    match load_mcp_tools(&path).await {
-        Ok(mcp_tools) => {
-            tools.extend(mcp_tools);
-        }
-        Err(e) => {
-            println!("⚠️  Warning: Failed to load MCP tools: {}", e);
-        }
-    }
+    let definition = match agent.as_deref() {
+        Some(name) => Some(AgentRegistry::find(&path, config, name)?),
+        None => None,
+    };
+
+    // An agent may pin its own provider/model; its key then comes from config
+    let agent_provider = definition.as_ref().and_then(|d| d.provider.clone());
+    let api_key = match &agent_provider {
+        Some(p) if *p != provider => config
+            .provider
+            .get(p)
+            .and_then(|c| c.api_key.clone())
+            .unwrap_or(api_key),
+        _ => api_key,
+    };
+    let provider = agent_provider.unwrap_or(provider);
+    let model_id = definition.as_ref().and_then(|d| d.model.clone()).unwrap_or(model_id);
+    let mode = definition.as_ref().and_then(|d| d.mode.clone()).unwrap_or(AgentMode::Build);
+
+    let model = ParentModel {
+        adapter: build_model_adapter(&provider, &api_key, &model_id)?,
+        model_id: ModelId(model_id.clone()),
+        provider,
+        api_key,
+    };
+
+    let session_worktree = if worktree.unwrap_or(false) {
+        Some(SessionWorktree::create(&path, &id.to_string(), &crate::domain::worktree::default_dir())?)
+    } else {
+        None
+    };
 
     let new_session = AgentSession {
         id,
-        workspace_path: path.clone(),
+        workspace_path: session_worktree.as_ref().map(|w| w.path.clone()).unwrap_or(path),
         model: ModelId(model_id.clone()),
-        mode: AgentMode::Build,
+        mode,
         messages: vec![],
         permissions: AgentPermissions { 
             config: config.permission.clone() 
         },
+        agent: None,
+        parent_id: None,
+        plan: None,
     };
 
-    let agent = Agent::new(
-        new_session.clone(),
-        model.clone(),
-        tools,
-        permission_manager,
-        Some(app.clone()),
-        Some(state.pending_confirmations.clone()),
-    );
-
-    let mut agents = state.agents.lock().await;
-    agents.insert(id, Arc::new(Mutex::new(agent)));
+    // Saved right away so the worktree is not taken for an orphan
+    if let Some(created) = &session_worktree {
+        let saved = state.with_storage(|storage| {
+            storage.save_session(&new_session)?;
+            storage.save_worktree(created)
+        });
+        if let Err(e) = saved {
+            let _ = created.remove();
+            return Err(e);
+        }
+    }
 
-    crate::config::start_config_watcher(
-        state.agents.clone(),
-        state.config_watchers.clone(),
-        path.clone(),
-    );
+    register_session_agent(&app, &state, new_session, model, definition).await?;
 
     Ok(id.to_string())
 }
 
+/// List named agents available in a workspace
+#[tauri::command]
+pub async fn list_agents(
+    workspace_path: String,
+) -> Result<Vec<AgentDefinition>, String> {
+    let path = PathBuf::from(&workspace_path);
+    let mut config_manager = crate::config::ConfigManager::new();
+    let _ = config_manager.load(Some(&path));
+
+    let mut agents: Vec<AgentDefinition> = AgentRegistry::discover(&path, config_manager.config())
+        .into_values()
+        .collect();
+    agents.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(agents)
+}
+
 #[tauri::command]
 pub async fn chat(
     state: State<'_, AppState>,
@@ -254,6 +275,46 @@ pub async fn chat(
     agent.step(Some(message), attachments).await
 }
 
+/// Send a message to the turn running in `session_id`; it is added as user
+/// input before the next model call. With `interrupt`, tool calls that have
+/// not started yet are cancelled. Returns false when no turn is running, in
+/// which case the message should be sent as a regular prompt.
+#[tauri::command]
+pub async fn steer_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    message: String,
+    interrupt: Option<bool>,
+) -> Result<bool, String> {
+    let uuid = Uuid::parse_str(&session_id).map_err(|_| "Invalid UUID")?;
+    let inbox = {
+        let steering = state.steering.lock().map_err(|_| "Failed to lock steering inboxes")?;
+        steering.get(&uuid).cloned().ok_or("Session not found".to_string())?
+    };
+
+    let queued = inbox
+        .push(SteeringMessage {
+            content: message,
+            interrupt: interrupt.unwrap_or(false),
+        })
+        .is_ok();
+    Ok(queued)
+}
+
+/// Sender whose chunks are forwarded to the frontend as `chat-token` events
+fn chat_token_channel(app: &tauri::AppHandle) -> tokio::sync::mpsc::Sender<String> {
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+
+    let app_handle = app.clone();
+    tokio::spawn(async move {
+        while let Some(chunk) = rx.recv().await {
+            let _ = app_handle.emit("chat-token", chunk);
+        }
+    });
+
+    tx
+}
+
 #[tauri::command]
 pub async fn stream_chat(
     app: tauri::AppHandle,
@@ -295,18 +356,48 @@ pub async fn stream_chat(
         agent.update_model(adapter, ModelId(m_id));
     }
 
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
-
-    let app_handle = app.clone();
-    tokio::spawn(async move {
-        while let Some(chunk) = rx.recv().await {
-            let _ = app_handle.emit("chat-token", chunk);
-        }
-    });
+    let tx = chat_token_channel(&app);
 
     agent.step_stream(Some(message), attachments, tx).await
 }
 
+/// Execute the session's plan step by step, streaming tokens like `stream_chat`
+#[tauri::command]
+pub async fn execute_plan(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<String, String> {
+    let uuid = Uuid::parse_str(&session_id).map_err(|_| "Invalid UUID")?;
+
+    let agent_arc = {
+        let agents = state.agents.lock().await;
+        agents.get(&uuid).cloned().ok_or("Session not found".to_string())?
+    };
+
+    let mut agent = agent_arc.lock().await;
+
+    let tx = chat_token_channel(&app);
+
+    agent.execute_plan(Some(tx)).await
+}
+
+#[tauri::command]
+pub async fn get_plan(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<Option<crate::domain::plan::Plan>, String> {
+    let uuid = Uuid::parse_str(&session_id).map_err(|_| "Invalid UUID")?;
+
+    let agent_arc = {
+        let agents = state.agents.lock().await;
+        agents.get(&uuid).cloned().ok_or("Session not found".to_string())?
+    };
+
+    let agent = agent_arc.lock().await;
+    Ok(agent.get_session().plan)
+}
+
 #[tauri::command]
 pub async fn confirm_action(
     state: State<'_, AppState>,
@@ -317,17 +408,14 @@ pub async fn confirm_action(
     pattern: Option<String>,
 ) -> Result<(), String> {
     println!("Confirming action: id={}, session={}, allowed={}, always={}, pattern={:?}", id, session_id, allowed, always, pattern);
-    let mut map = state.pending_confirmations.lock().map_err(|_| "Failed to lock")?;
-    if let Some(tx) = map.remove(&id) {
-        let _ = tx.send(crate::domain::models::ConfirmationResponse {
+    state.pending_requests.resolve_confirmation(
+        &id,
+        crate::domain::models::ConfirmationResponse {
             allowed,
             always,
             pattern,
-        });
-        Ok(())
-    } else {
-        Err("Confirmation ID not found or already processed".to_string())
-    }
+        },
+    )
 }
 
 #[tauri::command]
@@ -363,20 +451,141 @@ pub async fn list_sessions(
     state.with_storage(|storage| storage.list_sessions())
 }
 
+#[tauri::command]
+pub async fn list_subagent_sessions(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<Vec<crate::storage::SessionMetadata>, String> {
+    state.with_storage(|storage| storage.list_child_sessions(&session_id))
+}
+
+/// Checkpoint store of a live session, or of a saved one
+async fn session_checkpoints(state: &AppState, session_id: &str) -> Result<Arc<CheckpointStore>, String> {
+    let uuid = Uuid::parse_str(session_id).map_err(|_| "Invalid UUID")?;
+
+    let agent_arc = {
+        let agents = state.agents.lock().await;
+        agents.get(&uuid).cloned()
+    };
+    if let Some(agent_arc) = agent_arc {
+        if let Some(checkpoints) = agent_arc.lock().await.checkpoints() {
+            return Ok(checkpoints);
+        }
+    }
+
+    let session = state.with_storage(|storage| storage.load_session(session_id))?;
+    Ok(Arc::new(CheckpointStore::open(&session.workspace_path, session_id)))
+}
+
+#[tauri::command]
+pub async fn list_checkpoints(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<Vec<CheckpointSummary>, String> {
+    Ok(session_checkpoints(&state, &session_id).await?.list())
+}
+
+/// Revert files changed by the agent to their state before user message `turn`
+#[tauri::command]
+pub async fn restore_checkpoint(
+    state: State<'_, AppState>,
+    session_id: String,
+    turn: usize,
+    force: Option<bool>,
+) -> Result<RestoreReport, String> {
+    let checkpoints = session_checkpoints(&state, &session_id).await?;
+    checkpoints.restore(turn, force.unwrap_or(false))
+}
+
+/// Revert the last turn's file changes and remove it from the conversation
+#[tauri::command]
+pub async fn undo_turn(
+    state: State<'_, AppState>,
+    session_id: String,
+    force: Option<bool>,
+) -> Result<Value, String> {
+    let uuid = Uuid::parse_str(&session_id).map_err(|_| "Invalid UUID")?;
+
+    let agent_arc = {
+        let agents = state.agents.lock().await;
+        agents.get(&uuid).cloned().ok_or("Session not found".to_string())?
+    };
+
+    let mut agent = agent_arc.lock().await;
+    let turn = agent.turn_count().checked_sub(1).ok_or("Nothing to undo")?;
+    let checkpoints = agent.checkpoints().ok_or("Checkpoints are not enabled for this session")?;
+
+    let report = checkpoints.restore(turn, force.unwrap_or(false))?;
+    let message = if report.applied {
+        agent.truncate_to_turn(turn).and_then(|m| m.content)
+    } else {
+        None
+    };
+
+    Ok(json!({
+        "report": report,
+        "message": message,
+    }))
+}
+
+/// Shadow commit log of a live or saved session
+async fn session_shadow(state: &AppState, session_id: &str) -> Result<ShadowLog, String> {
+    let uuid = Uuid::parse_str(session_id).map_err(|_| "Invalid UUID")?;
+
+    let agent_arc = {
+        let agents = state.agents.lock().await;
+        agents.get(&uuid).cloned()
+    };
+    let workspace_path = match agent_arc {
+        Some(agent_arc) => agent_arc.lock().await.session.workspace_path.clone(),
+        None => state.with_storage(|storage| storage.load_session(session_id))?.workspace_path,
+    };
+    ShadowLog::open(&workspace_path, session_id)
+}
+
+#[tauri::command]
+pub async fn list_shadow_turns(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<Vec<ShadowTurn>, String> {
+    session_shadow(&state, &session_id).await?.turns()
+}
+
+/// Unified diff of what the agent changed in `turn`
+#[tauri::command]
+pub async fn shadow_turn_diff(
+    state: State<'_, AppState>,
+    session_id: String,
+    turn: usize,
+) -> Result<String, String> {
+    session_shadow(&state, &session_id).await?.diff_turn(turn)
+}
+
+/// Commit the changes of one turn onto the current branch
+#[tauri::command]
+pub async fn cherry_pick_turn(
+    state: State<'_, AppState>,
+    session_id: String,
+    turn: usize,
+) -> Result<String, String> {
+    session_shadow(&state, &session_id).await?.cherry_pick(turn)
+}
+
+/// Commit all of the session's turns onto the current branch as one commit
+#[tauri::command]
+pub async fn squash_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    message: Option<String>,
+) -> Result<String, String> {
+    session_shadow(&state, &session_id).await?.squash(message.as_deref())
+}
+
 #[tauri::command]
 pub async fn git_status_summary(
     workspace_path: String,
 ) -> Result<serde_json::Value, String> {
-    use crate::adapters::tools::git::GitTool;
-    use crate::domain::ports::Tool;
-
-    let path = PathBuf::from(&workspace_path);
-    let tool = GitTool::new(path);
-    let input = serde_json::json!({
-        "command": "status"
-    });
-
-    tool.execute(input).await.map_err(|e| e.to_string())
+    crate::adapters::tools::git::status_summary(&PathBuf::from(&workspace_path))
 }
 
 #[tauri::command]
@@ -412,7 +621,124 @@ pub async fn delete_session(
     state: State<'_, AppState>,
     session_id: String,
 ) -> Result<(), String> {
-    state.with_storage(|storage| storage.delete_session(&session_id))
+    // Agent work left in a worktree is only thrown away on request
+    let worktree = state.with_storage(|storage| storage.load_worktree(&session_id))?;
+    if let Some(worktree) = &worktree {
+        if let Some(reason) = worktree.unsaved_work()? {
+            return Err(format!(
+                "The session's worktree holds work that would be lost: {}. Merge or discard the worktree first.",
+                reason
+            ));
+        }
+    }
+
+    state.cancel_prompts(&session_id);
+    if let Ok(uuid) = Uuid::parse_str(&session_id) {
+        state.agents.lock().await.remove(&uuid);
+        if let Ok(mut steering) = state.steering.lock() {
+            steering.remove(&uuid);
+        }
+    }
+    state.lsp.release_session(&session_id).await;
+    state.with_storage(|storage| storage.delete_session(&session_id))?;
+    // A worktree with nothing to lose is now an orphan
+    if worktree.is_some() {
+        state.clean_orphaned_worktrees();
+    }
+    Ok(())
+}
+
+/// Language servers for a workspace: which are configured, which of those
+/// are installed and where they are running
+#[tauri::command]
+pub async fn lsp_status(
+    state: State<'_, AppState>,
+    workspace_path: String,
+) -> Result<crate::lsp::LspStatus, String> {
+    let path = PathBuf::from(&workspace_path);
+    let mut config_manager = crate::config::ConfigManager::new();
+    let _ = config_manager.load(Some(&path));
+    let config = config_manager.config().lsp.clone();
+
+    let enabled = config.as_ref().and_then(|c| c.enabled) != Some(false);
+    let specs = crate::lsp::resolve_servers(config.as_ref());
+    Ok(crate::lsp::LspStatus {
+        enabled,
+        servers: state.lsp.status(&path, &specs).await,
+    })
+}
+
+#[tauri::command]
+pub async fn get_session_worktree(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<Option<SessionWorktree>, String> {
+    state.with_storage(|storage| storage.load_worktree(&session_id))
+}
+
+/// Merge a session's worktree branch into the repository it was created
+/// from, then remove the worktree and end the live session
+#[tauri::command]
+pub async fn merge_session_worktree(
+    state: State<'_, AppState>,
+    session_id: String,
+    message: Option<String>,
+) -> Result<MergeOutcome, String> {
+    let worktree = idle_session_worktree(&state, &session_id)?;
+    let message = match message.map(|m| m.trim().to_string()).filter(|m| !m.is_empty()) {
+        Some(message) => message,
+        None => state
+            .with_storage(|storage| storage.get_session_summary(&session_id))?
+            .and_then(|summary| summary.lines().next().map(|line| line.trim().to_string()))
+            .filter(|line| !line.is_empty())
+            .unwrap_or_else(|| format!("Changes from session {}", worktree.branch)),
+    };
+
+    let outcome = worktree.merge_back(&message)?;
+    end_worktree_session(&state, &worktree).await?;
+    Ok(outcome)
+}
+
+/// Throw away a session's worktree and branch and end the live session
+#[tauri::command]
+pub async fn discard_session_worktree(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<(), String> {
+    let worktree = idle_session_worktree(&state, &session_id)?;
+    end_worktree_session(&state, &worktree).await
+}
+
+fn idle_session_worktree(state: &AppState, session_id: &str) -> Result<SessionWorktree, String> {
+    let uuid = Uuid::parse_str(session_id).map_err(|_| "Invalid UUID")?;
+    let busy = state
+        .steering
+        .lock()
+        .map(|steering| steering.get(&uuid).is_some_and(|inbox| inbox.is_running()))
+        .unwrap_or(false);
+    if busy {
+        return Err("The session is running a turn; wait for it to finish".to_string());
+    }
+    state
+        .with_storage(|storage| storage.load_worktree(session_id))?
+        .ok_or_else(|| "This session has no worktree".to_string())
+}
+
+/// Unload the session, remove its worktree and point the saved session
+/// back at the repository
+async fn end_worktree_session(state: &AppState, worktree: &SessionWorktree) -> Result<(), String> {
+    if let Ok(uuid) = Uuid::parse_str(&worktree.session_id) {
+        state.agents.lock().await.remove(&uuid);
+        if let Ok(mut steering) = state.steering.lock() {
+            steering.remove(&uuid);
+        }
+    }
+    state.lsp.release_session(&worktree.session_id).await;
+    worktree.remove()?;
+    state.with_storage(|storage| {
+        storage.delete_worktree(&worktree.session_id)?;
+        storage.set_workspace_path(&worktree.session_id, &worktree.repo_path)
+    })
 }
 
 #[tauri::command]
@@ -424,6 +750,19 @@ pub async fn rename_session(
     state.with_storage(|storage| storage.rename_session(&session_id, name))
 }
 
+/// Model for a session resumed from storage; defaults to the model it ran with
+fn resume_model(model_id: Option<String>, api_key: Option<String>, saved: &ModelId) -> Result<ParentModel, String> {
+    let api_key = api_key.unwrap_or_default();
+    let model_id = model_id.unwrap_or_else(|| saved.0.clone());
+    let provider = provider_for_model(&model_id);
+    Ok(ParentModel {
+        adapter: build_model_adapter(provider, &api_key, &model_id)?,
+        model_id: ModelId(model_id),
+        provider: provider.to_string(),
+        api_key,
+    })
+}
+
 #[tauri::command]
 pub async fn replay_session(
     state: State<'_, AppState>,
@@ -437,131 +776,153 @@ pub async fn replay_session(
     let uuid = original_session.id;
     let path = original_session.workspace_path.clone();
 
-    let api_key = api_key.unwrap_or_default();
-    let model_id_value = model_id.unwrap_or_else(|| original_session.model.0.clone());
-
-    let provider = if model_id_value.starts_with("llama")
-        || model_id_value.starts_with("mistral")
-        || model_id_value.starts_with("codellama")
-        || model_id_value.starts_with("deepseek") {
-        "ollama".to_string()
-    } else if model_id_value.starts_with("gemini") {
-        "gemini".to_string()
-    } else if model_id_value.starts_with("claude") {
-        "anthropic".to_string()
-    } else {
-        "openai".to_string()
-    };
+    let model = resume_model(model_id, api_key, &original_session.model)?;
+    let definition = saved_definition(&path, original_session.agent.as_deref());
 
-    let model: Arc<dyn ModelAdapter> = match provider.as_str() {
-        "openai" => Arc::new(OpenAIAdapter::new(api_key.clone())),
-        "gemini" => Arc::new(GeminiAdapter::new(api_key.clone(), model_id_value.clone())),
-        "anthropic" => Arc::new(AnthropicAdapter::new(api_key.clone(), model_id_value.clone())),
-        "ollama" => Arc::new(OllamaAdapter::new(None)),
-        _ => return Err(format!("Unsupported provider: {}", provider)),
+    let new_session = AgentSession {
+        id: uuid,
+        workspace_path: path,
+        model: model.model_id.clone(),
+        mode: original_session.mode,
+        messages: original_session.messages,
+        permissions: original_session.permissions,
+        agent: None,
+        parent_id: None,
+        plan: original_session.plan,
     };
 
-    let mut config_manager = crate::config::ConfigManager::new();
-    let _ = config_manager.load(Some(&path));
-    let config = config_manager.config();
-    let permission_manager = Arc::new(tokio::sync::Mutex::new(config.permission.clone()));
+    register_session_agent(&app, &state, new_session, model, definition).await?;
 
-    let mut tools: Vec<Arc<dyn crate::domain::ports::Tool>> = vec![
-        Arc::new(ReadFileTool::new(
-            path.clone(),
-            uuid.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(WriteFileTool::new(
-            path.clone(),
-            uuid.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(BashTool::new(
-            path.clone(),
-            uuid.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(GitTool::new(path.clone())),
-        Arc::new(SearchTool::new(path.clone())),
-        Arc::new(LspTool::new(
-            path.clone(),
-            permission_manager.clone(),
-            config.lsp.clone(),
-            uuid.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone()
-        )),
-        Arc::new(EditFileTool::new(
-            path.clone(),
-            uuid.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(SymbolsTool::new(path.clone())),
-        Arc::new(GlobTool::new(path.clone(), permission_manager.clone())),
-        Arc::new(ListTool::new(path.clone(), permission_manager.clone())),
-        Arc::new(WebFetchTool::new()),
-        Arc::new(PatchTool::new(path.clone())),
-        Arc::new(QuestionTool::new(app.clone())),
-        Arc::new(TodoWriteTool::new(path.clone())),
-        Arc::new(TodoReadTool::new(path.clone())),
-        Arc::new(SkillTool::new(
-            path.clone(),
-            uuid.to_string(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-    ];
-
-    // Load MCP tools from configuration
-    match load_mcp_tools(&path).await {
-        Ok(mcp_tools) => {
-            tools.extend(mcp_tools);
-        }
-        Err(e) => {
-            println!("⚠️  Warning: Failed to load MCP tools: {}", e);
-        }
+    Ok(uuid.to_string())
+}
+
+/// Current state of a session: the live agent's if loaded, else the saved one
+async fn session_snapshot(state: &AppState, session_id: &str) -> Result<AgentSession, String> {
+    let uuid = Uuid::parse_str(session_id).map_err(|_| "Invalid UUID")?;
+
+    let agent_arc = {
+        let agents = state.agents.lock().await;
+        agents.get(&uuid).cloned()
+    };
+    match agent_arc {
+        Some(agent_arc) => Ok(agent_arc.lock().await.get_session()),
+        None => state.with_storage(|storage| storage.load_session(session_id)),
     }
+}
 
-    let new_session = AgentSession {
-        id: uuid,
-        workspace_path: path.clone(),
-        model: ModelId(model_id_value),
-        mode: original_session.mode,
-        messages: original_session.messages,
-        permissions: AgentPermissions { 
-            config: config.permission.clone() 
-        },
+/// Start a new live session holding the first `message_index` messages of
+/// `session_id`, which must end before a user message. Both sessions are
+/// saved and linked in storage. Files in the workspace are left as they
+/// are; use checkpoints to roll them back.
+async fn fork_at(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    session_id: &str,
+    message_index: usize,
+    model_id: Option<String>,
+    api_key: Option<String>,
+) -> Result<Uuid, String> {
+    let source = session_snapshot(state, session_id).await?;
+    if source.parent_id.is_some() {
+        return Err("Sub-agent sessions cannot be forked".to_string());
+    }
+
+    let messages = source.transcript_before(message_index)?;
+    // The plan as it was at the cut, not as it was carried out after it
+    let plan = crate::domain::plan::Plan::as_of(&messages);
+
+    let model = resume_model(model_id, api_key, &source.model)?;
+    let definition = saved_definition(&source.workspace_path, source.agent.as_deref());
+
+    let id = Uuid::new_v4();
+    let fork = AgentSession {
+        id,
+        workspace_path: source.workspace_path.clone(),
+        model: model.model_id.clone(),
+        mode: source.mode.clone(),
+        messages,
+        permissions: source.permissions.clone(),
+        agent: None,
+        parent_id: None,
+        plan,
     };
+    let fork_copy = fork.clone();
 
-    let agent = Agent::new(
-        new_session,
-        model,
-        tools,
-        permission_manager,
-        Some(app.clone()),
-        Some(state.pending_confirmations.clone()),
-    );
+    register_session_agent(app, state, fork, model, definition).await?;
 
-    let mut agents = state.agents.lock().await;
-    agents.insert(uuid, Arc::new(Mutex::new(agent)));
+    state.with_storage(|storage| {
+        storage.save_session(&source)?;
+        storage.save_session(&fork_copy)?;
+        storage.set_fork_origin(&id.to_string(), session_id, message_index)
+    })?;
 
-    crate::config::start_config_watcher(
-        state.agents.clone(),
-        state.config_watchers.clone(),
-        path.clone(),
-    );
+    Ok(id)
+}
 
-    Ok(uuid.to_string())
+/// Branch a session before message `message_index` into a new session
+#[tauri::command]
+pub async fn fork_session(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    session_id: String,
+    message_index: usize,
+    model_id: Option<String>,
+    api_key: Option<String>,
+) -> Result<String, String> {
+    let id = fork_at(&app, &state, &session_id, message_index, model_id, api_key).await?;
+    Ok(id.to_string())
+}
+
+/// Fork before the user message at `message_index`, send `message` in its
+/// place (keeping its attachments) and stream the new answer. The original
+/// session is left untouched.
+#[tauri::command]
+pub async fn regenerate_from(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    session_id: String,
+    message_index: usize,
+    message: String,
+    model_id: Option<String>,
+    api_key: Option<String>,
+) -> Result<Value, String> {
+    let source = session_snapshot(&state, &session_id).await?;
+    let original = source
+        .messages
+        .iter()
+        .filter(|m| !matches!(m.role, Role::System))
+        .nth(message_index)
+        .filter(|m| m.starts_turn())
+        .ok_or_else(|| format!("Message {} is not a user message", message_index))?;
+    let attachments = original.attachments.clone();
+
+    let id = fork_at(&app, &state, &session_id, message_index, model_id, api_key).await?;
+
+    let agent_arc = {
+        let agents = state.agents.lock().await;
+        agents.get(&id).cloned().ok_or("Session not found".to_string())?
+    };
+    let mut agent = agent_arc.lock().await;
+
+    let tx = chat_token_channel(&app);
+    let response = agent.step_stream(Some(message), attachments, tx).await?;
+
+    let session = agent.get_session();
+    state.with_storage(|storage| storage.save_session(&session))?;
+
+    Ok(json!({
+        "session_id": id.to_string(),
+        "response": response,
+    }))
+}
+
+/// Sessions forked from `session_id`
+#[tauri::command]
+pub async fn list_branches(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<Vec<crate::storage::SessionMetadata>, String> {
+    state.with_storage(|storage| storage.list_forks(&session_id))
 }
 
 #[tauri::command]
@@ -601,13 +962,7 @@ pub async fn add_agent_to_orchestrator(
         orchestrator_guard.as_ref().ok_or("Orchestrator not initialized")?.clone()
     };
 
-    let model: Arc<dyn ModelAdapter> = match provider.as_str() {
-        "openai" => Arc::new(OpenAIAdapter::new(api_key.clone())),
-        "gemini" => Arc::new(GeminiAdapter::new(api_key.clone(), model_id.clone())),
-        "anthropic" => Arc::new(AnthropicAdapter::new(api_key.clone(), model_id.clone())),
-        "ollama" => Arc::new(OllamaAdapter::new(None)),
-        _ => return Err(format!("Unsupported provider: {}", provider)),
-    };
+    let model = build_model_adapter(&provider, &api_key, &model_id)?;
 
     let path = PathBuf::from(workspace_path);
     
@@ -616,73 +971,19 @@ pub async fn add_agent_to_orchestrator(
     let config = config_manager.config();
     let permission_manager = Arc::new(tokio::sync::Mutex::new(config.permission.clone()));
 
-    let mut tools: Vec<Arc<dyn crate::domain::ports::Tool>> = vec![
-        Arc::new(ReadFileTool::new(
-            path.clone(),
-            agent_id.clone(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(WriteFileTool::new(
-            path.clone(),
-            agent_id.clone(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(BashTool::new(
-            path.clone(),
-            agent_id.clone(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(GitTool::new(path.clone())),
-        Arc::new(SearchTool::new(path.clone())),
-        Arc::new(LspTool::new(
-            path.clone(),
-            permission_manager.clone(),
-            config.lsp.clone(),
-            agent_id.clone(),
-            app.clone(),
-            state.pending_confirmations.clone()
-        )),
-        Arc::new(EditFileTool::new(
-            path.clone(),
-            agent_id.clone(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-        Arc::new(SymbolsTool::new(path.clone())),
-        Arc::new(GlobTool::new(path.clone(), permission_manager.clone())),
-        Arc::new(ListTool::new(path.clone(), permission_manager.clone())),
-        Arc::new(WebFetchTool::new()),
-        Arc::new(PatchTool::new(path.clone())),
-        Arc::new(QuestionTool::new(app.clone())),
-        Arc::new(TodoWriteTool::new(path.clone())),
-        Arc::new(TodoReadTool::new(path.clone())),
-        Arc::new(SkillTool::new(
-            path.clone(),
-            agent_id.clone(),
-            app.clone(),
-            state.pending_confirmations.clone(),
-            permission_manager.clone()
-        )),
-    ];
-
-    // Load MCP tools from configuration
-    match load_mcp_tools(&path).await {
-        Ok(mcp_tools) => {
-            tools.extend(mcp_tools);
-        }
-        Err(e) => {
-            println!("⚠️  Warning: Failed to load MCP tools: {}", e);
-        }
-    }
+    let tool_context = ToolContext {
+        workspace_root: path.clone(),
+        interaction: desktop_interaction(&app, &state),
+        permission_manager,
+        lsp_config: config.lsp.clone(),
+        lsp: state.lsp.clone(),
+        checkpoints: None,
+    };
+    let tools = tool_context.session_tools(&agent_id).await;
 
-    orchestrator.add_agent(uuid, role_enum, model, tools, AgentMode::Build).await
+    orchestrator
+        .add_agent(uuid, role_enum, model, tools, AgentMode::Build, tool_context.interaction)
+        .await
 }
 
 #[tauri::command]
@@ -740,10 +1041,11 @@ pub async fn get_task_status(
 
 #[tauri::command]
 pub fn resolve_question(
+    state: State<'_, AppState>,
     question_id: String,
     answers: Value,
 ) -> Result<(), String> {
-    crate::adapters::tools::question::QuestionTool::resolve_question(question_id, answers)
+    state.pending_requests.resolve_question(&question_id, answers)
 }
 
 #[tauri::command]
@@ -852,7 +1154,10 @@ pub async fn search(
     use crate::domain::ports::Tool;
     
     let path = PathBuf::from(&workspace_path);
-    let tool = SearchTool::new(path);
+    let tool = SearchTool::new(
+        path,
+        Arc::new(tokio::sync::Mutex::new(crate::config::PermissionConfig::default())),
+    );
     
     let input = serde_json::json!({
         "pattern": pattern,
@@ -1271,6 +1576,7 @@ fn sanitize_permissions(mut config: PermissionConfig) -> PermissionConfig {
     clean(&mut config.todoread.rules);
     clean(&mut config.todowrite.rules);
     clean(&mut config.doom_loop.rules);
+    clean(&mut config.git_destructive.rules);
     config
 }
 
@@ -1315,11 +1621,12 @@ pub async fn save_permission_config(
     for (_, agent_arc) in agents.iter_mut() {
         let mut agent = agent_arc.lock().await;
         if agent.session.workspace_path == PathBuf::from(&workspace_path) {
+            let effective = agent.effective_permissions(&config);
             {
                 let mut perms = agent.permission_manager.lock().await;
-                *perms = config.clone();
+                *perms = effective.clone();
             }
-            agent.session.permissions.config = config.clone();
+            agent.session.permissions.config = effective;
         }
     }
 
@@ -0,0 +1,461 @@
+use crate::config::{AgentConfig, Config, PermissionConfig, SkillDiscovery};
+use crate::domain::models::AgentMode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A named agent: its own prompt template, model/provider, tool set,
+/// permission overrides and default mode.
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentDefinition {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// System prompt template. Supports `{{agent}}`, `{{mode}}`, `{{workspace}}`,
+    /// `{{mode_instructions}}`, `{{context}}` and `{{skills}}` placeholders.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Tool name globs; `!pattern` excludes. `None` means every tool.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+    /// Permission overrides, same shape as the `permission` config block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permission: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<AgentMode>,
+    /// Extra instructions: file paths (relative to the workspace) or literal text
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub instructions: Vec<String>,
+    pub source: AgentSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentSource {
+    Config,  // `agent` block in anvil.json
+    Global,  // ~/.config/anvil/agents/
+    Project, // .anvil/agents/
+}
+
+/// YAML frontmatter of an agent markdown file
+#[derive(Debug, Clone, Default, Deserialize)]
+struct AgentFrontmatter {
+    name: Option<String>,
+    description: Option<String>,
+    model: Option<String>,
+    provider: Option<String>,
+    tools: Option<Vec<String>>,
+    permission: Option<serde_json::Value>,
+    mode: Option<String>,
+    #[serde(default)]
+    instructions: Vec<String>,
+}
+
+/// Parse a mode name as used in config files and commands
+pub fn parse_mode(value: &str) -> Option<AgentMode> {
+    match value.to_lowercase().as_str() {
+        "plan" => Some(AgentMode::Plan),
+        "build" => Some(AgentMode::Build),
+        "research" => Some(AgentMode::Research),
+        _ => None,
+    }
+}
+
+fn mode_name(mode: &AgentMode) -> &'static str {
+    match mode {
+        AgentMode::Plan => "plan",
+        AgentMode::Build => "build",
+        AgentMode::Research => "research",
+    }
+}
+
+impl AgentDefinition {
+    /// Build a definition from an `agent` entry in anvil.json
+    pub fn from_config(name: &str, config: &AgentConfig) -> Result<Self, String> {
+        let mode = config
+            .mode
+            .as_deref()
+            .map(|m| parse_mode(m).ok_or_else(|| format!("Agent '{}': unknown mode '{}'", name, m)))
+            .transpose()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            description: config.description.clone(),
+            prompt: config.prompt.clone(),
+            model: config.model.clone(),
+            provider: config.provider.clone(),
+            tools: config.tools.clone(),
+            permission: config.permission.clone(),
+            mode,
+            instructions: config.instructions.clone(),
+            source: AgentSource::Config,
+            path: None,
+        })
+    }
+
+    /// Parse an agent markdown file: YAML frontmatter followed by the prompt template
+    pub fn parse(content: &str, file_stem: &str, source: AgentSource, path: Option<PathBuf>) -> Result<Self, String> {
+        let (frontmatter, body) = Self::split_frontmatter(content)?;
+        let meta: AgentFrontmatter = match frontmatter {
+            Some(yaml) if !yaml.trim().is_empty() => serde_yaml::from_str(yaml)
+                .map_err(|e| format!("Invalid agent frontmatter: {}", e))?,
+            _ => AgentFrontmatter::default(),
+        };
+
+        let name = meta.name.unwrap_or_else(|| file_stem.to_string());
+        if !SkillDiscovery::is_valid_skill_name(&name) {
+            return Err(format!(
+                "Invalid agent name '{}': must be lowercase alphanumeric with single hyphens",
+                name
+            ));
+        }
+
+        let mode = meta
+            .mode
+            .as_deref()
+            .map(|m| parse_mode(m).ok_or_else(|| format!("Agent '{}': unknown mode '{}'", name, m)))
+            .transpose()?;
+
+        let body = body.trim();
+        Ok(Self {
+            name,
+            description: meta.description,
+            prompt: if body.is_empty() { None } else { Some(body.to_string()) },
+            model: meta.model,
+            provider: meta.provider,
+            tools: meta.tools,
+            permission: meta.permission,
+            mode,
+            instructions: meta.instructions,
+            source,
+            path,
+        })
+    }
+
+    fn split_frontmatter(content: &str) -> Result<(Option<&str>, &str), String> {
+        let trimmed = content.trim_start();
+        if !trimmed.starts_with("---") {
+            return Ok((None, content));
+        }
+
+        let rest = &trimmed[3..];
+        let end = rest
+            .find("\n---")
+            .ok_or_else(|| "Unterminated frontmatter: missing closing ---".to_string())?;
+        let yaml = &rest[..end];
+        let after = &rest[end + 4..];
+        let body = after.strip_prefix('\n').or_else(|| after.strip_prefix("\r\n")).unwrap_or(after);
+        Ok((Some(yaml), body))
+    }
+
+    /// Layer another definition of the same agent on top of this one
+    fn merge(mut self, other: AgentDefinition) -> Self {
+        self.description = other.description.or(self.description);
+        self.prompt = other.prompt.or(self.prompt);
+        self.model = other.model.or(self.model);
+        self.provider = other.provider.or(self.provider);
+        self.tools = other.tools.or(self.tools);
+        self.permission = other.permission.or(self.permission);
+        self.mode = other.mode.or(self.mode);
+        self.instructions.extend(other.instructions);
+        self.source = other.source;
+        self.path = other.path.or(self.path);
+        self
+    }
+
+    /// Whether a tool is part of this agent's tool set
+    pub fn allows_tool(&self, tool_name: &str) -> bool {
+        let Some(patterns) = &self.tools else {
+            return true;
+        };
+
+        let matches = |pattern: &str| {
+            glob::Pattern::new(pattern)
+                .map(|p| p.matches(tool_name))
+                .unwrap_or(pattern == tool_name)
+        };
+
+        let mut has_includes = false;
+        let mut included = false;
+        for pattern in patterns {
+            if let Some(excluded) = pattern.strip_prefix('!') {
+                if matches(excluded) {
+                    return false;
+                }
+            } else {
+                has_includes = true;
+                included |= matches(pattern);
+            }
+        }
+
+        !has_includes || included
+    }
+
+    /// Base permissions with this agent's overrides applied
+    pub fn effective_permissions(&self, base: &PermissionConfig) -> Result<PermissionConfig, String> {
+        let mut config = base.clone();
+        if let Some(overrides) = &self.permission {
+            config
+                .apply_overrides(overrides)
+                .map_err(|e| format!("Agent '{}': {}", self.name, e))?;
+        }
+        Ok(config)
+    }
+
+    /// Render the system prompt template. Sections without a placeholder in the
+    /// template are appended after it so the agent never loses workspace context.
+    pub fn render_prompt(
+        &self,
+        template: &str,
+        mode: &AgentMode,
+        mode_instruction: &str,
+        context: &str,
+        skills: &str,
+        workspace: &Path,
+    ) -> String {
+        let mut prompt = template
+            .replace("{{agent}}", &self.name)
+            .replace("{{mode}}", mode_name(mode))
+            .replace("{{workspace}}", &workspace.to_string_lossy());
+
+        let mut appended = Vec::new();
+        for (placeholder, value) in [
+            ("{{mode_instructions}}", mode_instruction),
+            ("{{context}}", context),
+            ("{{skills}}", skills),
+        ] {
+            if prompt.contains(placeholder) {
+                prompt = prompt.replace(placeholder, value);
+            } else if !value.is_empty() {
+                appended.push(value);
+            }
+        }
+
+        let instructions = self.load_instructions(workspace);
+        if !instructions.is_empty() {
+            appended.push(&instructions);
+        }
+
+        Self::join_sections(prompt, &appended)
+    }
+
+    fn join_sections(prompt: String, sections: &[&str]) -> String {
+        let mut result = prompt.trim_end().to_string();
+        for section in sections {
+            result.push_str("\n\n");
+            result.push_str(section);
+        }
+        result
+    }
+
+    fn load_instructions(&self, workspace: &Path) -> String {
+        let parts: Vec<String> = self
+            .instructions
+            .iter()
+            .map(|entry| {
+                let candidate = workspace.join(entry);
+                if candidate.is_file() {
+                    fs::read_to_string(&candidate).unwrap_or_else(|e| {
+                        format!("[Warning: Could not read {}: {}]", candidate.display(), e)
+                    })
+                } else {
+                    entry.clone()
+                }
+            })
+            .collect();
+
+        if parts.is_empty() {
+            return String::new();
+        }
+
+        format!("Agent Instructions:\n{}", parts.join("\n\n"))
+    }
+}
+
+/// Discovers named agents from configuration and agent files
+pub struct AgentRegistry;
+
+impl AgentRegistry {
+    /// Discover all agents for a workspace.
+    ///
+    /// Precedence (lowest to highest): `agent` entries in anvil.json,
+    /// `~/.config/anvil/agents/*.md`, then `.anvil/agents/*.md` in the workspace.
+    pub fn discover(workspace_path: &Path, config: &Config) -> HashMap<String, AgentDefinition> {
+        let mut agents: HashMap<String, AgentDefinition> = HashMap::new();
+
+        for (name, agent_config) in &config.agent {
+            match AgentDefinition::from_config(name, agent_config) {
+                Ok(def) => Self::insert(&mut agents, def),
+                Err(e) => eprintln!("Warning: {}", e),
+            }
+        }
+
+        if let Some(config_dir) = dirs::config_dir() {
+            Self::scan_directory(&config_dir.join("anvil").join("agents"), AgentSource::Global, &mut agents);
+        }
+
+        Self::scan_directory(&workspace_path.join(".anvil").join("agents"), AgentSource::Project, &mut agents);
+
+        agents
+    }
+
+    /// Look up a single agent by name
+    pub fn find(workspace_path: &Path, config: &Config, name: &str) -> Result<AgentDefinition, String> {
+        Self::discover(workspace_path, config)
+            .remove(name)
+            .ok_or_else(|| format!("Unknown agent: {}", name))
+    }
+
+    fn insert(agents: &mut HashMap<String, AgentDefinition>, def: AgentDefinition) {
+        match agents.remove(&def.name) {
+            Some(existing) => {
+                agents.insert(def.name.clone(), existing.merge(def));
+            }
+            None => {
+                agents.insert(def.name.clone(), def);
+            }
+        }
+    }
+
+    fn scan_directory(dir: &Path, source: AgentSource, agents: &mut HashMap<String, AgentDefinition>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && p.extension().map(|ext| ext == "md").unwrap_or(false))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            let parsed = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| AgentDefinition::parse(&content, &stem, source.clone(), Some(path.clone())));
+
+            match parsed {
+                Ok(def) => Self::insert(agents, def),
+                Err(e) => eprintln!("Warning: Failed to load agent {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Action;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_agent_file() {
+        let content = r#"---
+description: Reviews diffs for bugs
+model: claude-sonnet-4
+provider: anthropic
+mode: research
+tools: ["read_file", "search", "git"]
+permission:
+  bash: deny
+---
+You are {{agent}}, a careful reviewer.
+"#;
+        let def = AgentDefinition::parse(content, "reviewer", AgentSource::Project, None).unwrap();
+        assert_eq!(def.name, "reviewer");
+        assert_eq!(def.description.as_deref(), Some("Reviews diffs for bugs"));
+        assert_eq!(def.provider.as_deref(), Some("anthropic"));
+        assert_eq!(def.mode, Some(AgentMode::Research));
+        assert_eq!(def.prompt.as_deref(), Some("You are {{agent}}, a careful reviewer."));
+        assert!(def.allows_tool("search"));
+        assert!(!def.allows_tool("bash"));
+    }
+
+    #[test]
+    fn test_parse_agent_without_frontmatter() {
+        let def = AgentDefinition::parse("Just a prompt", "helper", AgentSource::Global, None).unwrap();
+        assert_eq!(def.name, "helper");
+        assert_eq!(def.prompt.as_deref(), Some("Just a prompt"));
+        assert!(def.tools.is_none());
+    }
+
+    #[test]
+    fn test_parse_agent_rejects_bad_input() {
+        assert!(AgentDefinition::parse("---\nmode: fly\n---\nx", "a", AgentSource::Project, None).is_err());
+        assert!(AgentDefinition::parse("---\nname: a\n", "a", AgentSource::Project, None).is_err());
+        assert!(AgentDefinition::parse("x", "Bad_Name", AgentSource::Project, None).is_err());
+    }
+
+    #[test]
+    fn test_tool_patterns() {
+        let mut def = AgentDefinition::parse("x", "a", AgentSource::Project, None).unwrap();
+        def.tools = Some(vec!["mcp_*".to_string(), "read_file".to_string()]);
+        assert!(def.allows_tool("mcp_github_search"));
+        assert!(def.allows_tool("read_file"));
+        assert!(!def.allows_tool("write_file"));
+
+        def.tools = Some(vec!["!bash".to_string()]);
+        assert!(def.allows_tool("write_file"));
+        assert!(!def.allows_tool("bash"));
+    }
+
+    #[test]
+    fn test_effective_permissions() {
+        let mut def = AgentDefinition::parse("x", "a", AgentSource::Project, None).unwrap();
+        def.permission = Some(serde_json::json!({ "*": "deny", "read": "allow" }));
+        let config = def.effective_permissions(&PermissionConfig::default()).unwrap();
+        assert_eq!(config.bash.default, Action::Deny);
+        assert_eq!(config.read.default, Action::Allow);
+        // Default read rules survive the override
+        assert_eq!(config.read.evaluate(".env"), Action::Deny);
+    }
+
+    #[test]
+    fn test_render_prompt_placeholders() {
+        let def = AgentDefinition::parse("x", "writer", AgentSource::Project, None).unwrap();
+        let rendered = def.render_prompt(
+            "I am {{agent}} in {{mode}} mode.\n{{context}}",
+            &AgentMode::Build,
+            "BUILD",
+            "CTX",
+            "SKILLS",
+            Path::new("/tmp/ws"),
+        );
+        assert!(rendered.starts_with("I am writer in build mode.\nCTX"));
+        // Sections without a placeholder are appended
+        assert!(rendered.contains("BUILD"));
+        assert!(rendered.ends_with("SKILLS"));
+    }
+
+    #[test]
+    fn test_discover_merges_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = temp_dir.path();
+        let agents_dir = workspace.join(".anvil").join("agents");
+        fs::create_dir_all(&agents_dir).unwrap();
+        fs::write(agents_dir.join("reviewer.md"), "---\nmode: plan\n---\nReview things.").unwrap();
+
+        let mut config = Config::default();
+        config.agent.insert(
+            "reviewer".to_string(),
+            AgentConfig {
+                model: Some("gpt-4o".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let def = AgentRegistry::find(workspace, &config, "reviewer").unwrap();
+        assert_eq!(def.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(def.mode, Some(AgentMode::Plan));
+        assert_eq!(def.source, AgentSource::Project);
+        assert!(AgentRegistry::find(workspace, &config, "missing").is_err());
+    }
+}
@@ -165,8 +165,43 @@ pub struct PermissionConfig {
     pub todowrite: ToolPermission,
     #[serde(default)]
     pub doom_loop: ToolPermission,
+    /// Git operations that can discard work: switching branches, stashing
+    /// and restoring files. Rules match e.g. `restore src/main.rs`.
+    #[serde(default)]
+    pub git_destructive: ToolPermission,
     #[serde(default)]
     pub external_directory: Option<HashMap<String, Action>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxConfig>,
+}
+
+/// Sandbox for commands run by the bash tool. Linux only; commands run
+/// through bubblewrap (`bwrap`) in unprivileged user namespaces.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SandboxConfig {
+    #[serde(default = "default_sandbox_enabled")]
+    pub enabled: bool,
+    /// Allow network access from inside the sandbox
+    #[serde(default)]
+    pub network: bool,
+    /// Paths besides the workspace and /tmp that commands may write to
+    #[serde(default)]
+    pub writable: Vec<String>,
+    /// Environment variables passed through on top of the safe defaults
+    #[serde(default)]
+    pub env: Vec<String>,
+    /// Run commands whose bash permission is `ask` without prompting while
+    /// they are sandboxed; `deny` rules still apply
+    #[serde(default)]
+    pub auto_allow: bool,
+    /// Refuse to run commands when the sandbox is unavailable instead of
+    /// running them unsandboxed under the normal permission rules
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn default_sandbox_enabled() -> bool {
+    true
 }
 
 impl Default for PermissionConfig {
@@ -193,11 +228,10 @@ impl Default for PermissionConfig {
             lsp: allow.clone(),
             todoread: allow.clone(),
             todowrite: allow.clone(),
-            doom_loop: ToolPermission {
-                default: Action::Ask,
-                rules: Vec::new(),
-            },
+            doom_loop: default_ask_permission(),
+            git_destructive: default_ask_permission(),
             external_directory: None,
+            sandbox: None,
         }
     }
 }
@@ -231,6 +265,11 @@ impl PermissionConfig {
                                 .map_err(|e| format!("Invalid external_directory rules: {}", e))?;
                             config.external_directory = Some(rules);
                         }
+                        "sandbox" => {
+                            let sandbox: SandboxConfig = serde_json::from_value(val)
+                                .map_err(|e| format!("Invalid sandbox config: {}", e))?;
+                            config.sandbox = Some(sandbox);
+                        }
                         "bash" => {
                             config.bash = ToolPermission::from_json_value(val)?;
                             explicit_tools.push("bash".to_string());
@@ -287,6 +326,10 @@ impl PermissionConfig {
                             config.doom_loop = ToolPermission::from_json_value(val)?;
                             explicit_tools.push("doom_loop".to_string());
                         }
+                        "git_destructive" => {
+                            config.git_destructive = ToolPermission::from_json_value(val)?;
+                            explicit_tools.push("git_destructive".to_string());
+                        }
                         _ => {}
                     }
                 }
@@ -344,8 +387,87 @@ impl PermissionConfig {
             self.todowrite.default = action.clone();
         }
         if !is_explicit("doom_loop") {
-            self.doom_loop.default = action;
+            self.doom_loop.default = action.clone();
+        }
+        if !is_explicit("git_destructive") {
+            self.git_destructive.default = action;
+        }
+    }
+
+    fn tool_permission_mut(&mut self, tool: &str) -> Option<&mut ToolPermission> {
+        match tool {
+            "bash" => Some(&mut self.bash),
+            "edit" => Some(&mut self.edit),
+            "read" => Some(&mut self.read),
+            "write" => Some(&mut self.write),
+            "skill" => Some(&mut self.skill),
+            "list" => Some(&mut self.list),
+            "glob" => Some(&mut self.glob),
+            "grep" => Some(&mut self.grep),
+            "webfetch" => Some(&mut self.webfetch),
+            "task" => Some(&mut self.task),
+            "lsp" => Some(&mut self.lsp),
+            "todoread" => Some(&mut self.todoread),
+            "todowrite" => Some(&mut self.todowrite),
+            "doom_loop" => Some(&mut self.doom_loop),
+            "git_destructive" => Some(&mut self.git_destructive),
+            _ => None,
+        }
+    }
+
+    /// Apply overrides in the same shape as the `permission` config block.
+    /// Tools named in the overrides are replaced; `*` sets the default of the rest.
+    pub fn apply_overrides(&mut self, overrides: &serde_json::Value) -> Result<(), String> {
+        match overrides {
+            serde_json::Value::String(_) => {
+                let action: Action = serde_json::from_value(overrides.clone())
+                    .map_err(|e| format!("Invalid permission action: {}", e))?;
+                self.apply_global_default(action, &[]);
+            }
+            serde_json::Value::Object(map) => {
+                let mut global_default: Option<Action> = None;
+                let mut explicit_tools: Vec<String> = Vec::new();
+
+                for (key, val) in map {
+                    match key.as_str() {
+                        "*" => {
+                            global_default = Some(
+                                serde_json::from_value::<Action>(val.clone())
+                                    .map_err(|e| format!("Invalid permission action: {}", e))?,
+                            );
+                        }
+                        "external_directory" => {
+                            let rules: HashMap<String, Action> = serde_json::from_value(val.clone())
+                                .map_err(|e| format!("Invalid external_directory rules: {}", e))?;
+                            self.external_directory
+                                .get_or_insert_with(HashMap::new)
+                                .extend(rules);
+                        }
+                        "sandbox" => {
+                            let sandbox: SandboxConfig = serde_json::from_value(val.clone())
+                                .map_err(|e| format!("Invalid sandbox config: {}", e))?;
+                            self.sandbox = Some(sandbox);
+                        }
+                        tool => {
+                            let permission = ToolPermission::from_json_value(val.clone())?;
+                            let slot = self
+                                .tool_permission_mut(tool)
+                                .ok_or_else(|| format!("Unknown permission key: {}", tool))?;
+                            *slot = permission;
+                            explicit_tools.push(tool.to_string());
+                        }
+                    }
+                }
+
+                if let Some(action) = global_default {
+                    self.apply_global_default(action, &explicit_tools);
+                }
+            }
+            _ => return Err("Invalid permission format".to_string()),
         }
+
+        self.ensure_default_read_rules();
+        Ok(())
     }
 
     fn ensure_default_read_rules(&mut self) {
@@ -443,6 +565,20 @@ pub struct AgentConfig {
     pub provider: Option<String>,
     #[serde(default)]
     pub instructions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// System prompt template (see `AgentDefinition::prompt`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// Allowed tool name globs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+    /// Permission overrides applied on top of the session permissions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission: Option<serde_json::Value>,
+    /// Default mode: plan, build or research
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
     #[serde(flatten)]
     pub extra: HashMap<String, serde_json::Value>,
 }
@@ -451,7 +587,60 @@ pub struct AgentConfig {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct LspConfig {
     pub enabled: Option<bool>,
+    /// Server command lines used for any file no language server claims
     pub servers: Option<Vec<String>>,
+    /// Language servers by language name, e.g. `rust`. Fields given here
+    /// override the built-in server of the same name.
+    #[serde(default)]
+    pub languages: HashMap<String, LspServerConfig>,
+}
+
+/// One language server and the files it serves
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct LspServerConfig {
+    /// Program to run, found on PATH unless it is a path
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Sent as `initializationOptions` when the server starts
+    #[serde(default, alias = "initializationOptions")]
+    pub initialization_options: Option<serde_json::Value>,
+    /// File extensions without the dot, e.g. `rs`
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Files that mark a project root, e.g. `Cargo.toml`. The server runs
+    /// in the outermost directory containing one, within the workspace.
+    #[serde(default)]
+    pub root_markers: Vec<String>,
+    /// Set to false to turn off a built-in server
+    pub enabled: Option<bool>,
+}
+
+/// Checks run automatically after the agent edits files
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct VerifyConfig {
+    /// Defaults to true when commands are configured
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub commands: Vec<VerifyCommand>,
+    /// Failed verification rounds per turn before automatic checks pause (default: 3)
+    pub max_attempts: Option<u32>,
+}
+
+/// One verification command, e.g. `cargo check --message-format=json`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct VerifyCommand {
+    /// Label shown in reports; defaults to the command
+    pub name: Option<String>,
+    pub command: String,
+    /// How to read the output: `cargo`, `tsc` or `text` (default: guessed from the command)
+    pub format: Option<String>,
+    /// Only run when an edited file matches one of these globs
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Seconds before the command is killed (default: 120)
+    pub timeout: Option<u64>,
 }
 
 /// MCP tool filtering configuration
@@ -655,6 +844,13 @@ pub struct Config {
 
     /// MCP configuration
     pub mcp: Option<McpConfig>,
+
+    /// Verification commands run after edits
+    pub verify: Option<VerifyConfig>,
+
+    /// Commit every turn that changes files to `refs/anvil/<session>`
+    /// (default: false)
+    pub shadow_commits: Option<bool>,
 }
 
 /// Configuration manager that handles loading and merging configs
@@ -776,6 +972,15 @@ impl ConfigManager {
             if local.mcp.is_some() {
                 merged.mcp = Self::merge_mcp_config(&merged.mcp, &local.mcp);
             }
+
+            // Override verification commands
+            if local.verify.is_some() {
+                merged.verify = local.verify.clone();
+            }
+
+            if local.shadow_commits.is_some() {
+                merged.shadow_commits = local.shadow_commits;
+            }
         }
 
         // Set defaults for missing values
@@ -840,10 +1045,12 @@ impl ConfigManager {
             todoread: Self::merge_tool_permissions(&global.todoread, &local.todoread),
             todowrite: Self::merge_tool_permissions(&global.todowrite, &local.todowrite),
             doom_loop: Self::merge_tool_permissions(&global.doom_loop, &local.doom_loop),
+            git_destructive: Self::merge_tool_permissions(&global.git_destructive, &local.git_destructive),
             external_directory: local
                 .external_directory
                 .clone()
                 .or_else(|| global.external_directory.clone()),
+            sandbox: local.sandbox.clone().or_else(|| global.sandbox.clone()),
         }
     }
 
@@ -905,13 +1112,21 @@ impl ConfigManager {
         config.permission.todoread.default = Action::Allow;
         config.permission.todowrite.default = Action::Allow;
 
-        // Doom loop guard defaults to ask
+        // Doom loop guard and destructive git operations default to ask
         config.permission.doom_loop.default = Action::Ask;
+        config.permission.git_destructive.default = Action::Ask;
 
         config
     }
 }
 
+fn default_ask_permission() -> ToolPermission {
+    ToolPermission {
+        default: Action::Ask,
+        rules: Vec::new(),
+    }
+}
+
 fn default_read_rules() -> Vec<PermissionRule> {
     vec![
         PermissionRule {
@@ -1005,6 +1220,29 @@ mod tests {
         assert_eq!(config.permission.bash.rules[0].pattern, "git status *");
     }
 
+    #[test]
+    fn test_lsp_languages_parse() {
+        let json = r#"{
+            "lsp": {
+                "languages": {
+                    "rust": { "command": "rust-analyzer", "initializationOptions": { "check": { "command": "clippy" } } },
+                    "go": { "enabled": false },
+                    "zig": { "command": "zls", "extensions": ["zig"], "root_markers": ["build.zig"] }
+                }
+            }
+        }"#;
+
+        let config: Config = serde_json::from_str(json).unwrap();
+        let languages = config.lsp.unwrap().languages;
+        assert_eq!(
+            languages["rust"].initialization_options,
+            Some(serde_json::json!({ "check": { "command": "clippy" } }))
+        );
+        assert_eq!(languages["go"].enabled, Some(false));
+        assert!(languages["go"].command.is_empty());
+        assert_eq!(languages["zig"].root_markers, vec!["build.zig"]);
+    }
+
     #[test]
     fn test_config_save_and_load() {
         let temp_dir = TempDir::new().unwrap();
@@ -1119,6 +1357,27 @@ mod tests {
         assert_eq!(tp.evaluate("ls -la"), Action::Ask); // Default
     }
 
+    #[test]
+    fn test_sandbox_permission_parse() {
+        let config: PermissionConfig = serde_json::from_value(serde_json::json!({
+            "bash": "ask",
+            "sandbox": { "network": true, "auto_allow": true }
+        }))
+        .unwrap();
+        let sandbox = config.sandbox.clone().unwrap();
+        assert!(sandbox.enabled);
+        assert!(sandbox.network);
+        assert!(sandbox.auto_allow);
+        assert!(sandbox.writable.is_empty());
+
+        let mut overridden = config;
+        overridden
+            .apply_overrides(&serde_json::json!({ "sandbox": { "enabled": false } }))
+            .unwrap();
+        assert!(!overridden.sandbox.unwrap().enabled);
+        assert!(serde_json::from_value::<PermissionConfig>(serde_json::json!({ "sandbox": { "network": "yes" } })).is_err());
+    }
+
     #[test]
     fn test_default_global_config() {
         let config = ConfigManager::create_default_global_config();
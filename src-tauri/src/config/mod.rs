@@ -1,10 +1,13 @@
 pub mod manager;
 pub mod watcher;
 pub mod skills;
+pub mod agents;
 
 pub use manager::{
-    Action, AgentConfig, Config, ConfigManager, LspConfig, McpConfig, PermissionConfig,
-    PermissionRule, ProviderConfig, ResolvedMcpServer, ToolPermission,
+    Action, AgentConfig, Config, ConfigManager, LspConfig, LspServerConfig, McpConfig, PermissionConfig,
+    PermissionRule, ProviderConfig, ResolvedMcpServer, SandboxConfig, ToolPermission, VerifyCommand,
+    VerifyConfig,
 };
 pub use watcher::start_config_watcher;
 pub use skills::{SkillDiscovery, SkillLoader, Skill, LoadedSkill, SkillMetadata, SkillSource, SkillError};
+pub use agents::{AgentDefinition, AgentRegistry, AgentSource};
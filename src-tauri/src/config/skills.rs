@@ -175,7 +175,7 @@ impl SkillDiscovery {
 
     /// Validate skill name according to rules
     /// Regex: ^[a-z0-9]+(-[a-z0-9]+)*$
-    fn is_valid_skill_name(name: &str) -> bool {
+    pub(crate) fn is_valid_skill_name(name: &str) -> bool {
         if name.is_empty() || name.len() > 64 {
             return false;
         }
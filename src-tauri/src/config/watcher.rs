@@ -62,7 +62,7 @@ pub fn start_config_watcher(
                     let agent = agent_arc.lock().await;
                     if agent.session.workspace_path == workspace_path {
                         let mut perms = agent.permission_manager.lock().await;
-                        *perms = new_config.permission.clone();
+                        *perms = agent.effective_permissions(&new_config.permission);
                         println!("Updated permissions for agent {}", agent.session.id);
                     }
                 }
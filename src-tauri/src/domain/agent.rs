@@ -1,33 +1,44 @@
 use crate::domain::models::*;
-use crate::domain::ports::{ModelAdapter, Tool};
+use crate::domain::doom_loop::LoopDetector;
+use crate::domain::ports::{InteractionProvider, ModelAdapter, Tool};
+use crate::domain::steering::{SteeringInbox, SteeringMessage};
+use crate::domain::verify::{is_edit_call, Verifier, VERIFY_TOOL};
 use serde::Serialize;
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
-use tauri::{AppHandle, Emitter};
-use tokio::sync::{mpsc::Sender, oneshot};
-use uuid::Uuid;
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
 
 pub struct Agent {
     pub session: AgentSession,
     model: Arc<dyn ModelAdapter>,
     tools: Vec<Arc<dyn Tool>>,
     pub permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
-    app: Option<AppHandle>,
-    pending_confirmations: Option<Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>>,
+    interaction: Arc<dyn InteractionProvider>,
     research_overrides: HashMap<String, crate::config::ToolPermission>,
+    definition: Option<crate::config::AgentDefinition>,
+    checkpoints: Option<Arc<crate::domain::checkpoint::CheckpointStore>>,
+    shadow: Option<Arc<crate::domain::shadow::ShadowLog>>,
+    steering: Arc<SteeringInbox>,
+    verifier: Option<Verifier>,
+    lsp: Option<Arc<crate::lsp::LspPool>>,
 }
 
-#[derive(Serialize, Clone)]
-struct PermissionConfirmationRequest {
-    id: String,
-    session_id: String,
-    #[serde(rename = "type")]
-    type_: String,
-    tool_name: String,
-    input: String,
-    suggested_pattern: String,
+/// How the steps of a turn ended
+enum StepsEnd {
+    /// The model replied without calling tools
+    Answered(String),
+    /// The step limit was reached first
+    OutOfSteps(String),
+}
+
+impl StepsEnd {
+    fn into_text(self) -> String {
+        match self {
+            StepsEnd::Answered(text) | StepsEnd::OutOfSteps(text) => text,
+        }
+    }
 }
 
 #[derive(Serialize, Clone)]
@@ -52,17 +63,104 @@ impl Agent {
         model: Arc<dyn ModelAdapter>,
         tools: Vec<Arc<dyn Tool>>,
         permission_manager: Arc<tokio::sync::Mutex<crate::config::PermissionConfig>>,
-        app: Option<AppHandle>,
-        pending_confirmations: Option<Arc<Mutex<HashMap<String, oneshot::Sender<crate::domain::models::ConfirmationResponse>>>>>,
+        interaction: Arc<dyn InteractionProvider>,
     ) -> Self {
         Self {
             session,
             model,
             tools,
             permission_manager,
-            app,
-            pending_confirmations,
+            interaction,
             research_overrides: HashMap::new(),
+            definition: None,
+            checkpoints: None,
+            shadow: None,
+            steering: Arc::new(SteeringInbox::new()),
+            verifier: None,
+            lsp: None,
+        }
+    }
+
+    /// Run the workspace checks after edits and offer the `verify` tool
+    pub fn set_verifier(&mut self, verifier: Option<Verifier>) {
+        self.verifier = verifier;
+    }
+
+    /// Keep language servers in sync with the files the agent edits
+    pub fn set_lsp(&mut self, lsp: Arc<crate::lsp::LspPool>) {
+        self.lsp = Some(lsp);
+    }
+
+    /// Inbox for messages sent while a turn is running
+    pub fn steering(&self) -> Arc<SteeringInbox> {
+        self.steering.clone()
+    }
+
+    /// Open a new file checkpoint at every user message
+    pub fn set_checkpoints(&mut self, checkpoints: Arc<crate::domain::checkpoint::CheckpointStore>) {
+        self.checkpoints = Some(checkpoints);
+    }
+
+    pub fn checkpoints(&self) -> Option<Arc<crate::domain::checkpoint::CheckpointStore>> {
+        self.checkpoints.clone()
+    }
+
+    /// Commit the working tree after every turn that changes it
+    pub fn set_shadow(&mut self, shadow: Arc<crate::domain::shadow::ShadowLog>) {
+        self.shadow = Some(shadow);
+    }
+
+    /// Number of turns the user started so far; the index of the next turn
+    pub fn turn_count(&self) -> usize {
+        self.session.messages.iter().filter(|m| m.starts_turn()).count()
+    }
+
+    /// Drop the user message that opened `turn` and everything after it
+    pub fn truncate_to_turn(&mut self, turn: usize) -> Option<Message> {
+        let position = self
+            .session
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.starts_turn())
+            .nth(turn)
+            .map(|(i, _)| i)?;
+        let removed = self.session.messages.split_off(position);
+        removed.into_iter().next()
+    }
+
+    fn push_user_message(&mut self, input: String, attachments: Option<Vec<Attachment>>) {
+        if let Some(checkpoints) = &self.checkpoints {
+            checkpoints.begin_turn(self.turn_count());
+        }
+        self.session.messages.push(Message {
+            role: Role::User,
+            content: Some(input),
+            tool_calls: None,
+            tool_call_id: None,
+            attachments,
+            synthetic: false,
+        });
+    }
+
+    /// Run this session as a named agent
+    pub fn set_definition(&mut self, definition: crate::config::AgentDefinition) {
+        self.session.agent = Some(definition.name.clone());
+        self.definition = Some(definition);
+    }
+
+    pub fn definition(&self) -> Option<&crate::config::AgentDefinition> {
+        self.definition.as_ref()
+    }
+
+    /// Permissions for this session given freshly loaded base permissions
+    pub fn effective_permissions(&self, base: &crate::config::PermissionConfig) -> crate::config::PermissionConfig {
+        match &self.definition {
+            Some(definition) => definition.effective_permissions(base).unwrap_or_else(|e| {
+                eprintln!("Warning: {}", e);
+                base.clone()
+            }),
+            None => base.clone(),
         }
     }
 
@@ -85,7 +183,7 @@ impl Agent {
             "bash" => &mut config.bash,
             "read_file" | "read" => &mut config.read,
             "write_file" | "write" => &mut config.write,
-            "edit_file" | "edit" | "patch" => &mut config.edit,
+            "edit_file" | "edit" | "multi_edit" | "patch" => &mut config.edit,
             "list" => &mut config.list,
             "glob" => &mut config.glob,
             "search" | "grep" => &mut config.grep,
@@ -95,6 +193,7 @@ impl Agent {
             "todoread" => &mut config.todoread,
             "todowrite" => &mut config.todowrite,
             "doom_loop" => &mut config.doom_loop,
+            "git_destructive" => &mut config.git_destructive,
             "skill" => &mut config.skill,
             _ => return,
         };
@@ -123,6 +222,11 @@ impl Agent {
         )
     }
 
+    /// Read-only tools, except `lsp` requests that change files
+    fn is_research_allowed_call(tool_name: &str, args: &Value) -> bool {
+        Self::is_research_allowed_tool(tool_name) && !is_edit_call(tool_name, args)
+    }
+
     fn research_override_action(&self, tool_name: &str, input: &str) -> crate::config::Action {
         self.research_overrides
             .get(tool_name)
@@ -141,27 +245,17 @@ impl Agent {
         });
     }
 
-    pub async fn step(&mut self, user_input: Option<String>, attachments: Option<Vec<Attachment>>) -> Result<String, String> {
-        // 1. Add User Message
-        if let Some(input) = user_input {
-            self.session.messages.push(Message {
-                role: Role::User,
-                content: Some(input),
-                tool_calls: None,
-                tool_call_id: None,
-                attachments,
-            });
-        }
-
-        // 2. Build Context
+    /// Rebuild the system message from the current mode, workspace context,
+    /// skills and (when set) the agent definition's prompt template.
+    async fn refresh_system_message(&mut self) {
         let context_summary = crate::domain::context::ContextBuilder::build(&self.session.workspace_path);
         
         // Handle Modes (Plan, Build, Research)
         let mode_instruction = match self.session.mode {
             crate::domain::models::AgentMode::Plan => 
-                "You are in PLAN mode. Provide a detailed, step-by-step plan for the user's request. DO NOT execute any tools. Just describe what you would do.",
+                "You are in PLAN mode. Investigate the codebase with read-only tools (read_file, list, glob, search, grep, lsp, symbols, webfetch) but DO NOT modify anything; lsp rename, format and applying code actions are not available. When you understand the task, call submit_plan with ordered steps, the files each step touches, its rationale and acceptance checks, then briefly summarize the plan for the user.",
             crate::domain::models::AgentMode::Research => 
-                "You are in RESEARCH mode. Prefer read-only tools like read_file, list, glob, search, grep, lsp, symbols, and webfetch. If you need a restricted tool (write, edit, patch, bash, git, task, todowrite, skill), you must ask the user for approval before proceeding.",
+                "You are in RESEARCH mode. Prefer read-only tools like read_file, list, glob, search, grep, lsp, symbols, and webfetch. If you need a restricted tool (write, edit, patch, bash, git, task, todowrite, skill, or an lsp rename, format or code action), you must ask the user for approval before proceeding.",
             crate::domain::models::AgentMode::Build => 
                 "You are in BUILD mode. You are an autonomous coding agent. Execute tools to fulfill the request.",
         };
@@ -171,8 +265,17 @@ impl Agent {
         
         // Find existing system message or prepend one
         let system_msg_idx = self.session.messages.iter().position(|m| m.role == Role::System);
-        let system_content = format!(
-            "You are Anvil, an advanced AI coding agent.
+        let system_content = match self.definition.as_ref().and_then(|d| d.prompt.as_ref().map(|p| (d, p))) {
+            Some((definition, template)) => definition.render_prompt(
+                template,
+                &self.session.mode,
+                mode_instruction,
+                &context_summary,
+                &skills_info,
+                &self.session.workspace_path,
+            ),
+            None => format!(
+                "You are Anvil, an advanced AI coding agent.
 
 {}
 
@@ -198,10 +301,11 @@ IMPORTANT RULES:
 6. If uncertain whether to use tools, respond conversationally without tools.
 
 Previous instructions remain active.",
-            mode_instruction,
-            context_summary,
-            skills_info
-        );
+                mode_instruction,
+                context_summary,
+                skills_info
+            ),
+        };
 
         if let Some(idx) = system_msg_idx {
              self.session.messages[idx].content = Some(system_content);
@@ -212,61 +316,121 @@ Previous instructions remain active.",
                  tool_calls: None,
                  tool_call_id: None,
                  attachments: None,
+                 synthetic: false,
              });
         }
+    }
 
-        // If Plan Mode, skip the tool loop entirely and just chat
-        if self.session.mode == crate::domain::models::AgentMode::Plan {
-             let req = ChatRequest {
-                messages: self.session.messages.clone(),
-                model_id: self.session.model.clone(),
-                temperature: Some(0.7),
-                tools: None, // Disable tools for planning
-            };
-            let res = self.model.chat(req).await;
-            
-            self.session.messages.push(Message {
-                role: res.role.clone(),
-                content: Some(res.content.clone()),
-                tool_calls: None,
-                tool_call_id: None,
-                attachments: None,
-            });
-            
-            return Ok(res.content);
+    pub async fn step(&mut self, user_input: Option<String>, attachments: Option<Vec<Attachment>>) -> Result<String, String> {
+        self.run_turn(user_input, attachments, None).await
+    }
+
+    pub async fn step_stream(&mut self, user_input: Option<String>, attachments: Option<Vec<Attachment>>, tx: Sender<String>) -> Result<String, String> {
+        self.run_turn(user_input, attachments, Some(tx)).await
+    }
+
+    /// Run one user turn; streams tokens through `tx` when given
+    async fn run_turn(
+        &mut self,
+        user_input: Option<String>,
+        attachments: Option<Vec<Attachment>>,
+        tx: Option<Sender<String>>,
+    ) -> Result<String, String> {
+        let first_turn = self.turn_count();
+        let before = self.shadow.as_ref().and_then(|shadow| match shadow.snapshot() {
+            Ok(tree) => Some(tree),
+            Err(e) => {
+                eprintln!("Warning: shadow commit skipped: {}", e);
+                None
+            }
+        });
+
+        self.steering.begin_turn();
+        let mut result = self.run_steps(user_input, attachments, tx.clone()).await;
+        // Messages that came after the last check get another round, unless
+        // the turn stopped short of an answer
+        loop {
+            let leftovers = self.steering.end_turn();
+            if leftovers.is_empty() {
+                break;
+            }
+            if !matches!(result, Ok(StepsEnd::Answered(_))) {
+                self.return_steering(leftovers);
+                break;
+            }
+            self.steering.begin_turn();
+            self.deliver_steering(leftovers);
+            result = self.run_steps(None, None, tx.clone()).await;
+        }
+        let result = result.map(StepsEnd::into_text);
+
+        if let Some(before) = before {
+            self.record_shadow_turn(first_turn, before);
+        }
+        result
+    }
+
+    /// Commit what the turn changed. A turn without a new user message
+    /// continues the previous one.
+    fn record_shadow_turn(&self, first_turn: usize, before: git2::Oid) {
+        let Some(shadow) = &self.shadow else {
+            return;
+        };
+        let turn = if self.turn_count() > first_turn { first_turn } else { first_turn.saturating_sub(1) };
+        let prompt = self
+            .session
+            .messages
+            .iter()
+            .filter(|m| m.starts_turn())
+            .nth(turn)
+            .and_then(|m| m.content.clone())
+            .unwrap_or_default();
+
+        if let Err(e) = shadow.record_turn(turn, before, &prompt) {
+            eprintln!("Warning: shadow commit failed: {}", e);
+        }
+    }
+
+    async fn run_steps(
+        &mut self,
+        user_input: Option<String>,
+        attachments: Option<Vec<Attachment>>,
+        tx: Option<Sender<String>>,
+    ) -> Result<StepsEnd, String> {
+        // 1. Add User Message
+        if let Some(input) = user_input {
+            self.push_user_message(input, attachments);
         }
 
+        // 2. Build Context
+        self.refresh_system_message().await;
+
         // 3. Chat Loop (Re-act)
         // Safety limit to prevent infinite loops
         let mut steps = 0;
         const MAX_STEPS: u32 = 10;
-        let mut last_signature: Option<String> = None;
-        let mut repeat_count: u32 = 0;
+        let mut loops = LoopDetector::new();
+        let mut verify_failures = 0;
 
         loop {
             if steps >= MAX_STEPS {
-                return Ok("⚠️ Agent exceeded maximum steps without final response.".to_string());
+                return Ok(StepsEnd::OutOfSteps(
+                    "⚠️ Agent exceeded maximum steps without final response.".to_string(),
+                ));
             }
             steps += 1;
 
-            // Prepare Request
-            // Wrap tool schemas in OpenAI format: { type: "function", function: { ... } }
-            let tool_schemas: Vec<Value> = self.tools.iter().map(|t| {
-                json!({
-                    "type": "function",
-                    "function": t.schema()
-                })
-            }).collect();
-
             let req = ChatRequest {
                 messages: self.session.messages.clone(),
                 model_id: self.session.model.clone(),
                 temperature: Some(0.0), // Deterministic for tools
-                tools: Some(tool_schemas),
+                tools: Some(self.tool_schemas()),
             };
 
-            // Call Model
-            let res = self.model.chat(req).await;
+            let res = match &tx {
+                Some(tx) => self.model.stream(req, tx.clone()).await,
+                None => self.model.chat(req).await,
+            };
 
             // Append Assistant Message
             self.session.messages.push(Message {
@@ -275,547 +439,450 @@ Previous instructions remain active.",
                 tool_calls: res.tool_calls.clone(),
                 tool_call_id: res.tool_call_id.clone(),
                 attachments: None,
+                synthetic: false,
             });
 
-            // Check for Tool Calls
-            if let Some(tool_calls) = res.tool_calls {
-                if tool_calls.is_empty() {
-                     return Ok(res.content); // Done
+            // No tools called, return response unless the user added something meanwhile
+            let tool_calls = match res.tool_calls {
+                Some(calls) if !calls.is_empty() => calls,
+                _ if self.apply_steering() => {
+                    loops.reset();
+                    continue;
                 }
+                _ => return Ok(StepsEnd::Answered(res.content)),
+            };
 
-                // Execute Tools
-                for call in tool_calls {
-                    let args: Value = serde_json::from_str(&call.arguments).unwrap_or(json!({}));
-                    if self.session.mode == crate::domain::models::AgentMode::Research
-                        && !Self::is_research_allowed_tool(&call.name)
-                    {
-                        let input = Self::permission_input_for_tool(&call.name, &args);
-                        let suggested_pattern = Self::suggested_pattern_for_tool(&call.name, &input);
-                        let action = self.research_override_action(&call.name, &input);
-
-                        if !matches!(action, crate::config::Action::Allow) {
-                            let response = self
-                                .request_permission_confirmation("mode", &call.name, &input, suggested_pattern.clone())
-                                .await;
-                            match response {
-                                Ok(resp) if resp.allowed => {
-                                    if resp.always {
-                                        let pattern = resp.pattern.unwrap_or(suggested_pattern);
-                                        self.add_research_override_rule(&call.name, pattern);
-                                    }
-                                }
-                                _ => {
-                                    self.session.messages.push(Message {
-                                        role: Role::Tool,
-                                        content: Some(format!(
-                                            "Error: Tool '{}' blocked in RESEARCH mode.",
-                                            call.name
-                                        )),
-                                        tool_calls: None,
-                                        tool_call_id: Some(call.id),
-                                        attachments: None,
-                                    });
-                                    continue;
-                                }
-                            }
-                        }
-                    }
-                    let signature = format!("{}:{}", call.name, call.arguments);
-                    if last_signature.as_ref() == Some(&signature) {
-                        repeat_count += 1;
-                    } else {
-                        last_signature = Some(signature.clone());
-                        repeat_count = 1;
-                    }
-
-                    if repeat_count >= 3 {
-                        let action = {
-                            let config = self.permission_manager.lock().await;
-                            config.doom_loop.evaluate(&signature)
-                        };
-
-                        match action {
-                            crate::config::Action::Deny => {
-                                let result_content = format!("Error: Repeated tool call blocked (doom loop): {}", call.name);
-                                self.session.messages.push(Message {
-                                    role: Role::Tool,
-                                    content: Some(result_content),
-                                    tool_calls: None,
-                                    tool_call_id: Some(call.id),
-                                    attachments: None,
-                                });
-                                continue;
-                            }
-                            crate::config::Action::Ask => {
-                                let suggested_pattern = format!("{}:*", call.name);
-                                let response = self
-                                    .request_permission_confirmation("doom_loop", &call.name, &signature, suggested_pattern.clone())
-                                    .await;
-
-                                match response {
-                                    Ok(resp) if resp.allowed => {
-                                        if resp.always {
-                                            let pattern = resp.pattern.unwrap_or(suggested_pattern);
-                                            self.add_permission_rule("doom_loop", pattern, crate::config::Action::Allow).await;
-                                        }
-                                    }
-                                    _ => {
-                                        let result_content = format!("Error: Repeated tool call blocked by user: {}", call.name);
-                                        self.session.messages.push(Message {
-                                            role: Role::Tool,
-                                            content: Some(result_content),
-                                            tool_calls: None,
-                                            tool_call_id: Some(call.id),
-                                            attachments: None,
-                                        });
-                                        continue;
-                                    }
-                                }
-                            }
-                            crate::config::Action::Allow => {}
-                        }
-                    }
+            // Execute Tools
+            let mut edited = Vec::new();
+            let mut images = Vec::new();
+            for call in &tool_calls {
+                self.emit_tool_call(call);
+                // An interrupt cancels every call that has not started yet
+                let mut result_content = if self.steering.interrupt_requested() {
+                    "Error: Cancelled because the user interrupted with new instructions.".to_string()
+                } else {
+                    self.execute_tool_call(call, &mut loops).await
+                };
+                images.extend(Self::take_attachments(&mut result_content));
+                self.emit_tool_result(&call.id, &call.name, &result_content);
+
+                let args: Value = serde_json::from_str(&call.arguments).unwrap_or(json!({}));
+                if is_edit_call(&call.name, &args) && !result_content.starts_with("Error:") {
+                    edited.extend(crate::domain::verify::edited_paths(&call.name, &args, &result_content));
+                }
 
-                    let temp_external_rule = match self.ensure_external_directory_access(&call.name, &args).await {
-                        Ok(rule) => rule,
-                        Err(err) => {
-                            self.session.messages.push(Message {
-                                role: Role::Tool,
-                                content: Some(format!("Error: {}", err)),
-                                tool_calls: None,
-                                tool_call_id: Some(call.id),
-                                attachments: None,
-                            });
-                            continue;
-                        }
-                    };
-
-                    let action = self.resolve_tool_action(&call.name, &args).await;
-
-                    if matches!(action, crate::config::Action::Deny) {
-                        self.session.messages.push(Message {
-                            role: Role::Tool,
-                            content: Some(format!("Error: Permission denied for tool '{}'.", call.name)),
-                            tool_calls: None,
-                            tool_call_id: Some(call.id),
-                            attachments: None,
-                        });
-                        if let Some(pattern) = temp_external_rule {
-                            self.remove_external_directory_rule(&pattern).await;
-                        }
-                        continue;
-                    }
+                // Append Tool Output
+                self.session.messages.push(Message {
+                    role: Role::Tool,
+                    content: Some(result_content),
+                    tool_calls: None,
+                    tool_call_id: Some(call.id.clone()),
+                    attachments: None,
+                    synthetic: false,
+                });
+            }
 
-                    if matches!(action, crate::config::Action::Ask) && !Self::tool_confirms_internally(&call.name) {
-                        let input = Self::permission_input_for_tool(&call.name, &args);
-                        let suggested_pattern = Self::suggested_pattern_for_tool(&call.name, &input);
-                        let response = self
-                            .request_permission_confirmation("permission", &call.name, &input, suggested_pattern.clone())
-                            .await;
-
-                        match response {
-                            Ok(resp) if resp.allowed => {
-                                if resp.always {
-                                    let pattern = resp.pattern.unwrap_or(suggested_pattern);
-                                    self.add_permission_rule(&call.name, pattern, crate::config::Action::Allow).await;
-                                }
-                            }
-                            _ => {
-                                self.session.messages.push(Message {
-                                    role: Role::Tool,
-                                    content: Some(format!("Error: Permission denied for tool '{}'.", call.name)),
-                                    tool_calls: None,
-                                    tool_call_id: Some(call.id),
-                                    attachments: None,
-                                });
-                                if let Some(pattern) = temp_external_rule {
-                                    self.remove_external_directory_rule(&pattern).await;
-                                }
-                                continue;
-                            }
-                        }
-                    }
+            // Tool messages cannot carry images for most providers, so
+            // images from the results follow as user content, marked so it
+            // is not taken for a new turn
+            if !images.is_empty() {
+                let names: Vec<&str> = images.iter().map(|a| a.name.as_str()).collect();
+                self.session.messages.push(Message {
+                    role: Role::User,
+                    content: Some(format!("Image content returned by the tools above: {}", names.join(", "))),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    attachments: Some(images),
+                    synthetic: true,
+                });
+            }
 
-                    let tool = self.tools.iter().find(|t| t.name() == call.name);
-                    let result_content = if let Some(tool) = tool {
-                        match tool.execute(args).await {
-                            Ok(val) => val.to_string(),
-                            Err(err) => format!("Error: {}", err),
-                        }
-                    } else {
-                        format!("Error: Tool '{}' not found.", call.name)
-                    };
+            if let Some(lsp) = self.lsp.as_ref().filter(|_| !edited.is_empty()) {
+                let root = &self.session.workspace_path;
+                let paths: Vec<PathBuf> = edited.iter().map(|path| root.join(path)).collect();
+                lsp.did_change(root, &paths).await;
+            }
 
-                    if let Some(pattern) = temp_external_rule {
-                        self.remove_external_directory_rule(&pattern).await;
-                    }
+            // Checks the model already ran itself need not run again
+            let verified = tool_calls.iter().any(|c| c.name == VERIFY_TOOL);
+            if !edited.is_empty() && !verified {
+                self.verify_edits(&edited, &mut verify_failures).await;
+            }
 
-                    // Append Tool Output
-                    self.session.messages.push(Message {
-                        role: Role::Tool,
-                        content: Some(result_content),
-                        tool_calls: None,
-                        tool_call_id: Some(call.id),
-                        attachments: None,
-                    });
-                }
-                // Loop continues to feed tool outputs back to model
-            } else {
-                // No tools called, return response
-                return Ok(res.content);
+            if self.apply_steering() {
+                loops.reset();
             }
+            // Loop continues to feed tool outputs back to model
         }
     }
 
-    pub async fn step_stream(&mut self, user_input: Option<String>, attachments: Option<Vec<Attachment>>, tx: Sender<String>) -> Result<String, String> {
-        // 1. Add User Message
-        if let Some(input) = user_input {
-            self.session.messages.push(Message {
-                role: Role::User,
-                content: Some(input),
-                tool_calls: None,
-                tool_call_id: None,
-                attachments,
-            });
+    /// Run the configured checks after a batch of edits and record the
+    /// result as a `verify` call, so the model sees it like any tool output.
+    /// After `max_attempts` failed rounds in a turn the checks pause.
+    async fn verify_edits(&mut self, edited: &[String], failures: &mut u32) {
+        let Some(verifier) = self.verifier.as_ref() else {
+            return;
+        };
+        let max_attempts = verifier.max_attempts();
+        if *failures >= max_attempts {
+            return;
         }
 
-        // 2. Build Context
-        let context_summary = crate::domain::context::ContextBuilder::build(&self.session.workspace_path);
-        
-        // Handle Modes (Plan, Build, Research)
-        let mode_instruction = match self.session.mode {
-            crate::domain::models::AgentMode::Plan => 
-                "You are in PLAN mode. Provide a detailed, step-by-step plan for the user's request. DO NOT execute any tools. Just describe what you would do.",
-            crate::domain::models::AgentMode::Research => 
-                "You are in RESEARCH mode. Prefer read-only tools like read_file, list, glob, search, grep, lsp, symbols, and webfetch. If you need a restricted tool (write, edit, patch, bash, git, task, todowrite, skill), you must ask the user for approval before proceeding.",
-            crate::domain::models::AgentMode::Build => 
-                "You are in BUILD mode. You are an autonomous coding agent. Execute tools to fulfill the request.",
-        };
+        let report = verifier.run(Some(edited)).await;
+        if report.is_empty() {
+            return;
+        }
 
-        // Build skills information for system prompt
-        let skills_info = self.build_skills_info().await;
-        
-        // Find existing system message or prepend one
-        let system_msg_idx = self.session.messages.iter().position(|m| m.role == Role::System);
-        let system_content = format!(
-            "You are Anvil, an advanced AI coding agent.
+        let mut content = report.render();
+        if !report.passed() {
+            *failures += 1;
+            if *failures >= max_attempts {
+                content.push_str(&format!(
+                    "\n\nAutomatic verification is paused for the rest of this turn after {} failed attempts. Call {} to check again.",
+                    max_attempts, VERIFY_TOOL
+                ));
+            }
+        }
 
-{}
+        let call = ToolCall {
+            id: format!("verify-{}", uuid::Uuid::new_v4()),
+            name: VERIFY_TOOL.to_string(),
+            arguments: json!({ "files": edited }).to_string(),
+            signature: None,
+        };
+        self.emit_tool_call(&call);
+        self.emit_tool_result(&call.id, &call.name, &content);
+
+        self.session.messages.push(Message {
+            role: Role::Assistant,
+            content: None,
+            tool_calls: Some(vec![call.clone()]),
+            tool_call_id: None,
+            attachments: None,
+            synthetic: false,
+        });
+        self.session.messages.push(Message {
+            role: Role::Tool,
+            content: Some(content),
+            tool_calls: None,
+            tool_call_id: Some(call.id),
+            attachments: None,
+            synthetic: false,
+        });
+    }
 
-{}
+    /// Add messages the user sent during the turn as user input.
+    /// Returns whether there were any.
+    fn apply_steering(&mut self) -> bool {
+        let messages = self.steering.take();
+        self.deliver_steering(messages)
+    }
 
-{}
+    fn deliver_steering(&mut self, messages: Vec<SteeringMessage>) -> bool {
+        if messages.is_empty() {
+            return false;
+        }
 
-IMPORTANT RULES:
-1. When asked about available tools, ONLY list them. DO NOT execute them.
-2. Only execute tools when explicitly requested or when necessary to solve a user task.
-3. Never edit or write files unless you are sure the user wants you to modify the codebase.
-4. CRITICAL - DO NOT use tools for:
-   - Greetings (hello, hi, hey)
-   - Small talk or casual conversation
-   - Simple questions that don't require file access
-   - Questions about your capabilities (unless user asks for tool list)
-   - Any message where the user is just chatting
-5. Only use tools when:
-   - User explicitly asks to read/edit/write a file
-   - User asks you to analyze code
-   - User asks you to run a command
-   - You need to gather context to solve a coding problem
-6. If uncertain whether to use tools, respond conversationally without tools.
+        let interrupted = messages.iter().any(|m| m.interrupt);
+        let contents: Vec<String> = messages.into_iter().map(|m| m.content).collect();
+        for content in &contents {
+            self.push_user_message(content.clone(), None);
+        }
 
-Previous instructions remain active.",
-            mode_instruction,
-            context_summary,
-            skills_info
+        self.interaction.notify(
+            "agent-steering",
+            json!({
+                "session_id": self.session.id.to_string(),
+                "messages": contents,
+                "interrupted": interrupted,
+            }),
         );
+        true
+    }
 
-        if let Some(idx) = system_msg_idx {
-             self.session.messages[idx].content = Some(system_content);
-        } else {
-             self.session.messages.insert(0, Message {
-                 role: Role::System,
-                 content: Some(system_content),
-                 tool_calls: None,
-                 tool_call_id: None,
-                 attachments: None,
-             });
+    /// Tell the frontend about messages the turn ended without reading, so
+    /// they can be sent again as prompts
+    fn return_steering(&self, messages: Vec<SteeringMessage>) {
+        let contents: Vec<String> = messages.into_iter().map(|m| m.content).collect();
+        self.interaction.notify(
+            "agent-steering-undelivered",
+            json!({
+                "session_id": self.session.id.to_string(),
+                "messages": contents,
+            }),
+        );
+    }
+
+    /// Tool schemas offered to the model in the current mode.
+    /// Wrapped in OpenAI format: { type: "function", function: { ... } }
+    fn tool_schemas(&self) -> Vec<Value> {
+        let planning = self.session.mode == AgentMode::Plan;
+        let mut schemas: Vec<Value> = self
+            .tools
+            .iter()
+            .filter(|t| !planning || Self::is_research_allowed_tool(t.name()))
+            .map(|t| t.schema())
+            .collect();
+
+        if planning {
+            schemas.push(crate::domain::plan::submit_plan_schema());
+        } else if self.plan_executing() {
+            schemas.push(crate::domain::plan::update_plan_step_schema());
+        }
+        if !planning && self.verifier.is_some() {
+            schemas.push(crate::domain::verify::verify_schema());
         }
 
-        // If Plan Mode, skip the tool loop entirely and just chat
-        if self.session.mode == crate::domain::models::AgentMode::Plan {
-             let req = ChatRequest {
-                messages: self.session.messages.clone(),
-                model_id: self.session.model.clone(),
-                temperature: Some(0.7),
-                tools: None, // Disable tools for planning
-            };
-            let res = self.model.stream(req, tx).await;
-            
-            self.session.messages.push(Message {
-                role: res.role.clone(),
-                content: Some(res.content.clone()),
-                tool_calls: None,
-                tool_call_id: None,
-                attachments: None,
-            });
-            
-            return Ok(res.content);
+        schemas
+            .into_iter()
+            .map(|schema| json!({ "type": "function", "function": schema }))
+            .collect()
+    }
+
+    /// Run a single tool call through mode gating, doom-loop detection and
+    /// permissions. Returns the content of the tool message.
+    async fn execute_tool_call(&mut self, call: &ToolCall, loops: &mut LoopDetector) -> String {
+        let args: Value = serde_json::from_str(&call.arguments).unwrap_or(json!({}));
+
+        if let Some(result) = self.handle_plan_tool(&call.name, &args) {
+            return result;
         }
 
-        // 3. Chat Loop (Re-act)
-        // Safety limit to prevent infinite loops
-        let mut steps = 0;
-        const MAX_STEPS: u32 = 10;
-        let mut last_signature: Option<String> = None;
-        let mut repeat_count: u32 = 0;
+        if self.session.mode == AgentMode::Plan && !Self::is_research_allowed_call(&call.name, &args) {
+            return format!(
+                "Error: Tool '{}' is not available in PLAN mode. Investigate with read-only tools, then call {} with your plan.",
+                call.name,
+                crate::domain::plan::SUBMIT_PLAN_TOOL
+            );
+        }
 
-        loop {
-            if steps >= MAX_STEPS {
-                return Ok("⚠️ Agent exceeded maximum steps without final response.".to_string());
+        if self.session.mode == AgentMode::Research && !Self::is_research_allowed_call(&call.name, &args) {
+            let input = Self::permission_input_for_tool(&call.name, &args);
+            let suggested_pattern = Self::suggested_pattern_for_tool(&call.name, &input);
+            let action = self.research_override_action(&call.name, &input);
+
+            if !matches!(action, crate::config::Action::Allow) {
+                let response = self
+                    .request_permission_confirmation("mode", &call.name, &input, suggested_pattern.clone())
+                    .await;
+                match response {
+                    Ok(resp) if resp.allowed => {
+                        if resp.always {
+                            let pattern = resp.pattern.unwrap_or(suggested_pattern);
+                            self.add_research_override_rule(&call.name, pattern);
+                        }
+                    }
+                    _ => return format!("Error: Tool '{}' blocked in RESEARCH mode.", call.name),
+                }
             }
-            steps += 1;
-            println!("[DEBUG] Agent loop step {}", steps);
+        }
 
-            // Prepare Request
-            let tool_schemas: Vec<Value> = self.tools.iter().map(|t| {
-                json!({
-                    "type": "function",
-                    "function": t.schema()
-                })
-            }).collect();
+        if call.name == VERIFY_TOOL {
+            if let Some(verifier) = &self.verifier {
+                return verifier.run(None).await.render();
+            }
+        }
 
-            let req = ChatRequest {
-                messages: self.session.messages.clone(),
-                model_id: self.session.model.clone(),
-                temperature: Some(0.0), // Deterministic for tools
-                tools: Some(tool_schemas),
+        let signature = format!("{}:{}", call.name, call.arguments);
+        if let Some(kind) = loops.check(&call.name, &signature) {
+            let action = {
+                let config = self.permission_manager.lock().await;
+                config.doom_loop.evaluate(&signature)
             };
 
-            // Call Model via Stream
-            println!("[DEBUG] Calling model with {} messages", self.session.messages.len());
-            let res = self.model.stream(req, tx.clone()).await;
-            println!("[DEBUG] Model returned, has {} tool calls", res.tool_calls.as_ref().map(|t| t.len()).unwrap_or(0));
-
-            // Append Assistant Message
-            self.session.messages.push(Message {
-                role: res.role.clone(),
-                content: Some(res.content.clone()),
-                tool_calls: res.tool_calls.clone(),
-                tool_call_id: res.tool_call_id.clone(),
-                attachments: None,
-            });
-
-            // Check for Tool Calls
-            if let Some(ref tool_calls) = res.tool_calls {
-                println!("[DEBUG] Processing {} tool calls", tool_calls.len());
-                if tool_calls.is_empty() {
-                     println!("[DEBUG] No tool calls, returning response");
-                     return Ok(res.content); // Done
+            match action {
+                crate::config::Action::Deny => {
+                    return format!("Error: Tool call blocked (doom loop): {}", kind.reason());
                 }
-
-                // Execute Tools
-                for call in tool_calls {
-                    self.emit_tool_call(call);
-
-                    let args: Value = serde_json::from_str(&call.arguments).unwrap_or(json!({}));
-                    if self.session.mode == crate::domain::models::AgentMode::Research
-                        && !Self::is_research_allowed_tool(&call.name)
-                    {
-                        let input = Self::permission_input_for_tool(&call.name, &args);
-                        let suggested_pattern = Self::suggested_pattern_for_tool(&call.name, &input);
-                        let action = self.research_override_action(&call.name, &input);
-
-                        if !matches!(action, crate::config::Action::Allow) {
-                            let response = self
-                                .request_permission_confirmation("mode", &call.name, &input, suggested_pattern.clone())
-                                .await;
-                            match response {
-                                Ok(resp) if resp.allowed => {
-                                    if resp.always {
-                                        let pattern = resp.pattern.unwrap_or(suggested_pattern);
-                                        self.add_research_override_rule(&call.name, pattern);
-                                    }
-                                }
-                                _ => {
-                                    let err_msg = format!(
-                                        "Error: Tool '{}' blocked in RESEARCH mode.",
-                                        call.name
-                                    );
-                                    self.emit_tool_result(&call.id, &call.name, &err_msg);
-                                    self.session.messages.push(Message {
-                                        role: Role::Tool,
-                                        content: Some(err_msg),
-                                        tool_calls: None,
-                                        tool_call_id: Some(call.id.clone()),
-                                        attachments: None,
-                                    });
-                                    continue;
-                                }
+                crate::config::Action::Ask => {
+                    let suggested_pattern = format!("{}:*", call.name);
+                    let response = self
+                        .request_confirmation(
+                            "doom_loop",
+                            &call.name,
+                            &signature,
+                            suggested_pattern.clone(),
+                            Some(kind.reason()),
+                        )
+                        .await;
+
+                    match response {
+                        Ok(resp) if resp.allowed => {
+                            if resp.always {
+                                let pattern = resp.pattern.unwrap_or(suggested_pattern);
+                                self.add_permission_rule("doom_loop", pattern, crate::config::Action::Allow).await;
                             }
+                            // The user chose to go on; only a new loop should ask again
+                            loops.reset();
                         }
+                        _ => return format!("Error: Tool call blocked by user (doom loop): {}", kind.reason()),
                     }
-                    let signature = format!("{}:{}", call.name, call.arguments);
-                    if last_signature.as_ref() == Some(&signature) {
-                        repeat_count += 1;
-                    } else {
-                        last_signature = Some(signature.clone());
-                        repeat_count = 1;
-                    }
+                }
+                crate::config::Action::Allow => {}
+            }
+        }
 
-                    if repeat_count >= 3 {
-                        let action = {
-                            let config = self.permission_manager.lock().await;
-                            config.doom_loop.evaluate(&signature)
-                        };
+        let temp_external_rule = match self.ensure_external_directory_access(&call.name, &args).await {
+            Ok(rule) => rule,
+            Err(err) => return format!("Error: {}", err),
+        };
 
-                        match action {
-                            crate::config::Action::Deny => {
-                                let err_msg = format!("Error: Repeated tool call blocked (doom loop): {}", call.name);
-                                self.emit_tool_result(&call.id, &call.name, &err_msg);
-                                self.session.messages.push(Message {
-                                    role: Role::Tool,
-                                    content: Some(err_msg),
-                                    tool_calls: None,
-                                    tool_call_id: Some(call.id.clone()),
-                                    attachments: None,
-                                });
-                                continue;
-                            }
-                            crate::config::Action::Ask => {
-                                let suggested_pattern = format!("{}:*", call.name);
-                                let response = self
-                                    .request_permission_confirmation("doom_loop", &call.name, &signature, suggested_pattern.clone())
-                                    .await;
-
-                                match response {
-                                    Ok(resp) if resp.allowed => {
-                                        if resp.always {
-                                            let pattern = resp.pattern.unwrap_or(suggested_pattern);
-                                            self.add_permission_rule("doom_loop", pattern, crate::config::Action::Allow).await;
-                                        }
-                                    }
-                                    _ => {
-                                        let err_msg = format!("Error: Repeated tool call blocked by user: {}", call.name);
-                                        self.emit_tool_result(&call.id, &call.name, &err_msg);
-                                        self.session.messages.push(Message {
-                                            role: Role::Tool,
-                                            content: Some(err_msg),
-                                            tool_calls: None,
-                                            tool_call_id: Some(call.id.clone()),
-                                            attachments: None,
-                                        });
-                                        continue;
-                                    }
-                                }
-                            }
-                            crate::config::Action::Allow => {}
-                        }
-                    }
+        let result_content = self.execute_permitted_tool(call, args).await;
 
-                    let temp_external_rule = match self.ensure_external_directory_access(&call.name, &args).await {
-                        Ok(rule) => rule,
-                        Err(err) => {
-                            let err_msg = format!("Error: {}", err);
-                            self.emit_tool_result(&call.id, &call.name, &err_msg);
-                            self.session.messages.push(Message {
-                                role: Role::Tool,
-                                content: Some(err_msg),
-                                tool_calls: None,
-                                tool_call_id: Some(call.id.clone()),
-                                attachments: None,
-                            });
-                            continue;
-                        }
-                    };
-
-                    let action = self.resolve_tool_action(&call.name, &args).await;
-
-                    if matches!(action, crate::config::Action::Deny) {
-                        let err_msg = format!("Error: Permission denied for tool '{}'.", call.name);
-                        self.emit_tool_result(&call.id, &call.name, &err_msg);
-                        self.session.messages.push(Message {
-                            role: Role::Tool,
-                            content: Some(err_msg),
-                            tool_calls: None,
-                            tool_call_id: Some(call.id.clone()),
-                            attachments: None,
-                        });
-                        if let Some(pattern) = temp_external_rule {
-                            self.remove_external_directory_rule(&pattern).await;
-                        }
-                        continue;
-                    }
+        if let Some(pattern) = temp_external_rule {
+            self.remove_external_directory_rule(&pattern).await;
+        }
 
-                    if matches!(action, crate::config::Action::Ask) && !Self::tool_confirms_internally(&call.name) {
-                        let input = Self::permission_input_for_tool(&call.name, &args);
-                        let suggested_pattern = Self::suggested_pattern_for_tool(&call.name, &input);
-                        let response = self
-                            .request_permission_confirmation("permission", &call.name, &input, suggested_pattern.clone())
-                            .await;
-
-                        match response {
-                            Ok(resp) if resp.allowed => {
-                                if resp.always {
-                                    let pattern = resp.pattern.unwrap_or(suggested_pattern);
-                                    self.add_permission_rule(&call.name, pattern, crate::config::Action::Allow).await;
-                                }
-                            }
-                            _ => {
-                                let err_msg = format!("Error: Permission denied for tool '{}'.", call.name);
-                                self.emit_tool_result(&call.id, &call.name, &err_msg);
-                                self.session.messages.push(Message {
-                                    role: Role::Tool,
-                                    content: Some(err_msg),
-                                    tool_calls: None,
-                                    tool_call_id: Some(call.id.clone()),
-                                    attachments: None,
-                                });
-                                if let Some(pattern) = temp_external_rule {
-                                    self.remove_external_directory_rule(&pattern).await;
-                                }
-                                continue;
-                            }
-                        }
-                    }
+        loops.observe(&call.name, &signature, result_content.strip_prefix("Error: "));
+        result_content
+    }
 
-                    let tool = self.tools.iter().find(|t| t.name() == call.name);
-                    let result_content = if let Some(tool) = tool {
-                        match tool.execute(args).await {
-                            Ok(val) => val.to_string(),
-                            Err(err) => format!("Error: {}", err),
-                        }
-                    } else {
-                        format!("Error: Tool '{}' not found.", call.name)
-                    };
+    async fn execute_permitted_tool(&mut self, call: &ToolCall, args: Value) -> String {
+        let action = self.resolve_tool_action(&call.name, &args).await;
+
+        if matches!(action, crate::config::Action::Deny) {
+            return format!("Error: Permission denied for tool '{}'.", call.name);
+        }
 
-                    if let Some(pattern) = temp_external_rule {
-                        self.remove_external_directory_rule(&pattern).await;
+        if matches!(action, crate::config::Action::Ask) && !Self::tool_confirms_internally(&call.name) {
+            let input = Self::permission_input_for_tool(&call.name, &args);
+            let suggested_pattern = Self::suggested_pattern_for_tool(&call.name, &input);
+            let response = self
+                .request_permission_confirmation("permission", &call.name, &input, suggested_pattern.clone())
+                .await;
+
+            match response {
+                Ok(resp) if resp.allowed => {
+                    if resp.always {
+                        let pattern = resp.pattern.unwrap_or(suggested_pattern);
+                        self.add_permission_rule(&call.name, pattern, crate::config::Action::Allow).await;
                     }
+                }
+                _ => return format!("Error: Permission denied for tool '{}'.", call.name),
+            }
+        }
 
-                    self.emit_tool_result(&call.id, &call.name, &result_content);
+        let tool = self.tools.iter().find(|t| t.name() == call.name);
+        if let Some(tool) = tool {
+            match tool.execute(args).await {
+                Ok(val) => val.to_string(),
+                Err(err) => format!("Error: {}", err),
+            }
+        } else {
+            format!("Error: Tool '{}' not found.", call.name)
+        }
+    }
 
-                    // Append Tool Output
-                    self.session.messages.push(Message {
-                        role: Role::Tool,
-                        content: Some(result_content),
-                        tool_calls: None,
-                        tool_call_id: Some(call.id.clone()),
-                        attachments: None,
-                    });
-                    
-                    println!("[DEBUG] Tool '{}' executed, continuing loop", call.name);
+    fn plan_executing(&self) -> bool {
+        matches!(
+            self.session.plan.as_ref().map(|p| &p.status),
+            Some(crate::domain::plan::PlanStatus::Executing)
+        )
+    }
+
+    /// Handle the internal plan tools; `None` for every other tool
+    fn handle_plan_tool(&mut self, tool_name: &str, args: &Value) -> Option<String> {
+        use crate::domain::plan::{Plan, SUBMIT_PLAN_TOOL, UPDATE_PLAN_STEP_TOOL};
+
+        let result = match tool_name {
+            SUBMIT_PLAN_TOOL if self.session.mode == AgentMode::Plan => Plan::from_submission(args).map(|plan| {
+                let message = format!(
+                    "Plan \"{}\" recorded with {} step(s). The user can now review and execute it.",
+                    plan.title,
+                    plan.steps.len()
+                );
+                self.session.plan = Some(plan);
+                message
+            }),
+            UPDATE_PLAN_STEP_TOOL if self.plan_executing() => self
+                .session
+                .plan
+                .as_mut()
+                .expect("executing plan")
+                .update_step(args),
+            _ => return None,
+        };
+
+        Some(match result {
+            Ok(message) => {
+                self.emit_plan_updated();
+                message
+            }
+            Err(err) => format!("Error: {}", err),
+        })
+    }
+
+    fn emit_plan_updated(&self) {
+        self.interaction.notify(
+            "plan-updated",
+            json!({
+                "session_id": self.session.id.to_string(),
+                "plan": self.session.plan,
+            }),
+        );
+    }
+
+    /// Switch to BUILD and carry out the session plan one step at a time.
+    /// Stops at the first failed step; returns a progress report.
+    pub async fn execute_plan(&mut self, tx: Option<Sender<String>>) -> Result<String, String> {
+        use crate::domain::plan::{PlanStatus, StepStatus};
+
+        {
+            let plan = self.session.plan.as_mut().ok_or("This session has no plan to execute")?;
+            if plan.next_pending().is_none() {
+                return Err("Every step of the plan is already finished".to_string());
+            }
+            plan.status = PlanStatus::Executing;
+        }
+        self.session.mode = AgentMode::Build;
+        self.emit_plan_updated();
+
+        while let Some(index) = self.session.plan.as_ref().and_then(|p| p.next_pending()) {
+            let prompt = {
+                let plan = self.session.plan.as_mut().expect("plan");
+                plan.steps[index].status = StepStatus::InProgress;
+                plan.step_prompt(index)
+            };
+            self.emit_plan_updated();
+
+            let result = self.run_turn(Some(prompt), None, tx.clone()).await;
+
+            let plan = self.session.plan.as_mut().expect("plan");
+            let step = &mut plan.steps[index];
+            match result {
+                Err(err) => {
+                    step.status = StepStatus::Failed;
+                    step.notes = Some(err);
                 }
-                // Loop continues to feed tool outputs back to model
-            } else {
-                // No tools called, return response
-                return Ok(res.content);
+                Ok(response) if step.status == StepStatus::InProgress => {
+                    // The agent finished without reporting; treat as done but keep its answer
+                    step.status = StepStatus::Done;
+                    if step.notes.is_none() {
+                        let summary: String = response.chars().take(300).collect();
+                        step.notes = Some(format!("No progress report. Final response: {}", summary));
+                    }
+                }
+                Ok(_) => {}
             }
 
+            if step.status == StepStatus::Failed {
+                plan.status = PlanStatus::Failed;
+                let report = plan.report();
+                self.emit_plan_updated();
+                return Ok(report);
+            }
+            self.emit_plan_updated();
         }
+
+        let plan = self.session.plan.as_mut().expect("plan");
+        plan.status = PlanStatus::Completed;
+        let report = plan.report();
+        self.emit_plan_updated();
+        Ok(report)
     }
 
     fn tool_confirms_internally(tool_name: &str) -> bool {
-        matches!(tool_name, "bash" | "write_file" | "edit_file" | "read_file" | "lsp" | "skill")
+        matches!(tool_name, "bash" | "write_file" | "edit_file" | "multi_edit" | "read_file" | "lsp" | "skill")
     }
 
     fn normalize_path(path: &Path) -> PathBuf {
@@ -871,6 +938,22 @@ Previous instructions remain active.",
         }
     }
 
+    /// Remove the `attachments` a tool put in its result, such as an image
+    /// from read_file, so they are not sent to the model as text
+    fn take_attachments(content: &mut String) -> Vec<Attachment> {
+        if !content.starts_with('{') || !content.contains("\"attachments\"") {
+            return Vec::new();
+        }
+        let Ok(mut value) = serde_json::from_str::<Value>(content) else {
+            return Vec::new();
+        };
+        let Some(raw) = value.as_object_mut().and_then(|o| o.remove("attachments")) else {
+            return Vec::new();
+        };
+        *content = value.to_string();
+        serde_json::from_value(raw).unwrap_or_default()
+    }
+
     fn resolve_path_input(tool_name: &str, args: &Value, workspace_root: &PathBuf) -> Option<PathBuf> {
         let path_str = match tool_name {
             "read_file" | "write_file" | "edit_file" => args.get("path").and_then(|v| v.as_str()),
@@ -907,41 +990,32 @@ Previous instructions remain active.",
         input: &str,
         suggested_pattern: String,
     ) -> Result<crate::domain::models::ConfirmationResponse, String> {
-        let Some(app) = self.app.as_ref() else {
-            return Err("Confirmation unavailable for this action.".to_string());
-        };
-        let Some(pending) = self.pending_confirmations.as_ref() else {
-            return Err("Confirmation unavailable for this action.".to_string());
-        };
-
-        let request_id = Uuid::new_v4().to_string();
-        let (tx, rx) = oneshot::channel();
-
-        {
-            let mut map = pending.lock().map_err(|_| "Failed to lock confirmation map".to_string())?;
-            map.insert(request_id.clone(), tx);
-        }
+        self.request_confirmation(request_type, tool_name, input, suggested_pattern, None)
+            .await
+    }
 
-        let event = PermissionConfirmationRequest {
-            id: request_id.clone(),
-            session_id: self.session.id.to_string(),
-            type_: request_type.to_string(),
-            tool_name: tool_name.to_string(),
-            input: input.to_string(),
+    /// Ask the user about a tool call; `reason` explains why they are asked
+    async fn request_confirmation(
+        &self,
+        request_type: &str,
+        tool_name: &str,
+        input: &str,
+        suggested_pattern: String,
+        reason: Option<String>,
+    ) -> Result<crate::domain::models::ConfirmationResponse, String> {
+        let mut request = ConfirmationRequest::permission(
+            &self.session.id.to_string(),
+            request_type,
+            tool_name,
+            input,
             suggested_pattern,
-        };
-
-        app.emit("request-confirmation", &event)
-            .map_err(|e| format!("Failed to emit confirmation event: {}", e))?;
+        );
+        request.reason = reason;
 
-        rx.await.map_err(|_| "Confirmation channel closed without response".to_string())
+        self.interaction.confirm(request).await
     }
 
     fn emit_tool_call(&self, call: &crate::domain::models::ToolCall) {
-        let Some(app) = self.app.as_ref() else {
-            return;
-        };
-
         let event = ToolCallEvent {
             session_id: self.session.id.to_string(),
             tool_call_id: call.id.clone(),
@@ -949,14 +1023,12 @@ Previous instructions remain active.",
             arguments: call.arguments.clone(),
         };
 
-        let _ = app.emit("agent-tool-call", &event);
+        if let Ok(payload) = serde_json::to_value(&event) {
+            self.interaction.notify("agent-tool-call", payload);
+        }
     }
 
     fn emit_tool_result(&self, call_id: &str, tool_name: &str, content: &str) {
-        let Some(app) = self.app.as_ref() else {
-            return;
-        };
-
         let event = ToolResultEvent {
             session_id: self.session.id.to_string(),
             tool_call_id: call_id.to_string(),
@@ -964,7 +1036,9 @@ Previous instructions remain active.",
             content: content.to_string(),
         };
 
-        let _ = app.emit("agent-tool-result", &event);
+        if let Ok(payload) = serde_json::to_value(&event) {
+            self.interaction.notify("agent-tool-result", payload);
+        }
     }
 
     async fn set_external_directory_rule(&mut self, pattern: String, action: crate::config::Action) {
@@ -1118,3 +1192,10 @@ Previous instructions remain active.",
         }
     }
 }
+
+impl Drop for Agent {
+    fn drop(&mut self) {
+        // Nobody is left to act on answers to this session's open prompts
+        self.interaction.cancel_session(&self.session.id.to_string());
+    }
+}
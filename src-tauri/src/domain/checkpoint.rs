@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Per-session store of file contents as they were before each turn modified them.
+///
+/// Layout: `.anvil/checkpoints/<session_id>/index.json` plus content-addressed
+/// blobs in `blobs/`. Works without git; only files changed through the
+/// write/edit/patch tools are tracked.
+pub struct CheckpointStore {
+    dir: PathBuf,
+    workspace_root: PathBuf,
+    index: Mutex<CheckpointIndex>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CheckpointIndex {
+    checkpoints: Vec<Checkpoint>,
+}
+
+/// File changes made during one turn (one user message)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Number of user messages before the one that started this turn
+    pub turn: usize,
+    pub created_at: String,
+    pub files: Vec<FileChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// Blob hash of the content before the turn; `None` if the file did not exist
+    pub before: Option<String>,
+    /// Hash of the content the tools left behind; `None` if the file is missing
+    pub after: Option<String>,
+    /// Whether a write was recorded after the snapshot
+    pub written: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckpointSummary {
+    pub turn: usize,
+    pub created_at: String,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RestoreReport {
+    /// False when conflicts were found and nothing was changed
+    pub applied: bool,
+    pub restored: Vec<String>,
+    pub removed: Vec<String>,
+    /// Files modified outside the agent since it last wrote them
+    pub conflicts: Vec<String>,
+}
+
+fn hash_bytes(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hash_file(path: &Path) -> Result<Option<String>, String> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(hash_bytes(&bytes))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+impl CheckpointStore {
+    pub fn open(workspace_root: &Path, session_id: &str) -> Self {
+        let dir = workspace_root.join(".anvil").join("checkpoints").join(session_id);
+        let index = fs::read_to_string(dir.join("index.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            dir,
+            workspace_root: workspace_root.to_path_buf(),
+            index: Mutex::new(index),
+        }
+    }
+
+    /// Start a new checkpoint for the user message that opens `turn`
+    pub fn begin_turn(&self, turn: usize) {
+        let Ok(mut index) = self.index.lock() else {
+            return;
+        };
+        index.checkpoints.push(Checkpoint {
+            turn,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            files: Vec::new(),
+        });
+        // Empty checkpoints are not persisted until a file is snapshotted
+    }
+
+    /// Record the current content of `path` before a tool modifies it.
+    /// Only the first snapshot of a file within a turn is kept.
+    pub fn snapshot(&self, path: &Path) -> Result<(), String> {
+        let mut index = self.index.lock().map_err(|_| "Failed to lock checkpoint index".to_string())?;
+        if index.checkpoints.is_empty() {
+            index.checkpoints.push(Checkpoint {
+                turn: 0,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                files: Vec::new(),
+            });
+        }
+
+        let checkpoint = index.checkpoints.last_mut().expect("checkpoint exists");
+        if checkpoint.files.iter().any(|f| f.path == path) {
+            return Ok(());
+        }
+
+        let before = match fs::read(path) {
+            Ok(bytes) => Some(self.store_blob(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("Failed to snapshot {}: {}", path.display(), e)),
+        };
+
+        checkpoint.files.push(FileChange {
+            path: path.to_path_buf(),
+            after: before.clone(),
+            before,
+            written: false,
+        });
+
+        self.save_index(&index)
+    }
+
+    /// Record what a tool left on disk so later user edits can be detected
+    pub fn record_write(&self, path: &Path) -> Result<(), String> {
+        let after = hash_file(path)?;
+        let mut index = self.index.lock().map_err(|_| "Failed to lock checkpoint index".to_string())?;
+
+        let Some(change) = index
+            .checkpoints
+            .last_mut()
+            .and_then(|c| c.files.iter_mut().find(|f| f.path == path))
+        else {
+            return Ok(());
+        };
+        change.after = after;
+        change.written = true;
+
+        self.save_index(&index)
+    }
+
+    pub fn list(&self) -> Vec<CheckpointSummary> {
+        let Ok(index) = self.index.lock() else {
+            return Vec::new();
+        };
+        index
+            .checkpoints
+            .iter()
+            .filter(|c| !c.files.is_empty())
+            .map(|c| CheckpointSummary {
+                turn: c.turn,
+                created_at: c.created_at.clone(),
+                files: c.files.iter().map(|f| self.display_path(&f.path)).collect(),
+            })
+            .collect()
+    }
+
+    /// Revert every tracked file to its state before `turn`. With conflicts and
+    /// no `force`, nothing is changed and the conflicting files are reported.
+    pub fn restore(&self, turn: usize, force: bool) -> Result<RestoreReport, String> {
+        let mut index = self.index.lock().map_err(|_| "Failed to lock checkpoint index".to_string())?;
+        let Some(start) = index.checkpoints.iter().position(|c| c.turn >= turn) else {
+            return Ok(RestoreReport {
+                applied: true,
+                ..Default::default()
+            });
+        };
+
+        // Earliest "before" wins as the restore target; the latest write is what we expect on disk
+        let mut order: Vec<PathBuf> = Vec::new();
+        let mut targets: HashMap<PathBuf, Option<String>> = HashMap::new();
+        let mut expected: HashMap<PathBuf, Option<String>> = HashMap::new();
+        for checkpoint in &index.checkpoints[start..] {
+            for change in &checkpoint.files {
+                if !targets.contains_key(&change.path) {
+                    order.push(change.path.clone());
+                    targets.insert(change.path.clone(), change.before.clone());
+                }
+                let on_disk = if change.written { change.after.clone() } else { change.before.clone() };
+                expected.insert(change.path.clone(), on_disk);
+            }
+        }
+
+        let mut report = RestoreReport::default();
+        for path in &order {
+            if hash_file(path)? != expected[path] {
+                report.conflicts.push(self.display_path(path));
+            }
+        }
+
+        if !report.conflicts.is_empty() && !force {
+            return Ok(report);
+        }
+
+        for path in &order {
+            match &targets[path] {
+                Some(hash) => {
+                    let bytes = fs::read(self.dir.join("blobs").join(hash))
+                        .map_err(|e| format!("Missing checkpoint data for {}: {}", path.display(), e))?;
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                    }
+                    fs::write(path, bytes).map_err(|e| format!("Failed to restore {}: {}", path.display(), e))?;
+                    report.restored.push(self.display_path(path));
+                }
+                None => {
+                    if path.exists() {
+                        fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+                    }
+                    report.removed.push(self.display_path(path));
+                }
+            }
+        }
+
+        index.checkpoints.truncate(start);
+        self.save_index(&index)?;
+        self.collect_garbage(&index);
+
+        report.applied = true;
+        Ok(report)
+    }
+
+    fn display_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.workspace_root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn store_blob(&self, bytes: &[u8]) -> Result<String, String> {
+        let hash = hash_bytes(bytes);
+        let blobs = self.dir.join("blobs");
+        let blob_path = blobs.join(&hash);
+        if !blob_path.exists() {
+            fs::create_dir_all(&blobs).map_err(|e| format!("Failed to create checkpoint dir: {}", e))?;
+            fs::write(&blob_path, bytes).map_err(|e| format!("Failed to write checkpoint: {}", e))?;
+        }
+        Ok(hash)
+    }
+
+    fn save_index(&self, index: &CheckpointIndex) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create checkpoint dir: {}", e))?;
+
+        // Keep checkpoints out of the user's git status
+        let ignore = self.dir.parent().map(|p| p.join(".gitignore"));
+        if let Some(ignore) = ignore.filter(|p| !p.exists()) {
+            let _ = fs::write(ignore, "*\n");
+        }
+
+        let stored = CheckpointIndex {
+            checkpoints: index.checkpoints.iter().filter(|c| !c.files.is_empty()).cloned().collect(),
+        };
+        let json = serde_json::to_string_pretty(&stored).map_err(|e| e.to_string())?;
+        fs::write(self.dir.join("index.json"), json).map_err(|e| format!("Failed to write checkpoint index: {}", e))
+    }
+
+    fn collect_garbage(&self, index: &CheckpointIndex) {
+        let live: HashSet<&String> = index
+            .checkpoints
+            .iter()
+            .flat_map(|c| c.files.iter().filter_map(|f| f.before.as_ref()))
+            .collect();
+
+        let Ok(entries) = fs::read_dir(self.dir.join("blobs")) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !live.contains(&name) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, content: &str) {
+        fs::write(path, content).unwrap();
+    }
+
+    /// Simulate a tool write: snapshot, write, record
+    fn tool_write(store: &CheckpointStore, path: &Path, content: &str) {
+        store.snapshot(path).unwrap();
+        write(path, content);
+        store.record_write(path).unwrap();
+    }
+
+    #[test]
+    fn test_restore_reverts_edits_and_creations() {
+        let temp = TempDir::new().unwrap();
+        let ws = temp.path();
+        let existing = ws.join("a.txt");
+        let created = ws.join("new.txt");
+        write(&existing, "one");
+
+        let store = CheckpointStore::open(ws, "s1");
+        store.begin_turn(0);
+        tool_write(&store, &existing, "two");
+        store.begin_turn(1);
+        tool_write(&store, &existing, "three");
+        tool_write(&store, &created, "fresh");
+
+        let report = store.restore(1, false).unwrap();
+        assert!(report.applied);
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "two");
+        assert!(!created.exists());
+
+        let report = store.restore(0, false).unwrap();
+        assert!(report.applied);
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "one");
+        assert!(store.list().is_empty());
+    }
+
+    #[test]
+    fn test_restore_detects_conflicts() {
+        let temp = TempDir::new().unwrap();
+        let ws = temp.path();
+        let file = ws.join("a.txt");
+        write(&file, "original");
+
+        let store = CheckpointStore::open(ws, "s1");
+        store.begin_turn(0);
+        tool_write(&store, &file, "agent");
+        write(&file, "user edit");
+
+        let report = store.restore(0, false).unwrap();
+        assert!(!report.applied);
+        assert_eq!(report.conflicts, vec!["a.txt".to_string()]);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "user edit");
+
+        let report = store.restore(0, true).unwrap();
+        assert!(report.applied);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "original");
+    }
+
+    #[test]
+    fn test_index_persists_across_instances() {
+        let temp = TempDir::new().unwrap();
+        let ws = temp.path();
+        let file = ws.join("a.txt");
+        write(&file, "v1");
+
+        {
+            let store = CheckpointStore::open(ws, "s1");
+            store.begin_turn(3);
+            tool_write(&store, &file, "v2");
+        }
+
+        let store = CheckpointStore::open(ws, "s1");
+        assert_eq!(store.list().len(), 1);
+        assert_eq!(store.list()[0].turn, 3);
+        store.restore(3, false).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "v1");
+    }
+}
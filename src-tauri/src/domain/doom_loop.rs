@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+
+/// How many recent tool calls the detector looks at
+const WINDOW: usize = 12;
+/// Identical calls in a row before the agent is considered stuck
+const REPEAT_LIMIT: usize = 3;
+/// Full repetitions of a multi-call cycle (A B A B A B) before flagging it
+const CYCLE_REPETITIONS: usize = 3;
+/// Longest cycle that is recognized
+const MAX_CYCLE_PERIOD: usize = 4;
+/// Consecutive failures of one tool with the same error
+const ERROR_LIMIT: usize = 3;
+/// Consecutive calls that neither changed the workspace nor learned anything new
+const NO_PROGRESS_LIMIT: usize = 8;
+
+/// Tools that modify the workspace when they succeed
+const MUTATING_TOOLS: &[&str] = &["write_file", "edit_file", "multi_edit", "patch", "bash", "git"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoopKind {
+    /// The same call over and over
+    Repeat { tool: String, count: usize },
+    /// A sequence of calls that keeps coming back, e.g. read A / edit A
+    Cycle { tools: Vec<String>, repetitions: usize },
+    /// One tool failing with the same error despite changed arguments
+    RepeatedError { tool: String, count: usize, error: String },
+    /// Many steps without workspace change or new information
+    NoProgress { steps: usize },
+}
+
+impl LoopKind {
+    /// Human-readable explanation shown in the confirmation prompt
+    pub fn reason(&self) -> String {
+        match self {
+            LoopKind::Repeat { tool, count } => {
+                format!("'{}' is about to run with the same arguments for the {}th time in a row.", tool, count)
+            }
+            LoopKind::Cycle { tools, repetitions } => format!(
+                "The agent is cycling through {} ({} times) without breaking out.",
+                tools.join(" → "),
+                repetitions
+            ),
+            LoopKind::RepeatedError { tool, count, error } => format!(
+                "'{}' failed {} times in a row with the same error: {}",
+                tool, count, error
+            ),
+            LoopKind::NoProgress { steps } => format!(
+                "The last {} tool calls neither changed the workspace nor produced new information.",
+                steps
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Observation {
+    tool: String,
+    signature: String,
+    /// Normalized error text when the call failed
+    error: Option<String>,
+    progress: bool,
+}
+
+/// Sliding-window detector for agents that are stuck.
+///
+/// `check` runs before a call with its signature (`name:arguments`);
+/// `observe` records the outcome afterwards.
+#[derive(Debug, Default)]
+pub struct LoopDetector {
+    history: VecDeque<Observation>,
+    /// Every signature seen this turn; repeats of old calls add no information
+    seen: std::collections::HashSet<String>,
+}
+
+impl LoopDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decide whether running `signature` next would continue a loop
+    pub fn check(&self, tool: &str, signature: &str) -> Option<LoopKind> {
+        let mut signatures: Vec<&str> = self.history.iter().map(|o| o.signature.as_str()).collect();
+        signatures.push(signature);
+
+        let repeats = signatures.iter().rev().take_while(|s| **s == signature).count();
+        if repeats >= REPEAT_LIMIT {
+            return Some(LoopKind::Repeat {
+                tool: tool.to_string(),
+                count: repeats,
+            });
+        }
+
+        if let Some(kind) = self.detect_cycle(&signatures) {
+            return Some(kind);
+        }
+
+        if let Some(kind) = self.detect_repeated_error(tool) {
+            return Some(kind);
+        }
+
+        let stalled = self.history.iter().rev().take_while(|o| !o.progress).count();
+        if stalled >= NO_PROGRESS_LIMIT {
+            return Some(LoopKind::NoProgress { steps: stalled });
+        }
+
+        None
+    }
+
+    /// Record the outcome of a call. `error` is the failure message, if any.
+    pub fn observe(&mut self, tool: &str, signature: &str, error: Option<&str>) {
+        let novel = self.seen.insert(signature.to_string());
+        let progress = error.is_none() && (novel || mutates(tool, signature));
+
+        self.history.push_back(Observation {
+            tool: tool.to_string(),
+            signature: signature.to_string(),
+            error: error.map(normalize_error),
+            progress,
+        });
+        while self.history.len() > WINDOW {
+            self.history.pop_front();
+        }
+    }
+
+    /// Forget the current streak, e.g. after the user chose to continue
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    fn detect_cycle(&self, signatures: &[&str]) -> Option<LoopKind> {
+        for period in 2..=MAX_CYCLE_PERIOD {
+            let needed = period * CYCLE_REPETITIONS;
+            if signatures.len() < needed {
+                break;
+            }
+            let tail = &signatures[signatures.len() - needed..];
+            let cycle = &tail[..period];
+            // A cycle of one repeated call is a plain repeat, not an oscillation
+            if cycle.iter().all(|s| *s == cycle[0]) {
+                continue;
+            }
+            if tail.chunks(period).all(|chunk| chunk == cycle) {
+                let tools = cycle
+                    .iter()
+                    .map(|s| s.split(':').next().unwrap_or(s).to_string())
+                    .collect();
+                return Some(LoopKind::Cycle {
+                    tools,
+                    repetitions: CYCLE_REPETITIONS,
+                });
+            }
+        }
+        None
+    }
+
+    fn detect_repeated_error(&self, tool: &str) -> Option<LoopKind> {
+        let recent: Vec<&Observation> = self.history.iter().rev().take(ERROR_LIMIT).collect();
+        if recent.len() < ERROR_LIMIT {
+            return None;
+        }
+
+        let error = recent[0].error.as_ref()?;
+        let same = recent
+            .iter()
+            .all(|o| o.tool == tool && o.error.as_ref() == Some(error));
+        same.then(|| LoopKind::RepeatedError {
+            tool: tool.to_string(),
+            count: ERROR_LIMIT,
+            error: error.clone(),
+        })
+    }
+}
+
+/// Make errors comparable across trivially different attempts: numbers
+/// (line numbers, pids, durations) are masked and whitespace collapsed.
+fn normalize_error(error: &str) -> String {
+    let mut normalized = String::new();
+    let mut in_number = false;
+    for c in error.chars() {
+        if c.is_ascii_digit() {
+            if !in_number {
+                normalized.push('#');
+            }
+            in_number = true;
+        } else {
+            in_number = false;
+            normalized.push(c);
+        }
+    }
+    let collapsed = normalized.split_whitespace().collect::<Vec<_>>().join(" ");
+    collapsed.chars().take(200).collect()
+}
+
+/// Whether a successful call changes the workspace. `lsp` only does for
+/// the requests that apply edits, so its arguments are read back out of
+/// the signature.
+fn mutates(tool: &str, signature: &str) -> bool {
+    if MUTATING_TOOLS.contains(&tool) {
+        return true;
+    }
+    let args = signature
+        .strip_prefix(tool)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .and_then(|args| serde_json::from_str(args).ok())
+        .unwrap_or_default();
+    crate::domain::verify::is_edit_call(tool, &args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(detector: &mut LoopDetector, tool: &str, args: &str, error: Option<&str>) -> Option<LoopKind> {
+        let signature = format!("{}:{}", tool, args);
+        let kind = detector.check(tool, &signature);
+        detector.observe(tool, &signature, error);
+        kind
+    }
+
+    #[test]
+    fn test_exact_repeat() {
+        let mut detector = LoopDetector::new();
+        assert_eq!(run(&mut detector, "read_file", "a", None), None);
+        assert_eq!(run(&mut detector, "read_file", "a", None), None);
+        assert!(matches!(
+            run(&mut detector, "read_file", "a", None),
+            Some(LoopKind::Repeat { count: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_oscillation() {
+        let mut detector = LoopDetector::new();
+        let mut flagged = None;
+        for _ in 0..3 {
+            flagged = flagged.or(run(&mut detector, "read_file", "a", None));
+            flagged = flagged.or(run(&mut detector, "edit_file", "a", None));
+        }
+        match flagged {
+            Some(LoopKind::Cycle { tools, .. }) => assert_eq!(tools, vec!["read_file", "edit_file"]),
+            other => panic!("expected cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_repeated_error_with_varied_arguments() {
+        let mut detector = LoopDetector::new();
+        run(&mut detector, "bash", "cargo tset", Some("error: no such command: `tset` (1 ms)"));
+        run(&mut detector, "bash", "cargo tset ", Some("error: no such command: `tset` (3 ms)"));
+        run(&mut detector, "bash", "cargo  tset", Some("error: no such command: `tset`  (12 ms)"));
+        assert!(matches!(
+            detector.check("bash", "bash:cargo tset -q"),
+            Some(LoopKind::RepeatedError { count: 3, .. })
+        ));
+        // A different tool is not part of the streak
+        assert_eq!(detector.check("read_file", "read_file:Cargo.toml"), None);
+    }
+
+    #[test]
+    fn test_no_progress_and_reset() {
+        let mut detector = LoopDetector::new();
+        // Exploring new files is progress
+        for i in 0..10 {
+            assert_eq!(run(&mut detector, "read_file", &i.to_string(), None), None);
+        }
+        // Failing edits are not
+        for i in 0..NO_PROGRESS_LIMIT {
+            run(&mut detector, "edit_file", &format!("x{}", i), Some(&format!("Error {}", i)));
+            run(&mut detector, "read_file", &i.to_string(), None);
+        }
+        assert!(matches!(
+            detector.check("list", "list:."),
+            Some(LoopKind::NoProgress { .. })
+        ));
+
+        detector.reset();
+        assert_eq!(detector.check("list", "list:."), None);
+    }
+
+    #[test]
+    fn test_lsp_edits_are_progress() {
+        assert!(mutates("lsp", r#"lsp:{"request":"rename","new_name":"b"}"#));
+        assert!(mutates("lsp", r#"lsp:{"request":"code_actions","apply":1}"#));
+        assert!(!mutates("lsp", r#"lsp:{"request":"code_actions"}"#));
+        assert!(!mutates("lsp", r#"lsp:{"request":"hover"}"#));
+        assert!(mutates("bash", "bash:ls"));
+    }
+}
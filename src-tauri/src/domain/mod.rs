@@ -3,3 +3,10 @@ pub mod ports;
 pub mod agent;
 pub mod context;
 pub mod orchestrator;
+pub mod checkpoint;
+pub mod plan;
+pub mod doom_loop;
+pub mod steering;
+pub mod verify;
+pub mod shadow;
+pub mod worktree;
@@ -27,6 +27,17 @@ pub struct Message {
     pub tool_call_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attachments: Option<Vec<Attachment>>,
+    /// Added by the agent rather than sent by the user, like the images
+    /// returned by tools. It does not start a turn.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub synthetic: bool,
+}
+
+impl Message {
+    /// Whether this message opens a turn: a user message the user sent
+    pub fn starts_turn(&self) -> bool {
+        self.role == Role::User && !self.synthetic
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,6 +70,103 @@ pub struct ConfirmationResponse {
     pub pattern: Option<String>,
 }
 
+/// An action waiting for the user's approval
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfirmationRequest {
+    pub session_id: String,
+    /// permission, shell, diff, doom_loop or mode
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub tool_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_content: Option<String>,
+    pub suggested_pattern: String,
+    /// Why the user is asked, when it is not obvious from the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Set for requests raised by a sub-agent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_session_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+}
+
+impl ConfirmationRequest {
+    /// Permission to run `tool_name` on `input`
+    pub fn permission(session_id: &str, kind: &str, tool_name: &str, input: &str, suggested_pattern: String) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            kind: kind.to_string(),
+            tool_name: tool_name.to_string(),
+            input: Some(input.to_string()),
+            suggested_pattern,
+            ..Default::default()
+        }
+    }
+
+    /// Permission to run a shell command
+    pub fn shell(session_id: &str, command: &str, suggested_pattern: String) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            kind: "shell".to_string(),
+            tool_name: "bash".to_string(),
+            command: Some(command.to_string()),
+            suggested_pattern,
+            ..Default::default()
+        }
+    }
+
+    /// Review of a file change before it is written
+    pub fn diff(session_id: &str, tool_name: &str, file_path: &str, old_content: Option<String>, new_content: String) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            kind: "diff".to_string(),
+            tool_name: tool_name.to_string(),
+            file_path: Some(file_path.to_string()),
+            old_content,
+            new_content: Some(new_content),
+            suggested_pattern: file_path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// One-line description for text interfaces
+    pub fn summary(&self) -> String {
+        let subject = self
+            .command
+            .as_deref()
+            .or(self.file_path.as_deref())
+            .or(self.input.as_deref())
+            .unwrap_or("");
+        format!("{} {}", self.tool_name, subject).trim().to_string()
+    }
+}
+
+/// One question asked through the `question` tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Question {
+    pub id: String,
+    pub header: String,
+    pub question: String,
+    pub options: Vec<QuestionOption>,
+    pub multiple: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuestionOption {
+    pub label: String,
+    pub description: String,
+    pub value: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentPermissions {
     pub config: crate::config::PermissionConfig,
@@ -75,6 +183,36 @@ pub struct AgentSession {
     pub mode: AgentMode,
     pub messages: Vec<Message>,
     pub permissions: AgentPermissions,
+    /// Named agent this session was started as
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// Session that spawned this one as a sub-agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Uuid>,
+    /// Plan produced in PLAN mode, carried into execution
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<crate::domain::plan::Plan>,
+}
+
+impl AgentSession {
+    /// The transcript without system messages, cut before message `index`
+    /// as storage numbers them. The cut must fall before a turn or at the
+    /// end, so no tool call is left without its results.
+    pub fn transcript_before(&self, index: usize) -> Result<Vec<Message>, String> {
+        let mut messages: Vec<Message> = self.messages.iter().filter(|m| m.role != Role::System).cloned().collect();
+        if index > messages.len() {
+            return Err(format!(
+                "Message index {} is out of range (session has {} messages)",
+                index,
+                messages.len()
+            ));
+        }
+        if index < messages.len() && !messages[index].starts_turn() {
+            return Err(format!("Message {} is not a user message; sessions can only be cut before one", index));
+        }
+        messages.truncate(index);
+        Ok(messages)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -94,3 +232,61 @@ pub struct ChatRequest {
     pub temperature: Option<f32>,
     pub tools: Option<Vec<serde_json::Value>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, tool_calls: Option<Vec<ToolCall>>) -> Message {
+        Message {
+            role,
+            content: Some(String::new()),
+            tool_calls,
+            tool_call_id: None,
+            attachments: None,
+            synthetic: false,
+        }
+    }
+
+    #[test]
+    fn test_transcript_is_only_cut_before_turns() {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "read_file".to_string(),
+            arguments: "{}".to_string(),
+            signature: None,
+        };
+        let mut images = message(Role::User, None);
+        images.synthetic = true;
+        let session = AgentSession {
+            id: Uuid::new_v4(),
+            workspace_path: PathBuf::from("/tmp/project"),
+            model: ModelId("gpt-4".to_string()),
+            mode: AgentMode::Build,
+            messages: vec![
+                message(Role::System, None),
+                message(Role::User, None),
+                message(Role::Assistant, Some(vec![call])),
+                message(Role::Tool, None),
+                images,
+                message(Role::Assistant, None),
+                message(Role::User, None),
+            ],
+            permissions: AgentPermissions { config: crate::config::PermissionConfig::default() },
+            agent: None,
+            parent_id: None,
+            plan: None,
+        };
+
+        // Between a tool call and its result, or before the tool images
+        for index in [1, 2, 3, 4] {
+            assert!(session.transcript_before(index).is_err(), "cut at {}", index);
+        }
+        assert!(session.transcript_before(0).unwrap().is_empty());
+        let kept = session.transcript_before(5).unwrap();
+        assert_eq!(kept.len(), 5);
+        assert!(kept.iter().all(|m| m.role != Role::System));
+        assert_eq!(session.transcript_before(6).unwrap().len(), 6);
+        assert!(session.transcript_before(7).is_err());
+    }
+}
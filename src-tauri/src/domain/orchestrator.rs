@@ -1,6 +1,6 @@
 use crate::domain::agent::Agent;
 use crate::domain::models::{AgentRole, ModelId, Message, Role};
-use crate::domain::ports::ModelAdapter;
+use crate::domain::ports::{InteractionProvider, ModelAdapter};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
@@ -67,6 +67,7 @@ impl Orchestrator {
         model: Arc<dyn ModelAdapter>,
         tools: Vec<Arc<dyn crate::domain::ports::Tool>>,
         initial_mode: crate::domain::models::AgentMode,
+        interaction: Arc<dyn InteractionProvider>,
     ) -> Result<(), String> {
         let workspace_path = {
             let ctx = self.context.lock().await;
@@ -87,9 +88,12 @@ impl Orchestrator {
             permissions: crate::domain::models::AgentPermissions {
                 config: config.permission.clone(),
             },
+            agent: None,
+            parent_id: None,
+            plan: None,
         };
 
-        let agent = Agent::new(session, model, tools, permission_manager, None, None);
+        let agent = Agent::new(session, model, tools, permission_manager, interaction);
         let mut agents = self.agents.lock().await;
         agents.insert(agent_id, Arc::new(tokio::sync::Mutex::new(agent)));
         Ok(())
@@ -155,6 +159,7 @@ impl Orchestrator {
                                 tool_calls: None,
                                 tool_call_id: None,
                                 attachments: None,
+                                synthetic: false,
                             });
                             ctx.active_task = None;
                         }
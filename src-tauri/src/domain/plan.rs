@@ -0,0 +1,327 @@
+use crate::domain::models::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Internal tool the agent calls in PLAN mode to hand over its plan
+pub const SUBMIT_PLAN_TOOL: &str = "submit_plan";
+/// Internal tool the agent calls while executing a plan to report progress
+pub const UPDATE_PLAN_STEP_TOOL: &str = "update_plan_step";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStatus {
+    Draft,
+    Executing,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Pending,
+    InProgress,
+    Done,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub title: String,
+    #[serde(default)]
+    pub rationale: String,
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Checks that tell whether the step is done
+    #[serde(default)]
+    pub acceptance: Vec<String>,
+    #[serde(default = "default_step_status")]
+    pub status: StepStatus,
+    /// Deviations from the plan reported while executing the step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+fn default_step_status() -> StepStatus {
+    StepStatus::Pending
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub title: String,
+    #[serde(default)]
+    pub summary: String,
+    pub steps: Vec<PlanStep>,
+    pub status: PlanStatus,
+    pub created_at: String,
+}
+
+impl Plan {
+    /// Build a plan from `submit_plan` arguments
+    pub fn from_submission(args: &Value) -> Result<Self, String> {
+        let title = args
+            .get("title")
+            .and_then(|v| v.as_str())
+            .filter(|t| !t.trim().is_empty())
+            .ok_or("Missing plan title")?
+            .to_string();
+        let summary = args.get("summary").and_then(|v| v.as_str()).unwrap_or("").to_string();
+
+        let steps_val = args.get("steps").and_then(|v| v.as_array()).ok_or("Missing plan steps")?;
+        if steps_val.is_empty() {
+            return Err("A plan needs at least one step".to_string());
+        }
+
+        let steps = steps_val
+            .iter()
+            .map(|step| {
+                let mut step: PlanStep = serde_json::from_value(step.clone())
+                    .map_err(|e| format!("Invalid plan step: {}", e))?;
+                step.status = StepStatus::Pending;
+                step.notes = None;
+                Ok(step)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            title,
+            summary,
+            steps,
+            status: PlanStatus::Draft,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
+    /// The plan as it stood at the end of `transcript`: the last plan
+    /// submitted there, as a draft. `None` when nothing was submitted or
+    /// its execution had already started, since the transcript does not
+    /// record every change to the steps.
+    pub fn as_of(transcript: &[Message]) -> Option<Self> {
+        let plan = transcript
+            .iter()
+            .flat_map(|m| m.tool_calls.iter().flatten())
+            .filter(|call| call.name == SUBMIT_PLAN_TOOL)
+            .filter_map(|call| serde_json::from_str(&call.arguments).ok())
+            .filter_map(|args: Value| Self::from_submission(&args).ok())
+            .next_back()?;
+        let step_prompts: Vec<String> = (0..plan.steps.len()).map(|i| plan.step_prompt(i)).collect();
+        let executed = transcript
+            .iter()
+            .filter(|m| m.starts_turn())
+            .any(|m| m.content.as_ref().is_some_and(|c| step_prompts.contains(c)));
+        (!executed).then_some(plan)
+    }
+
+    /// Index of the first step that has not been finished
+    pub fn next_pending(&self) -> Option<usize> {
+        self.steps
+            .iter()
+            .position(|s| matches!(s.status, StepStatus::Pending | StepStatus::InProgress))
+    }
+
+    /// Apply `update_plan_step` arguments
+    pub fn update_step(&mut self, args: &Value) -> Result<String, String> {
+        let number = args.get("step").and_then(|v| v.as_u64()).ok_or("Missing step number")? as usize;
+        let index = number
+            .checked_sub(1)
+            .filter(|i| *i < self.steps.len())
+            .ok_or_else(|| format!("Step {} does not exist", number))?;
+
+        let status = match args.get("status").and_then(|v| v.as_str()) {
+            Some("done") => StepStatus::Done,
+            Some("skipped") => StepStatus::Skipped,
+            Some("failed") => StepStatus::Failed,
+            Some("in_progress") => StepStatus::InProgress,
+            other => return Err(format!("Invalid step status: {:?}", other)),
+        };
+
+        let step = &mut self.steps[index];
+        step.status = status;
+        if let Some(notes) = args.get("notes").and_then(|v| v.as_str()).filter(|n| !n.trim().is_empty()) {
+            step.notes = Some(notes.to_string());
+        }
+        Ok(format!("Step {} marked {:?}", number, step.status))
+    }
+
+    /// Instructions for the agent to carry out one step
+    pub fn step_prompt(&self, index: usize) -> String {
+        let step = &self.steps[index];
+        let mut prompt = format!(
+            "Execute step {} of {} of the plan \"{}\": {}",
+            index + 1,
+            self.steps.len(),
+            self.title,
+            step.title
+        );
+        if !step.rationale.is_empty() {
+            prompt.push_str(&format!("\nRationale: {}", step.rationale));
+        }
+        if !step.files.is_empty() {
+            prompt.push_str(&format!("\nFiles: {}", step.files.join(", ")));
+        }
+        if !step.acceptance.is_empty() {
+            prompt.push_str("\nAcceptance checks:");
+            for check in &step.acceptance {
+                prompt.push_str(&format!("\n- {}", check));
+            }
+        }
+        prompt.push_str(&format!(
+            "\n\nWork only on this step. When finished, call {} with step {} and status \"done\", \"skipped\" or \"failed\". Put any deviation from the plan in notes.",
+            UPDATE_PLAN_STEP_TOOL,
+            index + 1
+        ));
+        prompt
+    }
+
+    /// Progress summary returned at the end of execution
+    pub fn report(&self) -> String {
+        let mut lines = vec![format!("Plan \"{}\": {:?}", self.title, self.status)];
+        for (i, step) in self.steps.iter().enumerate() {
+            let mark = match step.status {
+                StepStatus::Done => "x",
+                StepStatus::Skipped => "-",
+                StepStatus::Failed => "!",
+                StepStatus::Pending | StepStatus::InProgress => " ",
+            };
+            lines.push(format!("[{}] {}. {}", mark, i + 1, step.title));
+            if let Some(notes) = &step.notes {
+                lines.push(format!("    Deviation: {}", notes));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+pub fn submit_plan_schema() -> Value {
+    json!({
+        "name": SUBMIT_PLAN_TOOL,
+        "description": "Submit the final implementation plan. Call this once after investigating, with ordered steps the user can execute.",
+        "parameters": {
+            "type": "object",
+            "properties": {
+                "title": { "type": "string", "description": "Short name for the plan" },
+                "summary": { "type": "string", "description": "What the plan achieves and the overall approach" },
+                "steps": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "title": { "type": "string", "description": "What to do in this step" },
+                            "rationale": { "type": "string", "description": "Why this step is needed" },
+                            "files": { "type": "array", "items": { "type": "string" }, "description": "Files the step touches" },
+                            "acceptance": { "type": "array", "items": { "type": "string" }, "description": "Checks that show the step is complete" }
+                        },
+                        "required": ["title"]
+                    }
+                }
+            },
+            "required": ["title", "steps"]
+        }
+    })
+}
+
+pub fn update_plan_step_schema() -> Value {
+    json!({
+        "name": UPDATE_PLAN_STEP_TOOL,
+        "description": "Report progress on the plan being executed.",
+        "parameters": {
+            "type": "object",
+            "properties": {
+                "step": { "type": "integer", "description": "1-based step number" },
+                "status": { "type": "string", "enum": ["in_progress", "done", "skipped", "failed"] },
+                "notes": { "type": "string", "description": "Deviations from the plan, or why the step was skipped or failed" }
+            },
+            "required": ["step", "status"]
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Plan {
+        Plan::from_submission(&json!({
+            "title": "Add caching",
+            "steps": [
+                { "title": "Add cache module", "files": ["src/cache.rs"], "acceptance": ["cargo check passes"] },
+                { "title": "Wire cache into fetch" }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_from_submission() {
+        let plan = sample();
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.status, PlanStatus::Draft);
+        assert_eq!(plan.steps[0].files, vec!["src/cache.rs".to_string()]);
+        assert_eq!(plan.next_pending(), Some(0));
+
+        assert!(Plan::from_submission(&json!({ "title": "x", "steps": [] })).is_err());
+        assert!(Plan::from_submission(&json!({ "steps": [{ "title": "a" }] })).is_err());
+    }
+
+    #[test]
+    fn test_update_step() {
+        let mut plan = sample();
+        plan.update_step(&json!({ "step": 1, "status": "done", "notes": "used a HashMap" })).unwrap();
+        assert_eq!(plan.steps[0].status, StepStatus::Done);
+        assert_eq!(plan.steps[0].notes.as_deref(), Some("used a HashMap"));
+        assert_eq!(plan.next_pending(), Some(1));
+
+        assert!(plan.update_step(&json!({ "step": 3, "status": "done" })).is_err());
+        assert!(plan.update_step(&json!({ "step": 0, "status": "done" })).is_err());
+        assert!(plan.update_step(&json!({ "step": 2, "status": "maybe" })).is_err());
+    }
+
+    #[test]
+    fn test_step_prompt_and_report() {
+        let mut plan = sample();
+        let prompt = plan.step_prompt(0);
+        assert!(prompt.contains("step 1 of 2"));
+        assert!(prompt.contains("- cargo check passes"));
+
+        plan.update_step(&json!({ "step": 1, "status": "failed", "notes": "missing dep" })).unwrap();
+        let report = plan.report();
+        assert!(report.contains("[!] 1. Add cache module"));
+        assert!(report.contains("Deviation: missing dep"));
+    }
+
+    #[test]
+    fn test_plan_as_of_transcript() {
+        use crate::domain::models::{Role, ToolCall};
+        let message = |role: Role, content: &str, tool_calls: Option<Vec<ToolCall>>| Message {
+            role,
+            content: Some(content.to_string()),
+            tool_calls,
+            tool_call_id: None,
+            attachments: None,
+            synthetic: false,
+        };
+        let submission = json!({ "title": "Add caching", "steps": [{ "title": "Add cache module" }] });
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: SUBMIT_PLAN_TOOL.to_string(),
+            arguments: submission.to_string(),
+            signature: None,
+        };
+        let mut transcript = vec![
+            message(Role::User, "plan caching", None),
+            message(Role::Assistant, "", Some(vec![call])),
+            message(Role::Tool, "Plan recorded", None),
+        ];
+        assert!(Plan::as_of(&transcript[..1]).is_none());
+
+        let plan = Plan::as_of(&transcript).unwrap();
+        assert_eq!(plan.title, "Add caching");
+        assert_eq!(plan.status, PlanStatus::Draft);
+        assert_eq!(plan.next_pending(), Some(0));
+
+        let prompt = plan.step_prompt(0);
+        transcript.push(message(Role::User, &prompt, None));
+        assert!(Plan::as_of(&transcript).is_none());
+    }
+}
@@ -15,3 +15,20 @@ pub trait ModelAdapter: Send + Sync {
     async fn chat(&self, req: ChatRequest) -> ChatResponse;
     async fn stream(&self, req: ChatRequest, tx: Sender<String>) -> ChatResponse;
 }
+
+/// Channel to whoever supervises an agent: the desktop UI, a terminal user
+/// or an unattended policy
+#[async_trait]
+pub trait InteractionProvider: Send + Sync {
+    /// Ask for approval of an action
+    async fn confirm(&self, request: ConfirmationRequest) -> Result<ConfirmationResponse, String>;
+
+    /// Ask the user questions; returns answers keyed by question id
+    async fn ask(&self, session_id: &str, questions: Vec<Question>) -> Result<Value, String>;
+
+    /// Report progress such as tool calls or plan updates. Never blocks.
+    fn notify(&self, event: &str, payload: Value);
+
+    /// Abandon requests still pending for a session that went away
+    fn cancel_session(&self, _session_id: &str) {}
+}
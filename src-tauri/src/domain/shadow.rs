@@ -0,0 +1,452 @@
+use git2::{Commit, DiffFormat, IndexAddOption, Oid, Repository, Signature};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Diffs are cut at this size so one large turn stays readable
+const MAX_DIFF_BYTES: usize = 200 * 1024;
+
+const SESSION_TRAILER: &str = "Anvil-Session: ";
+const TURN_TRAILER: &str = "Anvil-Turn: ";
+
+/// Audit trail of a session's turns as commits on a hidden ref,
+/// `refs/anvil/<session_id>`.
+///
+/// Each turn that changes the working tree gets one commit holding the
+/// whole tree (minus ignored files) after the turn. When the tree was
+/// changed outside the agent since the previous commit, an extra commit
+/// without a turn records that first, so a turn's diff only holds its own
+/// changes. Trees are built in a copy of the index that is never written
+/// back; the user's index, HEAD and branches are left alone.
+pub struct ShadowLog {
+    workspace_root: PathBuf,
+    session_id: String,
+}
+
+/// A turn recorded on the shadow ref
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowTurn {
+    /// Number of user messages before the one that started the turn
+    pub turn: usize,
+    pub commit: String,
+    /// First line of the prompt that started the turn
+    pub summary: String,
+    pub created_at: String,
+    pub files: Vec<String>,
+}
+
+impl ShadowLog {
+    /// Shadow log of `session_id`, if `workspace_root` is a git repository
+    pub fn open(workspace_root: &Path, session_id: &str) -> Result<Self, String> {
+        let log = Self {
+            workspace_root: workspace_root.to_path_buf(),
+            session_id: session_id.to_string(),
+        };
+        let repo = log.repo()?;
+        if repo.is_bare() {
+            return Err("Shadow commits need a repository with a working tree".to_string());
+        }
+        Ok(log)
+    }
+
+    pub fn refname(&self) -> String {
+        format!("refs/anvil/{}", self.session_id)
+    }
+
+    fn repo(&self) -> Result<Repository, String> {
+        Repository::open(&self.workspace_root).map_err(|e| format!("Failed to open git repository: {}", e))
+    }
+
+    /// Tree of the working directory as it is now
+    pub fn snapshot(&self) -> Result<Oid, String> {
+        snapshot(&self.repo()?)
+    }
+
+    /// Commit the working tree after `turn` if it differs from `before`, the
+    /// snapshot taken when the turn started. Returns the new commit.
+    pub fn record_turn(&self, turn: usize, before: Oid, prompt: &str) -> Result<Option<Oid>, String> {
+        let repo = self.repo()?;
+        let after = snapshot(&repo)?;
+        if after == before {
+            return Ok(None);
+        }
+
+        let signature = Signature::now("Anvil Agent", "agent@anvil.local")
+            .map_err(|e| format!("Failed to create signature: {}", e))?;
+        let mut parent = self.tip(&repo)?.or_else(|| repo.head().ok().and_then(|h| h.peel_to_commit().ok()));
+
+        if parent.as_ref().map(|p| p.tree_id()) != Some(before) {
+            let message = format!(
+                "Changes outside the agent before turn {}\n\n{}{}\n",
+                turn, SESSION_TRAILER, self.session_id
+            );
+            let baseline = commit(&repo, &signature, &message, before, parent.as_ref())?;
+            parent = Some(baseline);
+        }
+
+        let message = format!(
+            "{}\n\n{}{}\n{}{}\n",
+            prompt_message(prompt),
+            SESSION_TRAILER,
+            self.session_id,
+            TURN_TRAILER,
+            turn
+        );
+        let recorded = commit(&repo, &signature, &message, after, parent.as_ref())?;
+        repo.reference(&self.refname(), recorded.id(), true, &format!("anvil: turn {}", turn))
+            .map_err(|e| format!("Failed to update {}: {}", self.refname(), e))?;
+        Ok(Some(recorded.id()))
+    }
+
+    /// Recorded turns, oldest first
+    pub fn turns(&self) -> Result<Vec<ShadowTurn>, String> {
+        let repo = self.repo()?;
+        let mut turns = Vec::new();
+        for commit in self.turn_commits(&repo)? {
+            let Some(turn) = turn_number(&commit) else {
+                continue;
+            };
+            let created_at = chrono::DateTime::from_timestamp(commit.time().seconds(), 0)
+                .map(|time| time.to_rfc3339())
+                .unwrap_or_default();
+            turns.push(ShadowTurn {
+                turn,
+                commit: commit.id().to_string(),
+                summary: commit.summary().unwrap_or("").to_string(),
+                created_at,
+                files: changed_files(&repo, &commit)?,
+            });
+        }
+        Ok(turns)
+    }
+
+    /// Unified diff of the changes made in `turn`
+    pub fn diff_turn(&self, turn: usize) -> Result<String, String> {
+        let repo = self.repo()?;
+        let commit = self.find_turn(&repo, turn)?;
+        let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
+        let tree = commit.tree().map_err(|e| format!("Failed to get tree: {}", e))?;
+        let diff = repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+            .map_err(|e| format!("Failed to compute diff: {}", e))?;
+
+        let mut text = String::new();
+        let mut truncated = false;
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            let content = String::from_utf8_lossy(line.content());
+            if truncated || text.len() + content.len() > MAX_DIFF_BYTES {
+                truncated = true;
+                return true;
+            }
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                text.push(line.origin());
+            }
+            text.push_str(&content);
+            true
+        })
+        .map_err(|e| format!("Failed to format diff: {}", e))?;
+        if truncated {
+            text.push_str("\n[diff truncated]\n");
+        }
+        Ok(text)
+    }
+
+    /// Commit the changes of `turn` onto the current branch. The working
+    /// tree is not touched; the index entries of the changed files are
+    /// updated to the new commit.
+    pub fn cherry_pick(&self, turn: usize) -> Result<String, String> {
+        let repo = self.repo()?;
+        let commit = self.find_turn(&repo, turn)?;
+        let message = strip_trailers(commit.message().unwrap_or(""));
+        let commits = [commit];
+        apply_to_head(&repo, &commits, &message)
+    }
+
+    /// Commit the changes of every turn onto the current branch as one
+    /// commit. Without `message`, one is written from the turns' prompts.
+    pub fn squash(&self, message: Option<&str>) -> Result<String, String> {
+        let repo = self.repo()?;
+        let commits: Vec<Commit> = self
+            .turn_commits(&repo)?
+            .into_iter()
+            .filter(|c| turn_number(c).is_some())
+            .collect();
+        if commits.is_empty() {
+            return Err("This session has no recorded turns".to_string());
+        }
+
+        let message = match message.map(str::trim).filter(|m| !m.is_empty()) {
+            Some(message) => message.to_string(),
+            None => squash_message(&commits),
+        };
+        apply_to_head(&repo, &commits, &message)
+    }
+
+    fn tip<'r>(&self, repo: &'r Repository) -> Result<Option<Commit<'r>>, String> {
+        match repo.find_reference(&self.refname()) {
+            Ok(reference) => reference
+                .peel_to_commit()
+                .map(Some)
+                .map_err(|e| format!("Failed to read {}: {}", self.refname(), e)),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read {}: {}", self.refname(), e)),
+        }
+    }
+
+    /// Commits made by this log, oldest first. Stops at the first commit
+    /// of another session or of the user's history.
+    fn turn_commits<'r>(&self, repo: &'r Repository) -> Result<Vec<Commit<'r>>, String> {
+        let mut commits = Vec::new();
+        let mut next = self.tip(repo)?;
+        while let Some(commit) = next {
+            if session_of(&commit) != Some(self.session_id.as_str()) {
+                break;
+            }
+            next = commit.parent(0).ok();
+            commits.push(commit);
+        }
+        commits.reverse();
+        Ok(commits)
+    }
+
+    /// The latest commit for `turn`; a turn number is reused when the
+    /// conversation was rewound
+    fn find_turn<'r>(&self, repo: &'r Repository, turn: usize) -> Result<Commit<'r>, String> {
+        self.turn_commits(repo)?
+            .into_iter()
+            .rev()
+            .find(|c| turn_number(c) == Some(turn))
+            .ok_or_else(|| format!("No shadow commit for turn {}", turn))
+    }
+}
+
+/// Tree of the working directory: the index updated with every
+/// non-ignored file, written as objects but never saved as the index
+fn snapshot(repo: &Repository) -> Result<Oid, String> {
+    let mut index = repo.index().map_err(|e| format!("Failed to read index: {}", e))?;
+    index
+        .add_all(["*"], IndexAddOption::DEFAULT, None)
+        .map_err(|e| format!("Failed to snapshot working tree: {}", e))?;
+    index
+        .update_all(["*"], None)
+        .map_err(|e| format!("Failed to snapshot working tree: {}", e))?;
+    index.write_tree().map_err(|e| format!("Failed to write tree: {}", e))
+}
+
+fn commit<'r>(
+    repo: &'r Repository,
+    signature: &Signature,
+    message: &str,
+    tree: Oid,
+    parent: Option<&Commit>,
+) -> Result<Commit<'r>, String> {
+    let tree = repo.find_tree(tree).map_err(|e| format!("Failed to find tree: {}", e))?;
+    let parents: Vec<&Commit> = parent.into_iter().collect();
+    let oid = repo
+        .commit(None, signature, signature, message, &tree, &parents)
+        .map_err(|e| format!("Failed to create commit: {}", e))?;
+    repo.find_commit(oid).map_err(|e| format!("Failed to find commit: {}", e))
+}
+
+/// Cherry-pick `commits` in order onto HEAD and commit the result as one
+/// commit. Returns the new commit's id.
+fn apply_to_head(repo: &Repository, commits: &[Commit], message: &str) -> Result<String, String> {
+    let head = repo
+        .head()
+        .and_then(|h| h.peel_to_commit())
+        .map_err(|e| format!("Failed to read HEAD: {}", e))?;
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now("Anvil Agent", "agent@anvil.local"))
+        .map_err(|e| format!("Failed to create signature: {}", e))?;
+
+    // Intermediate commits only carry the result into the next pick
+    let mut ours = head.clone();
+    for commit in commits {
+        let mut index = repo
+            .cherrypick_commit(commit, &ours, 0, None)
+            .map_err(|e| format!("Failed to apply turn: {}", e))?;
+        if index.has_conflicts() {
+            let turn = turn_number(commit).map(|t| t.to_string()).unwrap_or_default();
+            return Err(format!("Turn {} conflicts with the current branch. Nothing was committed.", turn));
+        }
+        let tree = index.write_tree_to(repo).map_err(|e| format!("Failed to write tree: {}", e))?;
+        ours = self::commit(repo, &signature, message, tree, Some(&ours))?;
+    }
+
+    if ours.tree_id() == head.tree_id() {
+        return Err("These changes are already on the current branch".to_string());
+    }
+    let tree = ours.tree().map_err(|e| format!("Failed to get tree: {}", e))?;
+    let oid = repo
+        .commit(Some("HEAD"), &signature, &signature, message, &tree, &[&head])
+        .map_err(|e| format!("Failed to create commit: {}", e))?;
+    let new_head = repo.find_commit(oid).map_err(|e| format!("Failed to find commit: {}", e))?;
+
+    // Keep the index in step with HEAD for the committed files, so they do
+    // not show up as staged reverts
+    let head_tree = head.tree().map_err(|e| format!("Failed to get tree: {}", e))?;
+    let diff = repo
+        .diff_tree_to_tree(Some(&head_tree), Some(&tree), None)
+        .map_err(|e| format!("Failed to compute diff: {}", e))?;
+    let paths: Vec<PathBuf> = diff
+        .deltas()
+        .flat_map(|d| [d.old_file().path(), d.new_file().path()])
+        .flatten()
+        .map(Path::to_path_buf)
+        .collect();
+    repo.reset_default(Some(new_head.as_object()), &paths)
+        .map_err(|e| format!("Committed {}, but failed to update the index: {}", oid, e))?;
+
+    Ok(oid.to_string())
+}
+
+fn changed_files(repo: &Repository, commit: &Commit) -> Result<Vec<String>, String> {
+    let parent_tree = commit.parent(0).ok().and_then(|p| p.tree().ok());
+    let tree = commit.tree().map_err(|e| format!("Failed to get tree: {}", e))?;
+    let diff = repo
+        .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+        .map_err(|e| format!("Failed to compute diff: {}", e))?;
+    Ok(diff
+        .deltas()
+        .filter_map(|d| d.new_file().path().or(d.old_file().path()))
+        .map(|p| p.to_string_lossy().to_string())
+        .collect())
+}
+
+fn trailer<'a>(commit: &'a Commit, key: &str) -> Option<&'a str> {
+    commit.message()?.lines().rev().find_map(|line| line.strip_prefix(key))
+}
+
+fn session_of<'a>(commit: &'a Commit) -> Option<&'a str> {
+    trailer(commit, SESSION_TRAILER).map(str::trim)
+}
+
+fn turn_number(commit: &Commit) -> Option<usize> {
+    trailer(commit, TURN_TRAILER)?.trim().parse().ok()
+}
+
+/// Commit message for a turn: the prompt's first line as the subject and
+/// the full prompt as the body when it is longer
+fn prompt_message(prompt: &str) -> String {
+    let prompt = prompt.trim();
+    let subject = subject_line(prompt);
+    if prompt.is_empty() {
+        "Agent turn".to_string()
+    } else if subject == prompt {
+        subject
+    } else {
+        format!("{}\n\n{}", subject, prompt)
+    }
+}
+
+fn subject_line(text: &str) -> String {
+    let first = text.lines().next().unwrap_or("").trim();
+    match first.char_indices().nth(72) {
+        Some((cut, _)) => format!("{}...", &first[..cut]),
+        None => first.to_string(),
+    }
+}
+
+fn strip_trailers(message: &str) -> String {
+    let kept: Vec<&str> = message
+        .lines()
+        .filter(|line| !line.starts_with(SESSION_TRAILER) && !line.starts_with(TURN_TRAILER))
+        .collect();
+    format!("{}\n", kept.join("\n").trim_end())
+}
+
+/// The first turn's subject, followed by a list of every turn
+fn squash_message(commits: &[Commit]) -> String {
+    let summaries: Vec<&str> = commits.iter().map(|c| c.summary().unwrap_or("Agent turn")).collect();
+    if summaries.len() == 1 {
+        return format!("{}\n", summaries[0]);
+    }
+    let list: Vec<String> = summaries.iter().map(|s| format!("- {}", s)).collect();
+    format!("{}\n\nSquashed from {} agent turns:\n{}\n", summaries[0], summaries.len(), list.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn init_repo(dir: &Path) -> Repository {
+        let repo = Repository::init(dir).unwrap();
+        fs::write(dir.join("a.txt"), "one\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        {
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature = Signature::now("Test", "test@example.com").unwrap();
+            repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[]).unwrap();
+        }
+        repo
+    }
+
+    #[test]
+    fn test_record_turn_leaves_index_and_head_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = init_repo(dir.path());
+        let head = repo.head().unwrap().target().unwrap();
+        let log = ShadowLog::open(dir.path(), "s1").unwrap();
+
+        let before = log.snapshot().unwrap();
+        assert_eq!(log.record_turn(0, before, "Look around").unwrap(), None);
+
+        let before = log.snapshot().unwrap();
+        fs::write(dir.path().join("a.txt"), "two\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "new\n").unwrap();
+        log.record_turn(1, before, "Change a\n\nand add b").unwrap().unwrap();
+
+        // The user edits between turns; that lands in a separate commit
+        fs::write(dir.path().join("c.txt"), "user\n").unwrap();
+        let before = log.snapshot().unwrap();
+        fs::write(dir.path().join("a.txt"), "three\n").unwrap();
+        log.record_turn(2, before, "Change a again").unwrap().unwrap();
+
+        assert_eq!(repo.head().unwrap().target().unwrap(), head);
+        let statuses = repo.statuses(None).unwrap();
+        assert!(statuses.iter().all(|s| !s.status().is_index_new() && !s.status().is_index_modified()));
+
+        let turns = log.turns().unwrap();
+        assert_eq!(turns.iter().map(|t| t.turn).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(turns[0].summary, "Change a");
+        assert_eq!(turns[0].files, vec!["a.txt", "b.txt"]);
+        assert_eq!(turns[1].files, vec!["a.txt"]);
+
+        let diff = log.diff_turn(2).unwrap();
+        assert!(diff.contains("-two\n+three\n") && !diff.contains("c.txt"));
+        assert!(log.diff_turn(5).is_err());
+    }
+
+    #[test]
+    fn test_cherry_pick_and_squash_commit_onto_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = init_repo(dir.path());
+        let log = ShadowLog::open(dir.path(), "s1").unwrap();
+
+        let before = log.snapshot().unwrap();
+        fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        log.record_turn(0, before, "Add b").unwrap();
+        let before = log.snapshot().unwrap();
+        fs::write(dir.path().join("a.txt"), "two\n").unwrap();
+        log.record_turn(1, before, "Change a").unwrap();
+
+        log.cherry_pick(1).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message().unwrap(), "Change a\n");
+        let tree = head.tree().unwrap();
+        assert!(tree.get_path(Path::new("b.txt")).is_err());
+        // a.txt is committed and not shown as a staged change
+        let status = repo.status_file(Path::new("a.txt")).unwrap();
+        assert!(status.is_empty());
+
+        let squashed = log.squash(None).unwrap();
+        let commit = repo.find_commit(Oid::from_str(&squashed).unwrap()).unwrap();
+        assert_eq!(commit.summary(), Some("Add b"));
+        assert!(commit.message().unwrap().contains("- Change a"));
+        assert!(commit.tree().unwrap().get_path(Path::new("b.txt")).is_ok());
+        assert!(log.squash(None).unwrap_err().contains("already on the current branch"));
+    }
+}
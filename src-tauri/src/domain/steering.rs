@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// A message sent by the user while a turn is running
+#[derive(Debug, Clone, PartialEq)]
+pub struct SteeringMessage {
+    pub content: String,
+    /// Cancel the tool calls that have not started yet
+    pub interrupt: bool,
+}
+
+#[derive(Debug, Default)]
+struct InboxState {
+    running: bool,
+    queue: VecDeque<SteeringMessage>,
+}
+
+/// Per-session inbox for steering a running turn. The agent drains it
+/// between tool steps; it is shared outside the agent's lock so messages can
+/// arrive while a turn holds the agent.
+#[derive(Debug, Default)]
+pub struct SteeringInbox {
+    state: Mutex<InboxState>,
+}
+
+impl SteeringInbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, InboxState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Queue a message for the running turn. Gives the message back when
+    /// no turn is running, so it can be sent as a regular prompt instead.
+    pub fn push(&self, message: SteeringMessage) -> Result<(), SteeringMessage> {
+        let mut state = self.lock();
+        if !state.running {
+            return Err(message);
+        }
+        state.queue.push_back(message);
+        Ok(())
+    }
+
+    pub fn begin_turn(&self) {
+        self.lock().running = true;
+    }
+
+    /// Stop accepting messages and return the ones the turn did not take.
+    /// Both happen under one lock, so every message is either returned
+    /// here or refused by [`push`](Self::push).
+    pub fn end_turn(&self) -> Vec<SteeringMessage> {
+        let mut state = self.lock();
+        state.running = false;
+        state.queue.drain(..).collect()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.lock().running
+    }
+
+    /// Whether a queued message asks to cancel the remaining tool calls
+    pub fn interrupt_requested(&self) -> bool {
+        self.lock().queue.iter().any(|m| m.interrupt)
+    }
+
+    pub fn take(&self) -> Vec<SteeringMessage> {
+        self.lock().queue.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str, interrupt: bool) -> SteeringMessage {
+        SteeringMessage {
+            content: content.to_string(),
+            interrupt,
+        }
+    }
+
+    #[test]
+    fn test_push_only_while_running() {
+        let inbox = SteeringInbox::new();
+        assert_eq!(inbox.push(message("early", false)), Err(message("early", false)));
+
+        inbox.begin_turn();
+        inbox.push(message("use the v2 API", false)).unwrap();
+        assert!(!inbox.interrupt_requested());
+        inbox.push(message("stop, wrong file", true)).unwrap();
+        assert!(inbox.interrupt_requested());
+
+        let taken = inbox.take();
+        assert_eq!(taken.len(), 2);
+        assert_eq!(taken[0].content, "use the v2 API");
+        assert!(inbox.take().is_empty());
+        assert!(!inbox.interrupt_requested());
+    }
+
+    #[test]
+    fn test_end_of_turn_returns_leftovers() {
+        let inbox = SteeringInbox::new();
+        inbox.begin_turn();
+        inbox.push(message("late", false)).unwrap();
+        assert_eq!(inbox.end_turn(), vec![message("late", false)]);
+
+        assert!(!inbox.is_running());
+        assert!(inbox.push(message("idle", false)).is_err());
+        assert!(inbox.take().is_empty());
+        assert!(inbox.end_turn().is_empty());
+    }
+}
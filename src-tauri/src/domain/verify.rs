@@ -0,0 +1,663 @@
+use crate::adapters::tools::bash::authorize_command;
+use crate::adapters::tools::shell::kill_process_group;
+use crate::config::{PermissionConfig, VerifyCommand, VerifyConfig};
+use crate::domain::ports::InteractionProvider;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Internal tool for running the workspace checks on demand; automatic
+/// rounds after edits are recorded as calls to it as well
+pub const VERIFY_TOOL: &str = "verify";
+
+/// Tools whose successful calls trigger verification
+pub const EDIT_TOOLS: &[&str] = &["write_file", "edit_file", "multi_edit", "patch"];
+/// `lsp` requests that change files; `code_actions` only when it applies one
+pub const LSP_EDIT_REQUESTS: &[&str] = &["rename", "format", "code_actions"];
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+/// Diagnostics listed per command; the rest are only counted
+const MAX_DIAGNOSTICS: usize = 20;
+/// Output lines kept when a command fails without parseable diagnostics
+const OUTPUT_TAIL_LINES: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn render(&self) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let location = match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => format!("{}:{}:{}: ", file, line, column),
+            (Some(file), Some(line), None) => format!("{}:{}: ", file, line),
+            (Some(file), None, _) => format!("{}: ", file),
+            _ => String::new(),
+        };
+        format!("{}{}: {}", location, severity, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    /// `cargo ... --message-format=json`
+    Cargo,
+    /// `tsc --pretty false`
+    Tsc,
+    /// `file:line:col: message` lines, as printed by most compilers and linters
+    Text,
+}
+
+impl OutputFormat {
+    fn of(command: &VerifyCommand) -> Self {
+        match command.format.as_deref() {
+            Some("cargo") => OutputFormat::Cargo,
+            Some("tsc") => OutputFormat::Tsc,
+            Some(_) => OutputFormat::Text,
+            None if command.command.contains("message-format=json") => OutputFormat::Cargo,
+            None if command.command.contains("tsc") => OutputFormat::Tsc,
+            None => OutputFormat::Text,
+        }
+    }
+
+    fn parse(self, output: &str) -> Vec<Diagnostic> {
+        match self {
+            OutputFormat::Cargo => parse_cargo(output),
+            OutputFormat::Tsc => parse_tsc(output),
+            OutputFormat::Text => parse_text(output),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandOutcome {
+    pub name: String,
+    pub success: bool,
+    pub timed_out: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Tail of the output when the command failed without parseable errors
+    pub output: Option<String>,
+}
+
+impl CommandOutcome {
+    fn count(&self, severity: Severity) -> usize {
+        self.diagnostics.iter().filter(|d| d.severity == severity).count()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub outcomes: Vec<CommandOutcome>,
+}
+
+impl VerifyReport {
+    /// True when no command ran, e.g. none matched the edited files
+    pub fn is_empty(&self) -> bool {
+        self.outcomes.is_empty()
+    }
+
+    pub fn passed(&self) -> bool {
+        self.outcomes.iter().all(|o| o.success)
+    }
+
+    /// Observation text for the model
+    pub fn render(&self) -> String {
+        if self.is_empty() {
+            return "No verification command applies to the edited files.".to_string();
+        }
+
+        let mut sections = Vec::new();
+        for outcome in &self.outcomes {
+            let status = if outcome.timed_out {
+                "timed out".to_string()
+            } else if outcome.success {
+                match outcome.count(Severity::Warning) {
+                    0 => "passed".to_string(),
+                    n => format!("passed with {} warning(s)", n),
+                }
+            } else {
+                format!(
+                    "failed with {} error(s), {} warning(s)",
+                    outcome.count(Severity::Error),
+                    outcome.count(Severity::Warning)
+                )
+            };
+
+            let mut lines = vec![format!("[{}] {}", outcome.name, status)];
+            // Warnings of passing commands are noise while errors are being fixed elsewhere
+            let shown: Vec<&Diagnostic> = outcome
+                .diagnostics
+                .iter()
+                .filter(|d| !outcome.success || d.severity == Severity::Warning)
+                .collect();
+            for diagnostic in shown.iter().take(MAX_DIAGNOSTICS) {
+                lines.push(format!("  {}", diagnostic.render()));
+            }
+            if shown.len() > MAX_DIAGNOSTICS {
+                lines.push(format!("  ... and {} more", shown.len() - MAX_DIAGNOSTICS));
+            }
+            if let Some(output) = &outcome.output {
+                lines.push(output.lines().map(|l| format!("  {}", l)).collect::<Vec<_>>().join("\n"));
+            }
+            sections.push(lines.join("\n"));
+        }
+
+        let summary = if self.passed() {
+            "Verification passed."
+        } else {
+            "Verification failed. Fix the errors below before moving on."
+        };
+        format!("{}\n\n{}", summary, sections.join("\n\n"))
+    }
+}
+
+/// Runs the workspace's verification commands like `bash` would: under
+/// its permission rules and inside the sandbox when one is configured
+#[derive(Clone)]
+pub struct Verifier {
+    root: PathBuf,
+    config: VerifyConfig,
+    session_id: String,
+    permission_manager: Arc<tokio::sync::Mutex<PermissionConfig>>,
+    interaction: Arc<dyn InteractionProvider>,
+}
+
+impl Verifier {
+    /// `None` when verification is disabled or nothing is configured
+    pub fn from_config(
+        root: &Path,
+        config: Option<&VerifyConfig>,
+        session_id: &str,
+        permission_manager: Arc<tokio::sync::Mutex<PermissionConfig>>,
+        interaction: Arc<dyn InteractionProvider>,
+    ) -> Option<Self> {
+        let config = config?;
+        if config.commands.is_empty() || config.enabled == Some(false) {
+            return None;
+        }
+        Some(Self {
+            root: root.to_path_buf(),
+            config: config.clone(),
+            session_id: session_id.to_string(),
+            permission_manager,
+            interaction,
+        })
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.config.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS)
+    }
+
+    /// Commands to run for `edited`; every command when the files are unknown
+    fn commands_for(&self, edited: Option<&[String]>) -> Vec<&VerifyCommand> {
+        self.config
+            .commands
+            .iter()
+            .filter(|command| match edited {
+                Some(files) if !command.files.is_empty() => {
+                    files.iter().any(|file| matches_any(&command.files, &self.relative(file)))
+                }
+                _ => true,
+            })
+            .collect()
+    }
+
+    fn relative(&self, file: &str) -> String {
+        let path = Path::new(file);
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Run the commands that apply to `edited`, one after another
+    pub async fn run(&self, edited: Option<&[String]>) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        for command in self.commands_for(edited) {
+            report.outcomes.push(self.run_command(command).await);
+        }
+        report
+    }
+
+    async fn run_command(&self, command: &VerifyCommand) -> CommandOutcome {
+        let name = command.name.clone().unwrap_or_else(|| command.command.clone());
+        let timeout = Duration::from_secs(command.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS));
+
+        let failed = |output: String, timed_out: bool| CommandOutcome {
+            name: name.clone(),
+            success: false,
+            timed_out,
+            diagnostics: vec![],
+            output: Some(output),
+        };
+
+        let sandbox = match authorize_command(
+            &command.command,
+            &self.session_id,
+            &self.root,
+            &self.permission_manager,
+            self.interaction.as_ref(),
+        )
+        .await
+        {
+            Ok(sandbox) => sandbox,
+            Err(e) => return failed(format!("Not run: {}", e), false),
+        };
+
+        let args = ["-c", command.command.as_str()];
+        let mut child = match &sandbox {
+            Some(sandbox) => sandbox.command("sh", &args, &self.root),
+            None => {
+                let mut child = tokio::process::Command::new("sh");
+                child.args(args).current_dir(&self.root);
+                child
+            }
+        };
+        child
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Its own group, so a timeout also kills what the command started
+        #[cfg(unix)]
+        child.process_group(0);
+
+        let child = match child.spawn() {
+            Ok(child) => child,
+            Err(e) => return failed(format!("Failed to run command: {}", e), false),
+        };
+        let pid = child.id();
+        let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => return failed(format!("Failed to run command: {}", e), false),
+            Err(_) => {
+                if let Some(pid) = pid {
+                    kill_process_group(pid);
+                }
+                return failed(format!("Killed after {} seconds", timeout.as_secs()), true);
+            }
+        };
+
+        let text = format!(
+            "{}\n{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let diagnostics = OutputFormat::of(command).parse(&text);
+        let success = output.status.success();
+        let has_errors = diagnostics.iter().any(|d| d.severity == Severity::Error);
+
+        CommandOutcome {
+            name,
+            success,
+            timed_out: false,
+            output: (!success && !has_errors).then(|| tail(&text, OUTPUT_TAIL_LINES)),
+            diagnostics,
+        }
+    }
+}
+
+fn matches_any(patterns: &[String], file: &str) -> bool {
+    let name = Path::new(file)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    patterns.iter().any(|pattern| match glob::Pattern::new(pattern) {
+        // Patterns without a directory match the file name anywhere
+        Ok(p) if !pattern.contains('/') => p.matches(&name),
+        Ok(p) => p.matches(file),
+        Err(_) => false,
+    })
+}
+
+fn tail(text: &str, lines: usize) -> String {
+    let all: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    all[all.len().saturating_sub(lines)..].join("\n")
+}
+
+/// Whether a call changes files: one of `EDIT_TOOLS`, or an `lsp`
+/// request that applies an edit from the server
+pub fn is_edit_call(tool_name: &str, args: &Value) -> bool {
+    if tool_name != "lsp" {
+        return EDIT_TOOLS.contains(&tool_name);
+    }
+    match args.get("request").and_then(|v| v.as_str()) {
+        Some("code_actions") => args.get("apply").is_some_and(|apply| !apply.is_null()),
+        Some(request) => LSP_EDIT_REQUESTS.contains(&request),
+        None => false,
+    }
+}
+
+/// Files an edit tool call touched, from its arguments or, for `lsp`, its
+/// result
+pub fn edited_paths(tool_name: &str, args: &Value, result: &str) -> Vec<String> {
+    match tool_name {
+        // The server decides which files a rename or format touches
+        "lsp" => serde_json::from_str::<Value>(result)
+            .ok()
+            .and_then(|result| result.get("files").and_then(|v| v.as_array()).cloned())
+            .map(|files| files.iter().filter_map(|f| f.as_str()).map(String::from).collect())
+            .unwrap_or_default(),
+        "patch" => {
+            let base = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
+            let patch = args.get("patch").and_then(|v| v.as_str()).unwrap_or("");
+            patch
+                .lines()
+                .filter_map(|line| line.strip_prefix("+++ "))
+                .map(|path| path.split('\t').next().unwrap_or(path).trim())
+                .filter(|path| *path != "/dev/null")
+                .map(|path| path.strip_prefix("b/").unwrap_or(path))
+                .map(|path| {
+                    if base.is_empty() {
+                        path.to_string()
+                    } else {
+                        Path::new(base).join(path).to_string_lossy().to_string()
+                    }
+                })
+                .collect()
+        }
+        "multi_edit" => args
+            .get("files")
+            .and_then(|v| v.as_array())
+            .map(|files| {
+                files
+                    .iter()
+                    .filter_map(|file| file.get("path").and_then(|v| v.as_str()))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => args
+            .get("path")
+            .and_then(|v| v.as_str())
+            .map(|p| vec![p.to_string()])
+            .unwrap_or_default(),
+    }
+}
+
+fn parse_cargo(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for line in output.lines() {
+        let Ok(value) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        if value.get("reason").and_then(|r| r.as_str()) != Some("compiler-message") {
+            continue;
+        }
+        let Some(message) = value.get("message") else {
+            continue;
+        };
+        let severity = match message.get("level").and_then(|l| l.as_str()) {
+            Some("error") => Severity::Error,
+            Some("warning") => Severity::Warning,
+            _ => continue,
+        };
+        let text = message.get("message").and_then(|m| m.as_str()).unwrap_or("").to_string();
+        let span = message
+            .get("spans")
+            .and_then(|s| s.as_array())
+            .and_then(|spans| spans.iter().find(|s| s["is_primary"].as_bool() == Some(true)));
+        // Summaries like "aborting due to 2 previous errors" carry no location
+        if span.is_none() && (text.starts_with("aborting due to") || text.contains("warning emitted") || text.contains("warnings emitted")) {
+            continue;
+        }
+
+        let diagnostic = Diagnostic {
+            severity,
+            file: span.and_then(|s| s["file_name"].as_str()).map(str::to_string),
+            line: span.and_then(|s| s["line_start"].as_u64()).map(|n| n as u32),
+            column: span.and_then(|s| s["column_start"].as_u64()).map(|n| n as u32),
+            message: text,
+        };
+        // The same diagnostic is reported once per target that includes the file
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+/// `src/a.ts(3,7): error TS2322: Type 'string' is not assignable ...`
+fn parse_tsc(output: &str) -> Vec<Diagnostic> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (location, rest) = line.split_once("): ")?;
+            let (severity, message) = if let Some(m) = rest.strip_prefix("error ") {
+                (Severity::Error, m)
+            } else if let Some(m) = rest.strip_prefix("warning ") {
+                (Severity::Warning, m)
+            } else {
+                return None;
+            };
+            let (file, position) = location.rsplit_once('(')?;
+            let (line, column) = position.split_once(',')?;
+            Some(Diagnostic {
+                severity,
+                file: Some(file.trim().to_string()),
+                line: line.parse().ok(),
+                column: column.parse().ok(),
+                message: message.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// `file:line[:col]: [error|warning]: message`, plus bare `error: ...` lines
+fn parse_text(output: &str) -> Vec<Diagnostic> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let lower = line.to_lowercase();
+            let severity = if lower.contains("error") {
+                Severity::Error
+            } else if lower.contains("warning") {
+                Severity::Warning
+            } else {
+                return None;
+            };
+
+            let mut parts = line.splitn(4, ':');
+            let file = parts.next()?.trim();
+            let line_number = parts.next().and_then(|p| p.trim().parse::<u32>().ok());
+            match line_number {
+                Some(line_number) if !file.is_empty() => {
+                    let third = parts.next().unwrap_or("");
+                    let (column, message) = match third.trim().parse::<u32>() {
+                        Ok(column) => (Some(column), parts.next().unwrap_or("")),
+                        Err(_) => (None, line.splitn(3, ':').nth(2).unwrap_or("")),
+                    };
+                    let message = message.trim();
+                    let message = message
+                        .strip_prefix("error:")
+                        .or_else(|| message.strip_prefix("warning:"))
+                        .unwrap_or(message)
+                        .trim();
+                    Some(Diagnostic {
+                        severity,
+                        file: Some(file.to_string()),
+                        line: Some(line_number),
+                        column,
+                        message: message.to_string(),
+                    })
+                }
+                _ if lower.starts_with("error") || lower.starts_with("warning") => Some(Diagnostic {
+                    severity,
+                    file: None,
+                    line: None,
+                    column: None,
+                    message: line.split_once(':').map(|(_, m)| m).unwrap_or(line).trim().to_string(),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+pub fn verify_schema() -> Value {
+    json!({
+        "name": VERIFY_TOOL,
+        "description": "Run the workspace's configured verification commands (build, type check, lint) and get their diagnostics. Checks also run automatically after edits.",
+        "parameters": {
+            "type": "object",
+            "properties": {}
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::interaction::{AutoPolicy, AutoResponder};
+    use crate::config::{Action, PermissionRule};
+
+    fn verifier(root: &str, config: &VerifyConfig, policy: AutoPolicy) -> Verifier {
+        let permissions = Arc::new(tokio::sync::Mutex::new(PermissionConfig::default()));
+        Verifier::from_config(Path::new(root), Some(config), "s1", permissions, Arc::new(AutoResponder::new(policy)))
+            .unwrap()
+    }
+
+    fn config(command: &str, timeout: Option<u64>) -> VerifyConfig {
+        VerifyConfig {
+            enabled: None,
+            commands: vec![VerifyCommand {
+                command: command.to_string(),
+                timeout,
+                ..Default::default()
+            }],
+            max_attempts: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_cargo_json() {
+        let output = r#"{"reason":"compiler-artifact","target":{}}
+{"reason":"compiler-message","message":{"level":"error","message":"mismatched types","spans":[{"file_name":"src/lib.rs","line_start":4,"column_start":9,"is_primary":true}]}}
+{"reason":"compiler-message","message":{"level":"warning","message":"unused variable: `x`","spans":[{"file_name":"src/main.rs","line_start":2,"column_start":5,"is_primary":true}]}}
+{"reason":"compiler-message","message":{"level":"error","message":"aborting due to 1 previous error","spans":[]}}
+{"reason":"build-finished","success":false}"#;
+        let diagnostics = parse_cargo(output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].render(), "src/lib.rs:4:9: error: mismatched types");
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_parse_tsc_and_text() {
+        let tsc = parse_tsc("src/app.ts(12,5): error TS2322: Type 'string' is not assignable to type 'number'.\nFound 1 error.");
+        assert_eq!(tsc.len(), 1);
+        assert_eq!(tsc[0].file.as_deref(), Some("src/app.ts"));
+        assert_eq!((tsc[0].line, tsc[0].column), (Some(12), Some(5)));
+
+        let text = parse_text("main.c:3:1: error: expected ';'\nlinking...\nerror: build failed");
+        assert_eq!(text.len(), 2);
+        assert_eq!(text[0].render(), "main.c:3:1: error: expected ';'");
+        assert_eq!(text[1].file, None);
+        assert_eq!(text[1].message, "build failed");
+    }
+
+    #[test]
+    fn test_commands_for_edited_files() {
+        let command = |name: &str, files: &[&str]| VerifyCommand {
+            name: Some(name.to_string()),
+            command: "true".to_string(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+            ..Default::default()
+        };
+        let config = VerifyConfig {
+            enabled: None,
+            commands: vec![command("cargo", &["*.rs"]), command("tsc", &["src/**/*.ts"]), command("all", &[])],
+            max_attempts: None,
+        };
+        let verifier = verifier("/ws", &config, AutoPolicy::DenyAll);
+        let names = |edited: Option<&[String]>| {
+            verifier
+                .commands_for(edited)
+                .iter()
+                .map(|c| c.name.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(Some(&["/ws/crates/a/lib.rs".to_string()])), vec!["cargo", "all"]);
+        assert_eq!(names(Some(&["src/ui/app.ts".to_string()])), vec!["tsc", "all"]);
+        assert_eq!(names(None), vec!["cargo", "tsc", "all"]);
+        assert_eq!(verifier.max_attempts(), DEFAULT_MAX_ATTEMPTS);
+    }
+
+    #[test]
+    fn test_edited_paths_from_patch() {
+        let args = json!({
+            "patch": "--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1 +1 @@\n-x\n+y\n--- a/old.rs\n+++ /dev/null\n",
+            "path": "crate"
+        });
+        assert_eq!(edited_paths("patch", &args, ""), vec!["crate/src/a.rs"]);
+        assert_eq!(edited_paths("write_file", &json!({"path": "b.ts"}), ""), vec!["b.ts"]);
+        let args = json!({"files": [{"path": "a.rs", "edits": []}, {"path": "b.rs", "content": ""}]});
+        assert_eq!(edited_paths("multi_edit", &args, ""), vec!["a.rs", "b.rs"]);
+    }
+
+    #[test]
+    fn test_lsp_edits_count_as_edits() {
+        let rename = json!({"request": "rename", "file_path": "src/a.rs", "new_name": "b"});
+        assert!(is_edit_call("lsp", &rename));
+        assert!(is_edit_call("lsp", &json!({"request": "format"})));
+        assert!(is_edit_call("lsp", &json!({"request": "code_actions", "apply": 0})));
+        assert!(!is_edit_call("lsp", &json!({"request": "code_actions"})));
+        assert!(!is_edit_call("lsp", &json!({"request": "hover", "file_path": "src/a.rs"})));
+        assert!(is_edit_call("edit_file", &json!({})));
+
+        let result = json!({"status": "success", "files": ["src/a.rs", "src/b.rs"]}).to_string();
+        assert_eq!(edited_paths("lsp", &rename, &result), vec!["src/a.rs", "src/b.rs"]);
+    }
+
+    #[tokio::test]
+    async fn test_commands_follow_bash_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let marker = dir.path().join("ran");
+        let config = config(&format!("touch {}", marker.display()), None);
+
+        let report = verifier(&root, &config, AutoPolicy::DenyAll).run(None).await;
+        assert!(!report.passed());
+        assert!(report.render().contains("Not run"));
+        assert!(!marker.exists());
+
+        let allowed = verifier(&root, &config, AutoPolicy::DenyAll);
+        allowed.permission_manager.lock().await.bash.rules.push(PermissionRule {
+            pattern: "touch *".to_string(),
+            action: Action::Allow,
+        });
+        assert!(allowed.run(None).await.passed());
+        assert!(marker.exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_timeout_kills_the_whole_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("survived");
+        let config = config(&format!("(sleep 2; touch {}) & sleep 30", marker.display()), Some(1));
+
+        let report = verifier(&dir.path().to_string_lossy(), &config, AutoPolicy::AllowAll).run(None).await;
+        assert!(report.outcomes[0].timed_out);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(!marker.exists());
+    }
+}
@@ -0,0 +1,339 @@
+use git2::{build::CheckoutBuilder, BranchType, IndexAddOption, Repository, Signature, WorktreeAddOptions, WorktreePruneOptions};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A git worktree and branch dedicated to one session, so sessions on the
+/// same repository do not edit each other's files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionWorktree {
+    pub session_id: String,
+    /// Working directory of the repository the worktree was created from
+    pub repo_path: PathBuf,
+    pub path: PathBuf,
+    pub branch: String,
+    /// Commit the branch started from
+    pub base: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeOutcome {
+    pub commit: String,
+    pub fast_forward: bool,
+    pub files: Vec<String>,
+}
+
+/// Where worktrees are created unless the caller picks a directory
+pub fn default_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("anvil")
+        .join("worktrees")
+}
+
+impl SessionWorktree {
+    /// Create branch `anvil/<id>` at HEAD of `repo_path` and check it out in
+    /// a new worktree under `dir`
+    pub fn create(repo_path: &Path, session_id: &str, dir: &Path) -> Result<Self, String> {
+        let repo = open(repo_path)?;
+        let workdir = repo.workdir().ok_or("Worktrees need a repository with a working tree")?.to_path_buf();
+        let head = repo
+            .head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| format!("Worktrees need a commit to start from: {}", e))?;
+
+        let short = &session_id[..session_id.len().min(8)];
+        let branch_name = format!("anvil/{}", short);
+        let repo_name = workdir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let path = dir.join(format!("{}-{}", repo_name, short));
+        if path.exists() {
+            return Err(format!("{} already exists", path.display()));
+        }
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+        let branch = repo
+            .branch(&branch_name, &head, false)
+            .map_err(|e| format!("Failed to create branch {}: {}", branch_name, e))?;
+        let mut opts = WorktreeAddOptions::new();
+        opts.reference(Some(branch.get()));
+        if let Err(e) = repo.worktree(&worktree_name(session_id), &path, Some(&opts)) {
+            let _ = repo.find_branch(&branch_name, BranchType::Local).and_then(|mut b| b.delete());
+            return Err(format!("Failed to create worktree: {}", e));
+        }
+
+        Ok(Self {
+            session_id: session_id.to_string(),
+            repo_path: workdir,
+            path,
+            branch: branch_name,
+            base: head.id().to_string(),
+        })
+    }
+
+    /// Commit what is left in the worktree, then merge the branch into
+    /// whatever is checked out in the repository: a fast-forward when
+    /// possible, a merge commit otherwise. Nothing changes when the merge
+    /// conflicts or local changes in the repository would be overwritten.
+    pub fn merge_back(&self, message: &str) -> Result<MergeOutcome, String> {
+        self.commit_pending(message)?;
+
+        let repo = open(&self.repo_path)?;
+        let theirs = repo
+            .find_branch(&self.branch, BranchType::Local)
+            .and_then(|b| b.get().peel_to_commit())
+            .map_err(|e| format!("Failed to read branch {}: {}", self.branch, e))?;
+        let ours = repo
+            .head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| format!("Failed to read HEAD: {}", e))?;
+
+        if ours.id() == theirs.id() || repo.graph_descendant_of(ours.id(), theirs.id()).unwrap_or(false) {
+            return Err(format!("{} has no changes to merge", self.branch));
+        }
+        let fast_forward = repo.merge_base(ours.id(), theirs.id()).map(|base| base == ours.id()).unwrap_or(false);
+
+        let tree = if fast_forward {
+            theirs.tree().map_err(|e| format!("Failed to get tree: {}", e))?
+        } else {
+            let mut index = repo
+                .merge_commits(&ours, &theirs, None)
+                .map_err(|e| format!("Failed to merge {}: {}", self.branch, e))?;
+            if index.has_conflicts() {
+                let conflicts: Vec<String> = index
+                    .conflicts()
+                    .map_err(|e| e.to_string())?
+                    .flatten()
+                    .filter_map(|c| c.our.or(c.their))
+                    .map(|entry| String::from_utf8_lossy(&entry.path).to_string())
+                    .collect();
+                return Err(format!(
+                    "Merging {} conflicts in {}. Nothing was changed; resolve it with git or discard the worktree.",
+                    self.branch,
+                    conflicts.join(", ")
+                ));
+            }
+            let tree = index.write_tree_to(&repo).map_err(|e| format!("Failed to write tree: {}", e))?;
+            repo.find_tree(tree).map_err(|e| format!("Failed to find tree: {}", e))?
+        };
+
+        repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe()))
+            .map_err(|e| format!("Cannot merge into {}: {}. Commit or stash your changes there first.", self.repo_path.display(), e))?;
+
+        let commit = if fast_forward {
+            let mut head = repo.head().map_err(|e| format!("Failed to read HEAD: {}", e))?;
+            if head.is_branch() {
+                head.set_target(theirs.id(), &format!("anvil: merge {}", self.branch))
+                    .map_err(|e| format!("Failed to update HEAD: {}", e))?;
+            } else {
+                repo.set_head_detached(theirs.id()).map_err(|e| format!("Failed to update HEAD: {}", e))?;
+            }
+            theirs.id()
+        } else {
+            let signature = signature(&repo)?;
+            repo.commit(Some("HEAD"), &signature, &signature, &format!("Merge {}\n\n{}", self.branch, message), &tree, &[&ours, &theirs])
+                .map_err(|e| format!("Failed to create merge commit: {}", e))?
+        };
+
+        let old_tree = ours.tree().map_err(|e| format!("Failed to get tree: {}", e))?;
+        let diff = repo
+            .diff_tree_to_tree(Some(&old_tree), Some(&tree), None)
+            .map_err(|e| format!("Failed to compute diff: {}", e))?;
+        let files = diff
+            .deltas()
+            .filter_map(|d| d.new_file().path().or(d.old_file().path()))
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+
+        Ok(MergeOutcome { commit: commit.to_string(), fast_forward, files })
+    }
+
+    /// Why removing the worktree would lose work: uncommitted changes in it,
+    /// or commits on its branch that the repository's HEAD does not have.
+    /// `None` when there is nothing to lose.
+    pub fn unsaved_work(&self) -> Result<Option<String>, String> {
+        if self.path.exists() {
+            let worktree = open(&self.path)?;
+            let mut opts = git2::StatusOptions::new();
+            opts.include_untracked(true).recurse_untracked_dirs(true);
+            let changed = worktree
+                .statuses(Some(&mut opts))
+                .map_err(|e| format!("Failed to read worktree status: {}", e))?
+                .len();
+            if changed > 0 {
+                return Ok(Some(format!("{} has {} uncommitted change(s)", self.path.display(), changed)));
+            }
+        }
+
+        let repo = open(&self.repo_path)?;
+        let Ok(branch) = repo.find_branch(&self.branch, BranchType::Local) else {
+            return Ok(None);
+        };
+        let tip = branch
+            .get()
+            .peel_to_commit()
+            .map_err(|e| format!("Failed to read branch {}: {}", self.branch, e))?
+            .id();
+        let head = repo
+            .head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| format!("Failed to read HEAD: {}", e))?
+            .id();
+        let merged = tip.to_string() == self.base || tip == head || repo.graph_descendant_of(head, tip).unwrap_or(false);
+        Ok((!merged).then(|| format!("branch {} has commits that are not merged", self.branch)))
+    }
+
+    /// Delete the worktree directory, its git metadata and the branch.
+    /// Parts that are already gone are skipped. Work that was not merged is
+    /// lost; check [`Self::unsaved_work`] unless the user chose to discard.
+    pub fn remove(&self) -> Result<(), String> {
+        let repo = open(&self.repo_path)?;
+        match repo.find_worktree(&worktree_name(&self.session_id)) {
+            Ok(worktree) => worktree
+                .prune(Some(WorktreePruneOptions::new().valid(true).locked(true).working_tree(true)))
+                .map_err(|e| format!("Failed to remove worktree: {}", e))?,
+            Err(_) if self.path.exists() => std::fs::remove_dir_all(&self.path)
+                .map_err(|e| format!("Failed to remove {}: {}", self.path.display(), e))?,
+            Err(_) => {}
+        }
+
+        if let Ok(mut branch) = repo.find_branch(&self.branch, BranchType::Local) {
+            branch.delete().map_err(|e| format!("Failed to delete branch {}: {}", self.branch, e))?;
+        }
+        Ok(())
+    }
+
+    /// Commit uncommitted changes in the worktree to its branch
+    fn commit_pending(&self, message: &str) -> Result<(), String> {
+        let repo = open(&self.path)?;
+        let mut index = repo.index().map_err(|e| format!("Failed to read index: {}", e))?;
+        index
+            .add_all(["*"], IndexAddOption::DEFAULT, None)
+            .and_then(|_| index.update_all(["*"], None))
+            .map_err(|e| format!("Failed to stage worktree changes: {}", e))?;
+        index.write().map_err(|e| format!("Failed to write index: {}", e))?;
+        let tree = index.write_tree().map_err(|e| format!("Failed to write tree: {}", e))?;
+
+        let head = repo
+            .head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| format!("Failed to read worktree HEAD: {}", e))?;
+        if head.tree_id() == tree {
+            return Ok(());
+        }
+        let tree = repo.find_tree(tree).map_err(|e| format!("Failed to find tree: {}", e))?;
+        let signature = signature(&repo)?;
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &[&head])
+            .map_err(|e| format!("Failed to commit worktree changes: {}", e))?;
+        Ok(())
+    }
+}
+
+fn open(path: &Path) -> Result<Repository, String> {
+    Repository::open(path).map_err(|e| format!("Failed to open git repository at {}: {}", path.display(), e))
+}
+
+/// Name of the worktree's metadata under `.git/worktrees`
+fn worktree_name(session_id: &str) -> String {
+    format!("anvil-{}", &session_id[..session_id.len().min(8)])
+}
+
+fn signature(repo: &Repository) -> Result<Signature<'static>, String> {
+    repo.signature()
+        .or_else(|_| Signature::now("Anvil Agent", "agent@anvil.local"))
+        .map_err(|e| format!("Failed to create signature: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn commit_file(repo: &Repository, name: &str, content: &str, message: &str) {
+        let workdir = repo.workdir().unwrap().to_path_buf();
+        fs::write(workdir.join(name), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents).unwrap();
+    }
+
+    #[test]
+    fn test_merge_back_fast_forwards_and_merges() {
+        let temp = tempfile::tempdir().unwrap();
+        let repo_path = temp.path().join("repo");
+        let repo = Repository::init(&repo_path).unwrap();
+        commit_file(&repo, "a.txt", "one\n", "initial");
+
+        let first = SessionWorktree::create(&repo_path, "11111111-aaaa", &temp.path().join("wt")).unwrap();
+        assert_eq!(first.branch, "anvil/11111111");
+        assert_eq!(fs::read_to_string(first.path.join("a.txt")).unwrap(), "one\n");
+        fs::write(first.path.join("a.txt"), "two\n").unwrap();
+
+        // Another session edits a different file meanwhile
+        let second = SessionWorktree::create(&repo_path, "22222222-bbbb", &temp.path().join("wt")).unwrap();
+        fs::write(second.path.join("b.txt"), "b\n").unwrap();
+        assert_eq!(fs::read_to_string(repo_path.join("a.txt")).unwrap(), "one\n");
+
+        let outcome = first.merge_back("Change a").unwrap();
+        assert!(outcome.fast_forward);
+        assert_eq!(outcome.files, vec!["a.txt"]);
+        assert_eq!(fs::read_to_string(repo_path.join("a.txt")).unwrap(), "two\n");
+        first.remove().unwrap();
+        assert!(!first.path.exists());
+        assert!(repo.find_branch("anvil/11111111", BranchType::Local).is_err());
+
+        let outcome = second.merge_back("Add b").unwrap();
+        assert!(!outcome.fast_forward);
+        assert_eq!(fs::read_to_string(repo_path.join("b.txt")).unwrap(), "b\n");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.parent_count(), 2);
+        assert!(repo.statuses(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_conflicting_merge_changes_nothing() {
+        let temp = tempfile::tempdir().unwrap();
+        let repo_path = temp.path().join("repo");
+        let repo = Repository::init(&repo_path).unwrap();
+        commit_file(&repo, "a.txt", "one\n", "initial");
+
+        let worktree = SessionWorktree::create(&repo_path, "33333333-cccc", &temp.path().join("wt")).unwrap();
+        fs::write(worktree.path.join("a.txt"), "session\n").unwrap();
+        commit_file(&repo, "a.txt", "main\n", "main change");
+        let head = repo.head().unwrap().target().unwrap();
+
+        let err = worktree.merge_back("Change a").unwrap_err();
+        assert!(err.contains("conflicts in a.txt"));
+        assert_eq!(repo.head().unwrap().target().unwrap(), head);
+        assert_eq!(fs::read_to_string(repo_path.join("a.txt")).unwrap(), "main\n");
+
+        worktree.remove().unwrap();
+        worktree.remove().unwrap();
+        assert!(!worktree.path.exists());
+    }
+
+    #[test]
+    fn test_unsaved_work_until_merged() {
+        let temp = tempfile::tempdir().unwrap();
+        let repo_path = temp.path().join("repo");
+        let repo = Repository::init(&repo_path).unwrap();
+        commit_file(&repo, "a.txt", "one\n", "initial");
+
+        let worktree = SessionWorktree::create(&repo_path, "44444444-dddd", &temp.path().join("wt")).unwrap();
+        assert_eq!(worktree.unsaved_work().unwrap(), None);
+
+        fs::write(worktree.path.join("new.txt"), "new\n").unwrap();
+        assert!(worktree.unsaved_work().unwrap().unwrap().contains("uncommitted"));
+
+        worktree.commit_pending("Add new").unwrap();
+        assert!(worktree.unsaved_work().unwrap().unwrap().contains("not merged"));
+
+        worktree.merge_back("Add new").unwrap();
+        assert_eq!(worktree.unsaved_work().unwrap(), None);
+        worktree.remove().unwrap();
+        assert_eq!(worktree.unsaved_work().unwrap(), None);
+    }
+}
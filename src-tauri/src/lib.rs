@@ -7,7 +7,10 @@ pub mod terminal;
 pub mod storage;
 pub mod config;
 pub mod mcp;
+pub mod lsp;
 pub mod workflows;
+pub mod session;
+pub mod cli;
 
 use app_state::AppState;
 use tauri::Manager;
@@ -31,14 +34,19 @@ pub fn run() {
             if let Err(e) = app.state::<AppState>().init_storage(db_path.to_str().expect("Invalid UTF-8 path")) {
                 eprintln!("Failed to initialize storage: {}", e);
             }
+            app.state::<AppState>().clean_orphaned_worktrees();
             
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
         greet, 
         commands::create_session, 
+        commands::list_agents,
         commands::chat, 
         commands::stream_chat,
+        commands::steer_session,
+        commands::execute_plan,
+        commands::get_plan,
         commands::read_file,
         commands::spawn_terminal,
         commands::write_terminal,
@@ -48,6 +56,21 @@ pub fn run() {
         commands::save_session,
         commands::load_session,
         commands::list_sessions,
+        commands::list_subagent_sessions,
+        commands::list_checkpoints,
+        commands::restore_checkpoint,
+        commands::undo_turn,
+        commands::list_shadow_turns,
+        commands::shadow_turn_diff,
+        commands::cherry_pick_turn,
+        commands::squash_session,
+        commands::get_session_worktree,
+        commands::merge_session_worktree,
+        commands::discard_session_worktree,
+        commands::lsp_status,
+        commands::fork_session,
+        commands::regenerate_from,
+        commands::list_branches,
         commands::delete_session,
         commands::rename_session,
         commands::git_status_summary,
@@ -79,6 +102,13 @@ pub fn run() {
         commands::save_workflow,
         commands::delete_workflow
      ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Language servers are child processes; stop them before exiting
+            if let tauri::RunEvent::Exit = event {
+                let lsp = app.state::<AppState>().lsp.clone();
+                tauri::async_runtime::block_on(lsp.shutdown_all());
+            }
+        });
 }
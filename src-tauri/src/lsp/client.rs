@@ -0,0 +1,499 @@
+//! One running language server and the documents it has open
+//!
+//! A background task reads everything the server sends: responses are
+//! routed to the request waiting for them, pushed diagnostics are cached
+//! per document and requests from the server get a minimal answer so it
+//! does not stall.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{oneshot, Notify};
+use tokio::time::{timeout, Duration, Instant};
+use url::Url;
+
+use super::servers::ServerSpec;
+
+/// Indexing a large workspace can hold up the `initialize` response
+const INITIALIZE_TIMEOUT: Duration = Duration::from_secs(60);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Pending = Arc<Mutex<HashMap<i64, oneshot::Sender<Value>>>>;
+
+/// Latest diagnostics the server pushed for a document and how many
+/// pushes there have been, so callers can wait for a fresh one
+#[derive(Default)]
+struct DiagnosticsCache {
+    entries: HashMap<String, (u64, Value)>,
+}
+
+struct OpenDocument {
+    version: i64,
+    text: String,
+    /// Diagnostics generation when the server was last sent this document,
+    /// while no newer diagnostics have been asked for
+    awaiting_diagnostics: Option<u64>,
+}
+
+pub struct LspClient {
+    command: String,
+    root: PathBuf,
+    child: tokio::sync::Mutex<Child>,
+    writer: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Pending,
+    next_id: AtomicI64,
+    alive: Arc<AtomicBool>,
+    documents: tokio::sync::Mutex<HashMap<String, OpenDocument>>,
+    diagnostics: Arc<Mutex<DiagnosticsCache>>,
+    diagnostics_changed: Arc<Notify>,
+    capabilities: Value,
+}
+
+impl LspClient {
+    /// Spawn the server of `spec` in `root` and run the initialize handshake
+    pub async fn start(spec: &ServerSpec, root: &Path) -> Result<Self, String> {
+        let program = spec.installed_at().ok_or_else(|| format!("`{}` is not installed or not on PATH", spec.program))?;
+        let mut child = Command::new(program)
+            .args(&spec.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to start LSP server: {}", e))?;
+
+        let stdin = child.stdin.take().ok_or("Failed to open LSP stdin")?;
+        let stdout = child.stdout.take().ok_or("Failed to open LSP stdout")?;
+
+        let writer = Arc::new(tokio::sync::Mutex::new(stdin));
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+        let alive = Arc::new(AtomicBool::new(true));
+        let diagnostics = Arc::new(Mutex::new(DiagnosticsCache::default()));
+        let diagnostics_changed = Arc::new(Notify::new());
+
+        tokio::spawn(read_loop(
+            BufReader::new(stdout),
+            writer.clone(),
+            pending.clone(),
+            alive.clone(),
+            diagnostics.clone(),
+            diagnostics_changed.clone(),
+        ));
+
+        let mut client = Self {
+            command: spec.command_line(),
+            root: root.to_path_buf(),
+            child: tokio::sync::Mutex::new(child),
+            writer,
+            pending,
+            next_id: AtomicI64::new(1),
+            alive,
+            documents: tokio::sync::Mutex::new(HashMap::new()),
+            diagnostics,
+            diagnostics_changed,
+            capabilities: Value::Null,
+        };
+        client.capabilities = client.initialize(spec.initialization_options.as_ref()).await?;
+        Ok(client)
+    }
+
+    async fn initialize(&self, initialization_options: Option<&Value>) -> Result<Value, String> {
+        let workspace_uri = path_to_uri(&self.root)?;
+        let workspace_name = self
+            .root
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("workspace")
+            .to_string();
+
+        let result = self
+            .request(
+                "initialize",
+                json!({
+                    "processId": std::process::id(),
+                    "rootUri": workspace_uri,
+                    "rootPath": self.root.to_string_lossy(),
+                    "workspaceFolders": [{
+                        "uri": workspace_uri,
+                        "name": workspace_name,
+                    }],
+                    "initializationOptions": initialization_options,
+                    "capabilities": {
+                        "textDocument": {
+                            "synchronization": { "dynamicRegistration": false, "didSave": false },
+                            "definition": { "dynamicRegistration": false },
+                            "references": { "dynamicRegistration": false },
+                            "implementation": { "dynamicRegistration": false },
+                            "hover": { "contentFormat": ["markdown", "plaintext"] },
+                            "documentSymbol": { "hierarchicalDocumentSymbolSupport": true },
+                            "callHierarchy": { "dynamicRegistration": false },
+                            "rename": { "prepareSupport": false },
+                            "formatting": { "dynamicRegistration": false },
+                            "codeAction": {
+                                "codeActionLiteralSupport": {
+                                    "codeActionKind": {
+                                        "valueSet": ["", "quickfix", "refactor", "refactor.extract", "refactor.inline", "refactor.rewrite", "source", "source.organizeImports"]
+                                    }
+                                },
+                                "resolveSupport": { "properties": ["edit"] },
+                                "dataSupport": true
+                            },
+                            "publishDiagnostics": { "relatedInformation": true, "versionSupport": true }
+                        },
+                        "workspace": {
+                            "configuration": true,
+                            "workspaceFolders": true,
+                            "symbol": { "dynamicRegistration": false },
+                            "workspaceEdit": {
+                                "documentChanges": true,
+                                "resourceOperations": ["create", "rename", "delete"]
+                            }
+                        }
+                    }
+                }),
+                INITIALIZE_TIMEOUT,
+            )
+            .await?;
+        self.notify("initialized", json!({})).await?;
+        Ok(result.get("capabilities").cloned().unwrap_or(Value::Null))
+    }
+
+    /// Server command line this client was started with
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// What the server announced it supports in its initialize response
+    pub fn capabilities(&self) -> &Value {
+        &self.capabilities
+    }
+
+    /// False once the server closed its output or was shut down
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    pub async fn request(&self, method: &str, params: Value, wait: Duration) -> Result<Value, String> {
+        if !self.is_alive() {
+            return Err("LSP server is not running".to_string());
+        }
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = oneshot::channel();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(id, sender);
+        }
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(e) = write_message(&mut *self.writer.lock().await, &message).await {
+            self.forget(id);
+            return Err(e);
+        }
+
+        let response = match timeout(wait, receiver).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => return Err("LSP server closed the connection".to_string()),
+            Err(_) => {
+                self.forget(id);
+                return Err(format!("Timed out waiting for LSP response to {}", method));
+            }
+        };
+
+        if let Some(error) = response.get("error") {
+            return Err(format!("LSP error: {}", error));
+        }
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+
+    pub async fn notify(&self, method: &str, params: Value) -> Result<(), String> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        write_message(&mut *self.writer.lock().await, &message).await
+    }
+
+    fn forget(&self, id: i64) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(&id);
+        }
+    }
+
+    /// Bring the server's copy of `path` in line with the file on disk.
+    /// Documents not yet open are opened as `language_id`; with `None`
+    /// only documents the server already has are updated. Returns whether
+    /// the server was sent anything.
+    pub async fn sync_document(&self, path: &Path, language_id: Option<&str>) -> Result<bool, String> {
+        let uri = path_to_uri(path)?;
+        let mut documents = self.documents.lock().await;
+
+        let text = match tokio::fs::read_to_string(path).await {
+            Ok(text) => text,
+            Err(e) => {
+                if documents.remove(&uri).is_some() {
+                    self.notify("textDocument/didClose", json!({ "textDocument": { "uri": uri } })).await?;
+                    return Ok(true);
+                }
+                if language_id.is_none() {
+                    return Ok(false);
+                }
+                return Err(format!("Failed to read file: {}", e));
+            }
+        };
+
+        match documents.get_mut(&uri) {
+            Some(document) if document.text == text => Ok(false),
+            Some(document) => {
+                document.version += 1;
+                self.notify(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": uri, "version": document.version },
+                        "contentChanges": [{ "text": text }]
+                    }),
+                )
+                .await?;
+                document.text = text;
+                document.awaiting_diagnostics = Some(self.diagnostics_generation(&uri));
+                Ok(true)
+            }
+            None => {
+                let Some(language_id) = language_id else {
+                    return Ok(false);
+                };
+                self.notify(
+                    "textDocument/didOpen",
+                    json!({
+                        "textDocument": {
+                            "uri": uri,
+                            "languageId": language_id,
+                            "version": 1,
+                            "text": text
+                        }
+                    }),
+                )
+                .await?;
+                let awaiting_diagnostics = Some(self.diagnostics_generation(&uri));
+                documents.insert(uri, OpenDocument { version: 1, text, awaiting_diagnostics });
+                Ok(true)
+            }
+        }
+    }
+
+    /// Diagnostics for `path` after syncing it. When the server has not
+    /// published any since it last received the document, waits up to
+    /// `wait` for a fresh set.
+    pub async fn diagnostics(&self, path: &Path, language_id: &str, wait: Duration) -> Result<Value, String> {
+        let uri = path_to_uri(path)?;
+        self.sync_document(path, Some(language_id)).await?;
+        let awaiting = self
+            .documents
+            .lock()
+            .await
+            .get_mut(&uri)
+            .and_then(|document| document.awaiting_diagnostics.take());
+
+        if let Some(baseline) = awaiting {
+            let deadline = Instant::now() + wait;
+            loop {
+                let notified = self.diagnostics_changed.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                if self.diagnostics_generation(&uri) > baseline || !self.is_alive() {
+                    break;
+                }
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() || timeout(remaining, notified).await.is_err() {
+                    break;
+                }
+            }
+        }
+
+        let cache = self.diagnostics.lock().map_err(|e| e.to_string())?;
+        Ok(cache.entries.get(&uri).map(|(_, value)| value.clone()).unwrap_or(json!([])))
+    }
+
+    /// Number of diagnostics pushes for `uri` so far
+    fn diagnostics_generation(&self, uri: &str) -> u64 {
+        self.diagnostics
+            .lock()
+            .ok()
+            .and_then(|cache| cache.entries.get(uri).map(|(generation, _)| *generation))
+            .unwrap_or(0)
+    }
+
+    /// Ask the server to exit, then make sure the process is gone
+    pub async fn shutdown(&self) {
+        if self.is_alive() {
+            let _ = self.request("shutdown", Value::Null, SHUTDOWN_TIMEOUT).await;
+            let _ = self.notify("exit", Value::Null).await;
+        }
+        self.alive.store(false, Ordering::SeqCst);
+        let mut child = self.child.lock().await;
+        if timeout(SHUTDOWN_TIMEOUT, child.wait()).await.is_err() {
+            let _ = child.kill().await;
+        }
+    }
+}
+
+async fn read_loop<R: AsyncBufRead + Unpin>(
+    mut reader: R,
+    writer: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Pending,
+    alive: Arc<AtomicBool>,
+    diagnostics: Arc<Mutex<DiagnosticsCache>>,
+    diagnostics_changed: Arc<Notify>,
+) {
+    while let Ok(message) = read_message(&mut reader).await {
+        let method = message.get("method").and_then(|m| m.as_str());
+        let id = message.get("id").cloned();
+
+        match (method, id) {
+            (Some(method), Some(id)) => {
+                let reply = json!({ "jsonrpc": "2.0", "id": id, "result": server_request_reply(method, &message) });
+                let _ = write_message(&mut *writer.lock().await, &reply).await;
+            }
+            (Some("textDocument/publishDiagnostics"), None) => {
+                let params = message.get("params").cloned().unwrap_or(Value::Null);
+                if let (Some(uri), Ok(mut cache)) = (params.get("uri").and_then(|u| u.as_str()), diagnostics.lock()) {
+                    let generation = cache.entries.get(uri).map(|(g, _)| g + 1).unwrap_or(1);
+                    let value = params.get("diagnostics").cloned().unwrap_or(json!([]));
+                    cache.entries.insert(uri.to_string(), (generation, value));
+                }
+                diagnostics_changed.notify_waiters();
+            }
+            (None, Some(id)) => {
+                let sender = id
+                    .as_i64()
+                    .and_then(|id| pending.lock().ok().and_then(|mut pending| pending.remove(&id)));
+                if let Some(sender) = sender {
+                    let _ = sender.send(message);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    alive.store(false, Ordering::SeqCst);
+    // Dropping the senders fails every request still waiting
+    if let Ok(mut pending) = pending.lock() {
+        pending.clear();
+    }
+    diagnostics_changed.notify_waiters();
+}
+
+/// Answer for a request the server sends us. `workspace/configuration`
+/// wants one entry per item; everything else is acknowledged with null.
+fn server_request_reply(method: &str, message: &Value) -> Value {
+    match method {
+        "workspace/configuration" => {
+            let items = message
+                .pointer("/params/items")
+                .and_then(|items| items.as_array())
+                .map(|items| items.len())
+                .unwrap_or(0);
+            Value::Array(vec![Value::Null; items])
+        }
+        _ => Value::Null,
+    }
+}
+
+pub fn path_to_uri(path: &Path) -> Result<String, String> {
+    let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    Url::from_file_path(canonical)
+        .map(|url| url.to_string())
+        .map_err(|_| "Failed to convert file path to URI".to_string())
+}
+
+pub fn infer_language_id(path: &Path) -> Option<&'static str> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("rs") => Some("rust"),
+        Some("ts") => Some("typescript"),
+        Some("tsx") => Some("typescriptreact"),
+        Some("js") => Some("javascript"),
+        Some("jsx") => Some("javascriptreact"),
+        Some("py") => Some("python"),
+        Some("go") => Some("go"),
+        Some("java") => Some("java"),
+        Some("c") => Some("c"),
+        Some("h") => Some("c"),
+        Some("cpp") => Some("cpp"),
+        Some("hpp") => Some("cpp"),
+        Some("json") => Some("json"),
+        Some("yaml") | Some("yml") => Some("yaml"),
+        Some("md") => Some("markdown"),
+        _ => None,
+    }
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, value: &Value) -> Result<(), String> {
+    let payload = value.to_string();
+    let header = format!("Content-Length: {}\r\n\r\n", payload.len());
+    writer.write_all(header.as_bytes()).await.map_err(|e| e.to_string())?;
+    writer.write_all(payload.as_bytes()).await.map_err(|e| e.to_string())?;
+    writer.flush().await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Value, String> {
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut line = String::new();
+        let bytes = reader.read_line(&mut line).await.map_err(|e| e.to_string())?;
+        if bytes == 0 {
+            return Err("LSP server closed the connection".to_string());
+        }
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some(value) = trimmed.strip_prefix("Content-Length:") {
+            content_length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = content_length.ok_or("Missing Content-Length header from LSP server".to_string())?;
+    let mut buffer = vec![0u8; length];
+    reader.read_exact(&mut buffer).await.map_err(|e| e.to_string())?;
+    serde_json::from_slice(&buffer).map_err(|e| format!("Invalid LSP JSON: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{infer_language_id, read_message, server_request_reply, write_message};
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    #[test]
+    fn infer_language_from_extension() {
+        assert_eq!(infer_language_id(&PathBuf::from("main.rs")), Some("rust"));
+        assert_eq!(infer_language_id(&PathBuf::from("app.tsx")), Some("typescriptreact"));
+        assert_eq!(infer_language_id(&PathBuf::from("script.py")), Some("python"));
+        assert_eq!(infer_language_id(&PathBuf::from("README.md")), Some("markdown"));
+    }
+
+    #[tokio::test]
+    async fn messages_round_trip_through_framing() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &json!({ "id": 1, "result": "é" })).await.unwrap();
+        write_message(&mut buffer, &json!({ "method": "exit" })).await.unwrap();
+
+        let mut reader = tokio::io::BufReader::new(buffer.as_slice());
+        assert_eq!(read_message(&mut reader).await.unwrap(), json!({ "id": 1, "result": "é" }));
+        assert_eq!(read_message(&mut reader).await.unwrap(), json!({ "method": "exit" }));
+        assert!(read_message(&mut reader).await.unwrap_err().contains("closed"));
+    }
+
+    #[test]
+    fn configuration_requests_get_one_entry_per_item() {
+        let request = json!({ "params": { "items": [{ "section": "a" }, { "section": "b" }] } });
+        assert_eq!(server_request_reply("workspace/configuration", &request), json!([null, null]));
+        assert_eq!(server_request_reply("client/registerCapability", &request), json!(null));
+    }
+}
@@ -0,0 +1,189 @@
+//! Turning `TextEdit`s and `WorkspaceEdit`s from a server into file
+//! contents. Positions count UTF-16 code units, as LSP does by default.
+
+use serde_json::Value;
+use std::path::PathBuf;
+use url::Url;
+
+/// One step of a `WorkspaceEdit`, in the order the server gave them
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileChange {
+    Edit { path: PathBuf, edits: Vec<Value> },
+    Create { path: PathBuf, overwrite: bool },
+    Rename { from: PathBuf, to: PathBuf, overwrite: bool },
+    Delete { path: PathBuf },
+}
+
+/// Flatten a `WorkspaceEdit`. `documentChanges` wins over `changes` when
+/// a server sends both, as the spec says.
+pub fn workspace_edit_changes(edit: &Value) -> Result<Vec<FileChange>, String> {
+    if let Some(document_changes) = edit.get("documentChanges").and_then(|c| c.as_array()) {
+        return document_changes.iter().map(document_change).collect();
+    }
+
+    let mut changes = Vec::new();
+    if let Some(map) = edit.get("changes").and_then(|c| c.as_object()) {
+        for (uri, edits) in map {
+            changes.push(FileChange::Edit {
+                path: uri_to_path(uri)?,
+                edits: edits.as_array().cloned().unwrap_or_default(),
+            });
+        }
+    }
+    Ok(changes)
+}
+
+fn document_change(change: &Value) -> Result<FileChange, String> {
+    let overwrite = change.pointer("/options/overwrite").and_then(|v| v.as_bool()).unwrap_or(false);
+    let uri = |key: &str| {
+        change
+            .get(key)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| format!("Workspace edit is missing '{}'", key))
+            .and_then(uri_to_path)
+    };
+
+    match change.get("kind").and_then(|k| k.as_str()) {
+        Some("create") => Ok(FileChange::Create { path: uri("uri")?, overwrite }),
+        Some("rename") => Ok(FileChange::Rename { from: uri("oldUri")?, to: uri("newUri")?, overwrite }),
+        Some("delete") => Ok(FileChange::Delete { path: uri("uri")? }),
+        Some(other) => Err(format!("Unsupported workspace edit operation: {}", other)),
+        None => {
+            let path = change
+                .pointer("/textDocument/uri")
+                .and_then(|v| v.as_str())
+                .ok_or("Workspace edit is missing 'textDocument.uri'")
+                .map_err(String::from)
+                .and_then(uri_to_path)?;
+            let edits = change.get("edits").and_then(|e| e.as_array()).cloned().unwrap_or_default();
+            Ok(FileChange::Edit { path, edits })
+        }
+    }
+}
+
+pub fn uri_to_path(uri: &str) -> Result<PathBuf, String> {
+    Url::parse(uri)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| format!("Not a file URI: {}", uri))
+}
+
+/// Apply `TextEdit`s, all given against the original `text`
+pub fn apply_text_edits(text: &str, edits: &[Value]) -> Result<String, String> {
+    let mut spans = Vec::with_capacity(edits.len());
+    for (index, edit) in edits.iter().enumerate() {
+        let start = position_offset(text, edit.pointer("/range/start"))?;
+        let end = position_offset(text, edit.pointer("/range/end"))?;
+        if end < start {
+            return Err("Text edit range ends before it starts".to_string());
+        }
+        let new_text = edit.get("newText").and_then(|t| t.as_str()).unwrap_or("");
+        spans.push((start, end, index, new_text));
+    }
+
+    // Back to front so earlier offsets stay valid; edits inserting at the
+    // same position keep their order
+    spans.sort_by_key(|span| std::cmp::Reverse((span.0, span.2)));
+    for pair in spans.windows(2) {
+        if pair[1].1 > pair[0].0 {
+            return Err("Text edits overlap".to_string());
+        }
+    }
+
+    let mut result = text.to_string();
+    for (start, end, _, new_text) in spans {
+        result.replace_range(start..end, new_text);
+    }
+    Ok(result)
+}
+
+fn position_offset(text: &str, position: Option<&Value>) -> Result<usize, String> {
+    let position = position.ok_or("Text edit is missing its range")?;
+    let line = position.get("line").and_then(|v| v.as_u64()).ok_or("Position is missing 'line'")?;
+    let character = position.get("character").and_then(|v| v.as_u64()).unwrap_or(0);
+    Ok(offset_at(text, line as usize, character as usize))
+}
+
+/// Byte offset of a line and UTF-16 column, clamped to the end of the line
+/// or of the text
+pub fn offset_at(text: &str, line: usize, character: usize) -> usize {
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (offset, ch) in text[line_start..].char_indices() {
+        if units >= character || ch == '\n' {
+            return line_start + offset;
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_text_edits, offset_at, workspace_edit_changes, FileChange};
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn edit(start: (u64, u64), end: (u64, u64), text: &str) -> serde_json::Value {
+        json!({
+            "range": {
+                "start": { "line": start.0, "character": start.1 },
+                "end": { "line": end.0, "character": end.1 }
+            },
+            "newText": text
+        })
+    }
+
+    #[test]
+    fn offsets_count_utf16_units() {
+        let text = "a😀b\nsecond";
+        assert_eq!(offset_at(text, 0, 3), "a😀".len());
+        assert_eq!(offset_at(text, 1, 2), "a😀b\nse".len());
+        assert_eq!(offset_at(text, 0, 99), "a😀b".len());
+        assert_eq!(offset_at(text, 5, 0), text.len());
+    }
+
+    #[test]
+    fn edits_apply_against_the_original_text() {
+        let text = "let foo = 1;\nprint(foo);\n";
+        let edits = vec![edit((0, 4), (0, 7), "bar"), edit((1, 6), (1, 9), "bar"), edit((0, 0), (0, 0), "// x\n")];
+        assert_eq!(apply_text_edits(text, &edits).unwrap(), "// x\nlet bar = 1;\nprint(bar);\n");
+    }
+
+    #[test]
+    fn inserts_at_one_position_keep_their_order() {
+        let edits = vec![edit((0, 1), (0, 1), "1"), edit((0, 1), (0, 1), "2")];
+        assert_eq!(apply_text_edits("ab", &edits).unwrap(), "a12b");
+    }
+
+    #[test]
+    fn overlapping_edits_are_rejected() {
+        let edits = vec![edit((0, 0), (0, 3), "x"), edit((0, 2), (0, 4), "y")];
+        assert!(apply_text_edits("abcdef", &edits).unwrap_err().contains("overlap"));
+    }
+
+    #[test]
+    fn document_changes_take_precedence() {
+        let workspace_edit = json!({
+            "changes": { "file:///tmp/ignored.rs": [] },
+            "documentChanges": [
+                { "textDocument": { "uri": "file:///tmp/a.rs", "version": 3 }, "edits": [edit((0, 0), (0, 1), "b")] },
+                { "kind": "rename", "oldUri": "file:///tmp/a.rs", "newUri": "file:///tmp/b.rs" }
+            ]
+        });
+        let changes = workspace_edit_changes(&workspace_edit).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(&changes[0], FileChange::Edit { path, edits } if path == &PathBuf::from("/tmp/a.rs") && edits.len() == 1));
+        assert_eq!(
+            changes[1],
+            FileChange::Rename { from: PathBuf::from("/tmp/a.rs"), to: PathBuf::from("/tmp/b.rs"), overwrite: false }
+        );
+    }
+}
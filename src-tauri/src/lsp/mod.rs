@@ -0,0 +1,15 @@
+//! Language servers shared by the sessions of a workspace
+//!
+//! Servers are started once per workspace and command and stay up between
+//! `lsp` tool calls, so slow indexers like rust-analyzer only index once.
+//! Which server handles a file comes from built-in defaults and the
+//! `lsp.languages` config.
+
+pub mod client;
+pub mod edits;
+pub mod pool;
+pub mod servers;
+
+pub use client::{infer_language_id, LspClient};
+pub use pool::LspPool;
+pub use servers::{resolve_servers, LspStatus, ServerSpec, ServerStatus};
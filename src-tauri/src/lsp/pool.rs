@@ -0,0 +1,244 @@
+//! Language servers kept running per workspace and server command
+//!
+//! Sessions register the workspace they work in; the servers started for
+//! a workspace are shut down when its last session is released. Nested
+//! workspaces, like a session worktree inside the repository, get servers
+//! of their own. A server that crashed is started again on next use, a few
+//! times at most.
+
+use super::client::LspClient;
+use super::servers::{ServerSpec, ServerStatus};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Crashes after which a server is no longer restarted for a workspace
+const MAX_RESTARTS: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ServerKey {
+    /// Workspace of the sessions using the server
+    workspace: PathBuf,
+    /// Project root the server runs in, inside the workspace
+    root: PathBuf,
+    command: String,
+}
+
+/// A server's place in the pool. The client is started outside the pool's
+/// lock, so other servers stay usable meanwhile; callers asking for the
+/// same server wait on the cell instead of starting a second one.
+#[derive(Default)]
+struct ServerSlot {
+    /// Set once the server is up; replaced by an empty cell after a crash
+    client: Arc<OnceCell<Arc<LspClient>>>,
+    restarts: u32,
+}
+
+#[derive(Default)]
+pub struct LspPool {
+    servers: tokio::sync::Mutex<HashMap<ServerKey, ServerSlot>>,
+    sessions: Mutex<HashMap<PathBuf, HashSet<String>>>,
+}
+
+impl LspPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Running server of `spec` in `root` for the sessions of `workspace`,
+    /// started on first use
+    pub async fn client(&self, workspace: &Path, root: &Path, spec: &ServerSpec) -> Result<Arc<LspClient>, String> {
+        let command = spec.command_line();
+        let key = ServerKey { workspace: workspace.to_path_buf(), root: root.to_path_buf(), command: command.clone() };
+        let cell = {
+            let mut servers = self.servers.lock().await;
+            let slot = servers.entry(key.clone()).or_default();
+
+            if let Some(client) = slot.client.get() {
+                if client.is_alive() {
+                    return Ok(client.clone());
+                }
+                slot.client = Arc::default();
+                slot.restarts += 1;
+                if slot.restarts <= MAX_RESTARTS {
+                    eprintln!("Warning: LSP server `{}` exited; restarting", command);
+                }
+            }
+            if slot.restarts > MAX_RESTARTS {
+                return Err(format!(
+                    "LSP server `{}` crashed {} times; not restarting it for this workspace",
+                    command, MAX_RESTARTS
+                ));
+            }
+            slot.client.clone()
+        };
+
+        let client = cell
+            .get_or_try_init(|| async { LspClient::start(spec, root).await.map(Arc::new) })
+            .await?
+            .clone();
+
+        // The workspace may have been released while the server started
+        let kept = matches!(self.servers.lock().await.get(&key), Some(slot) if Arc::ptr_eq(&slot.client, &cell));
+        if !kept {
+            client.shutdown().await;
+            return Err(format!("LSP server `{}` was shut down while it started", command));
+        }
+        Ok(client)
+    }
+
+    /// Tell the servers of `workspace` about files changed on disk. Only
+    /// documents a server has open are sent.
+    pub async fn did_change(&self, workspace: &Path, paths: &[PathBuf]) {
+        for client in self.clients_of(workspace).await {
+            for path in paths {
+                if let Err(e) = client.sync_document(path, None).await {
+                    eprintln!("Warning: failed to sync {} with LSP server: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    /// Keep the servers of `root` running while `session_id` is open
+    pub fn register_session(&self, session_id: &str, root: &Path) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.entry(root.to_path_buf()).or_default().insert(session_id.to_string());
+        }
+    }
+
+    /// Forget `session_id` and shut down the servers of workspaces that
+    /// no open session uses any more
+    pub async fn release_session(&self, session_id: &str) {
+        let idle: Vec<PathBuf> = match self.sessions.lock() {
+            Ok(mut sessions) => {
+                for ids in sessions.values_mut() {
+                    ids.remove(session_id);
+                }
+                let idle = sessions
+                    .iter()
+                    .filter(|(_, ids)| ids.is_empty())
+                    .map(|(root, _)| root.clone())
+                    .collect();
+                sessions.retain(|_, ids| !ids.is_empty());
+                idle
+            }
+            Err(_) => return,
+        };
+
+        for workspace in idle {
+            self.shutdown_where(|key| key.workspace == workspace).await;
+        }
+    }
+
+    /// Installation and running state of `specs` in and below `root`
+    pub async fn status(&self, root: &Path, specs: &[ServerSpec]) -> Vec<ServerStatus> {
+        let servers = self.servers.lock().await;
+        specs
+            .iter()
+            .map(|spec| {
+                let command = spec.command_line();
+                let running = servers
+                    .iter()
+                    .filter(|(key, slot)| {
+                        key.command == command
+                            && key.root.starts_with(root)
+                            && slot.client.get().is_some_and(|client| client.is_alive())
+                    })
+                    .map(|(key, _)| key.root.to_string_lossy().to_string())
+                    .collect();
+                ServerStatus {
+                    language: spec.language.clone(),
+                    command,
+                    extensions: spec.extensions.clone(),
+                    installed_at: spec.installed_at().map(|path| path.to_string_lossy().to_string()),
+                    running,
+                }
+            })
+            .collect()
+    }
+
+    pub async fn shutdown_all(&self) {
+        self.shutdown_where(|_| true).await;
+    }
+
+    async fn shutdown_where(&self, matches: impl Fn(&ServerKey) -> bool) {
+        let stopped: Vec<ServerSlot> = {
+            let mut servers = self.servers.lock().await;
+            let keys: Vec<ServerKey> = servers.keys().filter(|key| matches(key)).cloned().collect();
+            keys.iter().filter_map(|key| servers.remove(key)).collect()
+        };
+        for client in stopped.iter().filter_map(|slot| slot.client.get()) {
+            client.shutdown().await;
+        }
+    }
+
+    async fn clients_of(&self, workspace: &Path) -> Vec<Arc<LspClient>> {
+        self.servers
+            .lock()
+            .await
+            .iter()
+            .filter(|(key, _)| key.workspace == workspace)
+            .filter_map(|(_, slot)| slot.client.get().cloned())
+            .filter(|client| client.is_alive())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn add_server(pool: &LspPool, workspace: &str, root: &str) {
+        let key = ServerKey { workspace: workspace.into(), root: root.into(), command: "ls".to_string() };
+        pool.servers.lock().await.insert(key, ServerSlot::default());
+    }
+
+    async fn workspaces(pool: &LspPool) -> Vec<PathBuf> {
+        let mut workspaces: Vec<PathBuf> = pool.servers.lock().await.keys().map(|key| key.workspace.clone()).collect();
+        workspaces.sort();
+        workspaces
+    }
+
+    #[tokio::test]
+    async fn releasing_a_workspace_keeps_nested_workspaces_running() {
+        let pool = LspPool::new();
+        pool.register_session("repo", Path::new("/repo"));
+        pool.register_session("worktree", Path::new("/repo/.worktrees/a"));
+        add_server(&pool, "/repo", "/repo/app").await;
+        add_server(&pool, "/repo/.worktrees/a", "/repo/.worktrees/a/app").await;
+
+        pool.release_session("repo").await;
+        assert_eq!(workspaces(&pool).await, vec![PathBuf::from("/repo/.worktrees/a")]);
+
+        pool.release_session("worktree").await;
+        assert!(workspaces(&pool).await.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn starting_a_server_does_not_block_the_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = Arc::new(LspPool::new());
+        // Never answers `initialize`
+        let silent = ServerSpec {
+            language: "silent".to_string(),
+            program: "sleep".to_string(),
+            args: vec!["30".to_string()],
+            initialization_options: None,
+            extensions: Vec::new(),
+            root_markers: Vec::new(),
+        };
+        let starting = {
+            let (pool, root, spec) = (pool.clone(), dir.path().to_path_buf(), silent.clone());
+            tokio::spawn(async move { pool.client(&root, &root, &spec).await.map(|_| ()) })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        let status = tokio::time::timeout(std::time::Duration::from_secs(1), pool.status(dir.path(), &[silent]))
+            .await
+            .expect("pool is locked while a server starts");
+        assert!(status[0].running.is_empty());
+        starting.abort();
+    }
+}
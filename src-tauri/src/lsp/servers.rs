@@ -0,0 +1,271 @@
+//! Which language server handles a file: built-in defaults for common
+//! languages, overridden or extended by `lsp.languages` in config
+
+use crate::config::{LspConfig, LspServerConfig};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// A language server resolved from config
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerSpec {
+    pub language: String,
+    pub program: String,
+    pub args: Vec<String>,
+    pub initialization_options: Option<Value>,
+    /// Empty for servers from `lsp.servers`, which take any file
+    pub extensions: Vec<String>,
+    pub root_markers: Vec<String>,
+}
+
+impl ServerSpec {
+    fn from_config(language: &str, config: &LspServerConfig) -> Self {
+        Self {
+            language: language.to_string(),
+            program: config.command.clone(),
+            args: config.args.clone(),
+            initialization_options: config.initialization_options.clone(),
+            extensions: config.extensions.clone(),
+            root_markers: config.root_markers.clone(),
+        }
+    }
+
+    /// Command line as shown to the user and used to tell servers apart
+    pub fn command_line(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn handles(&self, path: &Path) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext)))
+    }
+
+    /// Where the program is installed, if it can be found
+    pub fn installed_at(&self) -> Option<PathBuf> {
+        find_program(&self.program)
+    }
+
+    /// Directory the server for `file` runs in: the outermost directory
+    /// between the workspace and the file that holds a root marker, so one
+    /// server covers a whole Cargo or npm workspace
+    pub fn project_root(&self, file: &Path, workspace_root: &Path) -> PathBuf {
+        let mut root = workspace_root.to_path_buf();
+        for dir in file.ancestors().skip(1) {
+            if !dir.starts_with(workspace_root) {
+                break;
+            }
+            if self.root_markers.iter().any(|marker| dir.join(marker).exists()) {
+                root = dir.to_path_buf();
+            }
+        }
+        root
+    }
+}
+
+fn builtin(language: &str, command: &str, args: &[&str], extensions: &[&str], root_markers: &[&str]) -> (String, LspServerConfig) {
+    let strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+    (
+        language.to_string(),
+        LspServerConfig {
+            command: command.to_string(),
+            args: strings(args),
+            initialization_options: None,
+            extensions: strings(extensions),
+            root_markers: strings(root_markers),
+            enabled: None,
+        },
+    )
+}
+
+/// Servers known without any configuration
+pub fn builtin_servers() -> Vec<(String, LspServerConfig)> {
+    vec![
+        builtin("rust", "rust-analyzer", &[], &["rs"], &["Cargo.toml"]),
+        builtin(
+            "typescript",
+            "typescript-language-server",
+            &["--stdio"],
+            &["ts", "tsx", "js", "jsx", "mjs", "cjs", "mts", "cts"],
+            &["tsconfig.json", "jsconfig.json", "package.json"],
+        ),
+        builtin(
+            "python",
+            "pyright-langserver",
+            &["--stdio"],
+            &["py", "pyi"],
+            &["pyproject.toml", "pyrightconfig.json", "setup.py", "setup.cfg", "requirements.txt"],
+        ),
+        builtin("go", "gopls", &[], &["go"], &["go.work", "go.mod"]),
+    ]
+}
+
+/// Every enabled server: built-ins with configured languages applied, in
+/// name order, then the plain command lines from `lsp.servers`
+pub fn resolve_servers(config: Option<&LspConfig>) -> Vec<ServerSpec> {
+    let mut languages: std::collections::BTreeMap<String, LspServerConfig> = builtin_servers().into_iter().collect();
+    if let Some(config) = config {
+        for (language, server) in &config.languages {
+            match languages.get_mut(language) {
+                // Fields left out keep the built-in values
+                Some(existing) => {
+                    if !server.command.is_empty() {
+                        existing.command = server.command.clone();
+                        existing.args = server.args.clone();
+                    }
+                    if !server.extensions.is_empty() {
+                        existing.extensions = server.extensions.clone();
+                    }
+                    if !server.root_markers.is_empty() {
+                        existing.root_markers = server.root_markers.clone();
+                    }
+                    if server.initialization_options.is_some() {
+                        existing.initialization_options = server.initialization_options.clone();
+                    }
+                    existing.enabled = server.enabled;
+                }
+                None => {
+                    languages.insert(language.clone(), server.clone());
+                }
+            }
+        }
+    }
+
+    let mut specs: Vec<ServerSpec> = languages
+        .iter()
+        .filter(|(_, server)| server.enabled != Some(false) && !server.command.is_empty())
+        .map(|(language, server)| ServerSpec::from_config(language, server))
+        .collect();
+
+    let legacy = config.and_then(|c| c.servers.as_ref()).into_iter().flatten();
+    for command in legacy {
+        let mut parts = command.split_whitespace().map(String::from);
+        if let Some(program) = parts.next() {
+            specs.push(ServerSpec {
+                language: program.clone(),
+                program,
+                args: parts.collect(),
+                initialization_options: None,
+                extensions: Vec::new(),
+                root_markers: Vec::new(),
+            });
+        }
+    }
+    specs
+}
+
+/// Server for `path`: an installed one for its language if there is
+/// one, otherwise an error saying what to install or configure
+pub fn server_for<'a>(specs: &'a [ServerSpec], path: &Path) -> Result<&'a ServerSpec, String> {
+    let candidates: Vec<&ServerSpec> = specs.iter().filter(|spec| spec.handles(path)).collect();
+    if let Some(spec) = candidates.iter().find(|spec| spec.installed_at().is_some()) {
+        return Ok(spec);
+    }
+    match candidates.first() {
+        Some(spec) => Err(format!(
+            "The {} language server `{}` is not installed or not on PATH",
+            spec.language, spec.program
+        )),
+        None => Err(format!(
+            "No language server handles {}. Add one under lsp.languages in .anvil/anvil.json",
+            path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+        )),
+    }
+}
+
+/// `name` may be a language or a full command line
+pub fn server_named<'a>(specs: &'a [ServerSpec], name: &str) -> Option<&'a ServerSpec> {
+    specs
+        .iter()
+        .find(|spec| spec.language == name || spec.command_line() == name || spec.program == name)
+}
+
+/// Full path of `program`, searched on PATH unless it contains a path
+/// separator
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let candidate = Path::new(program);
+    if candidate.components().count() > 1 {
+        return candidate.is_file().then(|| candidate.to_path_buf());
+    }
+    let path = std::env::var_os("PATH")?;
+    let suffixes: &[&str] = if cfg!(windows) { &["", ".exe", ".cmd", ".bat"] } else { &[""] };
+    std::env::split_paths(&path)
+        .flat_map(|dir| suffixes.iter().map(move |suffix| dir.join(format!("{}{}", program, suffix))))
+        .find(|file| file.is_file())
+}
+
+/// Language servers of a workspace as `lsp_status` reports them
+#[derive(Debug, Clone, Serialize)]
+pub struct LspStatus {
+    pub enabled: bool,
+    pub servers: Vec<ServerStatus>,
+}
+
+/// What `lsp_status` reports for one server
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerStatus {
+    pub language: String,
+    pub command: String,
+    pub extensions: Vec<String>,
+    /// Where the program was found; `None` when it is not installed
+    pub installed_at: Option<String>,
+    /// Project roots the server is running in
+    pub running: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_servers, server_named, ServerSpec};
+    use crate::config::{LspConfig, LspServerConfig};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    #[test]
+    fn configured_languages_replace_and_disable_builtins() {
+        let mut languages = HashMap::new();
+        languages.insert(
+            "rust".to_string(),
+            LspServerConfig { command: "ra-multiplex".to_string(), ..Default::default() },
+        );
+        languages.insert("go".to_string(), LspServerConfig { enabled: Some(false), ..Default::default() });
+        let config = LspConfig { enabled: None, servers: Some(vec!["clangd --log=error".to_string()]), languages };
+
+        let specs = resolve_servers(Some(&config));
+        let names: Vec<&str> = specs.iter().map(|s| s.language.as_str()).collect();
+        assert_eq!(names, vec!["python", "rust", "typescript", "clangd"]);
+        let rust = server_named(&specs, "rust").unwrap();
+        assert_eq!(rust.program, "ra-multiplex");
+        assert_eq!(rust.root_markers, vec!["Cargo.toml"]);
+        assert!(rust.handles(Path::new("src/lib.rs")));
+        assert_eq!(server_named(&specs, "clangd --log=error").unwrap().args, vec!["--log=error"]);
+        assert!(specs.last().unwrap().handles(Path::new("main.c")));
+        assert!(!server_named(&specs, "typescript").unwrap().handles(Path::new("main.rs")));
+    }
+
+    #[test]
+    fn project_root_is_outermost_marker_inside_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path();
+        std::fs::create_dir_all(workspace.join("app/crates/core/src")).unwrap();
+        std::fs::write(workspace.join("app/Cargo.toml"), "").unwrap();
+        std::fs::write(workspace.join("app/crates/core/Cargo.toml"), "").unwrap();
+
+        let spec = ServerSpec {
+            language: "rust".to_string(),
+            program: "rust-analyzer".to_string(),
+            args: Vec::new(),
+            initialization_options: None,
+            extensions: vec!["rs".to_string()],
+            root_markers: vec!["Cargo.toml".to_string()],
+        };
+        let file = workspace.join("app/crates/core/src/lib.rs");
+        assert_eq!(spec.project_root(&file, workspace), workspace.join("app"));
+        assert_eq!(spec.project_root(&workspace.join("main.rs"), workspace), workspace.to_path_buf());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::adapters::tools::registry::ToolContext;
+use crate::adapters::tools::task::ParentModel;
+use crate::config::{AgentDefinition, AgentRegistry, ConfigManager};
+use crate::domain::agent::Agent;
+use crate::domain::checkpoint::CheckpointStore;
+use crate::domain::models::{AgentPermissions, AgentSession};
+use crate::domain::shadow::ShadowLog;
+use crate::domain::ports::InteractionProvider;
+use crate::domain::verify::Verifier;
+use crate::lsp::LspPool;
+use crate::storage::Storage;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// What an agent needs from its host: the desktop app or the CLI
+#[derive(Clone)]
+pub struct SessionHost {
+    /// Where events go and confirmations and questions are answered
+    pub interaction: Arc<dyn InteractionProvider>,
+    pub storage: Arc<Mutex<Option<Storage>>>,
+    /// Language servers, kept running while the workspace has sessions
+    pub lsp: Arc<LspPool>,
+}
+
+/// Build the agent for `session` with its tools and checkpoints.
+/// Permissions come from the workspace config with the agent definition's
+/// overrides applied.
+pub async fn build_agent(
+    host: &SessionHost,
+    mut session: AgentSession,
+    model: ParentModel,
+    definition: Option<AgentDefinition>,
+) -> Result<Agent, String> {
+    let path = session.workspace_path.clone();
+
+    let mut config_manager = ConfigManager::new();
+    let _ = config_manager.load(Some(&path));
+    let config = config_manager.config();
+
+    let permissions = match &definition {
+        Some(def) => def.effective_permissions(&config.permission)?,
+        None => config.permission.clone(),
+    };
+    let permission_manager = Arc::new(tokio::sync::Mutex::new(permissions.clone()));
+    session.permissions = AgentPermissions { config: permissions };
+
+    let checkpoints = Arc::new(CheckpointStore::open(&path, &session.id.to_string()));
+    let tool_context = ToolContext {
+        workspace_root: path.clone(),
+        interaction: host.interaction.clone(),
+        permission_manager: permission_manager.clone(),
+        lsp_config: config.lsp.clone(),
+        lsp: host.lsp.clone(),
+        checkpoints: Some(checkpoints.clone()),
+    };
+    let tools = tool_context
+        .agent_tools(session.id, host.storage.clone(), model.clone(), definition.as_ref())
+        .await;
+
+    let mut agent = Agent::new(
+        session,
+        model.adapter,
+        tools,
+        permission_manager,
+        host.interaction.clone(),
+    );
+    if let Some(def) = definition {
+        agent.set_definition(def);
+    }
+    agent.set_checkpoints(checkpoints);
+    if config.shadow_commits == Some(true) {
+        match ShadowLog::open(&path, &agent.session.id.to_string()) {
+            Ok(shadow) => agent.set_shadow(Arc::new(shadow)),
+            Err(e) => eprintln!("Warning: shadow commits disabled: {}", e),
+        }
+    }
+    agent.set_verifier(Verifier::from_config(
+        &path,
+        config.verify.as_ref(),
+        &agent.session.id.to_string(),
+        agent.permission_manager.clone(),
+        host.interaction.clone(),
+    ));
+    host.lsp.register_session(&agent.session.id.to_string(), &path);
+    agent.set_lsp(host.lsp.clone());
+
+    Ok(agent)
+}
+
+/// Agent definition a saved session was started as, if it still exists
+pub fn saved_definition(path: &Path, agent: Option<&str>) -> Option<AgentDefinition> {
+    let name = agent?;
+    let mut config_manager = ConfigManager::new();
+    let _ = config_manager.load(Some(path));
+    match AgentRegistry::find(path, config_manager.config(), name) {
+        Ok(def) => Some(def),
+        Err(e) => {
+            eprintln!("Warning: resuming session without its agent: {}", e);
+            None
+        }
+    }
+}
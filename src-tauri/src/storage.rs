@@ -1,13 +1,58 @@
 use crate::domain::models::*;
+use crate::domain::worktree::SessionWorktree;
 use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
 use serde::Serialize;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+/// workspace_path, model, mode, agent, parent_id, plan
+type SessionRow = (String, String, String, Option<String>, Option<String>, Option<String>);
+
 pub struct Storage {
     db: Connection,
 }
 
+/// A message as it is stored in the `messages` table
+struct MessageColumns {
+    role: String,
+    content: String,
+    tool_calls: String,
+    tool_call_id: Option<String>,
+    attachments: String,
+    synthetic: bool,
+}
+
+impl MessageColumns {
+    fn of(message: &Message) -> Self {
+        Self {
+            role: format!("{:?}", message.role),
+            content: message.content.clone().unwrap_or_default(),
+            tool_calls: message
+                .tool_calls
+                .as_ref()
+                .and_then(|t| serde_json::to_string(t).ok())
+                .unwrap_or_default(),
+            tool_call_id: message.tool_call_id.clone(),
+            attachments: message
+                .attachments
+                .as_ref()
+                .and_then(|a| serde_json::to_string(a).ok())
+                .unwrap_or_default(),
+            synthetic: message.synthetic,
+        }
+    }
+
+    /// Whether both are the same message; attachments are left out, they
+    /// never change without the rest
+    fn same_message(&self, other: &Self) -> bool {
+        self.role == other.role
+            && self.content == other.content
+            && self.tool_calls == other.tool_calls
+            && self.tool_call_id == other.tool_call_id
+            && self.synthetic == other.synthetic
+    }
+}
+
 impl Storage {
     pub fn new(db_path: &str) -> Result<Self, String> {
         let db_path = Path::new(db_path);
@@ -35,12 +80,12 @@ impl Storage {
         )
         .map_err(|e| e.to_string())?;
 
-        if let Err(e) = db.execute("ALTER TABLE sessions ADD COLUMN name TEXT", []) {
-            let message = e.to_string();
-            if !message.contains("duplicate column name") {
-                return Err(message);
-            }
-        }
+        add_column(&db, "sessions", "name TEXT")?;
+        add_column(&db, "sessions", "agent TEXT")?;
+        add_column(&db, "sessions", "parent_id TEXT")?;
+        add_column(&db, "sessions", "plan TEXT")?;
+        add_column(&db, "sessions", "forked_from TEXT")?;
+        add_column(&db, "sessions", "fork_index INTEGER")?;
 
         db.execute(
             "CREATE TABLE IF NOT EXISTS messages (
@@ -58,12 +103,23 @@ impl Storage {
         )
         .map_err(|e| e.to_string())?;
 
-        if let Err(e) = db.execute("ALTER TABLE messages ADD COLUMN attachments TEXT", []) {
-            let message = e.to_string();
-            if !message.contains("duplicate column name") {
-                return Err(message);
-            }
-        }
+        add_column(&db, "messages", "attachments TEXT")?;
+        add_column(&db, "messages", "synthetic INTEGER NOT NULL DEFAULT 0")?;
+
+        // Not tied to sessions by a foreign key: a worktree outlives its
+        // deleted session until it is cleaned up
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS worktrees (
+                session_id TEXT PRIMARY KEY,
+                repo_path TEXT NOT NULL,
+                path TEXT NOT NULL,
+                branch TEXT NOT NULL,
+                base TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            )",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
 
         // Create indexes for better performance
         db.execute(
@@ -85,61 +141,92 @@ impl Storage {
         let tx = self.db.unchecked_transaction().map_err(|e| e.to_string())?;
 
         tx.execute(
-            "INSERT INTO sessions (id, workspace_path, model, mode, created_at, name)
-             VALUES (?1, ?2, ?3, ?4, datetime('now'), (SELECT name FROM sessions WHERE id = ?1))
+            "INSERT INTO sessions (id, workspace_path, model, mode, created_at, name, agent, parent_id, plan)
+             VALUES (?1, ?2, ?3, ?4, datetime('now'), (SELECT name FROM sessions WHERE id = ?1), ?5, ?6, ?7)
              ON CONFLICT(id) DO UPDATE SET
                 workspace_path = excluded.workspace_path,
                 model = excluded.model,
                 mode = excluded.mode,
                 created_at = excluded.created_at,
-                name = COALESCE(sessions.name, excluded.name)",
+                name = COALESCE(sessions.name, excluded.name),
+                agent = excluded.agent,
+                parent_id = excluded.parent_id,
+                plan = excluded.plan",
             params![
                 session.id.to_string(),
                 session.workspace_path.to_string_lossy(),
                 session.model.0,
                 format!("{:?}", session.mode),
+                session.agent,
+                session.parent_id.map(|id| id.to_string()),
+                session.plan.as_ref().and_then(|p| serde_json::to_string(p).ok()),
             ],
         )
         .map_err(|e| e.to_string())?;
 
-        // Clear existing messages for this session
-        tx.execute(
-            "DELETE FROM messages WHERE session_id = ?1",
-            params![session.id.to_string()],
-        )
-        .map_err(|e| e.to_string())?;
-
-        // Insert all messages except System messages (they're reconstructed on replay)
-        for message in &session.messages {
-            // Skip System messages - they contain workspace state that's recreated on session replay
-            if matches!(message.role, Role::System) {
-                continue;
-            }
-
-            let tool_calls_json = message
-                .tool_calls
-                .as_ref()
-                .and_then(|t| serde_json::to_string(t).ok())
-                .unwrap_or_default();
+        // System messages hold workspace state that is rebuilt on replay
+        let messages: Vec<MessageColumns> = session
+            .messages
+            .iter()
+            .filter(|m| m.role != Role::System)
+            .map(MessageColumns::of)
+            .collect();
+
+        // Messages are appended, or cut back before a turn and continued,
+        // so rows up to the first difference are kept as they are
+        let stored: Vec<(i64, MessageColumns)> = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT id, role, content, tool_calls, tool_call_id, synthetic
+                     FROM messages WHERE session_id = ?1 ORDER BY id ASC",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map(params![session.id.to_string()], |row| {
+                    Ok((
+                        row.get(0)?,
+                        MessageColumns {
+                            role: row.get(1)?,
+                            content: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                            tool_calls: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                            tool_call_id: row.get::<_, Option<String>>(4)?.filter(|id| !id.is_empty()),
+                            attachments: String::new(),
+                            synthetic: row.get(5)?,
+                        },
+                    ))
+                })
+                .map_err(|e| e.to_string())?;
+            rows.collect::<SqliteResult<Vec<_>>>().map_err(|e| e.to_string())?
+        };
+        let kept = stored
+            .iter()
+            .zip(&messages)
+            .take_while(|((_, row), message)| row.same_message(message))
+            .count();
 
-            let attachments_json = message
-                .attachments
-                .as_ref()
-                .and_then(|a| serde_json::to_string(a).ok())
-                .unwrap_or_default();
+        if let Some((first_stale, _)) = stored.get(kept) {
+            tx.execute(
+                "DELETE FROM messages WHERE session_id = ?1 AND id >= ?2",
+                params![session.id.to_string(), first_stale],
+            )
+            .map_err(|e| e.to_string())?;
+        }
 
+        for message in &messages[kept..] {
             tx.execute(
-                "INSERT INTO messages (session_id, role, content, tool_calls, tool_call_id, attachments, timestamp)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, datetime('now'))",
+                "INSERT INTO messages (session_id, role, content, tool_calls, tool_call_id, attachments, synthetic, timestamp)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, datetime('now'))",
                 params![
                     session.id.to_string(),
-                    format!("{:?}", message.role),
-                    message.content.clone().unwrap_or_default(),
-                    tool_calls_json,
-                    message.tool_call_id.clone(),
-                    attachments_json,
+                    message.role,
+                    message.content,
+                    message.tool_calls,
+                    message.tool_call_id,
+                    message.attachments,
+                    message.synthetic,
                 ],
-            ).map_err(|e| e.to_string())?;
+            )
+            .map_err(|e| e.to_string())?;
         }
 
         tx.commit().map_err(|e| e.to_string())?;
@@ -147,17 +234,17 @@ impl Storage {
     }
 
     pub fn load_session(&self, session_id: &str) -> Result<AgentSession, String> {
-        let session_data: Option<(String, String, String)> = self
+        let session_data: Option<SessionRow> = self
             .db
             .query_row(
-                "SELECT workspace_path, model, mode FROM sessions WHERE id = ?1",
+                "SELECT workspace_path, model, mode, agent, parent_id, plan FROM sessions WHERE id = ?1",
                 params![session_id],
-                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
             )
             .optional()
             .map_err(|e: rusqlite::Error| e.to_string())?;
 
-        let (workspace_path, model, mode) = session_data.ok_or("Session not found")?;
+        let (workspace_path, model, mode, agent, parent_id, plan) = session_data.ok_or("Session not found")?;
 
         let uuid = Uuid::parse_str(session_id).map_err(|_| "Invalid session ID")?;
 
@@ -165,7 +252,7 @@ impl Storage {
             let mut stmt = self
                 .db
                 .prepare(
-                    "SELECT role, content, tool_calls, tool_call_id, attachments 
+                    "SELECT role, content, tool_calls, tool_call_id, attachments, synthetic
                  FROM messages 
                  WHERE session_id = ?1 
                  ORDER BY id ASC",
@@ -209,6 +296,7 @@ impl Storage {
                         tool_calls,
                         tool_call_id,
                         attachments,
+                        synthetic: row.get(5)?,
                     })
                 })
                 .map_err(|e| e.to_string())?;
@@ -230,6 +318,9 @@ impl Storage {
             model: ModelId(model),
             mode: agent_mode,
             messages,
+            agent,
+            parent_id: parent_id.and_then(|id| Uuid::parse_str(&id).ok()),
+            plan: plan.and_then(|p| serde_json::from_str(&p).ok()),
             permissions: AgentPermissions {
                 config: {
                     let mut config_manager = crate::config::ConfigManager::new();
@@ -240,17 +331,45 @@ impl Storage {
         })
     }
 
+    /// Top-level sessions; sub-agent transcripts are listed per parent
     pub fn list_sessions(&self) -> Result<Vec<SessionMetadata>, String> {
-        let mut stmt = self.db.prepare(
-            "SELECT s.id, s.workspace_path, s.model, s.mode, s.created_at, s.name, COUNT(m.id) as message_count
+        self.query_sessions("WHERE s.parent_id IS NULL", params![])
+    }
+
+    /// Sub-agent sessions spawned by `parent_id`
+    pub fn list_child_sessions(&self, parent_id: &str) -> Result<Vec<SessionMetadata>, String> {
+        self.query_sessions("WHERE s.parent_id = ?1", params![parent_id])
+    }
+
+    /// Sessions forked from `session_id`
+    pub fn list_forks(&self, session_id: &str) -> Result<Vec<SessionMetadata>, String> {
+        self.query_sessions("WHERE s.forked_from = ?1", params![session_id])
+    }
+
+    /// Record that `session_id` was forked from `source_id` before message `message_index`
+    pub fn set_fork_origin(&self, session_id: &str, source_id: &str, message_index: usize) -> Result<(), String> {
+        self.db
+            .execute(
+                "UPDATE sessions SET forked_from = ?1, fork_index = ?2 WHERE id = ?3",
+                params![source_id, message_index as i64, session_id],
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn query_sessions(&self, filter: &str, args: &[&dyn rusqlite::ToSql]) -> Result<Vec<SessionMetadata>, String> {
+        let mut stmt = self.db.prepare(&format!(
+            "SELECT s.id, s.workspace_path, s.model, s.mode, s.created_at, s.name, COUNT(m.id) as message_count, s.agent, s.parent_id, s.forked_from, s.fork_index
              FROM sessions s
              LEFT JOIN messages m ON s.id = m.session_id
+             {}
              GROUP BY s.id
-             ORDER BY s.created_at DESC"
-        ).map_err(|e: rusqlite::Error| e.to_string())?;
+             ORDER BY s.created_at DESC",
+            filter
+        )).map_err(|e: rusqlite::Error| e.to_string())?;
 
         let sessions = stmt
-            .query_map([], |row| {
+            .query_map(args, |row| {
                 let mode_str: String = row.get(3)?;
                 let mode = match mode_str.as_str() {
                     "Plan" => "Plan".to_string(),
@@ -266,6 +385,10 @@ impl Storage {
                     created_at: row.get(4)?,
                     name: row.get(5)?,
                     message_count: row.get(6)?,
+                    agent: row.get(7)?,
+                    parent_id: row.get(8)?,
+                    forked_from: row.get(9)?,
+                    fork_index: row.get(10)?,
                 })
             })
             .map_err(|e| e.to_string())?;
@@ -277,7 +400,10 @@ impl Storage {
 
     pub fn delete_session(&self, session_id: &str) -> Result<(), String> {
         self.db
-            .execute("DELETE FROM sessions WHERE id = ?1", params![session_id])
+            .execute(
+                "DELETE FROM sessions WHERE id = ?1 OR parent_id = ?1",
+                params![session_id],
+            )
             .map(|_| ())
             .map_err(|e| e.to_string())
     }
@@ -295,6 +421,79 @@ impl Storage {
             .map_err(|e| e.to_string())
     }
 
+    pub fn save_worktree(&self, worktree: &SessionWorktree) -> Result<(), String> {
+        self.db
+            .execute(
+                "INSERT OR REPLACE INTO worktrees (session_id, repo_path, path, branch, base) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    worktree.session_id,
+                    worktree.repo_path.to_string_lossy(),
+                    worktree.path.to_string_lossy(),
+                    worktree.branch,
+                    worktree.base,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn load_worktree(&self, session_id: &str) -> Result<Option<SessionWorktree>, String> {
+        Ok(self.query_worktrees("WHERE session_id = ?1", params![session_id])?.pop())
+    }
+
+    /// Worktrees whose session no longer exists, or was never saved
+    pub fn orphaned_worktrees(&self) -> Result<Vec<SessionWorktree>, String> {
+        self.query_worktrees(
+            "WHERE NOT EXISTS (SELECT 1 FROM sessions s WHERE s.id = worktrees.session_id)",
+            params![],
+        )
+    }
+
+    pub fn delete_worktree(&self, session_id: &str) -> Result<(), String> {
+        self.db
+            .execute("DELETE FROM worktrees WHERE session_id = ?1", params![session_id])
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Point a session at a different directory, e.g. back at the
+    /// repository once its worktree is gone
+    pub fn set_workspace_path(&self, session_id: &str, path: &Path) -> Result<(), String> {
+        self.db
+            .execute(
+                "UPDATE sessions SET workspace_path = ?1 WHERE id = ?2",
+                params![path.to_string_lossy(), session_id],
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn query_worktrees(&self, filter: &str, args: &[&dyn rusqlite::ToSql]) -> Result<Vec<SessionWorktree>, String> {
+        let mut stmt = self
+            .db
+            .prepare(&format!(
+                "SELECT session_id, repo_path, path, branch, base FROM worktrees {} ORDER BY created_at",
+                filter
+            ))
+            .map_err(|e: rusqlite::Error| e.to_string())?;
+
+        let worktrees = stmt
+            .query_map(args, |row| {
+                Ok(SessionWorktree {
+                    session_id: row.get(0)?,
+                    repo_path: PathBuf::from(row.get::<_, String>(1)?),
+                    path: PathBuf::from(row.get::<_, String>(2)?),
+                    branch: row.get(3)?,
+                    base: row.get(4)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        worktrees
+            .collect::<SqliteResult<Vec<_>>>()
+            .map_err(|e| e.to_string())
+    }
+
     pub fn get_session_summary(&self, session_id: &str) -> Result<Option<String>, String> {
         let summary: Option<String> = self
             .db
@@ -323,4 +522,155 @@ pub struct SessionMetadata {
     pub created_at: String,
     pub name: Option<String>,
     pub message_count: i64,
+    pub agent: Option<String>,
+    pub parent_id: Option<String>,
+    /// Session this one was branched from, and the message index it was cut at
+    pub forked_from: Option<String>,
+    pub fork_index: Option<i64>,
+}
+
+/// Add a column to an existing table, ignoring databases that already have it
+fn add_column(db: &Connection, table: &str, column_def: &str) -> Result<(), String> {
+    if let Err(e) = db.execute(&format!("ALTER TABLE {} ADD COLUMN {}", table, column_def), []) {
+        let message = e.to_string();
+        if !message.contains("duplicate column name") {
+            return Err(message);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(dir: &tempfile::TempDir) -> Storage {
+        Storage::new(&dir.path().join("sessions.db").to_string_lossy()).unwrap()
+    }
+
+    fn message(role: Role, content: &str) -> Message {
+        Message {
+            role,
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            attachments: None,
+            synthetic: false,
+        }
+    }
+
+    fn session(messages: Vec<Message>) -> AgentSession {
+        AgentSession {
+            id: Uuid::new_v4(),
+            workspace_path: PathBuf::from("/tmp/project"),
+            model: ModelId("gpt-4".to_string()),
+            mode: AgentMode::Build,
+            messages,
+            permissions: AgentPermissions { config: crate::config::PermissionConfig::default() },
+            agent: None,
+            parent_id: None,
+            plan: None,
+        }
+    }
+
+    #[test]
+    fn test_synthetic_messages_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir);
+        let mut images = message(Role::User, "Image content returned by the tools above: a.png");
+        images.synthetic = true;
+        let saved = session(vec![message(Role::User, "look at a.png"), message(Role::Tool, "ok"), images]);
+        storage.save_session(&saved).unwrap();
+
+        let loaded = storage.load_session(&saved.id.to_string()).unwrap();
+        let turns: Vec<bool> = loaded.messages.iter().map(Message::starts_turn).collect();
+        assert_eq!(turns, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_forks_are_listed_for_their_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir);
+        let source = session(vec![message(Role::User, "first"), message(Role::Assistant, "answer")]);
+        let fork = session(vec![message(Role::User, "first")]);
+        let other = session(Vec::new());
+        for session in [&source, &fork, &other] {
+            storage.save_session(session).unwrap();
+        }
+        storage.set_fork_origin(&fork.id.to_string(), &source.id.to_string(), 1).unwrap();
+
+        let forks = storage.list_forks(&source.id.to_string()).unwrap();
+        assert_eq!(forks.len(), 1);
+        assert_eq!(forks[0].id, fork.id.to_string());
+        assert_eq!(forks[0].forked_from.as_deref(), Some(source.id.to_string().as_str()));
+        assert_eq!(forks[0].fork_index, Some(1));
+        assert_eq!(forks[0].message_count, 1);
+        assert!(storage.list_forks(&fork.id.to_string()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_deleting_a_session_deletes_its_subagents() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir);
+        let parent = session(vec![message(Role::User, "delegate")]);
+        let mut child = session(vec![message(Role::User, "subtask")]);
+        child.parent_id = Some(parent.id);
+        let unrelated = session(vec![message(Role::User, "other")]);
+        let mut unrelated_child = session(Vec::new());
+        unrelated_child.parent_id = Some(unrelated.id);
+        for session in [&parent, &child, &unrelated, &unrelated_child] {
+            storage.save_session(session).unwrap();
+        }
+
+        let top_level: Vec<String> = storage.list_sessions().unwrap().into_iter().map(|s| s.id).collect();
+        assert_eq!(top_level.len(), 2);
+        assert!(!top_level.contains(&child.id.to_string()));
+        let children = storage.list_child_sessions(&parent.id.to_string()).unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].id, child.id.to_string());
+
+        storage.delete_session(&parent.id.to_string()).unwrap();
+        assert!(storage.load_session(&parent.id.to_string()).is_err());
+        assert!(storage.load_session(&child.id.to_string()).is_err());
+        assert_eq!(storage.load_session(&unrelated.id.to_string()).unwrap().messages.len(), 1);
+        assert_eq!(storage.list_child_sessions(&unrelated.id.to_string()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_saving_keeps_unchanged_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir);
+        let message_ids = |id: &Uuid| -> Vec<i64> {
+            let mut stmt = storage.db.prepare("SELECT id FROM messages WHERE session_id = ?1 ORDER BY id").unwrap();
+            let ids = stmt.query_map(params![id.to_string()], |row| row.get(0)).unwrap();
+            ids.map(|id| id.unwrap()).collect()
+        };
+        let mut saved = session(vec![
+            message(Role::System, "workspace"),
+            message(Role::User, "first"),
+            message(Role::Assistant, "one"),
+        ]);
+        storage.save_session(&saved).unwrap();
+        let first = message_ids(&saved.id);
+
+        saved.messages.push(message(Role::User, "second"));
+        saved.messages.push(message(Role::Assistant, "two"));
+        storage.save_session(&saved).unwrap();
+        let appended = message_ids(&saved.id);
+        assert_eq!(appended.len(), 4);
+        assert_eq!(appended[..2], first[..]);
+
+        // Cut back and continued differently
+        saved.messages.truncate(3);
+        saved.messages.push(message(Role::User, "again"));
+        storage.save_session(&saved).unwrap();
+        let rewritten = message_ids(&saved.id);
+        assert_eq!(rewritten.len(), 3);
+        assert_eq!(rewritten[..2], first[..]);
+        assert!(!appended.contains(&rewritten[2]));
+
+        let loaded = storage.load_session(&saved.id.to_string()).unwrap();
+        let contents: Vec<String> = loaded.messages.iter().filter_map(|m| m.content.clone()).collect();
+        assert_eq!(contents, vec!["first", "one", "again"]);
+    }
 }
@@ -28,6 +28,8 @@ interface ActivityStreamProps {
   isLoading?: boolean;
   view?: 'stream' | 'timeline';
   meta?: { mode: string; model: string };
+  /** Output streamed by tool calls that are still running, by call id */
+  liveOutput?: Record<string, string>;
 }
 
 function LoadingBlock() {
@@ -47,6 +49,12 @@ function truncateOutput(content: string, maxChars = 1400) {
   return `${content.slice(0, maxChars)}\n...`;
 }
 
+// Running output is most useful at its end
+function tailOutput(content: string, maxChars = 1400) {
+  if (content.length <= maxChars) return content;
+  return `...\n${content.slice(-maxChars)}`;
+}
+
 function stripLegacyToolLog(content: string) {
   return content
     .replace(/> Executing tool: `[^`]+`[^\n]*\n?/g, "")
@@ -131,6 +139,12 @@ function summarizeToolCall(toolName: string, rawArgs: string) {
     read_file: { type: 'read', desc: normalize(args.path) },
     write_file: { type: 'write', desc: normalize(args.path) },
     edit_file: { type: 'edit', desc: normalize(args.path) },
+    multi_edit: {
+      type: 'edit',
+      desc: Array.isArray(args.files)
+        ? normalize(args.files.map((file: { path?: string }) => file?.path ?? '').filter(Boolean))
+        : null
+    },
     patch: { type: 'edit', desc: normalize(args.path) },
     list: { type: 'search', desc: normalize(args.path ?? '.') },
     glob: { type: 'search', desc: normalize(args.pattern) },
@@ -187,7 +201,7 @@ function isErrorContent(content: string) {
   return false;
 }
 
-export function ActivityStream({ messages, isLoading, view = 'stream', meta }: ActivityStreamProps) {
+export function ActivityStream({ messages, isLoading, view = 'stream', meta, liveOutput }: ActivityStreamProps) {
   const turns = useMemo(() => {
     const list: ActivityTurn[] = [];
     let currentTurn: ActivityTurn | null = null;
@@ -218,6 +232,11 @@ export function ActivityStream({ messages, isLoading, view = 'stream', meta }: A
         return;
       }
 
+      // Added by the agent to pass tool images to the model
+      if (msg.synthetic) {
+        return;
+      }
+
       if (msg.role === 'User') {
         currentTurn = { id: `turn-${idx}`, items: [] };
         list.push(currentTurn);
@@ -268,6 +287,9 @@ export function ActivityStream({ messages, isLoading, view = 'stream', meta }: A
               actionType: summary.actionType,
               actionTitle: summary.title,
               actionDescription: summary.description,
+              actionContent: status === 'running' && liveOutput?.[call.id]
+                ? tailOutput(liveOutput[call.id])
+                : undefined,
               actionStatus: status
             };
             toolIndex.set(call.id, { turn, itemIndex: turn.items.length });
@@ -310,7 +332,7 @@ export function ActivityStream({ messages, isLoading, view = 'stream', meta }: A
     });
 
     return list.filter(turn => turn.items.length > 0);
-  }, [messages, isLoading]);
+  }, [messages, isLoading, liveOutput]);
 
   if (turns.length === 0 && !isLoading) {
     return null;
@@ -17,7 +17,7 @@ import { estimateUsage } from "../utils/usage";
 import { useAgentEvents } from "../hooks/useAgentEvents";
 
 export function Chat() {
-    const { sessionId, messages, addMessage, workspacePath, setSessionId, appendTokenToLastMessage, updateLastMessageContent, files, setFiles } = useStore();
+    const { sessionId, messages, addMessage, workspacePath, setSessionId, appendTokenToLastMessage, updateLastMessageContent, files, setFiles, liveOutput } = useStore();
     const { enabledModels, activeModelId, setActiveModel, activeProviderId, apiKeys } = useProviderStore();
     const { activeMode, setActiveMode, temperature, setTemperature, isEditorOpen, setEditorOpen, setSettingsOpen, isQuestionOpen } = useUIStore();
     const [input, setInput] = useState("");
@@ -724,10 +724,27 @@ export function Chat() {
         return true;
     };
 
-    async function handleSend() {
+    async function handleSend(interrupt = false) {
         if (!input.trim()) return;
         if (handleSlashCommand(input)) return;
 
+        // While a turn is running, steer it instead of queueing a new one
+        if (loading && sessionId) {
+            try {
+                const queued = await invoke<boolean>("steer_session", { sessionId, message: input, interrupt });
+                if (queued) {
+                    addMessage({ role: "User", content: input });
+                    // Output after the steering message streams into a fresh reply
+                    addMessage({ role: "Assistant", content: "" });
+                    setInput("");
+                    return;
+                }
+            } catch (e) {
+                addMessage({ role: "System", content: `Failed to send message to the running agent: ${e}` });
+                return;
+            }
+        }
+
         if (imageAttachments.length > 0 && !supportsImages) {
             addMessage({ role: "System", content: "Image upload is not supported for the active model. Please switch to a vision-capable model." });
             return;
@@ -933,6 +950,7 @@ export function Chat() {
                         isLoading={loading}
                         view={activityView}
                         meta={{ mode: activeMode, model: activeModelId }}
+                        liveOutput={liveOutput}
                     />
                 </div>
             </div>
@@ -1081,7 +1099,7 @@ export function Chat() {
 
                                 if (e.key === "Enter" && !e.shiftKey) {
                                     e.preventDefault();
-                                    handleSend();
+                                    handleSend(e.altKey);
                                 }
                                 }}
                                 placeholder="Explain your changes or ask a question..."
@@ -1291,8 +1309,8 @@ export function Chat() {
                                     <ImageIcon size={18} />
                                 </button>
                                 <button 
-                                    onClick={handleSend}
-                                    disabled={!input.trim() || loading}
+                                    onClick={() => handleSend()}
+                                    disabled={!input.trim()}
                                     className="ml-1 p-2 bg-[var(--accent)] hover:bg-[var(--accent)]/80 disabled:bg-zinc-900 disabled:text-zinc-700 text-white rounded-xl transition-all active:scale-95 group/send"
                                 >
                                     <Send size={20} className="group-hover/send:translate-x-0.5 group-hover/send:-translate-y-0.5 transition-transform" strokeWidth={2.5} />
@@ -1301,6 +1319,12 @@ export function Chat() {
                         </div>
                         <div className="mt-3 flex items-center justify-center gap-4 text-[10px] text-zinc-600 font-bold uppercase tracking-widest">
                             <span>Shift + Enter for new line</span>
+                            {loading && (
+                                <>
+                                    <span className="w-1 h-1 bg-zinc-800 rounded-full" />
+                                    <span>Alt + Enter to interrupt</span>
+                                </>
+                            )}
                             <span className="w-1 h-1 bg-zinc-800 rounded-full" />
                             <span>Control + P for commands</span>
                         </div>
@@ -26,7 +26,7 @@ export function ConfirmationModal() {
         : isShell
             ? 'Confirm Shell Command'
             : isDoomLoop
-                ? 'Repeated Tool Call'
+                ? 'Possible Loop'
                 : isMode
                     ? 'Research Mode'
                     : 'Permission Request';
@@ -146,7 +146,7 @@ export function ConfirmationModal() {
                     ) : (
                         <div className="p-6">
                             <p className="text-xs text-zinc-400 mb-3 uppercase font-bold tracking-widest">
-                                {isDoomLoop ? 'REPEATED TOOL CALL' : isMode ? 'RESEARCH MODE' : 'REQUEST'}
+                                {isDoomLoop ? 'POSSIBLE LOOP' : isMode ? 'RESEARCH MODE' : 'REQUEST'}
                             </p>
                             <div className="bg-[#09090b] border border-[var(--border)] rounded-lg p-4 font-mono text-sm text-zinc-200 break-all mb-6">
                                 {pendingRequest.tool_name ? (
@@ -181,7 +181,7 @@ export function ConfirmationModal() {
                             </div>
                             {isDoomLoop && (
                                 <p className="mt-6 text-[10px] text-zinc-500 leading-relaxed italic border-l-2 border-[var(--border)] pl-4">
-                                    This tool call has repeated multiple times with the same inputs. Allow once to continue, or allow always to suppress this warning for matching calls.
+                                    {pendingRequest.reason ?? 'This tool call has repeated multiple times with the same inputs.'} Allow once to continue, or allow always to suppress this warning for matching calls.
                                 </p>
                             )}
                         </div>
@@ -27,6 +27,7 @@ vi.mock('@tauri-apps/api/core', () => ({
         todoread: { default: 'allow', rules: [] },
         todowrite: { default: 'allow', rules: [] },
         doom_loop: { default: 'ask', rules: [] },
+        git_destructive: { default: 'ask', rules: [] },
       });
     }
     if (cmd === 'save_permission_config') {
@@ -63,6 +64,7 @@ describe('PermissionsSettings Component', () => {
             todoread: { default: 'allow', rules: [] },
             todowrite: { default: 'allow', rules: [] },
             doom_loop: { default: 'ask', rules: [] },
+            git_destructive: { default: 'ask', rules: [] },
         },
         setPermissions: vi.fn(),
     } as any)
@@ -101,6 +103,7 @@ describe('PermissionsSettings Component', () => {
           todoread: { default: 'allow', rules: [] },
           todowrite: { default: 'allow', rules: [] },
           doom_loop: { default: 'ask', rules: [] },
+          git_destructive: { default: 'ask', rules: [] },
         },
         setPermissions: vi.fn(),
       } as any)
@@ -119,7 +122,7 @@ describe('PermissionsSettings Component', () => {
           expect(screen.getByText('Read Files')).toBeInTheDocument()
       })
       
-      const tools = ['Read Files', 'Write Files', 'Edit Files', 'Terminal', 'List', 'Glob', 'Search', 'Web Fetch', 'Subagents', 'LSP', 'Todo Read', 'Todo Write', 'Doom Loop', 'Skills']
+      const tools = ['Read Files', 'Write Files', 'Edit Files', 'Terminal', 'List', 'Glob', 'Search', 'Web Fetch', 'Subagents', 'LSP', 'Todo Read', 'Todo Write', 'Doom Loop', 'Git (destructive)', 'Skills']
       
       tools.forEach(tool => {
         expect(screen.getByText(tool)).toBeInTheDocument()
@@ -143,6 +146,7 @@ describe('PermissionsSettings Component', () => {
           todoread: { default: 'allow', rules: [] },
           todowrite: { default: 'allow', rules: [] },
           doom_loop: { default: 'ask', rules: [] },
+          git_destructive: { default: 'ask', rules: [] },
         },
         setPermissions: vi.fn(),
       } as any)
@@ -194,6 +198,7 @@ describe('PermissionsSettings Component', () => {
           todoread: { default: 'allow', rules: [] },
           todowrite: { default: 'allow', rules: [] },
           doom_loop: { default: 'ask', rules: [] },
+          git_destructive: { default: 'ask', rules: [] },
         },
         setPermissions: setPermissionsMock,
       } as any)
@@ -254,6 +259,7 @@ describe('PermissionsSettings Component', () => {
             todoread: { default: 'allow', rules: [] },
             todowrite: { default: 'allow', rules: [] },
             doom_loop: { default: 'ask', rules: [] },
+            git_destructive: { default: 'ask', rules: [] },
         },
         setPermissions: setPermissionsMock
       } as any);
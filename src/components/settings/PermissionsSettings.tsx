@@ -1,12 +1,12 @@
 import { useState, useEffect, useRef } from 'react';
 import { useSettingsStore, PermissionAction, PermissionRule, PermissionConfig, ToolPermission, PermissionValue } from '../../stores/settings';
 import clsx from 'clsx';
-import { Shield, Terminal, FileEdit, FileText, File, Plus, Trash2, ChevronDown, ChevronRight, Save, RotateCw, Brain, Search, Globe, ListChecks, Workflow, Repeat } from 'lucide-react';
+import { Shield, Terminal, FileEdit, FileText, File, Plus, Trash2, ChevronDown, ChevronRight, Save, RotateCw, Brain, Search, Globe, ListChecks, Workflow, Repeat, GitBranch } from 'lucide-react';
 import type { LucideIcon } from 'lucide-react';
 import { invoke } from '@tauri-apps/api/core';
 import { useStore } from '../../store';
 
-type ToolKey = Exclude<keyof PermissionConfig, 'external_directory'>;
+type ToolKey = Exclude<keyof PermissionConfig, 'external_directory' | 'sandbox'>;
 
 export function PermissionsSettings() {
     const { permissions, setPermissions } = useSettingsStore();
@@ -61,6 +61,7 @@ export function PermissionsSettings() {
         todoread: { default: 'allow', rules: [] },
         todowrite: { default: 'allow', rules: [] },
         doom_loop: { default: 'ask', rules: [] },
+        git_destructive: { default: 'ask', rules: [] },
     };
 
     const normalizePermission = (value: PermissionValue | undefined, key: ToolKey): ToolPermission => {
@@ -91,7 +92,9 @@ export function PermissionsSettings() {
         todoread: normalizePermission(config?.todoread ?? permissions.todoread, 'todoread'),
         todowrite: normalizePermission(config?.todowrite ?? permissions.todowrite, 'todowrite'),
         doom_loop: normalizePermission(config?.doom_loop ?? permissions.doom_loop, 'doom_loop'),
-        external_directory: config?.external_directory ?? permissions.external_directory
+        git_destructive: normalizePermission(config?.git_destructive ?? permissions.git_destructive, 'git_destructive'),
+        external_directory: config?.external_directory ?? permissions.external_directory,
+        sandbox: config?.sandbox ?? permissions.sandbox
     });
 
     const handleLoad = async () => {
@@ -192,6 +195,7 @@ export function PermissionsSettings() {
         { key: 'todoread', label: 'Todo Read', icon: ListChecks, desc: 'Reading TODO tasks' },
         { key: 'todowrite', label: 'Todo Write', icon: ListChecks, desc: 'Updating TODO tasks' },
         { key: 'doom_loop', label: 'Doom Loop', icon: Repeat, desc: 'Repeated tool call guard' },
+        { key: 'git_destructive', label: 'Git (destructive)', icon: GitBranch, desc: 'Switching branches, stashing and restoring files' },
         { key: 'skill', label: 'Skills', icon: Brain, desc: 'Using AI skills/tools' },
     ];
 
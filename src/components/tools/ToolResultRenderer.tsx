@@ -98,6 +98,19 @@ export function ToolResultRenderer({ toolName, result }: ToolResultRendererProps
       );
     }
 
+    case 'multi_edit': {
+      const data = parsedResult as { status?: string; message?: string; files?: Array<{ path?: string }> };
+      return (
+        <EditFileCard
+          data={{
+            status: data.status || 'unknown',
+            message: data.message,
+            path: data.files?.map((file) => file.path).filter(Boolean).join(', '),
+          }}
+        />
+      );
+    }
+
     case 'bash': {
       const data = parsedResult as {
         stdout?: string;
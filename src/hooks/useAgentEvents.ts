@@ -29,6 +29,7 @@ export function useAgentEvents() {
                 if (!state.sessionId || state.sessionId !== session_id) {
                     return;
                 }
+                state.clearLiveOutput(tool_call_id);
                 state.addMessage({
                     role: "Tool",
                     content,
@@ -37,6 +38,44 @@ export function useAgentEvents() {
             }
         );
 
+        // Output of a running bash call; it belongs to the latest bash call without a result
+        const unlistenBashOutput = listen<{ session_id: string; stream: string; chunk: string }>(
+            "bash-output",
+            (event) => {
+                const { session_id, chunk } = event.payload;
+                const state = useStore.getState();
+                if (!state.sessionId || state.sessionId !== session_id) {
+                    return;
+                }
+                const finished = new Set(
+                    state.messages.filter((msg) => msg.role === "Tool").map((msg) => msg.tool_call_id)
+                );
+                const running = state.messages
+                    .flatMap((msg) => msg.tool_calls || [])
+                    .filter((call) => call.name === "bash" && !finished.has(call.id))
+                    .pop();
+                if (running) {
+                    state.appendLiveOutput(running.id, chunk);
+                }
+            }
+        );
+
+        // Messages sent while a turn was ending that it never read
+        const unlistenSteeringUndelivered = listen<{ session_id: string; messages: string[] }>(
+            "agent-steering-undelivered",
+            (event) => {
+                const { session_id, messages } = event.payload;
+                const state = useStore.getState();
+                if (!state.sessionId || state.sessionId !== session_id) {
+                    return;
+                }
+                state.addMessage({
+                    role: "System",
+                    content: `The agent stopped before reading ${messages.length === 1 ? "this message" : "these messages"}; send again to continue:\n\n${messages.join("\n\n")}`
+                });
+            }
+        );
+
         // Agent File Open Listener (For Agent-Aware Editor)
         const unlistenFileOpen = listen<{ path: string, reason: string, line_start?: number, line_end?: number }>("file-opened-by-agent", async (event) => {
             const { path, reason, line_start } = event.payload;
@@ -56,6 +95,8 @@ export function useAgentEvents() {
             unlistenFileOpen.then(f => f());
             unlistenToolCall.then(f => f());
             unlistenToolResult.then(f => f());
+            unlistenBashOutput.then(f => f());
+            unlistenSteeringUndelivered.then(f => f());
         };
     }, []);
 }
@@ -12,6 +12,8 @@ interface AppState {
     activeFileContent: string;
     openFiles: string[];
     messages: Message[];
+    /** Output of running tool calls by call id, dropped once the result arrives */
+    liveOutput: Record<string, string>;
     
     setSessionId: (id: string | null) => void;
     setWorkspacePath: (path: string) => void;
@@ -21,6 +23,8 @@ interface AppState {
     appendToolCallToLastAssistant: (call: { id: string; name: string; arguments: string }) => void;
     appendTokenToLastMessage: (token: string) => void;
     updateLastMessageContent: (content: string) => void;
+    appendLiveOutput: (toolCallId: string, chunk: string) => void;
+    clearLiveOutput: (toolCallId: string) => void;
     setActiveFile: (path: string | null) => void;
     setActiveFileContent: (content: string) => void;
     setFiles: (files: FileNode[]) => void;
@@ -42,6 +46,7 @@ export const useStore = create<AppState>()(
             activeFileContent: "// Select a file to view",
             openFiles: [],
             messages: [],
+            liveOutput: {},
 
             setSessionId: (id) => set({ sessionId: id }),
             setWorkspacePath: (path) => set({ workspacePath: path }),
@@ -92,6 +97,17 @@ export const useStore = create<AppState>()(
                 }
                 return { messages: msgs };
             }),
+            appendLiveOutput: (toolCallId, chunk) => set((state) => ({
+                liveOutput: {
+                    ...state.liveOutput,
+                    [toolCallId]: (state.liveOutput[toolCallId] || "") + chunk
+                }
+            })),
+            clearLiveOutput: (toolCallId) => set((state) => {
+                if (!(toolCallId in state.liveOutput)) return {};
+                const { [toolCallId]: _removed, ...rest } = state.liveOutput;
+                return { liveOutput: rest };
+            }),
             setActiveFile: (path) => set({ activeFile: path }),
             setActiveFileContent: (content) => set({ activeFileContent: content }),
             setFiles: (files) => set({ files }),
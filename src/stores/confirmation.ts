@@ -12,6 +12,7 @@ interface ConfirmationRequest {
     tool_name?: string;
     input?: string;
     suggested_pattern?: string;
+    reason?: string;
 }
 
 interface ConfirmationState {
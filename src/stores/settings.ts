@@ -35,7 +35,18 @@ export interface PermissionConfig {
     todoread: PermissionValue;
     todowrite: PermissionValue;
     doom_loop: PermissionValue;
+    git_destructive: PermissionValue;
     external_directory?: Record<string, PermissionAction>;
+    sandbox?: SandboxConfig;
+}
+
+export interface SandboxConfig {
+    enabled?: boolean;
+    network?: boolean;
+    writable?: string[];
+    env?: string[];
+    auto_allow?: boolean;
+    required?: boolean;
 }
 
 interface SettingsState {
@@ -94,6 +105,7 @@ export const useSettingsStore = create<SettingsState>()(
               todoread: { ...defaultToolPermission },
               todowrite: { ...defaultToolPermission },
               doom_loop: { ...defaultAskPermission },
+              git_destructive: { ...defaultAskPermission },
             },
             setTheme: (theme) => set({ theme }),
             setFontFamily: (fontFamily) => set({ fontFamily }),
@@ -112,7 +124,7 @@ export const useSettingsStore = create<SettingsState>()(
                     // Migration from version 0 to 1
                     // Convert string permissions to object permissions
                     const newState = { ...persistedState } as SettingsState;
-                    const tools = ['read', 'write', 'edit', 'bash', 'skill', 'list', 'glob', 'grep', 'webfetch', 'task', 'lsp', 'todoread', 'todowrite', 'doom_loop'];
+                    const tools = ['read', 'write', 'edit', 'bash', 'skill', 'list', 'glob', 'grep', 'webfetch', 'task', 'lsp', 'todoread', 'todowrite', 'doom_loop', 'git_destructive'];
                     
                     if (newState.permissions) {
                         tools.forEach(tool => {
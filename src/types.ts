@@ -10,6 +10,7 @@ export interface Message {
     tool_calls?: ToolCall[];
     tool_call_id?: string;
     attachments?: Attachment[];
+    synthetic?: boolean;
 }
 
 export interface ToolCall {